@@ -1,15 +1,18 @@
+#[cfg(feature = "gui")]
 use eframe::egui;
 use lifx_core::HSBK;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use strum_macros::{AsRefStr, EnumIter, VariantNames};
 
+#[cfg(feature = "gui")]
+use crate::scripting::Script;
+#[cfg(feature = "gui")]
+use crate::ui::{
+    brightness_slider, effect_config_ui, hsbk_sliders, hue_slider, kelvin_slider, saturation_slider,
+};
 use crate::{
-    color::HSBKField,
-    device_info::DeviceInfo,
-    scenes::Scene,
-    ui::{brightness_slider, hsbk_sliders, hue_slider, kelvin_slider, saturation_slider},
-    LifxManager,
+    color::HSBKField, device_info::DeviceInfo, effects::EffectConfig, scenes::Scene, LifxManager,
 };
 use rust_i18n::t;
 
@@ -44,6 +47,14 @@ pub enum UserAction {
         scene: Scene,
     },
     TogglePower,
+    StartEffect {
+        effect: EffectConfig,
+    },
+    StopEffect,
+    #[cfg(feature = "scripting")]
+    RunScript {
+        name: String,
+    },
 }
 
 /// Take a `Scene` and convert it into a `UserAction`
@@ -126,9 +137,9 @@ impl UserAction {
             }
             UserAction::SetPower { power } => {
                 log::info!("Executing action: Set Power - {}", power);
+                let power = if *power { u16::MAX } else { 0 };
                 match device {
                     DeviceInfo::Group(_group_info) => {
-                        let power = if *power { u16::MAX } else { 0 };
                         if let Err(e) = lifx_manager.set_group_power(
                             &_group_info,
                             &lifx_manager.bulbs.lock().unwrap(),
@@ -138,7 +149,7 @@ impl UserAction {
                         }
                     }
                     DeviceInfo::Bulb(bulb_info) => {
-                        if let Err(e) = lifx_manager.set_power(&&*bulb_info, *power as u16) {
+                        if let Err(e) = lifx_manager.set_power(&&*bulb_info, power) {
                             log::error!("Failed to set power: {}", e);
                         }
                     }
@@ -242,15 +253,40 @@ impl UserAction {
                     log::error!("Failed to apply scene: {:?}", e);
                 }
             }
+            UserAction::StartEffect { effect } => {
+                log::info!("Executing action: Start Effect - {}", effect.kind);
+                if let Err(e) = crate::effects::start_active_effect(device, effect.build()) {
+                    log::error!("Failed to start effect: {}", e);
+                }
+            }
+            UserAction::StopEffect => {
+                log::info!("Executing action: Stop Effect");
+                if let Err(e) = crate::effects::stop_active_effect(device.id()) {
+                    log::error!("Failed to stop effect: {}", e);
+                }
+            }
+            #[cfg(feature = "scripting")]
+            UserAction::RunScript { name } => {
+                log::info!("Executing action: Run Script - {}", name);
+                let device = match device {
+                    DeviceInfo::Bulb(bulb_info) => Some(bulb_info.serial()),
+                    DeviceInfo::Group(_) => None,
+                };
+                if let Err(e) = crate::scripting::run_script(name, device) {
+                    log::error!("Failed to run script: {}", e);
+                }
+            }
         }
     }
 
     /// Draw UI elements for the corresponding action
+    #[cfg(feature = "gui")]
     pub fn ui(
         &mut self,
         ui: &mut egui::Ui,
         device: Option<DeviceInfo>,
         scenes: Vec<Scene>,
+        scripts: &[Script],
     ) -> egui::Response {
         match self {
             UserAction::Refresh => ui.label(""),
@@ -297,6 +333,19 @@ impl UserAction {
                     .response
                     .on_hover_text(t!("action.scene_hover").to_string())
             }
+            UserAction::StartEffect { effect } => effect_config_ui(ui, effect),
+            UserAction::StopEffect => ui.label(""),
+            UserAction::RunScript { name } => {
+                egui::ComboBox::from_label(t!("action.script_label").to_string())
+                    .selected_text(name.as_str())
+                    .show_ui(ui, |ui| {
+                        for script in scripts {
+                            ui.selectable_value(name, script.name.clone(), &script.name);
+                        }
+                    })
+                    .response
+                    .on_hover_text(t!("action.script_hover").to_string())
+            }
         }
     }
 }
@@ -346,6 +395,14 @@ impl Display for UserAction {
             UserAction::SetScene { scene } => {
                 write!(f, "{}", t!("action.set_scene", name = &scene.name))
             }
+            UserAction::StartEffect { effect } => {
+                write!(f, "{}", t!("action.start_effect", name = effect.kind))
+            }
+            UserAction::StopEffect => write!(f, "{}", t!("action.stop_effect")),
+            #[cfg(feature = "scripting")]
+            UserAction::RunScript { name } => {
+                write!(f, "{}", t!("action.run_script", name = name))
+            }
         }
     }
 }
@@ -386,6 +443,26 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_run_script_display() {
+        let action = UserAction::RunScript {
+            name: "Party".to_string(),
+        };
+        assert_eq!(format!("{}", action), "Run Script: Party");
+        assert_eq!(action.as_ref(), "run_script");
+    }
+
+    #[test]
+    fn test_effect_action_display() {
+        let action = UserAction::StartEffect {
+            effect: EffectConfig::new(crate::effects::EffectKind::Chase),
+        };
+        assert_eq!(format!("{}", action), "Start Effect: Chase");
+        assert_eq!(action.as_ref(), "start_effect");
+        assert_eq!(format!("{}", UserAction::StopEffect), "Stop Effect");
+    }
+
     #[test]
     fn test_user_action_variants() {
         let mut variants = UserAction::iter();
@@ -0,0 +1,655 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+use lifx_core::{Waveform, HSBK};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use zbus::blocking::{connection, fdo::MonitoringProxy, Connection, MessageIterator};
+use zbus::message::Type as MessageType;
+use zbus::{fdo, interface, zvariant, MatchRule, SignalContext};
+
+use super::light_state;
+use crate::{
+    action::UserAction, color::ColorSpec, device_info::DeviceInfo, scenes::find_scene,
+    scenes::Scene, selector::Selector, BulbInfo, LifxManager,
+};
+use rust_i18n::t;
+
+pub const BUS_NAME: &str = "io.github.samclane.Mantle";
+pub const OBJECT_PATH: &str = "/io/github/samclane/Mantle";
+pub const INTERFACE: &str = "io.github.samclane.Mantle1";
+
+const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+/// Devices go offline without sending anything, so their state is re-checked this often.
+const ONLINE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const FLASH_PERIOD_MS: u32 = 400;
+const FLASH_CYCLES: f32 = 2.0;
+
+/// A device as returned by `ListDevices` and carried by `DeviceChanged`: the fields of
+/// `light_state` as a D-Bus `a{sv}` dictionary.
+pub type DeviceState = HashMap<String, zvariant::Value<'static>>;
+
+/// User-facing configuration of the D-Bus service, persisted with the app settings.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbusSettings {
+    pub enabled: bool,
+    /// Pulse `notification_light` whenever a desktop notification is shown.
+    pub flash_on_notifications: bool,
+    /// Selector of the light to flash, e.g. `id:d073d5000001`.
+    pub notification_light: String,
+}
+
+/// Exposes the device registry on the session bus as `io.github.samclane.Mantle`.
+///
+/// The object at `/io/github/samclane/Mantle` implements `io.github.samclane.Mantle1`:
+///
+/// - `ListDevices() -> aa{sv}`
+/// - `ListScenes() -> as`
+/// - `SetPower(s selector, b on, u duration_ms) -> u`
+/// - `SetColor(s selector, s color, u duration_ms) -> u`
+/// - `ApplyScene(s name)`
+/// - `RunAction(s selector, s action) -> u`, where `action` is a `UserAction` in JSON
+/// - signal `DeviceChanged(s id, a{sv} state)`
+///
+/// Methods that target a selector return how many online lights they reached. The bus
+/// name is released when the service is dropped.
+pub struct DbusService {
+    settings: DbusSettings,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    running: Arc<AtomicBool>,
+    signaller: Option<JoinHandle<()>>,
+    monitor: Option<Connection>,
+    // Dropped last so the signaller can still emit while it shuts down.
+    _connection: Connection,
+}
+
+impl DbusService {
+    pub fn start(
+        settings: &DbusSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<DbusService, anyhow::Error> {
+        Self::start_at(None, settings, manager, scenes)
+    }
+
+    /// Start on the bus at `address`, or on the session bus if `None`.
+    fn start_at(
+        address: Option<&str>,
+        settings: &DbusSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<DbusService, anyhow::Error> {
+        let flash_selector: Option<Selector> = if settings.flash_on_notifications {
+            if settings.notification_light.trim().is_empty() {
+                return Err(anyhow::anyhow!(
+                    t!("error.dbus_notification_light").to_string()
+                ));
+            }
+            Some(settings.notification_light.parse()?)
+        } else {
+            None
+        };
+        let scenes = Arc::new(RwLock::new(scenes));
+        let service = MantleInterface {
+            manager: manager.clone(),
+            scenes: scenes.clone(),
+        };
+        let connection = builder(address)?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, service)?
+            .build()?;
+        let monitor = match flash_selector {
+            Some(selector) => Some(watch_notifications(address, selector, manager.clone())?),
+            None => None,
+        };
+
+        let running = Arc::new(AtomicBool::new(true));
+        let signaller = {
+            let running = running.clone();
+            let connection = connection.clone();
+            spawn(move || emit_changes(&connection, &manager, &running))
+        };
+
+        Ok(DbusService {
+            settings: settings.clone(),
+            scenes,
+            running,
+            signaller: Some(signaller),
+            monitor,
+            _connection: connection,
+        })
+    }
+
+    pub fn settings(&self) -> &DbusSettings {
+        &self.settings
+    }
+
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for DbusService {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.signaller.take() {
+            let _ = handle.join();
+        }
+        // Closing the monitor connection ends its message loop.
+        if let Some(monitor) = self.monitor.take() {
+            let _ = monitor.close();
+        }
+    }
+}
+
+fn builder(address: Option<&str>) -> zbus::Result<connection::Builder<'static>> {
+    match address {
+        Some(address) => connection::Builder::address(address),
+        None => connection::Builder::session(),
+    }
+}
+
+struct MantleInterface {
+    manager: LifxManager,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+}
+
+impl MantleInterface {
+    /// The online bulbs matched by `selector`.
+    fn select(&self, selector: &str) -> fdo::Result<Vec<BulbInfo>> {
+        let parsed: Selector = selector
+            .parse()
+            .map_err(|e: anyhow::Error| fdo::Error::InvalidArgs(e.to_string()))?;
+        let bulbs = self
+            .manager
+            .bulbs
+            .lock()
+            .map_err(|_| fdo::Error::Failed("Device registry unavailable".to_string()))?;
+        let selected: Vec<BulbInfo> = parsed
+            .select(&bulbs)
+            .into_iter()
+            .filter(|b| b.is_online())
+            .cloned()
+            .collect();
+        if selected.is_empty() {
+            return Err(fdo::Error::Failed(format!("Could not find {}.", selector)));
+        }
+        Ok(selected)
+    }
+
+    fn for_each_bulb(
+        &self,
+        selector: &str,
+        mut action: impl FnMut(&BulbInfo) -> Result<usize, std::io::Error>,
+    ) -> fdo::Result<u32> {
+        let bulbs = self.select(selector)?;
+        for bulb in &bulbs {
+            action(bulb).map_err(|e| fdo::Error::IOError(e.to_string()))?;
+        }
+        Ok(bulbs.len() as u32)
+    }
+}
+
+#[interface(name = "io.github.samclane.Mantle1")]
+impl MantleInterface {
+    fn list_devices(&self) -> fdo::Result<Vec<DeviceState>> {
+        let bulbs = self
+            .manager
+            .bulbs
+            .lock()
+            .map_err(|_| fdo::Error::Failed("Device registry unavailable".to_string()))?;
+        Ok(Selector::All
+            .select(&bulbs)
+            .into_iter()
+            .map(|bulb| to_device_state(light_state(bulb)))
+            .collect())
+    }
+
+    fn list_scenes(&self) -> Vec<String> {
+        self.scenes
+            .read()
+            .map(|scenes| scenes.iter().map(|s| s.name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    fn set_power(&self, selector: &str, on: bool, duration_ms: u32) -> fdo::Result<u32> {
+        let level = if on { u16::MAX } else { 0 };
+        self.for_each_bulb(selector, |bulb| {
+            self.manager
+                .set_power_with_duration(&bulb, level, duration_ms)
+        })
+    }
+
+    fn set_color(&self, selector: &str, color: &str, duration_ms: u32) -> fdo::Result<u32> {
+        let spec: ColorSpec = color
+            .parse()
+            .map_err(|e: anyhow::Error| fdo::Error::InvalidArgs(e.to_string()))?;
+        self.for_each_bulb(selector, |bulb| {
+            let current = bulb
+                .get_color()
+                .copied()
+                .unwrap_or(crate::color::BASE_COLOR);
+            self.manager
+                .set_color(&bulb, spec.apply(current), Some(duration_ms))
+        })
+    }
+
+    fn apply_scene(&self, name: &str) -> fdo::Result<()> {
+        let scene = {
+            let scenes = self
+                .scenes
+                .read()
+                .map_err(|_| fdo::Error::Failed("Scenes unavailable".to_string()))?;
+            find_scene(&scenes, name)
+                .cloned()
+                .ok_or_else(|| fdo::Error::Failed(format!("Could not find scene {}.", name)))?
+        };
+        scene
+            .apply(&mut self.manager.clone())
+            .map_err(|errors| fdo::Error::Failed(errors.join("; ")))
+    }
+
+    fn run_action(&self, selector: &str, action: &str) -> fdo::Result<u32> {
+        let action: UserAction =
+            serde_json::from_str(action).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+        self.for_each_bulb(selector, |bulb| {
+            action.execute(
+                self.manager.clone(),
+                DeviceInfo::Bulb(Box::new(bulb.clone())),
+            );
+            Ok(1)
+        })
+    }
+
+    #[zbus(signal)]
+    async fn device_changed(
+        ctxt: &SignalContext<'_>,
+        id: &str,
+        state: DeviceState,
+    ) -> zbus::Result<()>;
+}
+
+/// Emit `DeviceChanged` whenever the stored state of a device changes, until `running`
+/// is cleared.
+fn emit_changes(connection: &Connection, manager: &LifxManager, running: &AtomicBool) {
+    let changes = manager.subscribe();
+    let mut sent: HashMap<u64, Map<String, Value>> = HashMap::new();
+    // Everything known at startup counts as already announced; ListDevices covers it.
+    if let Ok(bulbs) = manager.bulbs.lock() {
+        sent.extend(bulbs.iter().map(|(id, bulb)| (*id, light_state(bulb))));
+    }
+    while running.load(Ordering::SeqCst) {
+        let targets: Option<Vec<u64>> = match changes.recv_timeout(ONLINE_CHECK_INTERVAL) {
+            Ok(target) => Some(std::iter::once(target).chain(changes.try_iter()).collect()),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        let changed: Vec<(String, Map<String, Value>)> = {
+            let Ok(bulbs) = manager.bulbs.lock() else {
+                continue;
+            };
+            bulbs
+                .iter()
+                .filter(|(id, _)| targets.as_ref().is_none_or(|t| t.contains(id)))
+                .filter_map(|(id, bulb)| {
+                    let state = light_state(bulb);
+                    (sent.get(id) != Some(&state)).then(|| {
+                        sent.insert(*id, state.clone());
+                        (bulb.serial(), state)
+                    })
+                })
+                .collect()
+        };
+        for (id, state) in changed {
+            if let Err(e) = connection.emit_signal(
+                None::<&str>,
+                OBJECT_PATH,
+                INTERFACE,
+                "DeviceChanged",
+                &(id.as_str(), to_device_state(state)),
+            ) {
+                log::error!("Failed to emit DeviceChanged for {}: {}", id, e);
+            }
+        }
+    }
+}
+
+/// Pulse the selected lights whenever a desktop notification is shown. Returns the monitor
+/// connection; closing it stops the watch.
+fn watch_notifications(
+    address: Option<&str>,
+    selector: Selector,
+    manager: LifxManager,
+) -> Result<Connection, anyhow::Error> {
+    let connection = builder(address)?.build()?;
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::MethodCall)
+        .interface(NOTIFICATIONS_INTERFACE)?
+        .member("Notify")?
+        .build();
+    MonitoringProxy::new(&connection)?.become_monitor(&[rule], 0)?;
+    let messages = MessageIterator::from(&connection);
+    spawn(move || {
+        for message in messages {
+            let Ok(message) = message else {
+                break;
+            };
+            let header = message.header();
+            if header.member().map(|m| m.as_str()) == Some("Notify") {
+                flash(&manager, &selector);
+            }
+        }
+    });
+    Ok(connection)
+}
+
+fn flash(manager: &LifxManager, selector: &Selector) {
+    let selected: Vec<BulbInfo> = match manager.bulbs.lock() {
+        Ok(bulbs) => selector
+            .select(&bulbs)
+            .into_iter()
+            .filter(|b| b.is_online())
+            .cloned()
+            .collect(),
+        Err(_) => return,
+    };
+    for bulb in &selected {
+        let current = bulb
+            .get_color()
+            .copied()
+            .unwrap_or(crate::color::BASE_COLOR);
+        let color = HSBK {
+            saturation: 0,
+            brightness: u16::MAX,
+            ..current
+        };
+        if let Err(e) = manager.set_waveform(
+            &bulb,
+            Waveform::Pulse,
+            color,
+            FLASH_PERIOD_MS,
+            FLASH_CYCLES,
+            0.5,
+            true,
+        ) {
+            log::error!("Failed to flash {}: {}", bulb.serial(), e);
+        }
+    }
+}
+
+/// Convert the JSON state of a light into D-Bus variants. Nulls are left out, since
+/// D-Bus has no null; nested objects become `a{sv}` and arrays `av`.
+fn to_device_state(state: Map<String, Value>) -> DeviceState {
+    state
+        .into_iter()
+        .filter_map(|(key, value)| to_variant(value).map(|value| (key, value)))
+        .collect()
+}
+
+fn to_variant(value: Value) -> Option<zvariant::Value<'static>> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => b.into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64()?.into(),
+        },
+        Value::String(s) => s.into(),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| to_variant(item).unwrap_or_else(|| String::new().into()))
+            .map(zvariant::Value::new)
+            .collect::<Vec<_>>()
+            .into(),
+        Value::Object(map) => to_device_state(map).into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive, reply};
+    use lifx_core::Message;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use zbus::zvariant::OwnedValue;
+
+    /// A `dbus-daemon` of our own, so tests neither need nor disturb a session bus.
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// `None` if `dbus-daemon` is not installed.
+        fn start() -> Option<PrivateBus> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address=1"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(PrivateBus {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Connection {
+            builder(Some(&self.address)).unwrap().build().unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn call<B, R>(client: &Connection, method: &str, body: &B) -> zbus::Result<R>
+    where
+        B: serde::Serialize + zvariant::DynamicType,
+        R: for<'d> zvariant::DynamicDeserialize<'d>,
+    {
+        client
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE), method, body)?
+            .body()
+            .deserialize()
+    }
+
+    fn string(value: &OwnedValue) -> String {
+        <&str>::try_from(value).unwrap().to_string()
+    }
+
+    #[test]
+    fn lists_and_controls_devices() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let _service = DbusService::start_at(
+            Some(&bus.address),
+            &DbusSettings::default(),
+            manager,
+            vec![],
+        )
+        .unwrap();
+        let client = bus.connect();
+
+        let devices: Vec<HashMap<String, OwnedValue>> = call(&client, "ListDevices", &()).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(string(&devices[0]["label"]), "Desk");
+        assert_eq!(string(&devices[0]["id"]), "2a0000000000");
+
+        let reached: u32 = call(&client, "SetPower", &("label:Desk", true, 0u32)).unwrap();
+        assert_eq!(reached, 1);
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                ..
+            }
+        ));
+
+        let reached: u32 = call(&client, "SetColor", &("all", "red", 250u32)).unwrap();
+        assert_eq!(reached, 1);
+        match receive(&bulb) {
+            Message::LightSetColor {
+                color, duration, ..
+            } => {
+                assert_eq!(color.saturation, u16::MAX);
+                assert_eq!(duration, 250);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let missing = call::<_, u32>(&client, "SetPower", &("label:Porch", true, 0u32));
+        assert!(missing.is_err());
+        let invalid = call::<_, u32>(&client, "SetColor", &("all", "not-a-color", 0u32));
+        assert!(invalid.is_err());
+        let scene = call::<_, ()>(&client, "ApplyScene", &("Evening",));
+        assert!(scene.is_err());
+    }
+
+    #[test]
+    fn announces_device_changes() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let _service = DbusService::start_at(
+            Some(&bus.address),
+            &DbusSettings::default(),
+            manager.clone(),
+            vec![],
+        )
+        .unwrap();
+        let client = bus.connect();
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface(INTERFACE)
+            .unwrap()
+            .member("DeviceChanged")
+            .unwrap()
+            .build();
+        let mut signals = MessageIterator::for_match_rule(rule, &client, Some(16)).unwrap();
+
+        reply(
+            &bulb,
+            &manager,
+            Message::LightStatePower { level: u16::MAX },
+        );
+        let signal = signals.next().unwrap().unwrap();
+        let (id, state): (String, HashMap<String, OwnedValue>) =
+            signal.body().deserialize().unwrap();
+        assert_eq!(id, "2a0000000000");
+        assert_eq!(string(&state["power"]), "on");
+    }
+
+    #[test]
+    fn flashes_on_notifications() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not found, skipping");
+            return;
+        };
+        let (manager, bulb) = manager_with_fake_bulb(u16::MAX);
+        let settings = DbusSettings {
+            enabled: true,
+            flash_on_notifications: true,
+            notification_light: "label:Desk".to_string(),
+        };
+        let _service =
+            DbusService::start_at(Some(&bus.address), &settings, manager, vec![]).unwrap();
+
+        // Any Notify call counts, so a stand-in notification server is enough.
+        struct Notifications;
+        #[interface(name = "org.freedesktop.Notifications")]
+        impl Notifications {
+            fn notify(&self, summary: &str) -> u32 {
+                summary.len() as u32
+            }
+        }
+        let _server = builder(Some(&bus.address))
+            .unwrap()
+            .name(NOTIFICATIONS_INTERFACE)
+            .unwrap()
+            .serve_at("/org/freedesktop/Notifications", Notifications)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = bus.connect();
+        let _: u32 = client
+            .call_method(
+                Some(NOTIFICATIONS_INTERFACE),
+                "/org/freedesktop/Notifications",
+                Some(NOTIFICATIONS_INTERFACE),
+                "Notify",
+                &("Build finished",),
+            )
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+
+        match receive(&bulb) {
+            Message::SetWaveform {
+                transient,
+                waveform,
+                color,
+                ..
+            } => {
+                assert!(transient);
+                assert_eq!(waveform, Waveform::Pulse);
+                assert_eq!(color.brightness, u16::MAX);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flashing_requires_a_light() {
+        let (manager, _bulb) = manager_with_fake_bulb(0);
+        let settings = DbusSettings {
+            enabled: true,
+            flash_on_notifications: true,
+            notification_light: String::new(),
+        };
+        assert!(
+            DbusService::start_at(Some("unix:path=/nonexistent"), &settings, manager, vec![])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn json_state_becomes_variants() {
+        let state: Map<String, Value> = serde_json::from_value(serde_json::json!({
+            "label": "Desk",
+            "connected": true,
+            "zones": 8,
+            "brightness": 0.5,
+            "color": { "hue": 120.0 },
+            "power": null,
+        }))
+        .unwrap();
+        let state = to_device_state(state);
+        assert_eq!(state.len(), 5);
+        assert_eq!(state["zones"], zvariant::Value::I64(8));
+        assert_eq!(state["brightness"], zvariant::Value::F64(0.5));
+        assert!(!state.contains_key("power"));
+    }
+}
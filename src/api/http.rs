@@ -0,0 +1,811 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread::{spawn, JoinHandle};
+
+use lifx_core::{Waveform, HSBK};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, Server};
+
+use super::{generate_token, scene_uuid, token_matches, ColorJson, LightJson};
+use crate::{
+    color::{ColorSpec, BASE_COLOR},
+    device_info::DeviceInfo,
+    scenes::Scene,
+    selector::Selector,
+    BulbInfo, LifxManager,
+};
+use rust_i18n::t;
+
+pub const DEFAULT_HTTP_API_ADDR: &str = "127.0.0.1:56780";
+
+/// User-facing configuration of the local HTTP API, persisted with the app settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpApiSettings {
+    pub enabled: bool,
+    pub bind_addr: String,
+    /// Clients must send `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+impl Default for HttpApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_addr: DEFAULT_HTTP_API_ADDR.to_string(),
+            token: generate_token(),
+        }
+    }
+}
+
+/// Embedded HTTP server implementing a subset of the LIFX HTTP API against the LAN:
+/// `GET /v1/lights/:selector`, `PUT /v1/lights/:selector/state`,
+/// `POST /v1/lights/:selector/toggle`, `POST /v1/lights/:selector/effects/{pulse,breathe}`,
+/// `GET /v1/scenes` and `PUT /v1/scenes/scene_id::uuid/activate`.
+///
+/// The server stops when dropped.
+pub struct HttpApiServer {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+    settings: HttpApiSettings,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl HttpApiServer {
+    pub fn start(
+        settings: &HttpApiSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<HttpApiServer, anyhow::Error> {
+        if settings.token.is_empty() {
+            return Err(anyhow::anyhow!(t!("error.api_token_required").to_string()));
+        }
+        let server = Arc::new(
+            Server::http(settings.bind_addr.as_str()).map_err(|e| anyhow::anyhow!("{}", e))?,
+        );
+        let local_addr = server.server_addr().to_ip();
+        let scenes = Arc::new(RwLock::new(scenes));
+        let mut api = Api {
+            manager,
+            token: settings.token.clone(),
+            scenes: scenes.clone(),
+        };
+        let worker = server.clone();
+        let handle = spawn(move || {
+            for mut request in worker.incoming_requests() {
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("Authorization"))
+                    .map(|h| h.value.as_str().to_string());
+                // Clients without the token get their 401 before the body is read.
+                let mut body = String::new();
+                if api.is_authorized(authorization.as_deref()) {
+                    let mut reader = request.as_reader().take(MAX_BODY_BYTES + 1);
+                    if let Err(e) = reader.read_to_string(&mut body) {
+                        log::warn!("Failed to read HTTP API request body: {}", e);
+                    }
+                }
+                let method = request.method().as_str().to_string();
+                let response = if body.len() as u64 > MAX_BODY_BYTES {
+                    ApiResponse::error(413, "Request body is too large")
+                } else {
+                    api.handle(&method, request.url(), authorization.as_deref(), &body)
+                };
+                let content_type =
+                    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                        .expect("Invalid content type header");
+                let reply = Response::from_string(response.body.to_string())
+                    .with_status_code(response.status)
+                    .with_header(content_type);
+                if let Err(e) = request.respond(reply) {
+                    log::warn!("Failed to send HTTP API response: {}", e);
+                }
+            }
+        });
+        log::info!("HTTP API listening on {}", settings.bind_addr);
+        Ok(HttpApiServer {
+            server,
+            handle: Some(handle),
+            settings: settings.clone(),
+            scenes,
+            local_addr,
+        })
+    }
+
+    /// Settings the server was started with.
+    pub fn settings(&self) -> &HttpApiSettings {
+        &self.settings
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Replace the scenes served by `/v1/scenes` if they changed.
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for HttpApiServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("HTTP API thread panicked");
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ApiResponse {
+    status: u16,
+    body: Value,
+}
+
+impl ApiResponse {
+    fn ok(body: Value) -> Self {
+        ApiResponse { status: 200, body }
+    }
+
+    fn multi_status(results: Vec<Value>) -> Self {
+        ApiResponse {
+            status: 207,
+            body: json!({ "results": results }),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        ApiResponse {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StateRequest {
+    power: Option<String>,
+    color: Option<String>,
+    brightness: Option<f32>,
+    /// Seconds
+    duration: Option<f32>,
+    infrared: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ToggleRequest {
+    duration: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WaveformRequest {
+    color: Option<String>,
+    from_color: Option<String>,
+    /// Seconds per cycle
+    period: Option<f32>,
+    cycles: Option<f32>,
+    persist: bool,
+    power_on: Option<bool>,
+    /// Breathe only: where in the cycle the color peaks, `[0, 1]`
+    peak: Option<f32>,
+}
+
+/// LIFX API default transition time, in seconds.
+const DEFAULT_DURATION: f32 = 1.0;
+/// Largest request body read, far above any valid request.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+struct Api {
+    manager: LifxManager,
+    token: String,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+}
+
+impl Api {
+    /// Whether the `Authorization` header carries the bearer token.
+    fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        let presented = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .unwrap_or_default();
+        token_matches(&self.token, presented)
+    }
+
+    fn handle(
+        &mut self,
+        method: &str,
+        url: &str,
+        authorization: Option<&str>,
+        body: &str,
+    ) -> ApiResponse {
+        if !self.is_authorized(authorization) {
+            return ApiResponse::error(401, "Invalid or missing bearer token");
+        }
+
+        let path = url.split('?').next().unwrap_or_default();
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", ["v1", "lights", selector]) => self.list_lights(selector),
+            ("PUT", ["v1", "lights", selector, "state"]) => self.set_state(selector, body),
+            ("POST", ["v1", "lights", selector, "toggle"]) => self.toggle(selector, body),
+            ("POST", ["v1", "lights", selector, "effects", "pulse"]) => {
+                self.waveform(selector, body, Waveform::Pulse)
+            }
+            ("POST", ["v1", "lights", selector, "effects", "breathe"]) => {
+                self.waveform(selector, body, Waveform::Sine)
+            }
+            ("GET", ["v1", "scenes"]) => self.list_scenes(),
+            ("PUT", ["v1", "scenes", scene, "activate"]) => self.activate_scene(scene),
+            (_, ["v1", ..]) => ApiResponse::error(404, format!("No route for {} {}", method, path)),
+            _ => ApiResponse::error(404, "Not found"),
+        }
+    }
+
+    /// Resolve a selector to the bulbs it matches, or an error response.
+    fn select(&self, selector: &str) -> Result<Vec<BulbInfo>, ApiResponse> {
+        let parsed: Selector = selector
+            .parse()
+            .map_err(|e: anyhow::Error| ApiResponse::error(422, e.to_string()))?;
+        let bulbs = self
+            .manager
+            .bulbs
+            .lock()
+            .map_err(|_| ApiResponse::error(500, "Device registry unavailable"))?;
+        let selected: Vec<BulbInfo> = parsed.select(&bulbs).into_iter().cloned().collect();
+        if selected.is_empty() {
+            return Err(ApiResponse::error(
+                404,
+                format!("Could not find {}.", selector),
+            ));
+        }
+        Ok(selected)
+    }
+
+    /// Run `action` on every online bulb and report a LIFX-style result per bulb.
+    fn for_each_bulb(
+        &self,
+        bulbs: &[BulbInfo],
+        mut action: impl FnMut(&LifxManager, &BulbInfo) -> Result<usize, std::io::Error>,
+    ) -> ApiResponse {
+        let results = bulbs
+            .iter()
+            .map(|bulb| {
+                let status = if !bulb.is_online() {
+                    "offline"
+                } else if let Err(e) = action(&self.manager, bulb) {
+                    log::error!("HTTP API failed to update {}: {}", bulb.serial(), e);
+                    "error"
+                } else {
+                    "ok"
+                };
+                json!({ "id": bulb.serial(), "label": bulb.name_label(), "status": status })
+            })
+            .collect();
+        ApiResponse::multi_status(results)
+    }
+
+    fn list_lights(&self, selector: &str) -> ApiResponse {
+        match self.select(selector) {
+            Ok(bulbs) => {
+                let lights: Vec<LightJson> = bulbs.iter().map(LightJson::from).collect();
+                ApiResponse::ok(json!(lights))
+            }
+            Err(response) => response,
+        }
+    }
+
+    fn set_state(&self, selector: &str, body: &str) -> ApiResponse {
+        let request: StateRequest = match parse_body(body) {
+            Ok(request) => request,
+            Err(response) => return response,
+        };
+        let power = match request.power.as_deref() {
+            None => None,
+            Some("on") => Some(u16::MAX),
+            Some("off") => Some(0),
+            Some(other) => {
+                return ApiResponse::error(422, format!("power must be on or off, not {}", other))
+            }
+        };
+        let mut spec = match request.color.as_deref().map(str::parse::<ColorSpec>) {
+            None => ColorSpec::default(),
+            Some(Ok(spec)) => spec,
+            Some(Err(e)) => return ApiResponse::error(422, e.to_string()),
+        };
+        if let Some(brightness) = request.brightness {
+            match unit_to_u16(brightness) {
+                Some(value) => spec.brightness = Some(value),
+                None => return ApiResponse::error(422, "brightness must be between 0 and 1"),
+            }
+        }
+        let infrared = match request.infrared.map(unit_to_u16) {
+            None => None,
+            Some(Some(value)) => Some(value),
+            Some(None) => return ApiResponse::error(422, "infrared must be between 0 and 1"),
+        };
+        let duration = match seconds_to_ms(request.duration.unwrap_or(DEFAULT_DURATION)) {
+            Some(duration) => duration,
+            None => return ApiResponse::error(422, "duration must not be negative"),
+        };
+
+        let bulbs = match self.select(selector) {
+            Ok(bulbs) => bulbs,
+            Err(response) => return response,
+        };
+        let change_color = spec != ColorSpec::default();
+        self.for_each_bulb(&bulbs, |manager, bulb| {
+            let mut sent = 0;
+            if change_color {
+                // Keep each zone's own color where the change leaves it alone.
+                sent += match bulb.get_zone_count() {
+                    0 => {
+                        manager.set_color(&bulb, spec.apply(current_color(bulb)), Some(duration))?
+                    }
+                    zones => manager.set_zones_color_spec(&bulb, 0..=zones - 1, &spec, duration)?,
+                };
+            }
+            if let Some(level) = power {
+                sent += manager.set_power_with_duration(&bulb, level, duration)?;
+            }
+            if let Some(brightness) = infrared {
+                if bulb.features.infrared == Some(true) {
+                    sent += manager.set_infrared(&bulb, brightness)?;
+                }
+            }
+            Ok(sent)
+        })
+    }
+
+    fn toggle(&self, selector: &str, body: &str) -> ApiResponse {
+        let request: ToggleRequest = match parse_body(body) {
+            Ok(request) => request,
+            Err(response) => return response,
+        };
+        let duration = match seconds_to_ms(request.duration.unwrap_or(DEFAULT_DURATION)) {
+            Some(duration) => duration,
+            None => return ApiResponse::error(422, "duration must not be negative"),
+        };
+        let bulbs = match self.select(selector) {
+            Ok(bulbs) => bulbs,
+            Err(response) => return response,
+        };
+        // As in the LIFX API: if any selected light is on, turn them all off.
+        let level = if bulbs.iter().any(|b| b.power_level.data.unwrap_or(0) > 0) {
+            0
+        } else {
+            u16::MAX
+        };
+        self.for_each_bulb(&bulbs, |manager, bulb| {
+            manager.set_power_with_duration(&bulb, level, duration)
+        })
+    }
+
+    fn waveform(&self, selector: &str, body: &str, waveform: Waveform) -> ApiResponse {
+        let request: WaveformRequest = match parse_body(body) {
+            Ok(request) => request,
+            Err(response) => return response,
+        };
+        let parse = |color: Option<&str>| color.map(str::parse::<ColorSpec>).transpose();
+        let (color, from_color) = match (
+            parse(request.color.as_deref()),
+            parse(request.from_color.as_deref()),
+        ) {
+            (Ok(Some(color)), Ok(from_color)) => (color, from_color),
+            (Ok(None), _) => return ApiResponse::error(422, "color is required"),
+            (Err(e), _) | (_, Err(e)) => return ApiResponse::error(422, e.to_string()),
+        };
+        let period = match seconds_to_ms(request.period.unwrap_or(1.0)) {
+            Some(period) if period > 0 => period,
+            _ => return ApiResponse::error(422, "period must be positive"),
+        };
+        let cycles = request.cycles.unwrap_or(1.0);
+        if cycles <= 0.0 {
+            return ApiResponse::error(422, "cycles must be positive");
+        }
+        let peak = request.peak.unwrap_or(0.5);
+        if !(0.0..=1.0).contains(&peak) {
+            return ApiResponse::error(422, "peak must be between 0 and 1");
+        }
+        let power_on = request.power_on.unwrap_or(true);
+
+        let bulbs = match self.select(selector) {
+            Ok(bulbs) => bulbs,
+            Err(response) => return response,
+        };
+        self.for_each_bulb(&bulbs, |manager, bulb| {
+            let mut sent = 0;
+            let base = current_color(bulb);
+            if let Some(from_color) = from_color {
+                sent += manager.set_color(&bulb, from_color.apply(base), Some(0))?;
+            }
+            if power_on && bulb.power_level.data.unwrap_or(0) == 0 {
+                sent += manager.set_power(&bulb, u16::MAX)?;
+            }
+            sent += manager.set_waveform(
+                &bulb,
+                waveform,
+                color.apply(base),
+                period,
+                cycles,
+                peak,
+                !request.persist,
+            )?;
+            Ok(sent)
+        })
+    }
+
+    fn list_scenes(&self) -> ApiResponse {
+        let Ok(scenes) = self.scenes.read() else {
+            return ApiResponse::error(500, "Scenes unavailable");
+        };
+        let scenes: Vec<Value> = scenes
+            .iter()
+            .map(|scene| {
+                let states: Vec<Value> = scene
+                    .device_color_pairs
+                    .iter()
+                    .map(|(device, color)| {
+                        let color = HSBK::from(*color);
+                        let selector = match device {
+                            DeviceInfo::Bulb(bulb) => format!("id:{}", bulb.serial()),
+                            DeviceInfo::Group(group) => format!("group:{}", group.label),
+                        };
+                        json!({
+                            "selector": selector,
+                            "color": ColorJson::from(color),
+                            "brightness": color.brightness as f32 / u16::MAX as f32,
+                        })
+                    })
+                    .collect();
+                json!({ "uuid": scene_uuid(scene), "name": scene.name, "states": states })
+            })
+            .collect();
+        ApiResponse::ok(json!(scenes))
+    }
+
+    fn activate_scene(&mut self, scene_selector: &str) -> ApiResponse {
+        let Some(id) = scene_selector.strip_prefix("scene_id:") else {
+            return ApiResponse::error(422, "Scenes are selected with scene_id:<uuid>");
+        };
+        let scene = match self.scenes.read() {
+            Ok(scenes) => scenes
+                .iter()
+                .find(|s| scene_uuid(s) == id || s.name == id)
+                .cloned(),
+            Err(_) => return ApiResponse::error(500, "Scenes unavailable"),
+        };
+        let Some(scene) = scene else {
+            return ApiResponse::error(404, format!("Could not find {}.", scene_selector));
+        };
+        match scene.apply(&mut self.manager) {
+            Ok(()) => ApiResponse::multi_status(vec![json!({
+                "id": scene_uuid(&scene),
+                "label": scene.name,
+                "status": "ok",
+            })]),
+            Err(errors) => ApiResponse::error(500, errors.join("; ")),
+        }
+    }
+}
+
+fn parse_body<T: Default + for<'de> Deserialize<'de>>(body: &str) -> Result<T, ApiResponse> {
+    if body.trim().is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(body).map_err(|e| ApiResponse::error(400, format!("Invalid JSON: {}", e)))
+}
+
+fn current_color(bulb: &BulbInfo) -> HSBK {
+    bulb.get_color().copied().unwrap_or(BASE_COLOR)
+}
+
+fn unit_to_u16(value: f32) -> Option<u16> {
+    (0.0..=1.0)
+        .contains(&value)
+        .then(|| (value * u16::MAX as f32).round() as u16)
+}
+
+fn seconds_to_ms(seconds: f32) -> Option<u32> {
+    (seconds >= 0.0).then(|| (seconds * 1000.0).round().min(u32::MAX as f32) as u32)
+}
+
+/// Decode `%XX` escapes in a URL path segment (`+` is left alone, as in paths).
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let escape = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(escape, 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive};
+    use crate::{DeviceColor, RefreshableData};
+    use lifx_core::Message;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    const TOKEN: &str = "test-token";
+
+    /// An API backed by a manager that knows one fake bulb, plus the socket the bulb listens on.
+    fn api_with_bulb(power: u16) -> (Api, UdpSocket) {
+        let (manager, bulb_socket) = manager_with_fake_bulb(power);
+        let api = Api {
+            manager,
+            token: TOKEN.to_string(),
+            scenes: Arc::new(RwLock::new(vec![Scene::new(vec![], "Evening".to_string())])),
+        };
+        (api, bulb_socket)
+    }
+
+    fn call(api: &mut Api, method: &str, url: &str, body: &str) -> ApiResponse {
+        let auth = format!("Bearer {}", TOKEN);
+        api.handle(method, url, Some(&auth), body)
+    }
+
+    #[test]
+    fn rejects_missing_or_wrong_token() {
+        let (mut api, _bulb) = api_with_bulb(0);
+        assert_eq!(api.handle("GET", "/v1/lights/all", None, "").status, 401);
+        assert_eq!(
+            api.handle("GET", "/v1/lights/all", Some("Bearer nope"), "")
+                .status,
+            401
+        );
+    }
+
+    #[test]
+    fn lists_lights_in_lifx_shape() {
+        let (mut api, _bulb) = api_with_bulb(u16::MAX);
+        let response = call(&mut api, "GET", "/v1/lights/group:Office", "");
+        assert_eq!(response.status, 200);
+        let light = &response.body[0];
+        assert_eq!(light["label"], "Desk");
+        assert_eq!(light["power"], "on");
+        assert_eq!(light["group"]["name"], "Office");
+        assert_eq!(light["connected"], true);
+    }
+
+    #[test]
+    fn unknown_selector_is_404_and_bad_selector_is_422() {
+        let (mut api, _bulb) = api_with_bulb(0);
+        assert_eq!(
+            call(&mut api, "GET", "/v1/lights/label:Porch", "").status,
+            404
+        );
+        assert_eq!(
+            call(&mut api, "GET", "/v1/lights/name:Desk", "").status,
+            422
+        );
+        assert_eq!(call(&mut api, "GET", "/v2/nothing", "").status, 404);
+    }
+
+    #[test]
+    fn set_state_sends_power_and_color() {
+        let (mut api, bulb) = api_with_bulb(0);
+        let response = call(
+            &mut api,
+            "PUT",
+            "/v1/lights/label:Desk/state",
+            r#"{"power": "on", "color": "kelvin:2700", "brightness": 0.5, "duration": 0.25}"#,
+        );
+        assert_eq!(response.status, 207);
+        assert_eq!(response.body["results"][0]["status"], "ok");
+
+        match receive(&bulb) {
+            Message::LightSetColor {
+                color, duration, ..
+            } => {
+                assert_eq!(color.kelvin, 2700);
+                assert_eq!(color.saturation, 0);
+                assert_eq!(color.brightness, 32768);
+                assert_eq!(duration, 250);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                duration: 250
+            }
+        ));
+    }
+
+    #[test]
+    fn set_state_keeps_each_zone_color_on_strips() {
+        let (mut api, bulb) = api_with_bulb(u16::MAX);
+        let red = HSBK {
+            hue: 0,
+            saturation: u16::MAX,
+            brightness: u16::MAX,
+            kelvin: 3500,
+        };
+        let blue = HSBK { hue: 43690, ..red };
+        api.manager
+            .bulbs
+            .lock()
+            .unwrap()
+            .get_mut(&42)
+            .unwrap()
+            .color = DeviceColor::Multi(RefreshableData::new(
+            vec![Some(red), Some(red), Some(blue)],
+            Duration::from_secs(15),
+            Message::GetColorZones {
+                start_index: 0,
+                end_index: 255,
+            },
+        ));
+        let response = call(
+            &mut api,
+            "PUT",
+            "/v1/lights/all/state",
+            r#"{"brightness": 0.5, "duration": 0}"#,
+        );
+        assert_eq!(response.status, 207);
+
+        let mut zones = Vec::new();
+        for _ in 0..2 {
+            match receive(&bulb) {
+                Message::SetColorZones {
+                    start_index,
+                    end_index,
+                    color,
+                    ..
+                } => zones.push((start_index, end_index, color.hue, color.brightness)),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert_eq!(zones, vec![(0, 1, 0, 32768), (2, 2, 43690, 32768)]);
+    }
+
+    #[test]
+    fn set_state_validates_input() {
+        let (mut api, _bulb) = api_with_bulb(0);
+        let put = |api: &mut Api, body: &str| call(api, "PUT", "/v1/lights/all/state", body).status;
+        assert_eq!(put(&mut api, r#"{"power": "dim"}"#), 422);
+        assert_eq!(put(&mut api, r#"{"color": "mauve"}"#), 422);
+        assert_eq!(put(&mut api, r#"{"brightness": 2}"#), 422);
+        assert_eq!(put(&mut api, "{not json"), 400);
+    }
+
+    #[test]
+    fn toggle_turns_on_when_everything_is_off() {
+        let (mut api, bulb) = api_with_bulb(0);
+        let response = call(&mut api, "POST", "/v1/lights/all/toggle", "");
+        assert_eq!(response.status, 207);
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                duration: 1000
+            }
+        ));
+    }
+
+    #[test]
+    fn pulse_sends_a_transient_waveform() {
+        let (mut api, bulb) = api_with_bulb(u16::MAX);
+        let response = call(
+            &mut api,
+            "POST",
+            "/v1/lights/id:2a0000000000/effects/pulse",
+            r##"{"color": "#ff0000", "period": 0.5, "cycles": 3}"##,
+        );
+        assert_eq!(response.status, 207);
+        match receive(&bulb) {
+            Message::SetWaveform {
+                transient,
+                period,
+                cycles,
+                waveform,
+                color,
+                ..
+            } => {
+                assert!(transient);
+                assert_eq!(period, 500);
+                assert_eq!(cycles, 3.0);
+                assert_eq!(waveform, Waveform::Pulse);
+                assert_eq!(color.saturation, u16::MAX);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert_eq!(
+            call(&mut api, "POST", "/v1/lights/all/effects/pulse", "{}").status,
+            422
+        );
+    }
+
+    #[test]
+    fn lists_and_activates_scenes() {
+        let (mut api, _bulb) = api_with_bulb(0);
+        let response = call(&mut api, "GET", "/v1/scenes", "");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body[0]["name"], "Evening");
+        let uuid = response.body[0]["uuid"].as_str().unwrap().to_string();
+
+        let url = format!("/v1/scenes/scene_id:{}/activate", uuid);
+        assert_eq!(call(&mut api, "PUT", &url, "").status, 207);
+        assert_eq!(
+            call(&mut api, "PUT", "/v1/scenes/scene_id:missing/activate", "").status,
+            404
+        );
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("label:Desk%20Lamp"), "label:Desk Lamp");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn server_answers_over_http() {
+        let (api, _bulb) = api_with_bulb(0);
+        let settings = HttpApiSettings {
+            enabled: true,
+            bind_addr: "127.0.0.1:0".to_string(),
+            token: TOKEN.to_string(),
+        };
+        let server = HttpApiServer::start(&settings, api.manager.clone(), vec![]).unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        std::io::Write::write_all(
+            &mut stream,
+            format!(
+                "GET /v1/lights/all HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nConnection: close\r\n\r\n",
+                TOKEN
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"label\":\"Desk\""));
+        drop(server);
+    }
+
+    #[test]
+    fn start_requires_a_token() {
+        let manager = LifxManager::with_bind_addr("0.0.0.0:0").unwrap();
+        let settings = HttpApiSettings {
+            token: String::new(),
+            ..Default::default()
+        };
+        assert!(HttpApiServer::start(&settings, manager, vec![]).is_err());
+    }
+}
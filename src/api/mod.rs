@@ -0,0 +1,334 @@
+//! Remote-control APIs that let other programs drive `LifxManager`.
+
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+pub mod dbus;
+#[cfg(feature = "http-api")]
+pub mod http;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "websocket-api")]
+pub mod ws;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lifx_core::HSBK;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{products::get_product_name, scenes::Scene, BulbInfo, DeviceColor};
+
+/// Color in the units used by the LIFX HTTP API: hue in degrees, saturation in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ColorJson {
+    pub hue: f32,
+    pub saturation: f32,
+    pub kelvin: u16,
+}
+
+impl From<HSBK> for ColorJson {
+    fn from(color: HSBK) -> Self {
+        ColorJson {
+            hue: round3(color.hue as f32 / u16::MAX as f32 * 360.0),
+            saturation: round3(color.saturation as f32 / u16::MAX as f32),
+            kelvin: color.kelvin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NamedRef {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapabilitiesJson {
+    pub has_color: bool,
+    pub has_variable_color_temp: bool,
+    pub has_ir: bool,
+    pub has_chain: bool,
+    pub has_matrix: bool,
+    pub has_multizone: bool,
+    pub min_kelvin: Option<u32>,
+    pub max_kelvin: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProductJson {
+    pub name: Option<String>,
+    pub company: &'static str,
+    pub capabilities: CapabilitiesJson,
+}
+
+/// A light in the JSON shape returned by `GET /v1/lights/:selector` of the LIFX HTTP API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LightJson {
+    pub id: String,
+    pub uuid: String,
+    pub label: Option<String>,
+    pub connected: bool,
+    pub power: Option<&'static str>,
+    pub color: Option<ColorJson>,
+    pub brightness: Option<f32>,
+    pub zones: usize,
+    pub group: Option<NamedRef>,
+    pub location: Option<NamedRef>,
+    pub product: ProductJson,
+    pub seconds_since_seen: f32,
+}
+
+impl From<&BulbInfo> for LightJson {
+    fn from(bulb: &BulbInfo) -> Self {
+        let color = bulb.get_color().copied();
+        let features = &bulb.features;
+        LightJson {
+            id: bulb.serial(),
+            uuid: bulb.serial(),
+            label: bulb.name_label(),
+            connected: bulb.is_online(),
+            power: bulb
+                .power_level
+                .data
+                .map(|level| if level > 0 { "on" } else { "off" }),
+            color: color.map(ColorJson::from),
+            brightness: color.map(|c| round3(c.brightness as f32 / u16::MAX as f32)),
+            zones: bulb.get_zone_count(),
+            group: bulb.group.data.as_ref().map(|g| NamedRef {
+                id: hex(&g.group.0),
+                name: g.label.to_string(),
+            }),
+            location: bulb.location.data.as_ref().map(|l| {
+                let name = l.to_string_lossy().into_owned();
+                NamedRef {
+                    id: hex(&stable_hash(&name).to_be_bytes()),
+                    name,
+                }
+            }),
+            product: ProductJson {
+                name: get_product_name(bulb.model.as_ref()),
+                company: "LIFX",
+                capabilities: CapabilitiesJson {
+                    has_color: features.color.unwrap_or(false),
+                    has_variable_color_temp: features.temperature_range.is_some(),
+                    has_ir: features.infrared.unwrap_or(false),
+                    has_chain: features.chain.unwrap_or(false),
+                    has_matrix: features.matrix.unwrap_or(false)
+                        || matches!(bulb.color, DeviceColor::Matrix(_)),
+                    has_multizone: features.multizone.unwrap_or(false),
+                    min_kelvin: features.temperature_range.as_ref().map(|r| r.min),
+                    max_kelvin: features.temperature_range.as_ref().map(|r| r.max),
+                },
+            },
+            seconds_since_seen: round3(bulb.last_seen.elapsed().as_secs_f32()),
+        }
+    }
+}
+
+/// The state of a light as pushed to subscribers: the `LightJson` fields that change
+/// over time plus the per-zone colors of multizone and matrix devices.
+pub fn light_state(bulb: &BulbInfo) -> Map<String, Value> {
+    let mut state = match serde_json::to_value(LightJson::from(bulb)) {
+        Ok(Value::Object(state)) => state,
+        _ => Map::new(),
+    };
+    // Changes on every message, so it would turn every reply into a change.
+    state.remove("seconds_since_seen");
+    let zones = match &bulb.color {
+        DeviceColor::Multi(data) | DeviceColor::Matrix(data) => data.data.as_ref(),
+        DeviceColor::Single(_) | DeviceColor::Unknown => None,
+    };
+    if let Some(zones) = zones {
+        let zones: Vec<Value> = zones
+            .iter()
+            .map(|zone| match zone {
+                Some(color) => json!({
+                    "color": ColorJson::from(*color),
+                    "brightness": color.brightness as f32 / u16::MAX as f32,
+                }),
+                None => Value::Null,
+            })
+            .collect();
+        state.insert("zone_colors".to_string(), Value::Array(zones));
+    }
+    state
+}
+
+/// Stable identifier for a scene, since Mantle scenes are only identified by name.
+pub fn scene_uuid(scene: &Scene) -> String {
+    hex(&stable_hash(&scene.name).to_be_bytes())
+}
+
+/// A random token suitable for authenticating local API clients.
+pub fn generate_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    (0..4)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(nanos);
+            hasher.write_u8(i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// Compare a presented token against the expected one in constant time.
+pub fn token_matches(expected: &str, presented: &str) -> bool {
+    if expected.is_empty() || expected.len() != presented.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(presented.bytes())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// FNV-1a, used where an identifier has to stay the same between runs.
+fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn round3(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::device_info::GroupInfo;
+    use crate::LifxManager;
+    use lifx_core::{LifxIdent, LifxString, Message, RawMessage};
+    use std::ffi::CString;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    /// A manager that knows one fake bulb ("Desk" in group "Office", target 42),
+    /// plus the socket the bulb listens on.
+    pub(crate) fn manager_with_fake_bulb(power: u16) -> (LifxManager, UdpSocket) {
+        let bulb_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        bulb_socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let manager = LifxManager::with_bind_addr("0.0.0.0:0").unwrap();
+        let mut bulb = BulbInfo::new(manager.source, 42, bulb_socket.local_addr().unwrap());
+        bulb.name.update(CString::new("Desk").unwrap());
+        bulb.power_level.update(power);
+        bulb.group.update(GroupInfo::new(
+            LifxIdent([1; 16]),
+            LifxString::new(&CString::new("Office").unwrap()),
+        ));
+        manager.bulbs.lock().unwrap().insert(bulb.target, bulb);
+        (manager, bulb_socket)
+    }
+
+    /// The next message the fake bulb received.
+    pub(crate) fn receive(socket: &UdpSocket) -> Message {
+        let mut buf = [0; 1024];
+        let (n, _) = socket.recv_from(&mut buf).unwrap();
+        Message::from_raw(&RawMessage::unpack(&buf[..n]).unwrap()).unwrap()
+    }
+
+    /// Send a reply to the manager as if it came from the fake bulb.
+    pub(crate) fn reply(bulb: &UdpSocket, manager: &LifxManager, message: Message) {
+        let options = lifx_core::BuildOptions {
+            target: Some(42),
+            source: manager.source,
+            ..Default::default()
+        };
+        let raw = RawMessage::build(&options, message).unwrap();
+        let port = manager.socket.local_addr().unwrap().port();
+        bulb.send_to(&raw.pack().unwrap(), ("127.0.0.1", port))
+            .unwrap();
+    }
+
+    #[test]
+    fn light_json_from_bulb() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let bulbs = manager.bulbs.lock().unwrap();
+        let light = LightJson::from(&bulbs[&42]);
+        assert_eq!(light.id, "2a0000000000");
+        assert_eq!(light.label.as_deref(), Some("Desk"));
+        assert_eq!(light.power, Some("on"));
+        assert_eq!(light.group.unwrap().name, "Office");
+        assert!(light.connected);
+        assert!(light.color.is_none());
+    }
+
+    #[test]
+    fn subscribers_hear_about_replies() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let changes = manager.subscribe();
+        {
+            let bulbs = manager.bulbs.lock().unwrap();
+            manager.set_power(&&bulbs[&42], u16::MAX).unwrap();
+        }
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                ..
+            }
+        ));
+
+        reply(&bulb, &manager, Message::StatePower { level: u16::MAX });
+
+        assert_eq!(changes.recv_timeout(Duration::from_secs(2)), Ok(42));
+        assert_eq!(
+            manager.bulbs.lock().unwrap()[&42].power_level.data,
+            Some(u16::MAX)
+        );
+    }
+
+    #[test]
+    fn color_json_units() {
+        let color = ColorJson::from(HSBK {
+            hue: u16::MAX / 2,
+            saturation: u16::MAX,
+            brightness: 0,
+            kelvin: 2700,
+        });
+        assert_eq!(color.hue, 179.997);
+        assert_eq!(color.saturation, 1.0);
+        assert_eq!(color.kelvin, 2700);
+    }
+
+    #[test]
+    fn generated_tokens_are_unique_and_long() {
+        let a = generate_token();
+        let b = generate_token();
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret2"));
+        assert!(!token_matches("", ""));
+    }
+
+    #[test]
+    fn scene_uuid_is_stable() {
+        let scene = Scene::new(vec![], "Evening".to_string());
+        assert_eq!(scene_uuid(&scene), scene_uuid(&scene.clone()));
+        assert_ne!(
+            scene_uuid(&scene),
+            scene_uuid(&Scene::new(vec![], "Morning".to_string()))
+        );
+        assert_eq!(scene_uuid(&scene).len(), 16);
+    }
+}
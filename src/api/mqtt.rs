@@ -0,0 +1,841 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::Duration;
+
+use lifx_core::{LifxIdent, Waveform, HSBK};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{hex, scene_uuid};
+use crate::{color::BASE_COLOR, products::get_product_name, scenes::Scene, BulbInfo, LifxManager};
+use rust_i18n::t;
+
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Effects offered to Home Assistant, run as LIFX waveforms.
+const EFFECTS: [&str; 2] = ["pulse", "breathe"];
+/// How often state is re-published when no device reports a change,
+/// so availability follows `last_seen`.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// User-facing configuration of the MQTT bridge, persisted with the app settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    /// Prefix of Mantle's own state and command topics.
+    pub base_topic: String,
+    /// Home Assistant's discovery prefix.
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: DEFAULT_MQTT_PORT,
+            username: String::new(),
+            password: String::new(),
+            client_id: "mantle".to_string(),
+            base_topic: "mantle".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+/// Bridges the device registry to an MQTT broker for Home Assistant.
+///
+/// Each bulb and each group is published as a `light` entity using the JSON schema, with
+/// discovery config, retained state and per-light availability; saved scenes are published
+/// as `scene` entities. Commands received on the command topics go through `LifxManager`.
+///
+/// The bridge disconnects when dropped.
+pub struct MqttBridge {
+    running: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    client: Client,
+    publisher: Option<JoinHandle<()>>,
+    settings: MqttSettings,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    topics: Topics,
+}
+
+impl MqttBridge {
+    pub fn start(
+        settings: &MqttSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<MqttBridge, anyhow::Error> {
+        if settings.host.trim().is_empty()
+            || settings.client_id.trim().is_empty()
+            || settings.base_topic.trim().is_empty()
+            || settings.discovery_prefix.trim().is_empty()
+        {
+            return Err(anyhow::anyhow!(t!("error.mqtt_settings").to_string()));
+        }
+        let topics = Topics {
+            base: settings.base_topic.trim_end_matches('/').to_string(),
+            discovery: settings.discovery_prefix.trim_end_matches('/').to_string(),
+        };
+
+        let mut options = MqttOptions::new(
+            settings.client_id.trim(),
+            settings.host.trim(),
+            settings.port,
+        );
+        options.set_keep_alive(Duration::from_secs(30));
+        if !settings.username.is_empty() {
+            options.set_credentials(settings.username.clone(), settings.password.clone());
+        }
+        options.set_last_will(LastWill::new(
+            topics.bridge_status(),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, mut connection) = Client::new(options, 256);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let connected = Arc::new(AtomicBool::new(false));
+        let scenes = Arc::new(RwLock::new(scenes));
+        let (resync_tx, resync_rx) = mpsc::channel();
+
+        let commands = Commands {
+            manager: manager.clone(),
+            topics: topics.clone(),
+            scenes: scenes.clone(),
+        };
+        {
+            let running = running.clone();
+            let connected = connected.clone();
+            let client = client.clone();
+            let topics = topics.clone();
+            // Not joined on drop: a connection attempt to an unreachable broker can take a
+            // while to fail, and the thread exits on its next event anyway.
+            spawn(move || {
+                for notification in connection.iter() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match notification {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            log::info!("Connected to MQTT broker");
+                            connected.store(true, Ordering::SeqCst);
+                            for topic in topics.subscriptions() {
+                                if let Err(e) = client.try_subscribe(topic, QoS::AtLeastOnce) {
+                                    log::error!("Failed to subscribe to MQTT topic: {}", e);
+                                }
+                            }
+                            let _ = resync_tx.send(());
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            if publish.topic == topics.home_assistant_status() {
+                                // Home Assistant restarted and needs discovery again.
+                                if publish.payload.as_ref() == ONLINE.as_bytes() {
+                                    let _ = resync_tx.send(());
+                                }
+                            } else if let Err(e) = commands.handle(&publish.topic, &publish.payload)
+                            {
+                                log::warn!("MQTT command on {} failed: {}", publish.topic, e);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            connected.store(false, Ordering::SeqCst);
+                            log::warn!("MQTT connection error: {}", e);
+                            sleep(RECONNECT_DELAY);
+                        }
+                    }
+                }
+                connected.store(false, Ordering::SeqCst);
+            });
+        }
+
+        let publisher = {
+            let running = running.clone();
+            let connected = connected.clone();
+            let client = client.clone();
+            let topics = topics.clone();
+            let scenes = scenes.clone();
+            spawn(move || {
+                let changes = manager.subscribe();
+                // Payload last published on each retained topic.
+                let mut published: HashMap<String, String> = HashMap::new();
+                while running.load(Ordering::SeqCst) {
+                    let _ = changes.recv_timeout(PUBLISH_INTERVAL);
+                    changes.try_iter().for_each(drop);
+                    if resync_rx.try_iter().count() > 0 {
+                        published.clear();
+                    }
+                    if !connected.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let messages = match (manager.bulbs.lock(), scenes.read()) {
+                        (Ok(bulbs), Ok(scenes)) => topics.messages(&bulbs, &scenes),
+                        _ => continue,
+                    };
+                    publish_changes(&client, &mut published, messages);
+                }
+            })
+        };
+
+        log::info!(
+            "MQTT bridge connecting to {}:{}",
+            settings.host,
+            settings.port
+        );
+        Ok(MqttBridge {
+            running,
+            connected,
+            client,
+            publisher: Some(publisher),
+            settings: settings.clone(),
+            scenes,
+            topics,
+        })
+    }
+
+    /// Settings the bridge was started with.
+    pub fn settings(&self) -> &MqttSettings {
+        &self.settings
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Replace the scenes published to Home Assistant if they changed.
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for MqttBridge {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // A clean disconnect suppresses the last will, so report going offline first.
+        let _ =
+            self.client
+                .try_publish(self.topics.bridge_status(), QoS::AtLeastOnce, true, OFFLINE);
+        let _ = self.client.try_disconnect();
+        if let Some(handle) = self.publisher.take() {
+            if handle.join().is_err() {
+                log::error!("MQTT publisher thread panicked");
+            }
+        }
+    }
+}
+
+/// Publish the retained messages whose payload changed, and clear discovery config
+/// for entities that no longer exist so Home Assistant removes them.
+fn publish_changes(
+    client: &Client,
+    published: &mut HashMap<String, String>,
+    messages: HashMap<String, String>,
+) {
+    let removed: Vec<String> = published
+        .keys()
+        .filter(|topic| topic.ends_with("/config") && !messages.contains_key(*topic))
+        .cloned()
+        .collect();
+    for topic in removed {
+        if client
+            .try_publish(topic.as_str(), QoS::AtLeastOnce, true, "")
+            .is_ok()
+        {
+            published.remove(&topic);
+        }
+    }
+    for (topic, payload) in messages {
+        if published.get(&topic) == Some(&payload) {
+            continue;
+        }
+        // `try_publish` so a stalled connection cannot block this thread; anything that
+        // does not fit is retried on the next pass.
+        if client
+            .try_publish(topic.as_str(), QoS::AtLeastOnce, true, payload.as_bytes())
+            .is_ok()
+        {
+            published.insert(topic, payload);
+        }
+    }
+}
+
+/// A light entity: a single bulb, or every bulb in a group.
+struct LightEntity<'a> {
+    id: String,
+    name: String,
+    bulbs: Vec<&'a BulbInfo>,
+    is_group: bool,
+}
+
+impl LightEntity<'_> {
+    fn is_online(&self) -> bool {
+        self.bulbs.iter().any(|b| b.is_online())
+    }
+
+    fn is_on(&self) -> bool {
+        self.bulbs
+            .iter()
+            .any(|b| b.power_level.data.unwrap_or(0) > 0)
+    }
+
+    /// The color shown for the entity; for groups, that of the first lit member.
+    fn color(&self) -> Option<HSBK> {
+        self.bulbs
+            .iter()
+            .filter(|b| b.power_level.data.unwrap_or(0) > 0)
+            .chain(self.bulbs.iter())
+            .find_map(|b| b.get_color().copied())
+    }
+}
+
+fn group_entity_id(group: &LifxIdent) -> String {
+    format!("group_{}", hex(&group.0))
+}
+
+/// Every bulb and every group, ordered by id.
+fn light_entities(bulbs: &HashMap<u64, BulbInfo>) -> Vec<LightEntity<'_>> {
+    let mut entities: Vec<LightEntity> = bulbs
+        .values()
+        .map(|bulb| LightEntity {
+            id: bulb.serial(),
+            name: bulb.name_label().unwrap_or_else(|| bulb.serial()),
+            bulbs: vec![bulb],
+            is_group: false,
+        })
+        .collect();
+    let mut groups: HashMap<String, LightEntity> = HashMap::new();
+    for bulb in bulbs.values() {
+        if let Some(group) = &bulb.group.data {
+            groups
+                .entry(group_entity_id(&group.group))
+                .or_insert_with(|| LightEntity {
+                    id: group_entity_id(&group.group),
+                    name: group.label.to_string(),
+                    bulbs: Vec::new(),
+                    is_group: true,
+                })
+                .bulbs
+                .push(bulb);
+        }
+    }
+    entities.extend(groups.into_values());
+    entities.sort_by(|a, b| a.id.cmp(&b.id));
+    entities
+}
+
+fn kelvin_to_mireds(kelvin: u16) -> u32 {
+    1_000_000 / (kelvin.max(1) as u32)
+}
+
+fn mireds_to_kelvin(mireds: u32) -> u16 {
+    (1_000_000 / mireds.max(1)).min(u16::MAX as u32) as u16
+}
+
+/// The topic layout, rooted at the configured base topic and discovery prefix.
+#[derive(Debug, Clone)]
+struct Topics {
+    base: String,
+    discovery: String,
+}
+
+impl Topics {
+    fn bridge_status(&self) -> String {
+        format!("{}/status", self.base)
+    }
+
+    fn home_assistant_status(&self) -> String {
+        format!("{}/status", self.discovery)
+    }
+
+    fn light(&self, id: &str, leaf: &str) -> String {
+        format!("{}/light/{}/{}", self.base, id, leaf)
+    }
+
+    fn scene_command(&self, uuid: &str) -> String {
+        format!("{}/scene/{}/set", self.base, uuid)
+    }
+
+    fn discovery_config(&self, component: &str, id: &str) -> String {
+        format!("{}/{}/mantle/{}/config", self.discovery, component, id)
+    }
+
+    fn subscriptions(&self) -> [String; 3] {
+        [
+            self.light("+", "set"),
+            self.scene_command("+"),
+            self.home_assistant_status(),
+        ]
+    }
+
+    /// Every retained message describing the current registry and scenes.
+    fn messages(
+        &self,
+        bulbs: &HashMap<u64, BulbInfo>,
+        scenes: &[Scene],
+    ) -> HashMap<String, String> {
+        let mut messages = HashMap::new();
+        messages.insert(self.bridge_status(), ONLINE.to_string());
+        for entity in light_entities(bulbs) {
+            messages.insert(
+                self.discovery_config("light", &entity.id),
+                self.light_config(&entity).to_string(),
+            );
+            messages.insert(
+                self.light(&entity.id, "state"),
+                light_state(&entity).to_string(),
+            );
+            let availability = if entity.is_online() { ONLINE } else { OFFLINE };
+            messages.insert(
+                self.light(&entity.id, "availability"),
+                availability.to_string(),
+            );
+        }
+        for scene in scenes {
+            let uuid = scene_uuid(scene);
+            messages.insert(
+                self.discovery_config("scene", &uuid),
+                self.scene_config(scene, &uuid).to_string(),
+            );
+        }
+        messages
+    }
+
+    fn light_config(&self, entity: &LightEntity) -> Value {
+        let features = entity.bulbs.first().map(|b| &b.features);
+        let has_color = entity.is_group || features.is_some_and(|f| f.color == Some(true));
+        let kelvin_range = if entity.is_group {
+            None
+        } else {
+            features.and_then(|f| f.temperature_range.as_ref())
+        };
+        let has_temperature = entity.is_group || kelvin_range.is_some();
+        let mut modes = Vec::new();
+        if has_color {
+            modes.push("hs");
+        }
+        if has_temperature {
+            modes.push("color_temp");
+        }
+        if modes.is_empty() {
+            modes.push("brightness");
+        }
+
+        let model = if entity.is_group {
+            Some(t!("mqtt.group_model").to_string())
+        } else {
+            entity
+                .bulbs
+                .first()
+                .and_then(|b| get_product_name(b.model.as_ref()))
+        };
+        let mut config = json!({
+            "name": Value::Null,
+            "unique_id": format!("mantle_{}", entity.id),
+            "schema": "json",
+            "command_topic": self.light(&entity.id, "set"),
+            "state_topic": self.light(&entity.id, "state"),
+            "availability": [
+                { "topic": self.bridge_status() },
+                { "topic": self.light(&entity.id, "availability") },
+            ],
+            "availability_mode": "all",
+            "brightness": true,
+            "supported_color_modes": modes,
+            "effect": true,
+            "effect_list": EFFECTS,
+            "device": {
+                "identifiers": [format!("mantle_{}", entity.id)],
+                "name": entity.name,
+                "manufacturer": "LIFX",
+                "model": model,
+            },
+            "origin": origin(),
+        });
+        if has_temperature {
+            // Mireds are inverted: the coldest color has the fewest.
+            let (min_kelvin, max_kelvin) = kelvin_range
+                .map(|r| (r.min as u16, r.max as u16))
+                .unwrap_or((1500, 9000));
+            config["min_mireds"] = json!(kelvin_to_mireds(max_kelvin));
+            config["max_mireds"] = json!(kelvin_to_mireds(min_kelvin));
+        }
+        config
+    }
+
+    fn scene_config(&self, scene: &Scene, uuid: &str) -> Value {
+        json!({
+            "name": scene.name,
+            "unique_id": format!("mantle_scene_{}", uuid),
+            "command_topic": self.scene_command(uuid),
+            "payload_on": "ON",
+            "availability_topic": self.bridge_status(),
+            "device": {
+                "identifiers": ["mantle"],
+                "name": "Mantle",
+            },
+            "origin": origin(),
+        })
+    }
+}
+
+fn origin() -> Value {
+    json!({
+        "name": "Mantle",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+        "support_url": "https://github.com/samclane/mantle",
+    })
+}
+
+/// State in Home Assistant's JSON light schema.
+fn light_state(entity: &LightEntity) -> Value {
+    let mut state = json!({ "state": if entity.is_on() { "ON" } else { "OFF" } });
+    if let Some(color) = entity.color() {
+        state["brightness"] = json!((color.brightness as u32 * 255 / u16::MAX as u32) as u8);
+        if color.saturation == 0 {
+            state["color_mode"] = json!("color_temp");
+            state["color_temp"] = json!(kelvin_to_mireds(color.kelvin));
+        } else {
+            state["color_mode"] = json!("hs");
+            state["color"] = json!({
+                "h": (color.hue as f32 / u16::MAX as f32 * 360.0).round(),
+                "s": (color.saturation as f32 / u16::MAX as f32 * 100.0).round(),
+            });
+        }
+    }
+    state
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+struct HsColor {
+    h: f32,
+    s: f32,
+}
+
+/// A command in Home Assistant's JSON light schema.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct LightCommand {
+    state: Option<String>,
+    brightness: Option<u8>,
+    color: Option<HsColor>,
+    /// Mireds
+    color_temp: Option<u32>,
+    /// Seconds
+    transition: Option<f32>,
+    effect: Option<String>,
+}
+
+impl LightCommand {
+    /// The color the command asks for, on top of `base`, if it changes the color at all.
+    fn color(&self, base: HSBK) -> Option<HSBK> {
+        let mut color = base;
+        let mut changed = false;
+        if let Some(brightness) = self.brightness {
+            color.brightness = (brightness as u32 * u16::MAX as u32 / 255) as u16;
+            changed = true;
+        }
+        if let Some(hs) = &self.color {
+            color.hue = ((hs.h.rem_euclid(360.0) / 360.0) * u16::MAX as f32).round() as u16;
+            color.saturation = ((hs.s.clamp(0.0, 100.0) / 100.0) * u16::MAX as f32).round() as u16;
+            changed = true;
+        }
+        if let Some(mireds) = self.color_temp {
+            color.kelvin = mireds_to_kelvin(mireds);
+            color.saturation = 0;
+            changed = true;
+        }
+        changed.then_some(color)
+    }
+}
+
+/// Applies commands received from the broker.
+struct Commands {
+    manager: LifxManager,
+    topics: Topics,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+}
+
+impl Commands {
+    /// Handle a message on one of the command topics, returning how many devices it reached.
+    fn handle(&self, topic: &str, payload: &[u8]) -> Result<usize, anyhow::Error> {
+        let Some(rest) = topic.strip_prefix(&self.topics.base) else {
+            return Err(anyhow::anyhow!("Unexpected topic"));
+        };
+        let parts: Vec<&str> = rest.trim_start_matches('/').split('/').collect();
+        match parts.as_slice() {
+            ["light", id, "set"] => {
+                let command: LightCommand = serde_json::from_slice(payload)?;
+                self.light_command(id, &command)
+            }
+            ["scene", uuid, "set"] => self.activate_scene(uuid),
+            _ => Err(anyhow::anyhow!("Unexpected topic")),
+        }
+    }
+
+    fn light_command(&self, id: &str, command: &LightCommand) -> Result<usize, anyhow::Error> {
+        let bulbs: Vec<BulbInfo> = {
+            let bulbs = self
+                .manager
+                .bulbs
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Device registry unavailable"))?;
+            light_entities(&bulbs)
+                .into_iter()
+                .find(|entity| entity.id == id)
+                .map(|entity| entity.bulbs.into_iter().cloned().collect())
+                .ok_or_else(|| anyhow::anyhow!("Unknown light {}", id))?
+        };
+        let duration = command
+            .transition
+            .map(|s| (s.max(0.0) * 1000.0) as u32)
+            .unwrap_or(0);
+        let waveform = match command.effect.as_deref() {
+            None => None,
+            Some("pulse") => Some(Waveform::Pulse),
+            Some("breathe") => Some(Waveform::Sine),
+            Some(other) => return Err(anyhow::anyhow!("Unknown effect {}", other)),
+        };
+
+        let mut sent = 0;
+        for bulb in bulbs.iter().filter(|b| b.is_online()) {
+            if command.state.as_deref() == Some("OFF") {
+                sent += self.manager.set_power_with_duration(&bulb, 0, duration)?;
+                continue;
+            }
+            let base = bulb.get_color().copied().unwrap_or(BASE_COLOR);
+            let color = command.color(base);
+            if let Some(waveform) = waveform {
+                // Without a requested color, dip to darkness and back.
+                let target = color.unwrap_or(HSBK {
+                    brightness: 0,
+                    ..base
+                });
+                sent += self
+                    .manager
+                    .set_waveform(&bulb, waveform, target, 1000, 3.0, 0.5, true)?;
+            } else if let Some(color) = color {
+                sent += self.manager.set_color(&bulb, color, Some(duration))?;
+            }
+            if bulb.power_level.data.unwrap_or(0) == 0 {
+                sent += self
+                    .manager
+                    .set_power_with_duration(&bulb, u16::MAX, duration)?;
+            }
+        }
+        Ok(sent)
+    }
+
+    fn activate_scene(&self, uuid: &str) -> Result<usize, anyhow::Error> {
+        let scene = self
+            .scenes
+            .read()
+            .map_err(|_| anyhow::anyhow!("Scenes unavailable"))?
+            .iter()
+            .find(|s| scene_uuid(s) == uuid)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Unknown scene {}", uuid))?;
+        scene
+            .apply(&mut self.manager.clone())
+            .map_err(|errors| anyhow::anyhow!(errors.join("; ")))?;
+        Ok(scene.device_color_pairs.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive};
+    use lifx_core::Message;
+
+    fn topics() -> Topics {
+        Topics {
+            base: "mantle".to_string(),
+            discovery: "homeassistant".to_string(),
+        }
+    }
+
+    #[test]
+    fn publishes_bulbs_groups_and_scenes() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let scenes = vec![Scene::new(vec![], "Evening".to_string())];
+        let messages = topics().messages(&manager.bulbs.lock().unwrap(), &scenes);
+
+        assert_eq!(messages["mantle/status"], "online");
+        assert_eq!(messages["mantle/light/2a0000000000/availability"], "online");
+        let state: Value =
+            serde_json::from_str(&messages["mantle/light/2a0000000000/state"]).unwrap();
+        assert_eq!(state["state"], "ON");
+
+        let config: Value =
+            serde_json::from_str(&messages["homeassistant/light/mantle/2a0000000000/config"])
+                .unwrap();
+        assert_eq!(config["command_topic"], "mantle/light/2a0000000000/set");
+        assert_eq!(config["schema"], "json");
+        assert_eq!(config["device"]["name"], "Desk");
+        assert_eq!(config["effect_list"], json!(["pulse", "breathe"]));
+
+        let group = format!(
+            "homeassistant/light/mantle/group_{}/config",
+            "01".repeat(16)
+        );
+        let config: Value = serde_json::from_str(&messages[&group]).unwrap();
+        assert_eq!(config["device"]["name"], "Office");
+        assert_eq!(config["supported_color_modes"], json!(["hs", "color_temp"]));
+
+        let scene_topic = format!(
+            "homeassistant/scene/mantle/{}/config",
+            scene_uuid(&scenes[0])
+        );
+        let config: Value = serde_json::from_str(&messages[&scene_topic]).unwrap();
+        assert_eq!(config["name"], "Evening");
+    }
+
+    #[test]
+    fn state_reports_hs_or_color_temp() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let mut bulbs = manager.bulbs.lock().unwrap();
+        let bulb = bulbs.get_mut(&42).unwrap();
+        bulb.color = crate::DeviceColor::Single(crate::refreshable_data::RefreshableData::empty(
+            Duration::from_secs(1),
+            Message::LightGet,
+        ));
+        if let crate::DeviceColor::Single(data) = &mut bulb.color {
+            data.update(HSBK {
+                hue: 0,
+                saturation: 0,
+                brightness: u16::MAX,
+                kelvin: 2500,
+            });
+        }
+        let entity = LightEntity {
+            id: bulb.serial(),
+            name: "Desk".to_string(),
+            bulbs: vec![bulb],
+            is_group: false,
+        };
+        assert_eq!(
+            light_state(&entity),
+            json!({ "state": "ON", "brightness": 255, "color_mode": "color_temp", "color_temp": 400 })
+        );
+    }
+
+    #[test]
+    fn parses_home_assistant_commands() {
+        let command: LightCommand = serde_json::from_str(
+            r#"{"state": "ON", "brightness": 128, "color": {"h": 180, "s": 50}, "transition": 2}"#,
+        )
+        .unwrap();
+        let color = command.color(BASE_COLOR).unwrap();
+        assert_eq!(color.hue, 32768);
+        assert_eq!(color.saturation, 32768);
+        assert_eq!(color.brightness, 32896);
+        assert_eq!(command.transition, Some(2.0));
+
+        let command: LightCommand = serde_json::from_str(r#"{"color_temp": 250}"#).unwrap();
+        let color = command.color(BASE_COLOR).unwrap();
+        assert_eq!(color.kelvin, 4000);
+        assert_eq!(color.saturation, 0);
+
+        let command: LightCommand = serde_json::from_str(r#"{"state": "OFF"}"#).unwrap();
+        assert!(command.color(BASE_COLOR).is_none());
+    }
+
+    #[test]
+    fn commands_reach_the_bulb() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let commands = Commands {
+            manager,
+            topics: topics(),
+            scenes: Arc::new(RwLock::new(Vec::new())),
+        };
+
+        let sent = commands
+            .handle(
+                "mantle/light/2a0000000000/set",
+                br#"{"state": "ON", "brightness": 255, "transition": 0.5}"#,
+            )
+            .unwrap();
+        assert!(sent > 0);
+        match receive(&bulb) {
+            Message::LightSetColor {
+                color, duration, ..
+            } => {
+                assert_eq!(color.brightness, u16::MAX);
+                assert_eq!(duration, 500);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                duration: 500
+            }
+        ));
+
+        let group = format!("mantle/light/group_{}/set", "01".repeat(16));
+        commands
+            .handle(&group, br#"{"effect": "breathe"}"#)
+            .unwrap();
+        assert!(matches!(
+            receive(&bulb),
+            Message::SetWaveform {
+                waveform: Waveform::Sine,
+                transient: true,
+                ..
+            }
+        ));
+
+        assert!(commands
+            .handle("mantle/light/ffffffffffff/set", br#"{"state": "ON"}"#)
+            .is_err());
+        assert!(commands.handle("mantle/scene/unknown/set", b"ON").is_err());
+    }
+
+    #[test]
+    fn bridge_stops_without_a_broker() {
+        let (manager, _bulb) = manager_with_fake_bulb(0);
+        let settings = MqttSettings {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port: 1,
+            ..Default::default()
+        };
+        let bridge = MqttBridge::start(&settings, manager.clone(), vec![]).unwrap();
+        assert!(!bridge.is_connected());
+        let started = std::time::Instant::now();
+        drop(bridge);
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        let settings = MqttSettings {
+            host: " ".to_string(),
+            ..Default::default()
+        };
+        assert!(MqttBridge::start(&settings, manager, vec![]).is_err());
+    }
+
+    #[test]
+    fn mireds_conversion() {
+        assert_eq!(kelvin_to_mireds(2500), 400);
+        assert_eq!(mireds_to_kelvin(400), 2500);
+        assert_eq!(mireds_to_kelvin(0), u16::MAX);
+    }
+}
@@ -0,0 +1,1006 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use lifx_core::{ApplicationRequest, HSBK};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::{
+    color::BASE_COLOR, device_info::DeviceColor, scenes::find_scene, scenes::Scene,
+    selector::Selector, BulbInfo, LifxManager, RGB8,
+};
+use rust_i18n::t;
+
+pub const DEFAULT_OSC_ADDR: &str = "0.0.0.0:9000";
+pub const DEFAULT_OSC_FEEDBACK_ADDR: &str = "127.0.0.1:9001";
+
+/// Prefix of the built-in addresses.
+const ROOT: &str = "mantle";
+/// How long a receive waits before pending feedback is sent.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Devices go offline without sending anything, so feedback is re-checked this often.
+const FEEDBACK_INTERVAL: Duration = Duration::from_secs(1);
+const KELVIN_RANGE: std::ops::RangeInclusive<f32> = 1500.0..=9000.0;
+
+/// The light property an OSC address controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum OscControl {
+    #[default]
+    Brightness,
+    Hue,
+    Saturation,
+    Kelvin,
+    Power,
+    ZoneColor,
+    Scene,
+}
+
+impl OscControl {
+    fn from_segment(segment: &str) -> Option<OscControl> {
+        match segment {
+            "brightness" => Some(OscControl::Brightness),
+            "hue" => Some(OscControl::Hue),
+            "saturation" => Some(OscControl::Saturation),
+            "kelvin" => Some(OscControl::Kelvin),
+            "power" => Some(OscControl::Power),
+            _ => None,
+        }
+    }
+
+    fn segment(&self) -> &'static str {
+        match self {
+            OscControl::Brightness => "brightness",
+            OscControl::Hue => "hue",
+            OscControl::Saturation => "saturation",
+            OscControl::Kelvin => "kelvin",
+            OscControl::Power => "power",
+            OscControl::ZoneColor => "zone",
+            OscControl::Scene => "scene",
+        }
+    }
+
+    /// The current value of a scalar control in the units it is set with, for feedback.
+    fn value(&self, bulb: &BulbInfo) -> Option<f32> {
+        let color = bulb.get_color();
+        let unit = |value: u16| value as f32 / u16::MAX as f32;
+        match self {
+            OscControl::Brightness => color.map(|c| unit(c.brightness)),
+            OscControl::Hue => color.map(|c| unit(c.hue)),
+            OscControl::Saturation => color.map(|c| unit(c.saturation)),
+            OscControl::Kelvin => color.map(|c| c.kelvin as f32),
+            OscControl::Power => bulb
+                .power_level
+                .data
+                .map(|level| if level > 0 { 1.0 } else { 0.0 }),
+            OscControl::ZoneColor | OscControl::Scene => None,
+        }
+    }
+}
+
+impl Display for OscControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            OscControl::Brightness => t!("osc.control.brightness"),
+            OscControl::Hue => t!("osc.control.hue"),
+            OscControl::Saturation => t!("osc.control.saturation"),
+            OscControl::Kelvin => t!("osc.control.kelvin"),
+            OscControl::Power => t!("osc.control.power"),
+            OscControl::ZoneColor => t!("osc.control.zone_color"),
+            OscControl::Scene => t!("osc.control.scene"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// A user-defined address, e.g. a TouchOSC fader, bound to a light property.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscMapping {
+    pub address: String,
+    /// Selector of the lights to control; unused for scenes.
+    pub selector: String,
+    pub control: OscControl,
+    /// Zone index for `ZoneColor`.
+    pub zone: usize,
+    /// Scene name for `Scene`.
+    pub scene: String,
+}
+
+/// User-facing configuration of the OSC server, persisted with the app settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscSettings {
+    pub enabled: bool,
+    pub bind_addr: String,
+    /// Send the current state of every light to `feedback_addr`.
+    pub feedback: bool,
+    pub feedback_addr: String,
+    /// Checked before the built-in `/mantle/...` addresses.
+    pub mappings: Vec<OscMapping>,
+}
+
+impl Default for OscSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_addr: DEFAULT_OSC_ADDR.to_string(),
+            feedback: false,
+            feedback_addr: DEFAULT_OSC_FEEDBACK_ADDR.to_string(),
+            mappings: Vec::new(),
+        }
+    }
+}
+
+/// An OSC argument. Only the types sent by common controllers are supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    /// RGBA color (`r`).
+    Color([u8; 4]),
+    Bool(bool),
+    Nil,
+}
+
+impl OscArg {
+    fn tag(&self) -> char {
+        match self {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::Long(_) => 'h',
+            OscArg::Double(_) => 'd',
+            OscArg::String(_) => 's',
+            OscArg::Blob(_) => 'b',
+            OscArg::Color(_) => 'r',
+            OscArg::Bool(true) => 'T',
+            OscArg::Bool(false) => 'F',
+            OscArg::Nil => 'N',
+        }
+    }
+
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(v) => Some(*v as f32),
+            OscArg::Float(v) => Some(*v),
+            OscArg::Long(v) => Some(*v as f32),
+            OscArg::Double(v) => Some(*v as f32),
+            OscArg::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.address);
+        let tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(OscArg::tag))
+            .collect();
+        write_string(&mut out, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(v) => out.extend_from_slice(&v.to_be_bytes()),
+                OscArg::Float(v) => out.extend_from_slice(&v.to_be_bytes()),
+                OscArg::Long(v) => out.extend_from_slice(&v.to_be_bytes()),
+                OscArg::Double(v) => out.extend_from_slice(&v.to_be_bytes()),
+                OscArg::String(s) => write_string(&mut out, s),
+                OscArg::Blob(data) => {
+                    out.extend_from_slice(&(data.len() as i32).to_be_bytes());
+                    out.extend_from_slice(data);
+                    pad(&mut out);
+                }
+                OscArg::Color(rgba) => out.extend_from_slice(rgba),
+                OscArg::Bool(_) | OscArg::Nil => {}
+            }
+        }
+        out
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.push(0);
+    pad(out);
+}
+
+fn pad(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+/// Reads the big-endian, 4-byte aligned fields of an OSC packet.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], anyhow::Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow::anyhow!("Truncated OSC packet"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], anyhow::Error> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn string(&mut self) -> Result<String, anyhow::Error> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow::anyhow!("Unterminated OSC string"))?;
+        let s = String::from_utf8(rest[..len].to_vec())?;
+        self.take((len + 4) & !3)?;
+        Ok(s)
+    }
+
+    fn blob(&mut self) -> Result<Vec<u8>, anyhow::Error> {
+        let len = i32::from_be_bytes(self.array()?);
+        let len = usize::try_from(len)?;
+        let data = self.take(len)?.to_vec();
+        self.take((4 - len % 4) % 4)?;
+        Ok(data)
+    }
+}
+
+/// Decode a packet into its messages, flattening bundles. Time tags are ignored and
+/// bundled messages run immediately.
+pub fn decode_packet(data: &[u8]) -> Result<Vec<OscMessage>, anyhow::Error> {
+    let mut reader = Reader { data, pos: 0 };
+    if data.starts_with(b"#bundle\0") {
+        reader.take(16)?;
+        let mut messages = Vec::new();
+        while reader.pos < data.len() {
+            let len = usize::try_from(i32::from_be_bytes(reader.array()?))?;
+            messages.extend(decode_packet(reader.take(len)?)?);
+        }
+        return Ok(messages);
+    }
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err(anyhow::anyhow!("Invalid OSC address {}", address));
+    }
+    // Very old senders omit the type tag string; their arguments cannot be decoded.
+    if reader.pos >= data.len() {
+        return Ok(vec![OscMessage::new(address, vec![])]);
+    }
+    let tags = reader.string()?;
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or_else(|| anyhow::anyhow!("Missing OSC type tags"))?;
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(reader.array()?)),
+            'f' => OscArg::Float(f32::from_be_bytes(reader.array()?)),
+            'h' => OscArg::Long(i64::from_be_bytes(reader.array()?)),
+            'd' => OscArg::Double(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => OscArg::String(reader.string()?),
+            'b' => OscArg::Blob(reader.blob()?),
+            'r' => OscArg::Color(reader.array()?),
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            other => return Err(anyhow::anyhow!("Unsupported OSC type tag {}", other)),
+        });
+    }
+    Ok(vec![OscMessage::new(address, args)])
+}
+
+/// The address segment that names a light: its label with spaces as underscores, since
+/// OSC addresses cannot contain spaces, or its serial if it has no label.
+pub fn osc_name(bulb: &BulbInfo) -> String {
+    match bulb.name_label() {
+        Some(label) => label.split_whitespace().collect::<Vec<_>>().join("_"),
+        None => bulb.serial(),
+    }
+}
+
+/// What an incoming address resolves to.
+#[derive(Debug, Clone, PartialEq)]
+enum Route {
+    Light {
+        target: Target,
+        control: OscControl,
+        zone: usize,
+    },
+    Scene(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Selector(Selector),
+    /// The `<device>` segment of a built-in address: `all`, a serial, an `osc_name` or a
+    /// group name.
+    Segment(String),
+}
+
+impl Target {
+    fn select(&self, bulbs: &HashMap<u64, BulbInfo>) -> Vec<BulbInfo> {
+        let matches = |name: Option<String>, segment: &str| {
+            name.is_some_and(|name| {
+                name.split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join("_")
+                    .eq_ignore_ascii_case(segment)
+            })
+        };
+        let mut selected: Vec<BulbInfo> = match self {
+            Target::Selector(selector) => selector.select(bulbs).into_iter().cloned().collect(),
+            Target::Segment(segment) if segment.eq_ignore_ascii_case("all") => {
+                bulbs.values().cloned().collect()
+            }
+            Target::Segment(segment) => {
+                let by_name: Vec<BulbInfo> = bulbs
+                    .values()
+                    .filter(|b| {
+                        b.serial().eq_ignore_ascii_case(segment) || matches(b.name_label(), segment)
+                    })
+                    .cloned()
+                    .collect();
+                if by_name.is_empty() {
+                    bulbs
+                        .values()
+                        .filter(|b| matches(b.group_label(), segment))
+                        .cloned()
+                        .collect()
+                } else {
+                    by_name
+                }
+            }
+        };
+        selected.retain(|b| b.is_online());
+        selected
+    }
+}
+
+fn route(address: &str, mappings: &[OscMapping]) -> Option<Route> {
+    if let Some(mapping) = mappings.iter().find(|m| m.address == address) {
+        if mapping.control == OscControl::Scene {
+            return Some(Route::Scene(mapping.scene.clone()));
+        }
+        let selector = match mapping.selector.parse() {
+            Ok(selector) => selector,
+            Err(e) => {
+                log::error!("Invalid selector in OSC mapping {}: {}", address, e);
+                return None;
+            }
+        };
+        return Some(Route::Light {
+            target: Target::Selector(selector),
+            control: mapping.control,
+            zone: mapping.zone,
+        });
+    }
+    let segments: Vec<&str> = address.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        [ROOT, "scene", name] => Some(Route::Scene(name.to_string())),
+        [ROOT, device, "zone", zone, "color"] => Some(Route::Light {
+            target: Target::Segment(device.to_string()),
+            control: OscControl::ZoneColor,
+            zone: zone.parse().ok()?,
+        }),
+        [ROOT, device, control] => Some(Route::Light {
+            target: Target::Segment(device.to_string()),
+            control: OscControl::from_segment(control)?,
+            zone: 0,
+        }),
+        _ => None,
+    }
+}
+
+/// Receives OSC messages over UDP and applies them through `LifxManager`.
+///
+/// Built-in addresses, checked after the user's mappings:
+///
+/// - `/mantle/<device>/hue`, `/saturation`, `/brightness` take a value in `[0, 1]`
+/// - `/mantle/<device>/kelvin` takes a color temperature in kelvin
+/// - `/mantle/<device>/power` turns lights on for values above zero or `T`
+/// - `/mantle/<device>/zone/<n>/color` takes an RGBA color (`r`) or `h s b [k]` floats
+/// - `/mantle/scene/<name>` applies a scene (spaces in the name may be written as `_`)
+///
+/// `<device>` is `all`, a serial, a label with spaces as underscores, or a group name.
+/// With feedback enabled the same addresses are sent to the feedback target whenever a
+/// value changes, as are the addresses of scalar mappings. The server stops when dropped.
+pub struct OscServer {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    local_addr: SocketAddr,
+    settings: OscSettings,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+}
+
+impl OscServer {
+    pub fn start(
+        settings: &OscSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<OscServer, anyhow::Error> {
+        let socket = UdpSocket::bind(settings.bind_addr.as_str())?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_addr = socket.local_addr()?;
+        let feedback = if settings.feedback {
+            let target = settings
+                .feedback_addr
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        t!("error.osc_feedback_addr", addr = settings.feedback_addr).to_string()
+                    )
+                })?;
+            Some(Feedback::new(target, &manager)?)
+        } else {
+            None
+        };
+        let running = Arc::new(AtomicBool::new(true));
+        let scenes = Arc::new(RwLock::new(scenes));
+        let handler = Handler {
+            manager,
+            scenes: scenes.clone(),
+            mappings: settings.mappings.clone(),
+        };
+        let handle = {
+            let running = running.clone();
+            spawn(move || handler.run(&socket, feedback, &running))
+        };
+        Ok(OscServer {
+            running,
+            handle: Some(handle),
+            local_addr,
+            settings: settings.clone(),
+            scenes,
+        })
+    }
+
+    pub fn settings(&self) -> &OscSettings {
+        &self.settings
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Handler {
+    manager: LifxManager,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    mappings: Vec<OscMapping>,
+}
+
+impl Handler {
+    fn run(&self, socket: &UdpSocket, mut feedback: Option<Feedback>, running: &AtomicBool) {
+        let mut buf = [0u8; 8192];
+        while running.load(Ordering::SeqCst) {
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) => match decode_packet(&buf[..len]) {
+                    Ok(messages) => {
+                        for message in messages {
+                            if let Err(e) = self.handle(&message) {
+                                log::warn!("OSC {} from {}: {}", message.address, from, e);
+                            }
+                        }
+                    }
+                    Err(e) => log::warn!("Invalid OSC packet from {}: {}", from, e),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => log::error!("OSC receive error: {}", e),
+            }
+            if let Some(feedback) = feedback.as_mut() {
+                feedback.send_changes(&self.manager, &self.mappings);
+            }
+        }
+    }
+
+    fn handle(&self, message: &OscMessage) -> Result<(), anyhow::Error> {
+        let route = route(&message.address, &self.mappings)
+            .ok_or_else(|| anyhow::anyhow!("No route for this address"))?;
+        let first = message.args.first().and_then(OscArg::as_f32);
+        match route {
+            Route::Scene(name) => {
+                // Buttons also send 0 on release; only the press applies the scene.
+                if first.is_some_and(|value| value <= 0.0) {
+                    return Ok(());
+                }
+                let scene = {
+                    let scenes = self
+                        .scenes
+                        .read()
+                        .map_err(|_| anyhow::anyhow!("Scenes unavailable"))?;
+                    find_scene(&scenes, &name)
+                        .or_else(|| find_scene(&scenes, &name.replace('_', " ")))
+                        .cloned()
+                        .ok_or_else(|| anyhow::anyhow!("Could not find scene {}.", name))?
+                };
+                scene
+                    .apply(&mut self.manager.clone())
+                    .map_err(|errors| anyhow::anyhow!(errors.join("; ")))
+            }
+            Route::Light {
+                target,
+                control,
+                zone,
+            } => {
+                let bulbs = {
+                    let bulbs = self
+                        .manager
+                        .bulbs
+                        .lock()
+                        .map_err(|_| anyhow::anyhow!("Device registry unavailable"))?;
+                    target.select(&bulbs)
+                };
+                if control == OscControl::ZoneColor {
+                    let color = zone_color(&message.args)?;
+                    for bulb in &bulbs {
+                        self.set_zone(bulb, zone, color)?;
+                    }
+                    return Ok(());
+                }
+                let value = first.ok_or_else(|| anyhow::anyhow!("Expected a number"))?;
+                for bulb in &bulbs {
+                    self.set_scalar(bulb, control, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn set_scalar(
+        &self,
+        bulb: &BulbInfo,
+        control: OscControl,
+        value: f32,
+    ) -> std::io::Result<usize> {
+        let unit = (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+        let current = bulb.get_color().copied().unwrap_or(BASE_COLOR);
+        let color = match control {
+            OscControl::Power => {
+                let level = if value > 0.0 { u16::MAX } else { 0 };
+                return self.manager.set_power(&bulb, level);
+            }
+            OscControl::Hue => HSBK {
+                hue: unit,
+                ..current
+            },
+            OscControl::Saturation => HSBK {
+                saturation: unit,
+                ..current
+            },
+            OscControl::Brightness => HSBK {
+                brightness: unit,
+                ..current
+            },
+            OscControl::Kelvin => HSBK {
+                kelvin: value.clamp(*KELVIN_RANGE.start(), *KELVIN_RANGE.end()) as u16,
+                ..current
+            },
+            OscControl::ZoneColor | OscControl::Scene => return Ok(0),
+        };
+        self.manager.set_color(&bulb, color, None)
+    }
+
+    fn set_zone(&self, bulb: &BulbInfo, zone: usize, color: ZoneColor) -> std::io::Result<usize> {
+        let zones = match &bulb.color {
+            DeviceColor::Multi(data) | DeviceColor::Matrix(data) => data.data.as_ref(),
+            DeviceColor::Single(_) | DeviceColor::Unknown => None,
+        };
+        let Some(current) = zones.and_then(|zones| zones.get(zone).copied().flatten()) else {
+            return Ok(0);
+        };
+        let color = color.apply(current);
+        match &bulb.color {
+            DeviceColor::Matrix(_) => {
+                let updates = HashMap::from([(zone, color)]);
+                self.manager.set_extended_color_zones(
+                    &bulb,
+                    zones.unwrap_or(&Vec::new()),
+                    &updates,
+                    0,
+                )
+            }
+            _ => self.manager.set_color_zones(
+                &bulb,
+                zone as u8,
+                zone as u8,
+                color,
+                0,
+                ApplicationRequest::Apply,
+            ),
+        }
+    }
+}
+
+/// The color sent to a zone: RGB keeps the zone's kelvin, `h s b` floats may add one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ZoneColor {
+    hue: u16,
+    saturation: u16,
+    brightness: u16,
+    kelvin: Option<u16>,
+}
+
+impl ZoneColor {
+    fn apply(&self, current: HSBK) -> HSBK {
+        HSBK {
+            hue: self.hue,
+            saturation: self.saturation,
+            brightness: self.brightness,
+            kelvin: self.kelvin.unwrap_or(current.kelvin),
+        }
+    }
+}
+
+fn zone_color(args: &[OscArg]) -> Result<ZoneColor, anyhow::Error> {
+    if let Some(OscArg::Color([r, g, b, _])) = args.first() {
+        let hsbk: HSBK = RGB8::new(*r, *g, *b, None).into();
+        return Ok(ZoneColor {
+            hue: hsbk.hue,
+            saturation: hsbk.saturation,
+            brightness: hsbk.brightness,
+            kelvin: None,
+        });
+    }
+    let values: Vec<f32> = args.iter().filter_map(OscArg::as_f32).collect();
+    let unit = |value: f32| (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+    match values.as_slice() {
+        [h, s, b] | [h, s, b, _] => Ok(ZoneColor {
+            hue: unit(*h),
+            saturation: unit(*s),
+            brightness: unit(*b),
+            kelvin: values
+                .get(3)
+                .map(|k| k.clamp(*KELVIN_RANGE.start(), *KELVIN_RANGE.end()) as u16),
+        }),
+        _ => Err(anyhow::anyhow!(
+            "Expected an RGBA color or hue, saturation and brightness"
+        )),
+    }
+}
+
+/// Sends the state of every light to the feedback target, one message per value that
+/// changed since it was last sent.
+struct Feedback {
+    socket: UdpSocket,
+    target: SocketAddr,
+    changes: std::sync::mpsc::Receiver<u64>,
+    sent: HashMap<String, f32>,
+    last_check: Option<Instant>,
+}
+
+impl Feedback {
+    fn new(target: SocketAddr, manager: &LifxManager) -> std::io::Result<Feedback> {
+        let bind = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        Ok(Feedback {
+            socket: UdpSocket::bind(bind)?,
+            target,
+            changes: manager.subscribe(),
+            sent: HashMap::new(),
+            last_check: None,
+        })
+    }
+
+    fn send_changes(&mut self, manager: &LifxManager, mappings: &[OscMapping]) {
+        let changed = self.changes.try_iter().count() > 0;
+        if !changed
+            && self
+                .last_check
+                .is_some_and(|t| t.elapsed() < FEEDBACK_INTERVAL)
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+        let values = match manager.bulbs.lock() {
+            Ok(bulbs) => feedback_values(&bulbs, mappings),
+            Err(_) => return,
+        };
+        for (address, value) in values {
+            if self.sent.get(&address) == Some(&value) {
+                continue;
+            }
+            let arg = if address.ends_with("/kelvin") {
+                OscArg::Int(value as i32)
+            } else {
+                OscArg::Float(value)
+            };
+            let packet = OscMessage::new(address.clone(), vec![arg]).encode();
+            if let Err(e) = self.socket.send_to(&packet, self.target) {
+                log::warn!("Failed to send OSC feedback to {}: {}", self.target, e);
+                return;
+            }
+            self.sent.insert(address, value);
+        }
+    }
+}
+
+/// The feedback address and value of every scalar control of every online light, plus
+/// the mapped addresses, which report the first light they select.
+fn feedback_values(bulbs: &HashMap<u64, BulbInfo>, mappings: &[OscMapping]) -> Vec<(String, f32)> {
+    let scalars = [
+        OscControl::Power,
+        OscControl::Hue,
+        OscControl::Saturation,
+        OscControl::Brightness,
+        OscControl::Kelvin,
+    ];
+    let mut values: Vec<(String, f32)> = bulbs
+        .values()
+        .filter(|b| b.is_online())
+        .flat_map(|bulb| {
+            let name = osc_name(bulb);
+            scalars.iter().filter_map(move |control| {
+                control
+                    .value(bulb)
+                    .map(|value| (format!("/{}/{}/{}", ROOT, name, control.segment()), value))
+            })
+        })
+        .collect();
+    for mapping in mappings {
+        let Some(Route::Light {
+            target, control, ..
+        }) = route(&mapping.address, std::slice::from_ref(mapping))
+        else {
+            continue;
+        };
+        let mut selected = target.select(bulbs);
+        selected.sort_by_key(|b| (b.name_label(), b.serial()));
+        if let Some(value) = selected.first().and_then(|bulb| control.value(bulb)) {
+            values.push((mapping.address.clone(), value));
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive, reply};
+    use lifx_core::Message;
+
+    fn send(server: &OscServer, message: &OscMessage) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = ("127.0.0.1", server.local_addr().port());
+        socket.send_to(&message.encode(), target).unwrap();
+    }
+
+    fn start_server(manager: &LifxManager, settings: OscSettings) -> OscServer {
+        let settings = OscSettings {
+            enabled: true,
+            bind_addr: "127.0.0.1:0".to_string(),
+            ..settings
+        };
+        OscServer::start(&settings, manager.clone(), vec![]).unwrap()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let message = OscMessage::new(
+            "/mantle/Desk/zone/3/color",
+            vec![
+                OscArg::Int(-7),
+                OscArg::Float(0.25),
+                OscArg::String("hello".to_string()),
+                OscArg::Blob(vec![1, 2, 3, 4, 5]),
+                OscArg::Color([255, 128, 0, 255]),
+                OscArg::Bool(true),
+                OscArg::Double(1.5),
+                OscArg::Long(1 << 40),
+                OscArg::Nil,
+            ],
+        );
+        let encoded = message.encode();
+        assert_eq!(encoded.len() % 4, 0);
+        assert_eq!(decode_packet(&encoded).unwrap(), vec![message]);
+    }
+
+    #[test]
+    fn decodes_bundles() {
+        let first = OscMessage::new("/a", vec![OscArg::Float(1.0)]).encode();
+        let second = OscMessage::new("/b", vec![]).encode();
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&1u64.to_be_bytes());
+        for element in [&first, &second] {
+            bundle.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+        let messages = decode_packet(&bundle).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].address, "/b");
+        assert!(decode_packet(b"nope").is_err());
+        assert!(decode_packet(&first[..first.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn routes_built_in_addresses_and_mappings() {
+        assert_eq!(
+            route("/mantle/Desk_Lamp/hue", &[]),
+            Some(Route::Light {
+                target: Target::Segment("Desk_Lamp".to_string()),
+                control: OscControl::Hue,
+                zone: 0,
+            })
+        );
+        assert_eq!(
+            route("/mantle/Strip/zone/4/color", &[]),
+            Some(Route::Light {
+                target: Target::Segment("Strip".to_string()),
+                control: OscControl::ZoneColor,
+                zone: 4,
+            })
+        );
+        assert_eq!(
+            route("/mantle/scene/Movie_Night", &[]),
+            Some(Route::Scene("Movie_Night".to_string()))
+        );
+        assert_eq!(route("/mantle/Desk/volume", &[]), None);
+        assert_eq!(route("/1/fader1", &[]), None);
+
+        let mapping = OscMapping {
+            address: "/1/fader1".to_string(),
+            selector: "group:Stage".to_string(),
+            control: OscControl::Brightness,
+            ..Default::default()
+        };
+        assert_eq!(
+            route("/1/fader1", &[mapping]),
+            Some(Route::Light {
+                target: Target::Selector(Selector::Group("Stage".to_string())),
+                control: OscControl::Brightness,
+                zone: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn applies_values_to_lights() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let mapping = OscMapping {
+            address: "/1/fader1".to_string(),
+            selector: "label:Desk".to_string(),
+            control: OscControl::Hue,
+            ..Default::default()
+        };
+        let server = start_server(
+            &manager,
+            OscSettings {
+                mappings: vec![mapping],
+                ..Default::default()
+            },
+        );
+
+        send(
+            &server,
+            &OscMessage::new("/mantle/desk/power", vec![OscArg::Int(1)]),
+        );
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower {
+                level: u16::MAX,
+                ..
+            }
+        ));
+
+        send(
+            &server,
+            &OscMessage::new("/mantle/Office/brightness", vec![OscArg::Float(0.5)]),
+        );
+        match receive(&bulb) {
+            Message::LightSetColor { color, .. } => assert_eq!(color.brightness, 32768),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        send(
+            &server,
+            &OscMessage::new("/1/fader1", vec![OscArg::Float(1.0)]),
+        );
+        match receive(&bulb) {
+            Message::LightSetColor { color, .. } => assert_eq!(color.hue, u16::MAX),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn zone_colors_accept_rgba_and_floats() {
+        let red = zone_color(&[OscArg::Color([255, 0, 0, 255])]).unwrap();
+        assert_eq!(red.hue, 0);
+        assert_eq!(red.saturation, u16::MAX);
+        assert_eq!(red.kelvin, None);
+        let warm = zone_color(&[
+            OscArg::Float(0.5),
+            OscArg::Float(1.0),
+            OscArg::Float(1.0),
+            OscArg::Int(2700),
+        ])
+        .unwrap();
+        assert_eq!(warm.hue, 32768);
+        assert_eq!(warm.kelvin, Some(2700));
+        assert!(zone_color(&[OscArg::Float(0.5)]).is_err());
+    }
+
+    #[test]
+    fn sends_feedback_when_state_changes() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let _server = start_server(
+            &manager,
+            OscSettings {
+                feedback: true,
+                feedback_addr: listener.local_addr().unwrap().to_string(),
+                ..Default::default()
+            },
+        );
+
+        let mut buf = [0u8; 1024];
+        let next = |buf: &mut [u8]| {
+            let (len, _) = listener.recv_from(buf).unwrap();
+            decode_packet(&buf[..len]).unwrap().remove(0)
+        };
+        assert_eq!(
+            next(&mut buf),
+            OscMessage::new("/mantle/Desk/power", vec![OscArg::Float(0.0)])
+        );
+
+        reply(
+            &bulb,
+            &manager,
+            Message::LightStatePower { level: u16::MAX },
+        );
+        assert_eq!(
+            next(&mut buf),
+            OscMessage::new("/mantle/Desk/power", vec![OscArg::Float(1.0)])
+        );
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::{http, Message, WebSocket};
+
+use super::{generate_token, light_state, token_matches};
+use crate::{
+    action::UserAction, device_info::DeviceInfo, scenes::Scene, selector::Selector, BulbInfo,
+    LifxManager,
+};
+use rust_i18n::t;
+
+pub const DEFAULT_WEBSOCKET_API_ADDR: &str = "127.0.0.1:56781";
+
+/// How long a client read waits before pending device changes are pushed.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Devices go offline without sending anything, so their state is re-checked this often.
+const ONLINE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// User-facing configuration of the WebSocket API, persisted with the app settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebSocketApiSettings {
+    pub enabled: bool,
+    pub bind_addr: String,
+    /// Clients connect to `ws://<addr>/?token=<token>` or send `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+impl Default for WebSocketApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_addr: DEFAULT_WEBSOCKET_API_ADDR.to_string(),
+            token: generate_token(),
+        }
+    }
+}
+
+/// Messages pushed to clients.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    /// The full state of every known light, sent once after connecting.
+    Snapshot {
+        lights: Vec<Map<String, Value>>,
+    },
+    /// The fields of one light that changed since the last message about it.
+    /// A light the client has not seen yet arrives as a delta with every field.
+    Delta {
+        id: String,
+        changes: Map<String, Value>,
+    },
+    Result {
+        id: Option<Value>,
+        devices: usize,
+    },
+    Error {
+        id: Option<Value>,
+        error: String,
+    },
+}
+
+/// A control request from a client. Exactly one of `action` and `scene` is expected:
+/// `{"id": 1, "selector": "group:Office", "action": {"SetPower": {"power": true}}}`
+/// or `{"id": 2, "scene": "Movie Night"}`.
+#[derive(Debug, Deserialize)]
+struct ControlMessage {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    selector: Option<String>,
+    #[serde(default)]
+    action: Option<UserAction>,
+    #[serde(default)]
+    scene: Option<String>,
+}
+
+/// WebSocket server that streams changes to the device registry as JSON deltas and
+/// accepts control messages that are executed as `UserAction`s.
+///
+/// The server stops when dropped.
+pub struct WebSocketApiServer {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    settings: WebSocketApiSettings,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    local_addr: SocketAddr,
+}
+
+impl WebSocketApiServer {
+    pub fn start(
+        settings: &WebSocketApiSettings,
+        manager: LifxManager,
+        scenes: Vec<Scene>,
+    ) -> Result<WebSocketApiServer, anyhow::Error> {
+        if settings.token.is_empty() {
+            return Err(anyhow::anyhow!(t!("error.api_token_required").to_string()));
+        }
+        let listener = TcpListener::bind(settings.bind_addr.as_str())?;
+        // Non-blocking so the accept loop notices when the server is dropped.
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let scenes = Arc::new(RwLock::new(scenes));
+
+        let handle = {
+            let running = running.clone();
+            let scenes = scenes.clone();
+            let token = settings.token.clone();
+            spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, addr)) => {
+                            let client = Client {
+                                manager: manager.clone(),
+                                scenes: scenes.clone(),
+                                running: running.clone(),
+                                sent: HashMap::new(),
+                            };
+                            let token = token.clone();
+                            spawn(move || {
+                                if let Err(e) = client.serve(stream, &token) {
+                                    log::warn!("WebSocket client {} disconnected: {}", addr, e);
+                                }
+                            });
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(POLL_INTERVAL),
+                        Err(e) => {
+                            log::error!("WebSocket accept error: {}", e);
+                            sleep(POLL_INTERVAL);
+                        }
+                    }
+                }
+            })
+        };
+        log::info!("WebSocket API listening on {}", local_addr);
+        Ok(WebSocketApiServer {
+            running,
+            handle: Some(handle),
+            settings: settings.clone(),
+            scenes,
+            local_addr,
+        })
+    }
+
+    /// Settings the server was started with.
+    pub fn settings(&self) -> &WebSocketApiSettings {
+        &self.settings
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Replace the scenes clients can activate by name if they changed.
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+}
+
+impl Drop for WebSocketApiServer {
+    fn drop(&mut self) {
+        // Client threads notice the flag on their next poll and close their connections.
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                log::error!("WebSocket API thread panicked");
+            }
+        }
+    }
+}
+
+struct Client {
+    manager: LifxManager,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+    running: Arc<AtomicBool>,
+    /// Last state sent for each light, used to compute deltas.
+    sent: HashMap<u64, Map<String, Value>>,
+}
+
+impl Client {
+    fn serve(mut self, stream: TcpStream, token: &str) -> Result<(), anyhow::Error> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut socket = tungstenite::accept_hdr(stream, Authorize(token))
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let changes = self.manager.subscribe();
+        self.send_snapshot(&mut socket)?;
+        let mut last_online_check = Instant::now();
+
+        while self.running.load(Ordering::SeqCst) {
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let event = self.handle_control(&text);
+                    send(&mut socket, &event)?;
+                }
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+
+            let mut changed: HashSet<u64> = changes.try_iter().collect();
+            if last_online_check.elapsed() >= ONLINE_CHECK_INTERVAL {
+                changed.extend(self.sent.keys());
+                last_online_check = Instant::now();
+            }
+            self.send_deltas(&mut socket, changed)?;
+        }
+        socket.close(None)?;
+        socket.flush()?;
+        Ok(())
+    }
+
+    fn send_snapshot(&mut self, socket: &mut WebSocket<TcpStream>) -> Result<(), anyhow::Error> {
+        let lights = match self.manager.bulbs.lock() {
+            Ok(bulbs) => Selector::All
+                .select(&bulbs)
+                .into_iter()
+                .map(|bulb| {
+                    let state = light_state(bulb);
+                    self.sent.insert(bulb.target, state.clone());
+                    state
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        send(socket, &Event::Snapshot { lights })
+    }
+
+    fn send_deltas(
+        &mut self,
+        socket: &mut WebSocket<TcpStream>,
+        targets: HashSet<u64>,
+    ) -> Result<(), anyhow::Error> {
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let mut events = Vec::new();
+        if let Ok(bulbs) = self.manager.bulbs.lock() {
+            for target in targets {
+                let Some(bulb) = bulbs.get(&target) else {
+                    continue;
+                };
+                let state = light_state(bulb);
+                let changes = diff(self.sent.get(&target), &state);
+                if !changes.is_empty() {
+                    events.push(Event::Delta {
+                        id: bulb.serial(),
+                        changes,
+                    });
+                    self.sent.insert(target, state);
+                }
+            }
+        }
+        for event in events {
+            send(socket, &event)?;
+        }
+        Ok(())
+    }
+
+    fn handle_control(&self, text: &str) -> Event {
+        let message: ControlMessage = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return Event::Error {
+                    id: None,
+                    error: e.to_string(),
+                }
+            }
+        };
+        let id = message.id.clone();
+        match self.execute(message) {
+            Ok(devices) => Event::Result { id, devices },
+            Err(e) => Event::Error {
+                id,
+                error: e.to_string(),
+            },
+        }
+    }
+
+    /// Run the requested action on every online light matched by the selector.
+    fn execute(&self, message: ControlMessage) -> Result<usize, anyhow::Error> {
+        let action = match (message.action, message.scene) {
+            (Some(action), None) => action,
+            (None, Some(name)) => {
+                let scenes = self
+                    .scenes
+                    .read()
+                    .map_err(|_| anyhow::anyhow!("Scenes unavailable"))?;
+                let scene = scenes
+                    .iter()
+                    .find(|s| s.name == name)
+                    .ok_or_else(|| anyhow::anyhow!("Could not find scene {}.", name))?;
+                // Scenes carry their own devices, so the selector does not apply.
+                scene
+                    .apply(&mut self.manager.clone())
+                    .map_err(|errors| anyhow::anyhow!(errors.join("; ")))?;
+                return Ok(scene.device_color_pairs.len());
+            }
+            _ => return Err(anyhow::anyhow!("Expected exactly one of action or scene")),
+        };
+        let selector: Selector = message.selector.as_deref().unwrap_or("all").parse()?;
+        let selected: Vec<BulbInfo> = {
+            let bulbs = self
+                .manager
+                .bulbs
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Device registry unavailable"))?;
+            selector
+                .select(&bulbs)
+                .into_iter()
+                .filter(|b| b.is_online())
+                .cloned()
+                .collect()
+        };
+        if selected.is_empty() {
+            return Err(anyhow::anyhow!("Could not find {}.", selector));
+        }
+        for bulb in &selected {
+            action.execute(
+                self.manager.clone(),
+                DeviceInfo::Bulb(Box::new(bulb.clone())),
+            );
+        }
+        Ok(selected.len())
+    }
+}
+
+/// Accepts the handshake only if the token is in the query string or a bearer header,
+/// since browsers cannot set headers on WebSocket requests.
+struct Authorize<'a>(&'a str);
+
+impl Callback for Authorize<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let from_query = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        });
+        let from_header = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if [from_query, from_header]
+            .into_iter()
+            .flatten()
+            .any(|presented| token_matches(self.0, presented.trim()))
+        {
+            return Ok(response);
+        }
+        let mut response = ErrorResponse::new(Some("Invalid or missing token".to_string()));
+        *response.status_mut() = http::StatusCode::UNAUTHORIZED;
+        Err(response)
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, event: &Event) -> Result<(), anyhow::Error> {
+    socket.send(Message::Text(serde_json::to_string(event)?))?;
+    Ok(())
+}
+
+/// Fields of `current` that differ from `previous`.
+fn diff(previous: Option<&Map<String, Value>>, current: &Map<String, Value>) -> Map<String, Value> {
+    current
+        .iter()
+        .filter(|(key, value)| previous.and_then(|p| p.get(*key)) != Some(value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive, reply};
+    use serde_json::json;
+
+    const TOKEN: &str = "test-token";
+
+    fn start_server(manager: &LifxManager) -> WebSocketApiServer {
+        let settings = WebSocketApiSettings {
+            enabled: true,
+            bind_addr: "127.0.0.1:0".to_string(),
+            token: TOKEN.to_string(),
+        };
+        WebSocketApiServer::start(&settings, manager.clone(), vec![]).unwrap()
+    }
+
+    /// Open a client connection, or `None` if the handshake is refused.
+    fn connect(server: &WebSocketApiServer, token: &str) -> Option<WebSocket<TcpStream>> {
+        let addr = server.local_addr();
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let url = format!("ws://{}/?token={}", addr, token);
+        tungstenite::client(url.as_str(), stream)
+            .ok()
+            .map(|(socket, _)| socket)
+    }
+
+    fn next_event(socket: &mut WebSocket<TcpStream>) -> Value {
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_token() {
+        let (manager, _bulb) = manager_with_fake_bulb(0);
+        let server = start_server(&manager);
+        assert!(connect(&server, "nope").is_none());
+    }
+
+    #[test]
+    fn streams_snapshot_then_deltas() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let server = start_server(&manager);
+        let mut socket = connect(&server, TOKEN).unwrap();
+
+        let snapshot = next_event(&mut socket);
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(snapshot["lights"][0]["label"], "Desk");
+        assert_eq!(snapshot["lights"][0]["power"], "off");
+
+        reply(
+            &bulb,
+            &manager,
+            lifx_core::Message::LightStatePower { level: u16::MAX },
+        );
+        let delta = next_event(&mut socket);
+        assert_eq!(delta["type"], "delta");
+        assert_eq!(delta["id"], "2a0000000000");
+        assert_eq!(delta["changes"], json!({ "power": "on" }));
+    }
+
+    #[test]
+    fn control_messages_run_user_actions() {
+        let (manager, bulb) = manager_with_fake_bulb(0);
+        let server = start_server(&manager);
+        let mut socket = connect(&server, TOKEN).unwrap();
+        next_event(&mut socket);
+
+        let request = json!({
+            "id": 7,
+            "selector": "label:desk",
+            "action": { "SetPower": { "power": true } },
+        });
+        socket.send(Message::Text(request.to_string())).unwrap();
+        assert_eq!(
+            next_event(&mut socket),
+            json!({ "type": "result", "id": 7, "devices": 1 })
+        );
+        assert!(matches!(
+            receive(&bulb),
+            lifx_core::Message::LightSetPower {
+                level: u16::MAX,
+                ..
+            }
+        ));
+
+        let request = json!({ "id": 8, "selector": "label:Porch", "action": "TogglePower" });
+        socket.send(Message::Text(request.to_string())).unwrap();
+        let event = next_event(&mut socket);
+        assert_eq!(event["type"], "error");
+        assert_eq!(event["id"], 8);
+
+        socket
+            .send(Message::Text(json!({ "scene": "Missing" }).to_string()))
+            .unwrap();
+        assert_eq!(next_event(&mut socket)["type"], "error");
+    }
+
+    #[test]
+    fn diff_reports_changed_and_new_fields() {
+        let previous: Map<String, Value> =
+            serde_json::from_value(json!({ "power": "off", "label": "Desk" })).unwrap();
+        let current: Map<String, Value> =
+            serde_json::from_value(json!({ "power": "on", "label": "Desk", "zones": 8 })).unwrap();
+        assert_eq!(
+            Value::Object(diff(Some(&previous), &current)),
+            json!({ "power": "on", "zones": 8 })
+        );
+        assert_eq!(diff(None, &current).len(), 3);
+    }
+}
@@ -8,22 +8,35 @@ use std::{
     time::{Duration, Instant},
 };
 
+#[cfg(target_os = "linux")]
+use crate::api::dbus::DbusService;
 use crate::{
+    api::{http::HttpApiServer, mqtt::MqttBridge, osc::OscServer, ws::WebSocketApiServer},
     audio::AudioManager,
     capitalize_first_letter,
+    capture::CaptureService,
     color::{default_hsbk, DeltaColor},
     device_info::DeviceInfo,
     display_color_circle,
+    effects::{
+        AudioMapping, CaptureExclusions, EffectConfig, EffectEngine, ScreenAnalysis, ScreenMapping,
+        ScreenSession, SmoothingSettings,
+    },
+    ipc::{IpcCommand, IpcListener, IpcServer},
     listener::input_listener::InputListener,
     products::get_product_name,
-    scenes::Scene,
-    screencap::{RegionCaptureTarget, ScreenSubregion},
+    scenes::{find_scene, Scene},
+    screencap::{RegionCaptureTarget, ScreenSubregion, WindowIdentity},
+    scripting::{Script, ScriptEvent, ScriptLogLevel, ScriptRunner, ScriptTrigger, EXAMPLE_SCRIPT},
     settings::Settings,
     shortcut::{KeyboardShortcutAction, ShortcutManager},
     toggle_button,
     ui::{
         color_wheel, handle_audio, handle_eyedropper, handle_screencap, hsbk_sliders,
-        infrared_slider, matrix_grid, render_capture_target, rgb_input, zone_strip,
+        infrared_slider, matrix_grid, render_audio_mapping, render_capture_metrics,
+        render_capture_target, render_effect_controls, render_screen_analysis,
+        render_screen_mapping, render_screen_session, render_smoothing_controls, rgb_input,
+        zone_strip,
     },
     BulbInfo, LifxManager, ScreencapManager,
 };
@@ -65,6 +78,7 @@ enum TrayAction {
 // UI and window size constants
 pub const MAIN_WINDOW_SIZE: [f32; 2] = [420.0, 800.0];
 pub const ABOUT_WINDOW_SIZE: [f32; 2] = [320.0, 480.0];
+pub const SCRIPT_WINDOW_SIZE: [f32; 2] = [560.0, 640.0];
 pub const MIN_WINDOW_SIZE: [f32; 2] = [380.0, 220.0];
 
 // Icon data
@@ -75,27 +89,6 @@ pub const SUBREGION_ICON: &[u8; 218] = include_bytes!("../res/icons/square.png")
 pub const AUDIO_ICON: &[u8; 225] = include_bytes!("../res/icons/device-speaker.png");
 pub const SCREENSHOT_ICON: &[u8] = include_bytes!("../res/icons/screenshot.png");
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum WaveformMode {
-    Screencap,
-    Audio,
-}
-
-#[derive(Debug, Clone)]
-pub struct WaveformTracker {
-    pub active: bool,
-    pub last_update: Instant,
-    pub mode: WaveformMode,
-    pub region: RegionCaptureTarget,
-    pub stop_tx: Option<mpsc::Sender<()>>,
-}
-pub struct ColorChannelEntry {
-    pub tx: mpsc::Sender<HSBK>,
-    pub rx: mpsc::Receiver<HSBK>,
-    pub handle: Option<JoinHandle<()>>,
-}
-pub type ColorChannel = HashMap<u64, ColorChannelEntry>;
-
 #[derive(Deserialize, Serialize)]
 #[serde(default)]
 pub struct MantleApp {
@@ -120,10 +113,21 @@ pub struct MantleApp {
     pub search_query: String,
     pub show_about: bool,
     pub show_audio_debug: bool,
+    pub show_scripts: bool,
+    pub show_screen_session: bool,
     pub show_eyedropper: HashMap<u64, bool>,
     pub show_settings: bool,
     pub show_subregion: HashMap<u64, bool>,
     pub subregion_points: HashMap<u64, Arc<Mutex<ScreenSubregion>>>,
+    pub capture_smoothing: HashMap<u64, Arc<Mutex<SmoothingSettings>>>,
+    pub screen_mappings: HashMap<u64, Arc<Mutex<ScreenMapping>>>,
+    pub screen_analysis: HashMap<u64, Arc<Mutex<ScreenAnalysis>>>,
+    pub audio_mappings: HashMap<u64, Arc<Mutex<AudioMapping>>>,
+    /// Areas left out of screen sync, per capture target rather than per device.
+    pub capture_exclusions: Arc<Mutex<HashMap<String, CaptureExclusions>>>,
+    /// The window each device follows, so it's found again after a restart.
+    pub capture_windows: HashMap<u64, WindowIdentity>,
+    pub screen_session: Arc<Mutex<ScreenSession>>,
     #[serde(skip)]
     pub toasts: Toasts,
     #[serde(skip)]
@@ -137,21 +141,54 @@ pub struct MantleApp {
     #[serde(skip)]
     pub selected_zones: HashMap<u64, HashSet<usize>>,
     #[serde(skip)]
-    pub waveform_channel: ColorChannel,
+    pub effect_engine: EffectEngine,
     #[serde(skip)]
-    pub waveform_map: HashMap<u64, WaveformTracker>,
+    pub capture_service: CaptureService,
+    pub effect_configs: HashMap<u64, EffectConfig>,
+    #[serde(skip)]
+    pub capture_regions: HashMap<u64, RegionCaptureTarget>,
     #[serde(skip)]
     pub last_refresh: Instant,
     #[serde(skip)]
     pub last_schedule_check: Instant,
+    #[serde(skip)]
+    pub http_server: Option<HttpApiServer>,
+    #[serde(skip)]
+    pub websocket_server: Option<WebSocketApiServer>,
+    #[serde(skip)]
+    pub mqtt_bridge: Option<MqttBridge>,
+    #[serde(skip)]
+    pub osc_server: Option<OscServer>,
+    #[serde(skip)]
+    ipc_server: Option<IpcServer>,
+    #[cfg(target_os = "linux")]
+    #[serde(skip)]
+    pub dbus_service: Option<DbusService>,
+    #[serde(skip)]
+    pub script_runner: Option<ScriptRunner>,
+    #[serde(skip)]
+    pub selected_script: Option<usize>,
 }
 
 impl Default for MantleApp {
     fn default() -> Self {
         let input_listener = InputListener::new();
         let shortcut_manager = ShortcutManager::new(input_listener.clone());
-        let lifx_manager = LifxManager::new().expect("Failed to create manager");
+        // Something else may own the LIFX port; replies still arrive on an ephemeral one.
+        let lifx_manager = LifxManager::new()
+            .or_else(|e| {
+                log::error!(
+                    "Failed to bind the LIFX port, using an ephemeral one: {}",
+                    e
+                );
+                LifxManager::with_bind_addr("0.0.0.0:0")
+            })
+            .expect("Failed to create manager");
         let shortcut_handle = Some(shortcut_manager.start(lifx_manager.clone()));
+        let audio_manager = AudioManager::default();
+        let effect_engine = EffectEngine::start(lifx_manager.clone());
+        effect_engine.set_audio_source(audio_manager.clone_features());
+        effect_engine.make_active();
         Self {
             lighting_manager: lifx_manager,
             screen_manager: ScreencapManager::new().expect("Failed to create screen manager"),
@@ -166,10 +203,19 @@ impl Default for MantleApp {
             show_eyedropper: HashMap::new(),
             show_subregion: HashMap::new(),
             subregion_points: HashMap::new(),
+            capture_smoothing: HashMap::new(),
+            screen_mappings: HashMap::new(),
+            screen_analysis: HashMap::new(),
+            audio_mappings: HashMap::new(),
+            capture_exclusions: Arc::new(Mutex::new(HashMap::new())),
+            capture_windows: HashMap::new(),
+            screen_session: Arc::new(Mutex::new(ScreenSession::default())),
             settings: Settings::default(),
             selected_zones: HashMap::new(),
-            waveform_map: HashMap::new(),
-            waveform_channel: HashMap::new(),
+            effect_engine,
+            capture_service: CaptureService::start(),
+            effect_configs: HashMap::new(),
+            capture_regions: HashMap::new(),
             monitor_preview_textures: HashMap::new(),
             new_scene: Scene::new(vec![], t!("scenes.unnamed").to_string()),
             toasts: Toasts::new()
@@ -178,10 +224,21 @@ impl Default for MantleApp {
             window_visible: Arc::new(AtomicBool::new(true)),
             tray_icon: None,
             tray_event_rx: None,
-            audio_manager: AudioManager::default(),
+            audio_manager,
             show_audio_debug: false,
+            show_scripts: false,
+            show_screen_session: false,
             last_refresh: Instant::now(),
             last_schedule_check: Instant::now(),
+            http_server: None,
+            websocket_server: None,
+            mqtt_bridge: None,
+            osc_server: None,
+            ipc_server: None,
+            #[cfg(target_os = "linux")]
+            dbus_service: None,
+            script_runner: None,
+            selected_script: None,
         }
     }
 }
@@ -261,11 +318,7 @@ impl MantleApp {
         if let Some(ref rx) = self.tray_event_rx {
             while let Ok(action) = rx.try_recv() {
                 match action {
-                    TrayAction::Show => {
-                        self.window_visible.store(true, Ordering::SeqCst);
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
-                    }
+                    TrayAction::Show => self.show_window(ctx),
                     TrayAction::Hide => {
                         self.window_visible.store(false, Ordering::SeqCst);
                         ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
@@ -278,6 +331,76 @@ impl MantleApp {
         }
     }
 
+    fn show_window(&self, ctx: &egui::Context) {
+        #[cfg(windows)]
+        if !self.window_visible.load(Ordering::SeqCst) {
+            win32_show_mantle_window();
+        }
+        self.window_visible.store(true, Ordering::SeqCst);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    }
+
+    /// Accept commands from later launches and scripts on the single-instance socket.
+    pub fn attach_ipc(&mut self, listener: IpcListener, ctx: &egui::Context) {
+        let ctx = ctx.clone();
+        self.ipc_server = Some(listener.serve(move || ctx.request_repaint()));
+    }
+
+    fn handle_ipc_requests(&mut self, ctx: &egui::Context) {
+        while let Some(request) = self.ipc_server.as_ref().and_then(|s| s.try_recv()) {
+            let result = self.run_ipc_command(&request.command, ctx);
+            if let Err(e) = &result {
+                log::error!("IPC command {:?} failed: {}", request.command, e);
+            }
+            request.respond(result);
+        }
+    }
+
+    /// Carry out a command forwarded by another launch or a script.
+    pub fn run_ipc_command(
+        &mut self,
+        command: &IpcCommand,
+        ctx: &egui::Context,
+    ) -> Result<(), String> {
+        match command {
+            IpcCommand::Show => {
+                self.show_window(ctx);
+                Ok(())
+            }
+            IpcCommand::ApplyScene { name } => {
+                let scene = find_scene(&self.settings.scenes, name)
+                    .cloned()
+                    .ok_or_else(|| format!("No scene named '{}'", name))?;
+                scene
+                    .apply(&mut self.lighting_manager)
+                    .map_err(|errors| errors.join(", "))
+            }
+            IpcCommand::TogglePower => self
+                .lighting_manager
+                .toggle_power()
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            IpcCommand::RunShortcut { name } => {
+                let shortcut = self
+                    .settings
+                    .custom_shortcuts
+                    .iter()
+                    .find(|s| s.name == *name)
+                    .ok_or_else(|| format!("No shortcut named '{}'", name))?;
+                let device = shortcut
+                    .device
+                    .clone()
+                    .ok_or_else(|| format!("Shortcut '{}' has no device", name))?;
+                shortcut
+                    .action
+                    .execute(self.lighting_manager.clone(), device);
+                Ok(())
+            }
+        }
+    }
+
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         Self::configure_theme(&cc.egui_ctx);
         Self::configure_fonts(&cc.egui_ctx);
@@ -314,11 +437,29 @@ impl MantleApp {
                 ));
             }
             app.sync_auto_launch_state();
+            app.sync_http_api();
+            app.sync_websocket_api();
+            app.sync_mqtt();
+            app.sync_osc();
+            #[cfg(target_os = "linux")]
+            app.sync_dbus();
+            app.sync_scripting();
+            app.sync_safety();
+            app.sync_capture();
             app.setup_tray_icon(&cc.egui_ctx);
             return app;
         }
         let mut app = Self::default();
         app.sync_auto_launch_state();
+        app.sync_http_api();
+        app.sync_websocket_api();
+        app.sync_mqtt();
+        app.sync_osc();
+        #[cfg(target_os = "linux")]
+        app.sync_dbus();
+        app.sync_scripting();
+        app.sync_safety();
+        app.sync_capture();
         app.setup_tray_icon(&cc.egui_ctx);
         app
     }
@@ -424,7 +565,7 @@ impl MantleApp {
                 ui.vertical(|ui| {
                     ui.horizontal(|ui| {
                         let elapsed = bulb.last_seen.elapsed();
-                        let is_online = elapsed < Duration::from_secs(30);
+                        let is_online = bulb.is_online();
                         let dot_color = if is_online {
                             Color32::from_rgb(80, 200, 120)
                         } else {
@@ -565,10 +706,15 @@ impl MantleApp {
                 ui.add_space(4.0);
                 ui.horizontal(|ui| {
                     after_color = handle_eyedropper(self, ui, device).unwrap_or(after_color);
-                    after_color = handle_screencap(self, ui, device).unwrap_or(after_color);
-                    after_color = handle_audio(self, ui, device).unwrap_or(after_color);
+                    handle_screencap(self, ui, device);
+                    handle_audio(self, ui, device);
                 });
                 render_capture_target(self, ui, device);
+                render_screen_analysis(self, ui, device);
+                render_screen_mapping(self, ui, device);
+                render_audio_mapping(self, ui, device);
+                render_smoothing_controls(self, ui, device);
+                render_effect_controls(self, ui, device);
 
                 let has_infrared = match device {
                     DeviceInfo::Bulb(bulb) => bulb.features.infrared == Some(true),
@@ -1197,6 +1343,22 @@ impl MantleApp {
                 self.show_audio_debug = !self.show_audio_debug;
                 ui.close_menu();
             }
+            if ui
+                .add(egui::Button::new(t!("menu.screen_session").to_string()))
+                .on_hover_text(t!("menu.screen_session_hover").to_string())
+                .clicked()
+            {
+                self.show_screen_session = !self.show_screen_session;
+                ui.close_menu();
+            }
+            if ui
+                .add(egui::Button::new(t!("menu.scripts").to_string()))
+                .on_hover_text(t!("menu.scripts_hover").to_string())
+                .clicked()
+            {
+                self.show_scripts = !self.show_scripts;
+                ui.close_menu();
+            }
             if ui
                 .add(egui::Button::new(t!("menu.hide_to_tray").to_string()))
                 .on_hover_text(t!("menu.hide_to_tray_hover").to_string())
@@ -1422,10 +1584,232 @@ impl MantleApp {
                     ui.heading(t!("audio_debug.title").to_string());
                     ui.add_space(8.0);
                     self.audio_manager.ui(ui);
+                    ui.separator();
+                    render_capture_metrics(ui, &self.capture_service.metrics());
                 });
         }
     }
 
+    fn show_screen_session_window(&mut self, ctx: &egui::Context) {
+        let mut show_screen_session = self.show_screen_session;
+        if show_screen_session {
+            egui::Window::new(t!("session.title").to_string())
+                .default_width(SCRIPT_WINDOW_SIZE[0])
+                .open(&mut show_screen_session)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    render_screen_session(self, ui);
+                });
+            self.show_screen_session = show_screen_session;
+        }
+    }
+
+    fn show_scripts_window(&mut self, ctx: &egui::Context) {
+        let mut show_scripts = self.show_scripts;
+        if show_scripts {
+            egui::Window::new(t!("scripting.title").to_string())
+                .default_width(SCRIPT_WINDOW_SIZE[0])
+                .default_height(SCRIPT_WINDOW_SIZE[1])
+                .open(&mut show_scripts)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.render_script_picker(ui);
+                    ui.separator();
+                    if let Some(index) = self
+                        .selected_script
+                        .filter(|i| *i < self.settings.scripts.len())
+                    {
+                        self.render_script_editor(ui, index);
+                    } else {
+                        ui.label(t!("scripting.no_script").to_string());
+                    }
+                    ui.separator();
+                    self.render_script_log(ui);
+                });
+            self.show_scripts = show_scripts;
+        }
+    }
+
+    fn render_script_picker(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let selected = self
+                .selected_script
+                .and_then(|i| self.settings.scripts.get(i))
+                .map(|script| script.name.clone())
+                .unwrap_or_default();
+            egui::ComboBox::from_label(t!("scripting.script").to_string())
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (i, script) in self.settings.scripts.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected_script, Some(i), &script.name);
+                    }
+                });
+            if ui.button(t!("scripting.new").to_string()).clicked() {
+                self.settings.scripts.push(Script {
+                    name: t!(
+                        "scripting.unnamed",
+                        number = self.settings.scripts.len() + 1
+                    )
+                    .to_string(),
+                    source: EXAMPLE_SCRIPT.to_string(),
+                    ..Default::default()
+                });
+                self.selected_script = Some(self.settings.scripts.len() - 1);
+            }
+            if let Some(index) = self
+                .selected_script
+                .filter(|i| *i < self.settings.scripts.len())
+            {
+                if ui.button(t!("scripting.delete").to_string()).clicked() {
+                    let script = self.settings.scripts.remove(index);
+                    if let Some(runner) = &self.script_runner {
+                        runner.host().stop(&script.name);
+                    }
+                    self.selected_script = None;
+                }
+            }
+        });
+    }
+
+    fn render_script_editor(&mut self, ui: &mut Ui, index: usize) {
+        let script = &mut self.settings.scripts[index];
+        egui::Grid::new("script_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label(t!("scripting.name").to_string());
+                ui.text_edit_singleline(&mut script.name);
+                ui.end_row();
+
+                ui.label(t!("scripting.trigger_label").to_string());
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("script_trigger")
+                        .selected_text(script.trigger.to_string())
+                        .show_ui(ui, |ui| {
+                            for trigger in [
+                                ScriptTrigger::Manual,
+                                ScriptTrigger::Interval { seconds: 60 },
+                                ScriptTrigger::Daily { hour: 8, minute: 0 },
+                                ScriptTrigger::DeviceChange,
+                            ] {
+                                let selected = std::mem::discriminant(&script.trigger)
+                                    == std::mem::discriminant(&trigger);
+                                if ui.selectable_label(selected, trigger.to_string()).clicked()
+                                    && !selected
+                                {
+                                    script.trigger = trigger;
+                                }
+                            }
+                        });
+                    match &mut script.trigger {
+                        ScriptTrigger::Interval { seconds } => {
+                            ui.label(t!("scripting.every").to_string());
+                            ui.add(
+                                egui::DragValue::new(seconds)
+                                    .range(1..=86_400)
+                                    .suffix(t!("scripting.seconds_suffix").to_string()),
+                            );
+                        }
+                        ScriptTrigger::Daily { hour, minute } => {
+                            ui.label(t!("schedule.at").to_string());
+                            ui.add(
+                                egui::DragValue::new(hour)
+                                    .range(0..=23)
+                                    .suffix(t!("schedule.hour_suffix").to_string()),
+                            );
+                            ui.label(t!("schedule.colon").to_string());
+                            ui.add(
+                                egui::DragValue::new(minute)
+                                    .range(0..=59)
+                                    .suffix(t!("schedule.minute_suffix").to_string()),
+                            );
+                        }
+                        ScriptTrigger::Manual | ScriptTrigger::DeviceChange => {}
+                    }
+                    ui.checkbox(&mut script.enabled, t!("scripting.enabled").to_string())
+                        .on_hover_text(t!("scripting.enabled_hover").to_string());
+                });
+                ui.end_row();
+            });
+
+        ui.add_space(4.0);
+        ui.label(t!("scripting.api_help").to_string());
+        egui::ScrollArea::vertical()
+            .id_salt("script_source")
+            .max_height(280.0)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut script.source)
+                        .code_editor()
+                        .desired_rows(14)
+                        .desired_width(f32::INFINITY),
+                );
+            });
+
+        let script = script.clone();
+        let Some(host) = self.script_runner.as_ref().map(|r| r.host().clone()) else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let running = host.is_running(&script.name);
+            if ui
+                .add_enabled(!running, egui::Button::new(t!("scripting.run").to_string()))
+                .clicked()
+            {
+                host.set_scripts(&self.settings.scripts);
+                host.run(script.clone(), ScriptEvent::manual());
+            }
+            if ui
+                .add_enabled(running, egui::Button::new(t!("scripting.stop").to_string()))
+                .clicked()
+            {
+                host.stop(&script.name);
+            }
+            if running {
+                ui.spinner();
+                ui.label(t!("scripting.running").to_string());
+                ui.ctx().request_repaint_after(Duration::from_millis(200));
+            }
+        });
+    }
+
+    fn render_script_log(&mut self, ui: &mut Ui) {
+        let Some(log) = self.script_runner.as_ref().map(|r| r.host().log().clone()) else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.strong(t!("scripting.log").to_string());
+            if ui
+                .small_button(t!("scripting.clear_log").to_string())
+                .clicked()
+            {
+                log.clear();
+            }
+        });
+        egui::ScrollArea::vertical()
+            .id_salt("script_log")
+            .max_height(160.0)
+            .stick_to_bottom(true)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for entry in log.entries() {
+                    let secs = entry.timestamp % 86400;
+                    let text = format!(
+                        "{:02}:{:02}:{:02} [{}] {}",
+                        secs / 3600,
+                        secs / 60 % 60,
+                        secs % 60,
+                        entry.script,
+                        entry.message
+                    );
+                    let text = RichText::new(text).monospace();
+                    match entry.level {
+                        ScriptLogLevel::Info => ui.label(text),
+                        ScriptLogLevel::Error => ui.label(text.color(Color32::LIGHT_RED)),
+                    };
+                }
+            });
+    }
+
     fn check_scheduled_scenes(&mut self) {
         use std::time::SystemTime;
         let now = SystemTime::now()
@@ -1468,6 +1852,178 @@ impl MantleApp {
         }
     }
 
+    /// Start or stop the local HTTP API to match the settings, and keep its scenes current.
+    pub fn sync_http_api(&mut self) {
+        let wanted = &self.settings.http_api;
+        if !wanted.enabled {
+            self.http_server = None;
+            return;
+        }
+        if let Some(server) = &self.http_server {
+            if server.settings() == wanted {
+                server.set_scenes(&self.settings.scenes);
+                return;
+            }
+        }
+        // Drop the old server first so a restart on the same address can bind.
+        self.http_server = None;
+        match HttpApiServer::start(
+            wanted,
+            self.lighting_manager.clone(),
+            self.settings.scenes.clone(),
+        ) {
+            Ok(server) => self.http_server = Some(server),
+            Err(e) => {
+                log::error!("Failed to start HTTP API: {}", e);
+                self.settings.http_api.enabled = false;
+                self.error_toast(&t!("error.http_api_start", error = e.to_string()));
+            }
+        }
+    }
+
+    /// Start or stop the WebSocket API to match the settings, and keep its scenes current.
+    pub fn sync_websocket_api(&mut self) {
+        let wanted = &self.settings.websocket_api;
+        if !wanted.enabled {
+            self.websocket_server = None;
+            return;
+        }
+        if let Some(server) = &self.websocket_server {
+            if server.settings() == wanted {
+                server.set_scenes(&self.settings.scenes);
+                return;
+            }
+        }
+        self.websocket_server = None;
+        match WebSocketApiServer::start(
+            wanted,
+            self.lighting_manager.clone(),
+            self.settings.scenes.clone(),
+        ) {
+            Ok(server) => self.websocket_server = Some(server),
+            Err(e) => {
+                log::error!("Failed to start WebSocket API: {}", e);
+                self.settings.websocket_api.enabled = false;
+                self.error_toast(&t!("error.websocket_api_start", error = e.to_string()));
+            }
+        }
+    }
+
+    /// Start or stop the MQTT bridge to match the settings, and keep its scenes current.
+    pub fn sync_mqtt(&mut self) {
+        let wanted = &self.settings.mqtt;
+        if !wanted.enabled {
+            self.mqtt_bridge = None;
+            return;
+        }
+        if let Some(bridge) = &self.mqtt_bridge {
+            if bridge.settings() == wanted {
+                bridge.set_scenes(&self.settings.scenes);
+                return;
+            }
+        }
+        self.mqtt_bridge = None;
+        match MqttBridge::start(
+            wanted,
+            self.lighting_manager.clone(),
+            self.settings.scenes.clone(),
+        ) {
+            Ok(bridge) => self.mqtt_bridge = Some(bridge),
+            Err(e) => {
+                log::error!("Failed to start MQTT bridge: {}", e);
+                self.settings.mqtt.enabled = false;
+                self.error_toast(&t!("error.mqtt_start", error = e.to_string()));
+            }
+        }
+    }
+
+    /// Start or stop the OSC server to match the settings, and keep its scenes current.
+    pub fn sync_osc(&mut self) {
+        let wanted = &self.settings.osc;
+        if !wanted.enabled {
+            self.osc_server = None;
+            return;
+        }
+        if let Some(server) = &self.osc_server {
+            if server.settings() == wanted {
+                server.set_scenes(&self.settings.scenes);
+                return;
+            }
+        }
+        // Drop the old server first so a restart on the same port can bind.
+        self.osc_server = None;
+        match OscServer::start(
+            wanted,
+            self.lighting_manager.clone(),
+            self.settings.scenes.clone(),
+        ) {
+            Ok(server) => self.osc_server = Some(server),
+            Err(e) => {
+                log::error!("Failed to start OSC server: {}", e);
+                self.settings.osc.enabled = false;
+                self.error_toast(&t!("error.osc_start", error = e.to_string()));
+            }
+        }
+    }
+
+    /// Start the script runner if needed, and keep its scripts and scenes current.
+    pub fn sync_scripting(&mut self) {
+        match &self.script_runner {
+            Some(runner) => {
+                runner.host().set_scripts(&self.settings.scripts);
+                runner.host().set_scenes(&self.settings.scenes);
+            }
+            None => {
+                self.script_runner = Some(ScriptRunner::start(
+                    self.lighting_manager.clone(),
+                    self.settings.scripts.clone(),
+                    self.settings.scenes.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Apply the photosensitivity limits from the settings to everything sent to the lights.
+    pub fn sync_safety(&mut self) {
+        self.lighting_manager
+            .set_safety_limits(self.settings.safety);
+    }
+
+    /// Apply the screen capture settings to the capture thread.
+    pub fn sync_capture(&mut self) {
+        self.capture_service.set_settings(self.settings.capture);
+    }
+
+    /// Start or stop the D-Bus service to match the settings, and keep its scenes current.
+    #[cfg(target_os = "linux")]
+    pub fn sync_dbus(&mut self) {
+        let wanted = &self.settings.dbus;
+        if !wanted.enabled {
+            self.dbus_service = None;
+            return;
+        }
+        if let Some(service) = &self.dbus_service {
+            if service.settings() == wanted {
+                service.set_scenes(&self.settings.scenes);
+                return;
+            }
+        }
+        // Release the bus name before the new service claims it.
+        self.dbus_service = None;
+        match DbusService::start(
+            wanted,
+            self.lighting_manager.clone(),
+            self.settings.scenes.clone(),
+        ) {
+            Ok(service) => self.dbus_service = Some(service),
+            Err(e) => {
+                log::error!("Failed to start D-Bus service: {}", e);
+                self.settings.dbus.enabled = false;
+                self.error_toast(&t!("error.dbus_start", error = e.to_string()));
+            }
+        }
+    }
+
     fn show_toasts(&mut self, ctx: &egui::Context) {
         self.toasts.show(ctx);
     }
@@ -1514,6 +2070,7 @@ impl eframe::App for MantleApp {
         puffin::GlobalProfiler::lock().new_frame();
 
         self.handle_tray_events(ctx);
+        self.handle_ipc_requests(ctx);
         ctx.request_repaint_after(Duration::from_millis(self.settings.refresh_rate_ms));
 
         if Instant::now() - self.lighting_manager.last_discovery
@@ -1533,6 +2090,13 @@ impl eframe::App for MantleApp {
         }
         if self.last_schedule_check.elapsed() >= Duration::from_secs(1) {
             self.check_scheduled_scenes();
+            self.sync_http_api();
+            self.sync_websocket_api();
+            self.sync_mqtt();
+            self.sync_osc();
+            #[cfg(target_os = "linux")]
+            self.sync_dbus();
+            self.sync_scripting();
             self.last_schedule_check = Instant::now();
         }
 
@@ -1544,6 +2108,8 @@ impl eframe::App for MantleApp {
         self.update_ui(ctx);
         self.show_about_window(ctx);
         self.show_audio_debug_window(ctx);
+        self.show_screen_session_window(ctx);
+        self.show_scripts_window(ctx);
         self.settings_ui(ctx);
         self.show_toasts(ctx);
     }
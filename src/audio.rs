@@ -3,22 +3,195 @@ use cpal::{
     Host,
 };
 use eframe::egui::{self};
-use egui_plot::{Legend, Line, PlotPoints};
+use egui_plot::{Bar, BarChart, Legend, Line, PlotPoints, VLine};
 use lifx_core::HSBK;
-use rustfft::{num_complex::Complex, FftPlanner};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 use std::sync::{Arc, Mutex};
-
-use crate::color::DEFAULT_KELVIN;
+use std::time::Duration;
+
+use crate::color::{DeltaColor, DEFAULT_KELVIN};
+use crate::effects::{
+    spectrum::{
+        amplitude_to_level, log_band_edges, pulse_level, BeatDetector, Onset, BAND_COUNT,
+        MAX_FREQUENCY, MIN_FREQUENCY,
+    },
+    visualizer::{render_bars, render_spectrogram, SpectrumHistory},
+    AudioMapping, ColorSmoother, DeviceLayout, Effect, EffectInputs, Frame, SmoothingSettings,
+    SpectralFeatures,
+};
 use rust_i18n::t;
 
 pub const AUDIO_BUFFER_DEFAULT: usize = 48000;
+/// Samples per analysis frame, about 43 ms at 48 kHz with bins 23 Hz apart.
+pub const FRAME_SIZE: usize = 2048;
+/// Frames start this many samples apart, so each overlaps the previous one by half.
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+/// Seconds of onsets kept for the debug plot.
+const ONSET_HISTORY: f64 = 5.0;
+/// Hue step of `AudioMapping::HueStep` on each beat, a twelfth of the wheel.
+const HUE_STEP: u16 = u16::MAX / 12;
+
+/// Average the channels of interleaved `samples` into one.
+fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
 
-fn to_complex(buffer: &[f32]) -> Vec<Complex<f32>> {
-    buffer.iter().copied().map(|v| v.into()).collect()
+/// Hann window of `size` samples, which keeps a frame's cut-off ends from smearing energy
+/// across the spectrum.
+fn hann_window(size: usize) -> Vec<f32> {
+    (0..size)
+        .map(|index| {
+            let phase = 2.0 * std::f32::consts::PI * index as f32 / size as f32;
+            0.5 - 0.5 * phase.cos()
+        })
+        .collect()
 }
 
-fn to_real_f32(buffer: &[Complex<f32>]) -> Vec<f32> {
-    buffer.iter().map(|value| value.re).collect()
+/// Turns a mono stream into [`SpectralFeatures`], one Hann-windowed frame every
+/// `HOP_SIZE` samples, and looks for beats in them. The FFT is planned once and its
+/// buffers are reused.
+pub struct SpectralAnalyzer {
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// FFT bins of each band, lowest first.
+    bands: Vec<Range<usize>>,
+    /// Samples of the next frame, which starts with the end of the last one.
+    pending: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    features: SpectralFeatures,
+    detector: BeatDetector,
+    /// Frames analyzed so far, which keep the time.
+    frames: u64,
+    /// Time and onset of each frame analyzed since they were last taken.
+    onsets: Vec<(f64, Onset)>,
+}
+
+impl SpectralAnalyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FRAME_SIZE);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let bin_hz = sample_rate as f32 / FRAME_SIZE as f32;
+        let top = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let last_bin = FRAME_SIZE / 2;
+        let edges = log_band_edges(BAND_COUNT, MIN_FREQUENCY, top);
+        // Low bands can be narrower than a bin; those take the bin they fall in.
+        let bands = edges
+            .windows(2)
+            .map(|edge| {
+                let start = ((edge[0] / bin_hz).ceil() as usize).min(last_bin);
+                let end = ((edge[1] / bin_hz).ceil() as usize).min(last_bin + 1);
+                if end > start {
+                    start..end
+                } else {
+                    let bin = ((edge[0] / bin_hz) as usize).min(last_bin);
+                    bin..bin + 1
+                }
+            })
+            .collect();
+        SpectralAnalyzer {
+            sample_rate,
+            fft,
+            window: hann_window(FRAME_SIZE),
+            bands,
+            pending: Vec::with_capacity(FRAME_SIZE * 2),
+            buffer: vec![Complex::default(); FRAME_SIZE],
+            scratch,
+            features: SpectralFeatures::default(),
+            detector: BeatDetector::default(),
+            frames: 0,
+            onsets: Vec::new(),
+        }
+    }
+
+    /// Add mono samples, analyzing every frame they complete. Returns whether the
+    /// features changed.
+    pub fn push(&mut self, samples: &[f32]) -> bool {
+        self.pending.extend_from_slice(samples);
+        let mut analyzed = false;
+        while self.pending.len() >= FRAME_SIZE {
+            self.analyze_frame();
+            self.pending.drain(..HOP_SIZE);
+            analyzed = true;
+        }
+        analyzed
+    }
+
+    pub fn features(&self) -> &SpectralFeatures {
+        &self.features
+    }
+
+    /// The time and onset of every frame analyzed since the last call.
+    pub fn take_onsets(&mut self) -> Vec<(f64, Onset)> {
+        std::mem::take(&mut self.onsets)
+    }
+
+    fn analyze_frame(&mut self) {
+        let frame = &self.pending[..FRAME_SIZE];
+        let rms =
+            (frame.iter().map(|sample| sample * sample).sum::<f32>() / FRAME_SIZE as f32).sqrt();
+        for ((bin, sample), weight) in self.buffer.iter_mut().zip(frame).zip(&self.window) {
+            *bin = Complex::new(sample * weight, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // Amplitudes scaled so a full-scale sine peaks at 1.0 in its bin.
+        let window_sum: f32 = self.window.iter().sum();
+        let amplitudes: Vec<f32> = self.buffer[..=FRAME_SIZE / 2]
+            .iter()
+            .map(|bin| bin.norm() * 2.0 / window_sum)
+            .collect();
+        let bin_hz = self.sample_rate as f32 / FRAME_SIZE as f32;
+        // The DC bin carries no pitch, so it's left out of the peak and centroid.
+        let peak_frequency = amplitudes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(bin, _)| bin as f32 * bin_hz)
+            .unwrap_or_default();
+        let (weighted, total) = amplitudes.iter().enumerate().skip(1).fold(
+            (0.0, 0.0),
+            |(weighted, total), (bin, amplitude)| {
+                let power = amplitude * amplitude;
+                (weighted + bin as f32 * bin_hz * power, total + power)
+            },
+        );
+        let centroid = if total > 0.0 { weighted / total } else { 0.0 };
+        let bands: Vec<f32> = self
+            .bands
+            .iter()
+            .map(|bins| {
+                let power: f32 = amplitudes[bins.clone()].iter().map(|a| a * a).sum();
+                amplitude_to_level(power.sqrt())
+            })
+            .collect();
+        let time = self.frames as f64 * HOP_SIZE as f64 / self.sample_rate as f64;
+        self.frames += 1;
+        let onset = self.detector.push(time, &bands);
+        self.onsets.push((time, onset));
+        self.features = SpectralFeatures {
+            sample_rate: self.sample_rate,
+            bands,
+            rms,
+            peak_frequency,
+            centroid,
+            time,
+            beats: self.features.beats + onset.beat as u64,
+            last_beat: self.detector.last_beat(),
+            bpm: self.detector.bpm(),
+        };
+    }
 }
 
 /// Takes every `factor`-th element from the buffer, making  the size of the output buffer
@@ -42,7 +215,11 @@ pub struct AudioManager {
     configuration: Option<cpal::StreamConfig>,
     current_device: Option<cpal::Device>,
     host: Host,
+    /// The latest mono samples, for the debug plots.
     samples_buffer: Arc<Mutex<Vec<f32>>>,
+    features: Arc<Mutex<SpectralFeatures>>,
+    /// Time and onset of the latest frames, for the debug plots.
+    onsets: Arc<Mutex<VecDeque<(f64, Onset)>>>,
     stream: Option<cpal::Stream>,
 }
 
@@ -56,6 +233,8 @@ impl Clone for AudioManager {
             configuration: self.configuration.clone(),
             stream: None,
             samples_buffer: Arc::clone(&self.samples_buffer),
+            features: Arc::clone(&self.features),
+            onsets: Arc::clone(&self.onsets),
         }
     }
 }
@@ -76,11 +255,51 @@ impl Default for AudioManager {
             configuration,
             stream: None,
             samples_buffer: Arc::new(Mutex::new(Vec::new())),
+            features: Arc::new(Mutex::new(SpectralFeatures::default())),
+            onsets: Arc::new(Mutex::new(VecDeque::new())),
         }
     }
 }
 
 impl AudioManager {
+    /// Handles the interleaved samples of each stream callback: mixes them down to mono,
+    /// keeps the latest `max_size` for the plots and publishes their analysis and beats.
+    fn sample_handler(
+        &self,
+        config: &cpal::StreamConfig,
+        max_size: usize,
+    ) -> impl FnMut(&[f32]) + Send + 'static {
+        let buffer = Arc::clone(&self.samples_buffer);
+        let features = Arc::clone(&self.features);
+        let onsets = Arc::clone(&self.onsets);
+        let channels = config.channels as usize;
+        let mut analyzer = SpectralAnalyzer::new(config.sample_rate.0);
+        move |data: &[f32]| {
+            let mono = downmix(data, channels);
+            if analyzer.push(&mono) {
+                if let Ok(mut features) = features.lock() {
+                    features.clone_from(analyzer.features());
+                }
+                if let Ok(mut onsets) = onsets.lock() {
+                    onsets.extend(analyzer.take_onsets());
+                    let latest = onsets.back().map_or(0.0, |(time, _)| *time);
+                    while onsets
+                        .front()
+                        .is_some_and(|(time, _)| latest - time > ONSET_HISTORY)
+                    {
+                        onsets.pop_front();
+                    }
+                }
+            }
+            let mut buffer = buffer.lock().unwrap();
+            buffer.extend_from_slice(&mono);
+            if buffer.len() > max_size {
+                let excess = buffer.len() - max_size;
+                buffer.drain(0..excess);
+            }
+        }
+    }
+
     pub fn build_output_stream(
         &mut self,
         max_buffer_size: &usize,
@@ -95,19 +314,11 @@ impl AudioManager {
             .as_ref()
             .ok_or(cpal::BuildStreamError::InvalidArgument)?;
 
-        let buffer_clone = Arc::clone(&self.samples_buffer);
-        let max_size = *max_buffer_size;
+        let mut handle_samples = self.sample_handler(config, *max_buffer_size);
 
         let stream = device.build_output_stream(
             config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut buffer = buffer_clone.lock().unwrap();
-                buffer.extend_from_slice(data);
-                if buffer.len() > max_size {
-                    let excess = buffer.len() - max_size;
-                    buffer.drain(0..excess);
-                }
-            },
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| handle_samples(data),
             move |err| {
                 log::error!("an error occurred on the output audio stream: {}", err);
             },
@@ -133,19 +344,11 @@ impl AudioManager {
             .as_ref()
             .ok_or(cpal::BuildStreamError::InvalidArgument)?;
 
-        let buffer_clone = Arc::clone(&self.samples_buffer);
-        let max_size = *max_buffer_size;
+        let mut handle_samples = self.sample_handler(config, *max_buffer_size);
 
         let stream = device.build_input_stream(
             config,
-            move |data: &[f32], _| {
-                let mut buffer = buffer_clone.lock().unwrap();
-                buffer.extend_from_slice(data);
-                if buffer.len() > max_size {
-                    let excess = buffer.len() - max_size;
-                    buffer.drain(0..excess);
-                }
-            },
+            move |data: &[f32], _| handle_samples(data),
             move |err| {
                 log::error!("an error occurred on the input audio stream: {}", err);
             },
@@ -157,95 +360,11 @@ impl AudioManager {
         Ok(())
     }
 
-    fn fft(samples: &[f32]) -> Vec<Complex<f32>> {
-        let mut buffer = to_complex(samples);
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(buffer.len());
-        fft.process(&mut buffer);
-        buffer
-    }
-
-    pub fn fft_real(spectrum: &[f32]) -> Vec<f32> {
-        let buffer = Self::fft(spectrum);
-        to_real_f32(&buffer[0..buffer.len() / 2])
-    }
-
-    /// Compute the power spectrum of the given samples, using the FFT
-    /// and returning the squared magnitude of each frequency component.
-    pub fn power_spectrum(samples: &[f32]) -> Vec<f32> {
-        let buffer = Self::fft(samples);
-        buffer.iter().map(|value| value.norm_sqr()).collect()
-    }
-
-    /// Compute the power of the given samples, which is the average of the power spectrum.
-    pub fn power(samples: &[f32]) -> u16 {
-        let power_spectrum = Self::power_spectrum(samples);
-        let avg_power = power_spectrum.iter().sum::<f32>() / power_spectrum.len() as f32;
-        (avg_power.sqrt() * u16::MAX as f32) as u16
-    }
-
-    /// Convert the given samples to an HSBK color, using the signal power as the brightness
-    /// and the dominant frequency as the hue.
-    pub fn samples_to_hsbk(samples: Vec<f32>) -> HSBK {
-        let value = Self::power(&samples);
-
-        HSBK {
-            hue: Self::freq_to_hue(&samples),
-            saturation: u16::MAX,
-            brightness: value,
-            kelvin: DEFAULT_KELVIN,
-        }
-    }
-
-    /// Convert the given samples to an HSBK color, using the frequency centroid as the hue
-    pub fn freq_to_hue(samples: &[f32]) -> u16 {
-        let spectrum = Self::fft(samples);
-        let sample_rate = AUDIO_BUFFER_DEFAULT as f32;
-        let dominant_freq_hz = spectrum
-            .iter()
-            .enumerate()
-            .max_by(|(_, a), (_, b)| a.norm_sqr().partial_cmp(&b.norm_sqr()).unwrap())
-            .map(|(index, _)| index as f32 * sample_rate / spectrum.len() as f32)
-            .unwrap_or_default();
-        let max_freq = sample_rate / 2.0;
-        ((dominant_freq_hz / max_freq) * u16::MAX as f32) as u16
-    }
-
-    /// Compute the frequency centroid of the given samples, returning an HSBK color
-    /// with the hue set to the centroid frequency.
-    /// The brightness is set to the square root of the total power, and the saturation is set to
-    /// the maximum value.
-    pub fn freq_centroid(samples: &[f32]) -> HSBK {
-        let power_spectrum = AudioManager::power_spectrum(samples);
-
-        let total_power: f32 = power_spectrum.iter().sum();
-        let brightness = (total_power.sqrt().min(u16::MAX as f32)) as u16;
-
-        let sample_rate = AUDIO_BUFFER_DEFAULT;
-        let fft_size = power_spectrum.len() * 2;
-        let bin_size_hz: f32 = (sample_rate / fft_size) as f32;
-
-        let mut weighted_sum = 0.0;
-        let mut mag_sum = 0.0;
-        for (i, mag) in power_spectrum.iter().enumerate() {
-            let freq = i as f32 * bin_size_hz;
-            weighted_sum += freq * mag;
-            mag_sum += mag;
-        }
-        let centroid_freq = if mag_sum > 0.0 {
-            weighted_sum / mag_sum
-        } else {
-            0.0
-        };
-        let max_freq = sample_rate as f32 / 2.0;
-        let hue = ((centroid_freq / max_freq) as u16) * u16::MAX;
-
-        HSBK {
-            hue,
-            saturation: u16::MAX,
-            brightness,
-            kelvin: DEFAULT_KELVIN,
-        }
+    /// Sample rate of the stream in Hz, if there is an audio device.
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.configuration
+            .as_ref()
+            .map(|config| config.sample_rate.0)
     }
 
     /// List all audio output devices available on the system.
@@ -268,17 +387,65 @@ impl AudioManager {
         Arc::clone(&self.samples_buffer)
     }
 
-    /// Draw plots of the audio samples and their FFT.
+    /// The analysis of the stream, updated as audio arrives.
+    pub fn clone_features(&self) -> Arc<Mutex<SpectralFeatures>> {
+        Arc::clone(&self.features)
+    }
+
+    /// The latest analysis of the stream, with its beat count and tempo.
+    pub fn features(&self) -> SpectralFeatures {
+        self.features
+            .lock()
+            .map(|features| features.clone())
+            .unwrap_or_default()
+    }
+
+    /// Times of the beats found in the last few seconds, in the seconds of
+    /// [`SpectralFeatures::time`].
+    pub fn recent_beats(&self) -> Vec<f64> {
+        self.onsets
+            .lock()
+            .map(|onsets| {
+                onsets
+                    .iter()
+                    .filter(|(_, onset)| onset.beat)
+                    .map(|(time, _)| *time)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Draw plots of the audio samples and their band levels.
     pub fn ui(&self, ui: &mut eframe::egui::Ui) {
         let audio_data = self.get_samples_data();
+        let features = self.features();
+        let onsets: Vec<(f64, Onset)> = self
+            .onsets
+            .lock()
+            .map(|onsets| onsets.iter().copied().collect())
+            .unwrap_or_default();
 
-        if let Ok(ref data) = audio_data {
-            let spectrum = Self::fft_real(data);
-            let color = Self::power(data);
+        if let (Ok(ref data), false) = (audio_data, features.is_empty()) {
             egui::ScrollArea::vertical().show(ui, |ui| {
-                // show current color
                 ui.label(
-                    t!("audio_debug.current_color", color = format!("{:?}", color)).to_string(),
+                    t!(
+                        "audio_debug.features",
+                        level = format!("{:.0}", features.level() * 100.0),
+                        peak = format!("{:.0}", features.peak_frequency),
+                        centroid = format!("{:.0}", features.centroid),
+                        rate = features.sample_rate
+                    )
+                    .to_string(),
+                );
+                ui.label(
+                    t!(
+                        "audio_debug.tempo",
+                        bpm = features
+                            .bpm
+                            .map_or("–".to_string(), |bpm| format!("{:.0}", bpm)),
+                        beats = features.beats
+                    )
+                    .to_string(),
                 );
                 egui_plot::Plot::new("Audio Samples")
                     .allow_zoom(false)
@@ -289,14 +456,51 @@ impl AudioManager {
                         let lines = PlotPoints::from_ys_f32(&subsample(data, 10));
                         plot_ui.line(Line::new(lines));
                     });
-                egui_plot::Plot::new("FFT")
+                egui_plot::Plot::new("Bands")
                     .allow_zoom(false)
                     .allow_drag(false)
                     .allow_scroll(false)
+                    .include_y(0.0)
+                    .include_y(1.0)
                     .legend(Legend::default())
                     .show(ui, |plot_ui| {
-                        let lines = PlotPoints::from_ys_f32(&subsample(&spectrum, 10));
-                        plot_ui.line(Line::new(lines));
+                        let bars = features
+                            .bands
+                            .iter()
+                            .enumerate()
+                            .map(|(band, level)| Bar::new(band as f64, *level as f64))
+                            .collect();
+                        plot_ui.bar_chart(BarChart::new(bars));
+                    });
+                egui_plot::Plot::new("Onsets")
+                    .allow_zoom(false)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .include_y(0.0)
+                    .legend(Legend::default())
+                    .show(ui, |plot_ui| {
+                        let points = |value: fn(&Onset) -> f32| {
+                            PlotPoints::from_iter(
+                                onsets
+                                    .iter()
+                                    .map(|(time, onset)| [*time, value(onset) as f64]),
+                            )
+                        };
+                        plot_ui.line(
+                            Line::new(points(|onset| onset.flux))
+                                .name(t!("audio_debug.flux").to_string()),
+                        );
+                        plot_ui.line(
+                            Line::new(points(|onset| onset.threshold))
+                                .name(t!("audio_debug.threshold").to_string()),
+                        );
+                        for (time, _) in onsets.iter().filter(|(_, onset)| onset.beat) {
+                            plot_ui.vline(
+                                VLine::new(*time)
+                                    .color(egui::Color32::from_rgb(230, 160, 50))
+                                    .name(t!("audio_debug.beats").to_string()),
+                            );
+                        }
                     });
             });
         } else {
@@ -305,27 +509,164 @@ impl AudioManager {
     }
 }
 
+/// What an [`AudioEffect`] reads each frame. The mapping and smoothing are shared with the
+/// device panel, so changes apply while the effect runs.
+#[derive(Clone, Default)]
+pub struct AudioSyncOptions {
+    pub mapping: Arc<Mutex<AudioMapping>>,
+    pub smoothing: Arc<Mutex<SmoothingSettings>>,
+    /// Colors of each light in each saved scene, for `AudioMapping::SceneChange`.
+    pub scenes: Vec<HashMap<u64, HSBK>>,
+}
+
+/// Sets every light from the audio as its [`AudioMapping`] says: to the color of the
+/// spectrum, see [`SpectralFeatures::to_hsbk`], smoothed over time, to a pulse, hue step
+/// or scene on each beat, or to bars or a spectrogram across the zones of multizone lights.
+pub struct AudioEffect {
+    interval: Duration,
+    options: AudioSyncOptions,
+    smoother: ColorSmoother,
+    /// Beat count of the last frame, to tell when a new beat came.
+    beats: Option<u64>,
+    hue: u16,
+    scene: usize,
+    history: SpectrumHistory,
+}
+
+impl AudioEffect {
+    pub const KIND: &'static str = "audio";
+
+    pub fn new(interval: Duration, options: AudioSyncOptions) -> Self {
+        AudioEffect {
+            interval,
+            options,
+            smoother: ColorSmoother::default(),
+            beats: None,
+            hue: 0,
+            scene: 0,
+            history: SpectrumHistory::default(),
+        }
+    }
+}
+
+impl Effect for AudioEffect {
+    fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn transition_ms(&self) -> u32 {
+        (self.interval.as_millis() / 2) as u32
+    }
+
+    fn render(
+        &mut self,
+        _time: Duration,
+        devices: &[DeviceLayout],
+        inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let audio = &inputs.audio;
+        if audio.is_empty() {
+            return Vec::new();
+        }
+        let on_beat = self.beats.is_some_and(|beats| audio.beats > beats);
+        self.beats = Some(audio.beats);
+        self.history.push(audio);
+        let mapping = self.options.mapping.lock().map(|m| *m).unwrap_or_default();
+        let color = match mapping {
+            AudioMapping::Spectrum => {
+                let smoothing = self
+                    .options
+                    .smoothing
+                    .lock()
+                    .map(|s| *s)
+                    .unwrap_or_default();
+                let sample = DeltaColor {
+                    next: audio.to_hsbk(),
+                    duration: Some(self.transition_ms()),
+                };
+                return devices
+                    .iter()
+                    .filter_map(|device| {
+                        let delta = self.smoother.update(&smoothing, device.target, sample)?;
+                        Some((device.target, Frame::Whole(delta.next)))
+                    })
+                    .collect();
+            }
+            AudioMapping::BeatPulse => {
+                let level = audio
+                    .since_beat()
+                    .map_or(0.0, |since| pulse_level(since, audio.bpm));
+                HSBK {
+                    brightness: (level * u16::MAX as f32) as u16,
+                    ..audio.to_hsbk()
+                }
+            }
+            AudioMapping::HueStep => {
+                if on_beat {
+                    self.hue = self.hue.wrapping_add(HUE_STEP);
+                }
+                HSBK {
+                    hue: self.hue,
+                    saturation: u16::MAX,
+                    brightness: u16::MAX,
+                    kelvin: DEFAULT_KELVIN,
+                }
+            }
+            AudioMapping::SceneChange => {
+                if self.options.scenes.is_empty() {
+                    return Vec::new();
+                }
+                if on_beat {
+                    self.scene = (self.scene + 1) % self.options.scenes.len();
+                }
+                let scene = &self.options.scenes[self.scene % self.options.scenes.len()];
+                // Lights the scene leaves out keep their colors.
+                return devices
+                    .iter()
+                    .filter_map(|device| {
+                        let color = scene.get(&device.target)?;
+                        Some((device.target, Frame::Whole(*color)))
+                    })
+                    .collect();
+            }
+            AudioMapping::Bars | AudioMapping::MirroredBars | AudioMapping::Spectrogram => {
+                // Single-zone lights show the color of the spectrum instead.
+                return devices
+                    .iter()
+                    .map(|device| {
+                        let zones = match mapping {
+                            AudioMapping::Spectrogram => {
+                                render_spectrogram(device.zones, &self.history)
+                            }
+                            _ => render_bars(
+                                device.zones,
+                                &audio.bands,
+                                mapping == AudioMapping::MirroredBars,
+                            ),
+                        };
+                        let frame = zones.map_or(Frame::Whole(audio.to_hsbk()), Frame::Zones);
+                        (device.target, frame)
+                    })
+                    .collect();
+            }
+        };
+        devices
+            .iter()
+            .map(|device| (device.target, Frame::Whole(color)))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use cpal::BuildStreamError;
     use std::sync::{Arc, Mutex};
 
-    #[test]
-    fn test_to_complex_empty() {
-        let buffer: Vec<f32> = vec![];
-        let result = to_complex(&buffer);
-        assert!(result.is_empty());
-    }
-
-    #[test]
-    fn test_to_complex_and_to_real_roundtrip() {
-        let buffer = vec![0.0, 1.5, -3.2, 42.0];
-        let complex = to_complex(&buffer);
-        let real = to_real_f32(&complex);
-        assert_eq!(buffer, real);
-    }
-
     #[test]
     fn test_subsample_edge_cases() {
         // When factor is 1, we expect the same output.
@@ -335,59 +676,186 @@ mod tests {
         assert_eq!(subsample(&buffer, 10), vec![1.0]);
     }
 
-    // Tests for FFT-related functions
+    // Tests for the spectral analysis
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: u32, count: usize) -> Vec<f32> {
+        (0..count)
+            .map(|index| {
+                let time = index as f32 / sample_rate as f32;
+                amplitude * (2.0 * std::f32::consts::PI * frequency * time).sin()
+            })
+            .collect()
+    }
 
     #[test]
-    fn test_fft_real_length() {
-        // Use a small sample so that we can verify length invariance.
-        let samples = vec![1.0, 0.0, -1.0, 0.0];
-        let fft_re = AudioManager::fft_real(&samples);
-        assert_eq!(fft_re.len(), samples.len() / 2);
+    fn test_downmix_averages_channels() {
+        assert_eq!(downmix(&[1.0, 0.0, 0.5, 0.5], 2), vec![0.5, 0.5]);
+        assert_eq!(downmix(&[0.25, 0.75], 1), vec![0.25, 0.75]);
     }
 
     #[test]
-    fn test_power_spectrum_nonnegative() {
-        let samples = vec![0.5; 64];
-        let spectrum = AudioManager::power_spectrum(&samples);
-        // All values in a power spectrum should be nonnegative.
-        assert!(spectrum.iter().all(|&val| val >= 0.0));
+    fn test_hann_window_tapers_to_zero() {
+        let window = hann_window(8);
+        assert_eq!(window[0], 0.0);
+        assert!((window[4] - 1.0).abs() < 1e-6);
     }
 
     #[test]
-    fn test_power_zero_input() {
-        let samples = vec![0.0; 64];
-        // For zero input, the power should be zero.
-        assert_eq!(AudioManager::power(&samples), 0);
+    fn test_analyzer_uses_the_stream_sample_rate() {
+        for sample_rate in [44_100, 48_000, 96_000] {
+            let mut analyzer = SpectralAnalyzer::new(sample_rate);
+            assert!(analyzer.push(&sine(1_000.0, 0.5, sample_rate, FRAME_SIZE)));
+            let features = analyzer.features();
+            let bin_hz = sample_rate as f32 / FRAME_SIZE as f32;
+            assert_eq!(features.sample_rate, sample_rate);
+            assert!((features.peak_frequency - 1_000.0).abs() <= bin_hz);
+            assert!((features.centroid - 1_000.0).abs() <= bin_hz * 2.0);
+            // A sine's RMS is its amplitude over the square root of two.
+            assert!((features.rms - 0.5 / 2f32.sqrt()).abs() < 0.01);
+        }
     }
 
     #[test]
-    fn test_samples_to_hsbk_structure() {
-        let samples = vec![1.0, 0.5, 0.25, 0.125];
-        let hsbk = AudioManager::samples_to_hsbk(samples.clone());
-        // We expect saturation to be u16::MAX and kelvin equal to DEFAULT_KELVIN.
-        assert_eq!(hsbk.saturation, u16::MAX);
-        assert_eq!(hsbk.kelvin, DEFAULT_KELVIN);
-        // Brightness and hue are u16 fields on HSBK, so they are always valid for the LIFX wire format.
+    fn test_analyzer_bands_follow_the_tone() {
+        let sample_rate = 48_000;
+        let band_of = |frequency: f32| {
+            let mut analyzer = SpectralAnalyzer::new(sample_rate);
+            analyzer.push(&sine(frequency, 0.5, sample_rate, FRAME_SIZE));
+            let bands = &analyzer.features().bands;
+            assert_eq!(bands.len(), BAND_COUNT);
+            (0..bands.len())
+                .max_by(|a, b| bands[*a].total_cmp(&bands[*b]))
+                .unwrap()
+        };
+        assert!(band_of(100.0) < band_of(1_000.0));
+        assert!(band_of(1_000.0) < band_of(8_000.0));
     }
 
     #[test]
-    fn test_freq_to_hue_on_constant_signal() {
-        // If the input is constant, the FFT should have a dominant spike at index 0,
-        // yielding hue 0.
-        let samples = vec![1.0; 64];
-        let hue = AudioManager::freq_to_hue(&samples);
-        assert_eq!(hue, 0);
+    fn test_analyzer_frames_overlap() {
+        let mut analyzer = SpectralAnalyzer::new(48_000);
+        assert!(!analyzer.push(&vec![0.0; FRAME_SIZE - 1]));
+        assert!(analyzer.push(&[0.0]));
+        // Each further half frame completes another frame.
+        assert!(!analyzer.push(&vec![0.0; HOP_SIZE - 1]));
+        assert!(analyzer.push(&[0.0]));
     }
 
     #[test]
-    fn test_freq_centroid_edge() {
-        // When the power spectrum is zero everywhere, the centroid should be zero.
-        let samples = vec![0.0; 64];
-        let hsbk = AudioManager::freq_centroid(&samples);
-        // hue computed from a zero centroid will be 0.
-        assert_eq!(hsbk.hue, 0);
-        // brightness should be zero as well.
-        assert_eq!(hsbk.brightness, 0);
+    fn test_analyzer_silence() {
+        let mut analyzer = SpectralAnalyzer::new(48_000);
+        analyzer.push(&vec![0.0; FRAME_SIZE]);
+        let features = analyzer.features();
+        assert_eq!(features.rms, 0.0);
+        assert_eq!(features.centroid, 0.0);
+        assert_eq!(features.to_hsbk().brightness, 0);
+        assert!(features.bands.iter().all(|level| *level == 0.0));
+    }
+
+    // Tests for the beat mappings
+
+    fn light(target: u64) -> DeviceLayout {
+        DeviceLayout {
+            target,
+            label: target.to_string(),
+            index: 0,
+            zones: crate::effects::ZoneLayout::Single,
+            colors: vec![HSBK {
+                hue: 0,
+                saturation: 0,
+                brightness: 0,
+                kelvin: DEFAULT_KELVIN,
+            }],
+        }
+    }
+
+    fn beats(count: u64) -> EffectInputs {
+        EffectInputs {
+            audio: SpectralFeatures {
+                sample_rate: 48_000,
+                beats: count,
+                ..SpectralFeatures::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_hue_steps_on_beats() {
+        let options = AudioSyncOptions {
+            mapping: Arc::new(Mutex::new(AudioMapping::HueStep)),
+            ..AudioSyncOptions::default()
+        };
+        let mut effect = AudioEffect::new(Duration::from_millis(50), options);
+        let devices = [light(1)];
+        let hue = |frames: Vec<(u64, Frame)>| match frames[0].1 {
+            Frame::Whole(color) => color.hue,
+            _ => panic!("expected a whole-light frame"),
+        };
+        assert_eq!(hue(effect.render(Duration::ZERO, &devices, &beats(3))), 0);
+        assert_eq!(hue(effect.render(Duration::ZERO, &devices, &beats(3))), 0);
+        assert_eq!(
+            hue(effect.render(Duration::ZERO, &devices, &beats(4))),
+            HUE_STEP
+        );
+    }
+
+    #[test]
+    fn test_scenes_change_on_beats() {
+        let red = HSBK {
+            hue: 0,
+            saturation: u16::MAX,
+            brightness: u16::MAX,
+            kelvin: DEFAULT_KELVIN,
+        };
+        let blue = HSBK { hue: 43690, ..red };
+        let options = AudioSyncOptions {
+            mapping: Arc::new(Mutex::new(AudioMapping::SceneChange)),
+            scenes: vec![
+                HashMap::from([(1, red)]),
+                HashMap::from([(1, blue), (2, red)]),
+            ],
+            ..AudioSyncOptions::default()
+        };
+        let mut effect = AudioEffect::new(Duration::from_millis(50), options);
+        let devices = [light(1), light(2)];
+        assert_eq!(
+            effect.render(Duration::ZERO, &devices, &beats(0)),
+            vec![(1, Frame::Whole(red))]
+        );
+        assert_eq!(
+            effect.render(Duration::ZERO, &devices, &beats(1)),
+            vec![(1, Frame::Whole(blue)), (2, Frame::Whole(red))]
+        );
+        assert_eq!(
+            effect.render(Duration::ZERO, &devices, &beats(2)),
+            vec![(1, Frame::Whole(red))]
+        );
+    }
+
+    #[test]
+    fn test_visualizers_fill_multizone_lights() {
+        let options = AudioSyncOptions {
+            mapping: Arc::new(Mutex::new(AudioMapping::Bars)),
+            ..AudioSyncOptions::default()
+        };
+        let mut effect = AudioEffect::new(Duration::from_millis(50), options);
+        let strip = DeviceLayout {
+            zones: crate::effects::ZoneLayout::Strip { zones: 8 },
+            colors: vec![light(2).colors[0]; 8],
+            ..light(2)
+        };
+        let mut inputs = beats(0);
+        inputs.audio.bands = vec![1.0; BAND_COUNT];
+        inputs.audio.rms = 1.0;
+        let frames = effect.render(Duration::ZERO, &[light(1), strip], &inputs);
+        assert_eq!(frames[0].1, Frame::Whole(inputs.audio.to_hsbk()));
+        match &frames[1].1 {
+            Frame::Zones(colors) => {
+                assert_eq!(colors.len(), 8);
+                assert!(colors.iter().all(|color| color.brightness == u16::MAX));
+            }
+            _ => panic!("expected a zone frame"),
+        }
     }
 
     // Tests for audio stream construction
@@ -401,6 +869,8 @@ mod tests {
             configuration: None,
             stream: None,
             samples_buffer: Arc::new(Mutex::new(Vec::new())),
+            features: Arc::new(Mutex::new(SpectralFeatures::default())),
+            onsets: Arc::new(Mutex::new(VecDeque::new())),
         };
         // Expect an error when trying to build the stream.
         let max_buffer_size = AUDIO_BUFFER_DEFAULT;
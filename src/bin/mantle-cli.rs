@@ -0,0 +1,763 @@
+//! `mantle-cli`: control LIFX lights from shell scripts without opening the GUI.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use lifx_core::HSBK;
+use serde::Serialize;
+
+use mantle::color::{ColorSpec, BASE_COLOR};
+use mantle::device_info::DeviceInfo;
+use mantle::products::get_product_name;
+use mantle::selector::Selector;
+use mantle::storage::PersistedSettings;
+use mantle::{BulbInfo, DeviceColor, LifxManager};
+
+/// Runtime failure: network, file or protocol errors.
+const EXIT_FAILURE: u8 = 1;
+// clap exits with 2 on usage errors.
+/// Discovery finished without finding any device matching the selector.
+const EXIT_NO_DEVICES: u8 = 3;
+/// The requested scene does not exist in the saved settings.
+const EXIT_SCENE_NOT_FOUND: u8 = 4;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const QUERY_INTERVAL: Duration = Duration::from_millis(250);
+const REDISCOVER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Control LIFX lights on the local network.
+///
+/// Exit codes: 0 success, 1 failure, 2 usage error, 3 no matching devices,
+/// 4 scene not found.
+#[derive(Parser, Debug)]
+#[command(name = "mantle-cli", version)]
+struct Cli {
+    /// Devices to act on: all, id:<serial>, label:<name>, group:<name> or location:<name>
+    #[arg(short, long, global = true, default_value = "all")]
+    selector: Selector,
+    /// How long to wait for devices to answer discovery, in milliseconds
+    #[arg(short, long, global = true, default_value_t = 2000)]
+    timeout_ms: u64,
+    /// Print JSON instead of a table
+    #[arg(long, global = true)]
+    json: bool,
+    /// Local address to listen on. The default picks a free port so the GUI can keep running
+    #[arg(long, global = true, default_value = "0.0.0.0:0")]
+    bind: SocketAddr,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Discover devices and print their state
+    #[command(visible_alias = "discover")]
+    List,
+    /// Turn devices on or off
+    Power { state: PowerState },
+    /// Set the color of devices, e.g. `#ff8800`, `"kelvin:2700 brightness:0.5"` or `hsbk:120,1,0.5,3500`
+    Color {
+        color: ColorSpec,
+        /// Transition time in milliseconds
+        #[arg(short, long, default_value_t = 0)]
+        duration_ms: u32,
+    },
+    /// Print zone colors of multizone and matrix devices, or set a range of zones
+    Zones {
+        /// First zone to change
+        #[arg(long)]
+        start: Option<usize>,
+        /// Last zone to change (inclusive)
+        #[arg(long)]
+        end: Option<usize>,
+        /// Color to apply; prints the current zones when omitted
+        color: Option<ColorSpec>,
+        /// Transition time in milliseconds
+        #[arg(short, long, default_value_t = 0)]
+        duration_ms: u32,
+    },
+    /// Use scenes saved by the Mantle app
+    Scene {
+        /// Read settings from this app.ron instead of the GUI's storage directory
+        #[arg(long)]
+        settings: Option<PathBuf>,
+        #[command(subcommand)]
+        command: SceneCommand,
+    },
+    /// Print device changes until interrupted
+    Watch {
+        /// How often to poll for changes, in milliseconds
+        #[arg(short, long, default_value_t = 1000)]
+        interval_ms: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SceneCommand {
+    /// List saved scenes
+    List,
+    /// Apply a saved scene by name
+    Apply { name: String },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum PowerState {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Failed(anyhow::Error),
+    NoDevices(Selector),
+    SceneNotFound(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Failed(_) => EXIT_FAILURE,
+            CliError::NoDevices(_) => EXIT_NO_DEVICES,
+            CliError::SceneNotFound(_) => EXIT_SCENE_NOT_FOUND,
+        }
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CliError::Failed(e) => write!(f, "{:#}", e),
+            CliError::NoDevices(selector) => write!(f, "no devices found matching {}", selector),
+            CliError::SceneNotFound(name) => write!(f, "no saved scene named \"{}\"", name),
+        }
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for CliError {
+    fn from(e: E) -> Self {
+        CliError::Failed(e.into())
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mantle-cli: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    let timeout = Duration::from_millis(cli.timeout_ms);
+    match cli.command {
+        Command::Scene {
+            settings,
+            command: SceneCommand::List,
+        } => {
+            let settings = load_settings(settings)?;
+            for scene in &settings.scenes {
+                println!("{}", scene.name);
+            }
+            Ok(())
+        }
+        Command::Scene {
+            settings,
+            command: SceneCommand::Apply { name },
+        } => {
+            let settings = load_settings(settings)?;
+            let scene = settings
+                .find_scene(&name)
+                .ok_or_else(|| CliError::SceneNotFound(name.clone()))?;
+            let mut manager = LifxManager::with_bind_addr(cli.bind)?;
+            // Bulbs in a scene carry their address, but groups need discovery to resolve members.
+            if scene
+                .devices()
+                .iter()
+                .any(|d| matches!(d, DeviceInfo::Group(_)))
+            {
+                discover(&mut manager, timeout, &Selector::All)?;
+            }
+            scene
+                .apply(&mut manager)
+                .map_err(|errors| anyhow::anyhow!(errors.join("; ")))?;
+            Ok(())
+        }
+        command => {
+            let mut manager = LifxManager::with_bind_addr(cli.bind)?;
+            discover(&mut manager, timeout, &cli.selector)?;
+            run_device_command(&mut manager, command, &cli.selector, cli.json)
+        }
+    }
+}
+
+fn load_settings(path: Option<PathBuf>) -> Result<PersistedSettings, CliError> {
+    Ok(match path {
+        Some(path) => PersistedSettings::load_from(&path)?,
+        None => PersistedSettings::load()?,
+    })
+}
+
+fn run_device_command(
+    manager: &mut LifxManager,
+    command: Command,
+    selector: &Selector,
+    json: bool,
+) -> Result<(), CliError> {
+    if let Command::Watch { interval_ms } = command {
+        return watch(manager, selector, Duration::from_millis(interval_ms), json);
+    }
+
+    let bulbs = manager
+        .bulbs
+        .lock()
+        .map_err(|_| anyhow::anyhow!("bulb registry lock poisoned"))?;
+    let selected = selector.select(&bulbs);
+    if selected.is_empty() {
+        return Err(CliError::NoDevices(selector.clone()));
+    }
+
+    match command {
+        Command::List => {
+            let summaries: Vec<DeviceSummary> =
+                selected.iter().map(|b| DeviceSummary::from(*b)).collect();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                print!("{}", device_table(&summaries));
+            }
+        }
+        Command::Power { state } => {
+            let level = match state {
+                PowerState::On => u16::MAX,
+                PowerState::Off => 0,
+                // Like the LIFX cloud API: if anything is on, turn everything off.
+                PowerState::Toggle => {
+                    if selected.iter().any(|b| b.power_level.data.unwrap_or(0) > 0) {
+                        0
+                    } else {
+                        u16::MAX
+                    }
+                }
+            };
+            for bulb in &selected {
+                manager.set_power(bulb, level)?;
+            }
+        }
+        Command::Color { color, duration_ms } => {
+            for bulb in &selected {
+                let base = bulb.get_color().copied().unwrap_or(BASE_COLOR);
+                manager.set_color(bulb, color.apply(base), Some(duration_ms))?;
+            }
+        }
+        Command::Zones {
+            start,
+            end,
+            color,
+            duration_ms,
+        } => {
+            let zoned: Vec<&BulbInfo> = selected
+                .into_iter()
+                .filter(|b| b.is_multizone() || b.is_matrix())
+                .collect();
+            if zoned.is_empty() {
+                return Err(CliError::NoDevices(selector.clone()));
+            }
+            match color {
+                Some(spec) => {
+                    for bulb in zoned {
+                        set_zones(manager, bulb, start, end, &spec, duration_ms)?;
+                    }
+                }
+                None => {
+                    let zones: Vec<DeviceZones> =
+                        zoned.iter().map(|b| DeviceZones::from(*b)).collect();
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&zones)?);
+                    } else {
+                        print!("{}", zone_table(&zones));
+                    }
+                }
+            }
+        }
+        Command::Scene { .. } | Command::Watch { .. } => unreachable!("handled by the caller"),
+    }
+    Ok(())
+}
+
+fn set_zones(
+    manager: &LifxManager,
+    bulb: &BulbInfo,
+    start: Option<usize>,
+    end: Option<usize>,
+    spec: &ColorSpec,
+    duration_ms: u32,
+) -> Result<(), CliError> {
+    let count = bulb.get_zone_count();
+    if count == 0 {
+        return Err(anyhow::anyhow!("{} hasn't reported its zones yet", display_name(bulb)).into());
+    }
+    let start = start.unwrap_or(0);
+    let end = end.unwrap_or(count - 1).min(count - 1);
+    if start > end {
+        return Err(anyhow::anyhow!("zone range {}..={} is empty", start, end).into());
+    }
+
+    manager.set_zones_color_spec(&bulb, start..=end, spec, duration_ms)?;
+    Ok(())
+}
+
+/// Send discovery and keep querying devices for their details until `timeout` elapses.
+/// An `id:` selector can only match one device, so we stop as soon as it is fully known.
+fn discover(
+    manager: &mut LifxManager,
+    timeout: Duration,
+    selector: &Selector,
+) -> Result<(), CliError> {
+    let start = Instant::now();
+    let mut last_query = start;
+    while start.elapsed() < timeout {
+        sleep(POLL_INTERVAL.min(timeout.saturating_sub(start.elapsed())));
+        if manager.last_discovery.elapsed() >= REDISCOVER_INTERVAL {
+            manager.discover()?;
+        }
+        if last_query.elapsed() >= QUERY_INTERVAL {
+            manager.refresh()?;
+            last_query = Instant::now();
+        }
+        if matches!(selector, Selector::Id(_)) {
+            if let Ok(bulbs) = manager.bulbs.lock() {
+                let selected = selector.select(&bulbs);
+                if !selected.is_empty() && selected.iter().all(|b| is_fully_known(b)) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_fully_known(bulb: &BulbInfo) -> bool {
+    bulb.name.data.is_some()
+        && bulb.group.data.is_some()
+        && bulb.power_level.data.is_some()
+        && !matches!(bulb.color, DeviceColor::Unknown)
+}
+
+fn watch(
+    manager: &mut LifxManager,
+    selector: &Selector,
+    interval: Duration,
+    json: bool,
+) -> Result<(), CliError> {
+    let mut previous: BTreeMap<String, DeviceSummary> = BTreeMap::new();
+    let mut last_query = Instant::now();
+    loop {
+        if manager.last_discovery.elapsed() >= Duration::from_secs(10) {
+            manager.discover()?;
+        }
+        if last_query.elapsed() >= QUERY_INTERVAL {
+            manager.refresh()?;
+            last_query = Instant::now();
+        }
+        let current: BTreeMap<String, DeviceSummary> = match manager.bulbs.lock() {
+            Ok(bulbs) => selector
+                .select(&bulbs)
+                .into_iter()
+                .map(|b| (b.serial(), DeviceSummary::from(b)))
+                .collect(),
+            Err(_) => return Err(anyhow::anyhow!("bulb registry lock poisoned").into()),
+        };
+        for event in diff_summaries(&previous, &current) {
+            if json {
+                println!("{}", serde_json::to_string(&event)?);
+            } else {
+                println!("{}", event);
+            }
+        }
+        previous = current;
+        sleep(interval);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct ColorSummary {
+    /// Degrees, 0-360
+    hue: f32,
+    /// 0-1
+    saturation: f32,
+    /// 0-1
+    brightness: f32,
+    kelvin: u16,
+}
+
+impl From<HSBK> for ColorSummary {
+    fn from(c: HSBK) -> Self {
+        let ratio = |v: u16| (v as f32 / u16::MAX as f32 * 1000.0).round() / 1000.0;
+        ColorSummary {
+            hue: (c.hue as f32 / u16::MAX as f32 * 3600.0).round() / 10.0,
+            saturation: ratio(c.saturation),
+            brightness: ratio(c.brightness),
+            kelvin: c.kelvin,
+        }
+    }
+}
+
+impl Display for ColorSummary {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "h:{:.0} s:{:.0}% b:{:.0}% k:{}",
+            self.hue,
+            self.saturation * 100.0,
+            self.brightness * 100.0,
+            self.kelvin
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DeviceSummary {
+    id: String,
+    label: Option<String>,
+    group: Option<String>,
+    location: Option<String>,
+    model: Option<String>,
+    address: String,
+    power: Option<&'static str>,
+    color: Option<ColorSummary>,
+    zones: usize,
+    online: bool,
+}
+
+impl From<&BulbInfo> for DeviceSummary {
+    fn from(bulb: &BulbInfo) -> Self {
+        DeviceSummary {
+            id: bulb.serial(),
+            label: bulb.name_label(),
+            group: bulb.group_label(),
+            location: bulb
+                .location
+                .data
+                .as_ref()
+                .map(|l| l.to_string_lossy().into_owned()),
+            model: get_product_name(bulb.model.as_ref()),
+            address: bulb.addr.to_string(),
+            power: bulb
+                .power_level
+                .data
+                .map(|level| if level > 0 { "on" } else { "off" }),
+            color: bulb.get_color().map(|c| ColorSummary::from(*c)),
+            zones: bulb.get_zone_count(),
+            online: bulb.is_online(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceZones {
+    id: String,
+    label: Option<String>,
+    zones: Vec<Option<ColorSummary>>,
+}
+
+impl From<&BulbInfo> for DeviceZones {
+    fn from(bulb: &BulbInfo) -> Self {
+        DeviceZones {
+            id: bulb.serial(),
+            label: bulb.name_label(),
+            zones: bulb
+                .get_zone_colors()
+                .map(|zones| zones.iter().map(|z| z.map(ColorSummary::from)).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn display_name(bulb: &BulbInfo) -> String {
+    bulb.name_label().unwrap_or_else(|| bulb.serial())
+}
+
+fn or_dash(value: &Option<impl ToString>) -> String {
+    value
+        .as_ref()
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Render rows as left-aligned, space-separated columns.
+fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn device_table(devices: &[DeviceSummary]) -> String {
+    let rows: Vec<Vec<String>> = devices
+        .iter()
+        .map(|d| {
+            vec![
+                d.id.clone(),
+                or_dash(&d.label),
+                or_dash(&d.group),
+                or_dash(&d.power),
+                or_dash(&d.color),
+                d.zones.to_string(),
+                or_dash(&d.model),
+                d.address.clone(),
+            ]
+        })
+        .collect();
+    render_table(
+        &[
+            "ID", "LABEL", "GROUP", "POWER", "COLOR", "ZONES", "MODEL", "ADDRESS",
+        ],
+        &rows,
+    )
+}
+
+fn zone_table(devices: &[DeviceZones]) -> String {
+    let rows: Vec<Vec<String>> = devices
+        .iter()
+        .flat_map(|d| {
+            d.zones.iter().enumerate().map(move |(i, color)| {
+                vec![
+                    d.id.clone(),
+                    or_dash(&d.label),
+                    i.to_string(),
+                    or_dash(color),
+                ]
+            })
+        })
+        .collect();
+    render_table(&["ID", "LABEL", "ZONE", "COLOR"], &rows)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WatchEvent {
+    Added { device: DeviceSummary },
+    Removed { id: String },
+    Changed { device: DeviceSummary },
+}
+
+impl Display for WatchEvent {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            WatchEvent::Added { device } | WatchEvent::Changed { device } => {
+                let kind = if matches!(self, WatchEvent::Added { .. }) {
+                    "added"
+                } else {
+                    "changed"
+                };
+                write!(
+                    f,
+                    "{} {} {} power={} color={} online={}",
+                    kind,
+                    device.id,
+                    or_dash(&device.label),
+                    or_dash(&device.power),
+                    or_dash(&device.color),
+                    device.online
+                )
+            }
+            WatchEvent::Removed { id } => write!(f, "removed {}", id),
+        }
+    }
+}
+
+fn diff_summaries(
+    previous: &BTreeMap<String, DeviceSummary>,
+    current: &BTreeMap<String, DeviceSummary>,
+) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    for (id, device) in current {
+        match previous.get(id) {
+            None => events.push(WatchEvent::Added {
+                device: device.clone(),
+            }),
+            Some(old) if old != device => events.push(WatchEvent::Changed {
+                device: device.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for id in previous.keys().filter(|id| !current.contains_key(*id)) {
+        events.push(WatchEvent::Removed { id: id.clone() });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn summary(id: &str, power: Option<&'static str>) -> DeviceSummary {
+        DeviceSummary {
+            id: id.to_string(),
+            label: Some("Desk".to_string()),
+            group: None,
+            location: None,
+            model: None,
+            address: "192.168.1.2:56700".to_string(),
+            power,
+            color: None,
+            zones: 0,
+            online: true,
+        }
+    }
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_color_command() {
+        let cli = Cli::try_parse_from([
+            "mantle-cli",
+            "-s",
+            "label:Desk",
+            "color",
+            "kelvin:2700 brightness:0.5",
+            "-d",
+            "500",
+        ])
+        .unwrap();
+        assert_eq!(cli.selector, Selector::Label("Desk".to_string()));
+        match cli.command {
+            Command::Color { color, duration_ms } => {
+                assert_eq!(color, "kelvin:2700 brightness:0.5".parse().unwrap());
+                assert_eq!(duration_ms, 500);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_zones_without_color_lists_them() {
+        let cli = Cli::try_parse_from(["mantle-cli", "zones", "--start", "2"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Zones {
+                start: Some(2),
+                end: None,
+                color: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_bad_selector_and_power() {
+        assert!(Cli::try_parse_from(["mantle-cli", "-s", "name:x", "list"]).is_err());
+        assert!(Cli::try_parse_from(["mantle-cli", "power", "dim"]).is_err());
+        assert!(Cli::try_parse_from(["mantle-cli", "color", "mauve"]).is_err());
+        assert!(Cli::try_parse_from(["mantle-cli", "discover", "--json"]).is_ok());
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        assert_eq!(
+            CliError::NoDevices(Selector::All).exit_code(),
+            EXIT_NO_DEVICES
+        );
+        assert_eq!(
+            CliError::SceneNotFound("x".to_string()).exit_code(),
+            EXIT_SCENE_NOT_FOUND
+        );
+        assert_eq!(
+            CliError::from(anyhow::anyhow!("boom")).exit_code(),
+            EXIT_FAILURE
+        );
+    }
+
+    #[test]
+    fn color_summary_uses_friendly_units() {
+        let c = ColorSummary::from(HSBK {
+            hue: u16::MAX / 2,
+            saturation: u16::MAX,
+            brightness: 0,
+            kelvin: 3500,
+        });
+        assert_eq!(c.hue, 180.0);
+        assert_eq!(c.saturation, 1.0);
+        assert_eq!(c.brightness, 0.0);
+        assert_eq!(c.to_string(), "h:180 s:100% b:0% k:3500");
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let table = render_table(
+            &["A", "LONGER"],
+            &[
+                vec!["wide cell".to_string(), "x".to_string()],
+                vec!["y".to_string(), "z".to_string()],
+            ],
+        );
+        assert_eq!(table, "A          LONGER\nwide cell  x\ny          z\n");
+    }
+
+    #[test]
+    fn diff_reports_added_changed_and_removed() {
+        let previous = BTreeMap::from([
+            ("a".to_string(), summary("a", Some("on"))),
+            ("b".to_string(), summary("b", Some("on"))),
+        ]);
+        let current = BTreeMap::from([
+            ("a".to_string(), summary("a", Some("off"))),
+            ("c".to_string(), summary("c", None)),
+        ]);
+        let events = diff_summaries(&previous, &current);
+        assert_eq!(
+            events,
+            vec![
+                WatchEvent::Changed {
+                    device: summary("a", Some("off"))
+                },
+                WatchEvent::Added {
+                    device: summary("c", None)
+                },
+                WatchEvent::Removed {
+                    id: "b".to_string()
+                },
+            ]
+        );
+        assert!(diff_summaries(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn watch_event_json_is_tagged() {
+        let json = serde_json::to_value(WatchEvent::Removed {
+            id: "d073d5001337".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json["event"], "removed");
+        assert_eq!(json["id"], "d073d5001337");
+    }
+}
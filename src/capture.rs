@@ -0,0 +1,471 @@
+//! A persistent screen capture thread shared by every screen sync effect.
+//!
+//! Each monitor or window that an effect wants is captured once per interval no matter how
+//! many devices show it, and downsampled to the sample budget before anyone analyzes it.
+//! xcap can only capture whole monitors, so subregions are cropped from the shared
+//! downsampled monitor frame.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use xcap::image::RgbaImage;
+use xcap::XCapError;
+
+use crate::effects::edges::SampleRect;
+use crate::screencap::{ScreencapManager, WindowIdentity};
+use rust_i18n::t;
+
+pub const SAMPLE_BUDGET_RANGE: std::ops::RangeInclusive<usize> = 4_096..=1_048_576;
+
+/// How often the capture thread looks for sources that are due.
+const CAPTURE_TICK: Duration = Duration::from_millis(10);
+/// Sources nobody asked for in this long stop being captured.
+const KEEP_SOURCE_FOR: Duration = Duration::from_secs(2);
+/// Windows are listed again this often while one is captured, to follow it as it moves,
+/// resizes or reopens. xcap captures a window at the size it had when listed.
+const WINDOW_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Weight of the newest frame in the averaged timings.
+const METRICS_SMOOTHING: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Most pixels kept from each captured monitor or window.
+    pub sample_budget: usize,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        CaptureSettings {
+            sample_budget: 65_536,
+        }
+    }
+}
+
+/// Something the capture thread can take pictures of.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CaptureSource {
+    Monitor(u32),
+    /// Whichever window matches, looked up again on each capture.
+    Window(WindowIdentity),
+}
+
+/// Where a monitor sits on the desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorBounds {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorBounds {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+}
+
+/// A downsampled capture of a source.
+pub struct CapturedFrame {
+    pub image: RgbaImage,
+    /// Size of the source before downsampling, to map source coordinates onto `image`.
+    pub source_width: u32,
+    pub source_height: u32,
+    pub captured_at: Instant,
+}
+
+impl CapturedFrame {
+    /// The part of the frame showing the source rectangle at (`x`, `y`) of the given size,
+    /// clamped to the frame.
+    pub fn crop(&self, x: i32, y: i32, width: u32, height: u32) -> RgbaImage {
+        let rect = self.sample_rect(x, y, width, height);
+        xcap::image::imageops::crop_imm(&self.image, rect.x, rect.y, rect.width, rect.height)
+            .to_image()
+    }
+
+    /// Where the source rectangle at (`x`, `y`) of the given size lies in the frame,
+    /// clamped to it.
+    pub fn sample_rect(&self, x: i32, y: i32, width: u32, height: u32) -> SampleRect {
+        let scale_x = self.image.width() as f32 / self.source_width.max(1) as f32;
+        let scale_y = self.image.height() as f32 / self.source_height.max(1) as f32;
+        let left = ((x.max(0) as f32 * scale_x) as u32).min(self.image.width());
+        let top = ((y.max(0) as f32 * scale_y) as u32).min(self.image.height());
+        SampleRect {
+            x: left,
+            y: top,
+            width: ((width as f32 * scale_x).ceil() as u32).min(self.image.width() - left),
+            height: ((height as f32 * scale_y).ceil() as u32).min(self.image.height() - top),
+        }
+    }
+}
+
+/// Timings of the capture pipeline, averaged over recent frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureMetrics {
+    pub capture_ms: f32,
+    pub downsample_ms: f32,
+    pub analysis_ms: f32,
+    /// Frames captured per second over all sources.
+    pub frames_per_second: f32,
+    pub sources: usize,
+    /// Size of the last downsampled frame.
+    pub sample_width: u32,
+    pub sample_height: u32,
+    pub errors: u64,
+}
+
+struct Source {
+    interval: Duration,
+    last_wanted: Instant,
+    frame: Option<Arc<CapturedFrame>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    sources: HashMap<CaptureSource, Source>,
+    /// The monitors with the primary monitor first, as last seen by the capture thread.
+    monitors: Vec<MonitorBounds>,
+    settings: CaptureSettings,
+    metrics: CaptureMetrics,
+    last_frame_at: Option<Instant>,
+}
+
+/// Handle to the capture thread. Clones share the thread and its frames.
+#[derive(Clone, Default)]
+pub struct CaptureService {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl CaptureService {
+    /// Start the capture thread. It stops once every handle is dropped.
+    pub fn start() -> Self {
+        let service = CaptureService::default();
+        let weak = Arc::downgrade(&service.shared);
+        thread::spawn(move || {
+            let mut screens: Option<ScreencapManager> = None;
+            refresh_screens(&mut screens, &weak);
+            let mut refreshed_at = Instant::now();
+            while let Some(shared) = weak.upgrade() {
+                let (due, budget) = match shared.lock() {
+                    Ok(mut shared) => {
+                        let now = Instant::now();
+                        shared.sources.retain(|_, source| {
+                            now.duration_since(source.last_wanted) < KEEP_SOURCE_FOR
+                        });
+                        let due: Vec<CaptureSource> = shared
+                            .sources
+                            .iter()
+                            .filter(|(_, source)| {
+                                source.frame.as_ref().is_none_or(|frame| {
+                                    now.duration_since(frame.captured_at) >= source.interval
+                                })
+                            })
+                            .map(|(id, _)| id.clone())
+                            .collect();
+                        (due, shared.settings.sample_budget)
+                    }
+                    Err(_) => break,
+                };
+                drop(shared);
+                if due.is_empty() {
+                    thread::sleep(CAPTURE_TICK);
+                    continue;
+                }
+                for source in due {
+                    let started = Instant::now();
+                    let image = capture(&mut screens, &mut refreshed_at, &weak, &source);
+                    let captured = Instant::now();
+                    let image = image.map(|image| {
+                        let (source_width, source_height) = image.dimensions();
+                        (downsample(&image, budget), source_width, source_height)
+                    });
+                    let done = Instant::now();
+                    let Some(shared) = weak.upgrade() else {
+                        return;
+                    };
+                    let Ok(mut shared) = shared.lock() else {
+                        return;
+                    };
+                    let frame = match image {
+                        Ok((image, source_width, source_height)) => {
+                            shared.record_frame(
+                                captured - started,
+                                done - captured,
+                                image.dimensions(),
+                                done,
+                            );
+                            CapturedFrame {
+                                image,
+                                source_width,
+                                source_height,
+                                captured_at: started,
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Screen capture error: {}", e);
+                            shared.metrics.errors += 1;
+                            // An empty frame, so the source is retried after a full
+                            // interval rather than on every tick.
+                            CapturedFrame {
+                                image: RgbaImage::new(0, 0),
+                                source_width: 0,
+                                source_height: 0,
+                                captured_at: started,
+                            }
+                        }
+                    };
+                    if let Some(entry) = shared.sources.get_mut(&source) {
+                        entry.frame = Some(Arc::new(frame));
+                    }
+                }
+            }
+        });
+        service
+    }
+
+    /// The latest frame of `source`, which is captured every `interval` from now on until
+    /// nobody has asked for it in a while. `None` until the first capture is done.
+    pub fn request(&self, source: CaptureSource, interval: Duration) -> Option<Arc<CapturedFrame>> {
+        let mut shared = self.shared.lock().ok()?;
+        let entry = shared.sources.entry(source).or_insert(Source {
+            interval,
+            last_wanted: Instant::now(),
+            frame: None,
+        });
+        entry.interval = interval;
+        entry.last_wanted = Instant::now();
+        entry
+            .frame
+            .clone()
+            .filter(|frame| frame.image.width() > 0 && frame.image.height() > 0)
+    }
+
+    /// Ids of the connected monitors, primary first.
+    pub fn monitors(&self) -> Vec<u32> {
+        self.shared
+            .lock()
+            .map(|shared| shared.monitors.iter().map(|monitor| monitor.id).collect())
+            .unwrap_or_default()
+    }
+
+    /// The monitor showing the desktop point (`x`, `y`).
+    pub fn monitor_at(&self, x: i32, y: i32) -> Option<MonitorBounds> {
+        let shared = self.shared.lock().ok()?;
+        shared
+            .monitors
+            .iter()
+            .find(|monitor| monitor.contains(x, y))
+            .copied()
+    }
+
+    pub fn set_settings(&self, settings: CaptureSettings) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.settings = settings;
+        }
+    }
+
+    /// Add the time an effect spent turning frames into colors to the metrics.
+    pub fn record_analysis(&self, took: Duration) {
+        if let Ok(mut shared) = self.shared.lock() {
+            let ms = took.as_secs_f32() * 1000.0;
+            shared.metrics.analysis_ms = smooth(shared.metrics.analysis_ms, ms);
+        }
+    }
+
+    pub fn metrics(&self) -> CaptureMetrics {
+        self.shared
+            .lock()
+            .map(|shared| CaptureMetrics {
+                sources: shared.sources.len(),
+                ..shared.metrics
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Shared {
+    fn record_frame(
+        &mut self,
+        capture: Duration,
+        downsample: Duration,
+        (width, height): (u32, u32),
+        at: Instant,
+    ) {
+        let metrics = &mut self.metrics;
+        metrics.capture_ms = smooth(metrics.capture_ms, capture.as_secs_f32() * 1000.0);
+        metrics.downsample_ms = smooth(metrics.downsample_ms, downsample.as_secs_f32() * 1000.0);
+        if let Some(last) = self.last_frame_at {
+            let gap = at.duration_since(last).as_secs_f32();
+            if gap > 0.0 {
+                metrics.frames_per_second = smooth(metrics.frames_per_second, 1.0 / gap);
+            }
+        }
+        metrics.sample_width = width;
+        metrics.sample_height = height;
+        self.last_frame_at = Some(at);
+    }
+}
+
+fn smooth(average: f32, sample: f32) -> f32 {
+    if average == 0.0 {
+        sample
+    } else {
+        average + (sample - average) * METRICS_SMOOTHING
+    }
+}
+
+/// Reload the monitors and windows, and publish where the monitors are.
+fn refresh_screens(
+    screens: &mut Option<ScreencapManager>,
+    shared: &std::sync::Weak<Mutex<Shared>>,
+) {
+    let result = match screens {
+        Some(screens) => screens.refresh(),
+        None => ScreencapManager::new().map(|manager| {
+            *screens = Some(manager);
+        }),
+    };
+    if let Err(e) = result {
+        log::error!("Failed to list screens for capture: {}", e);
+        return;
+    }
+    let Some(screens) = screens else {
+        return;
+    };
+    let mut monitors: Vec<_> = screens.monitors.iter().collect();
+    monitors.sort_by_key(|monitor| !monitor.is_primary());
+    let bounds = monitors
+        .iter()
+        .map(|monitor| MonitorBounds {
+            id: monitor.id(),
+            x: monitor.x(),
+            y: monitor.y(),
+            width: monitor.width(),
+            height: monitor.height(),
+        })
+        .collect();
+    if let Some(shared) = shared.upgrade() {
+        if let Ok(mut shared) = shared.lock() {
+            shared.monitors = bounds;
+        }
+    }
+}
+
+/// Capture `source` at full resolution, reloading the screens once if a monitor isn't
+/// known yet. Windows are reloaded when the list is out of date, but no more often, so a
+/// closed window isn't searched for on every frame.
+fn capture(
+    screens: &mut Option<ScreencapManager>,
+    refreshed_at: &mut Instant,
+    shared: &std::sync::Weak<Mutex<Shared>>,
+    source: &CaptureSource,
+) -> Result<RgbaImage, XCapError> {
+    let is_window = matches!(source, CaptureSource::Window(_));
+    for attempt in 0..2 {
+        let reload = if is_window {
+            attempt == 0 && refreshed_at.elapsed() >= WINDOW_REFRESH_INTERVAL
+        } else {
+            attempt > 0
+        };
+        if reload {
+            refresh_screens(screens, shared);
+            *refreshed_at = Instant::now();
+        }
+        let Some(screens) = screens.as_ref() else {
+            continue;
+        };
+        let image = match source {
+            CaptureSource::Monitor(id) => screens
+                .monitors
+                .iter()
+                .find(|monitor| monitor.id() == *id)
+                .map(|monitor| monitor.capture_image()),
+            CaptureSource::Window(identity) => identity
+                .find(&screens.windows)
+                .map(|window| window.capture_image()),
+        };
+        if let Some(image) = image {
+            return image;
+        }
+    }
+    if is_window {
+        // Windows come and go, so a closed one gives an empty frame rather than an error
+        // until it's back, and its lights keep their colors.
+        return Ok(RgbaImage::new(0, 0));
+    }
+    Err(XCapError::new(&*t!("error.capture_source_gone")))
+}
+
+/// Keep every n-th pixel of every n-th row so that at most `budget` pixels remain.
+pub fn downsample(image: &RgbaImage, budget: usize) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let pixels = width as usize * height as usize;
+    let step = ((pixels as f64 / budget.max(1) as f64).sqrt().ceil() as u32).max(1);
+    if step == 1 {
+        return image.clone();
+    }
+    let sampled_width = width.div_ceil(step);
+    let sampled_height = height.div_ceil(step);
+    RgbaImage::from_fn(sampled_width, sampled_height, |x, y| {
+        *image.get_pixel(x * step, y * step)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcap::image::Rgba;
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+    }
+
+    #[test]
+    fn downsample_keeps_within_the_budget() {
+        let image = gradient(200, 100);
+        let sampled = downsample(&image, 1_000);
+        assert!(sampled.width() * sampled.height() <= 1_000);
+        assert_eq!(sampled.dimensions(), (40, 20));
+        assert_eq!(sampled.get_pixel(1, 1), &Rgba([5, 5, 0, 255]));
+        // Small images are left alone.
+        assert_eq!(downsample(&image, 100_000).dimensions(), (200, 100));
+    }
+
+    #[test]
+    fn crop_maps_source_coordinates_onto_the_sample() {
+        let image = gradient(200, 100);
+        let frame = CapturedFrame {
+            image: downsample(&image, 1_000),
+            source_width: 200,
+            source_height: 100,
+            captured_at: Instant::now(),
+        };
+        let crop = frame.crop(100, 50, 100, 50);
+        assert_eq!(crop.dimensions(), (20, 10));
+        assert_eq!(crop.get_pixel(0, 0), &Rgba([100, 50, 0, 255]));
+        // Rectangles past the edge are clamped.
+        assert_eq!(frame.crop(190, -10, 500, 500).dimensions(), (2, 20));
+    }
+
+    #[test]
+    fn monitor_bounds_contain_their_pixels() {
+        let monitor = MonitorBounds {
+            id: 1,
+            x: -1920,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        assert!(monitor.contains(-1920, 0));
+        assert!(monitor.contains(-1, 1079));
+        assert!(!monitor.contains(0, 500));
+        assert!(!monitor.contains(-100, 1080));
+    }
+}
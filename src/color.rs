@@ -1,8 +1,11 @@
+#[cfg(feature = "gui")]
 use eframe::egui::{Color32, Rgba};
 use lifx_core::HSBK;
 use serde::{Deserialize, Serialize};
 use strum_macros::{EnumIter, VariantNames};
 
+use rust_i18n::t;
+
 pub const DEFAULT_KELVIN: u16 = 3500;
 
 /// Enumerate each field of HSBK, the color space used by LIFX bulbs.
@@ -161,6 +164,7 @@ impl From<RGB8> for HSBK {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<RGB8> for Color32 {
     fn from(rgb: RGB8) -> Color32 {
         Color32::from_rgb(rgb.red, rgb.green, rgb.blue)
@@ -204,6 +208,14 @@ pub fn kelvin_to_rgb(temperature: u16) -> RGB8 {
     }
 }
 
+/// Starting point for partial color changes on a device that hasn't reported its color yet.
+pub const BASE_COLOR: HSBK = HSBK {
+    hue: 0,
+    saturation: 0,
+    brightness: u16::MAX,
+    kelvin: DEFAULT_KELVIN,
+};
+
 pub fn default_hsbk() -> HSBK {
     HSBK {
         hue: 0,
@@ -258,6 +270,7 @@ impl From<HSBK32> for RGB8 {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<Color32> for HSBK32 {
     fn from(color: Color32) -> HSBK32 {
         let rgb = RGB8 {
@@ -272,6 +285,7 @@ impl From<Color32> for HSBK32 {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<HSBK32> for Color32 {
     fn from(hsbk: HSBK32) -> Color32 {
         let rgb: RGB8 = hsbk.into();
@@ -280,6 +294,7 @@ impl From<HSBK32> for Color32 {
 }
 
 /// Given an Rgba color, return the contrast color to use for text.
+#[cfg(feature = "gui")]
 pub fn contrast_color(color: impl Into<Rgba>) -> Color32 {
     if color.into().intensity() < 0.5 {
         Color32::WHITE
@@ -297,10 +312,198 @@ pub struct DeltaColor {
     pub duration: Option<u32>,
 }
 
+/// Mix `amount` of `to` into `from`, taking the short way around the hue wheel.
+pub fn mix_hsbk(from: HSBK, to: HSBK, amount: f32) -> HSBK {
+    let amount = amount.clamp(0.0, 1.0);
+    let lerp = |a: u16, b: u16| (a as f32 + (b as f32 - a as f32) * amount).round() as u16;
+    let hue_delta = to.hue.wrapping_sub(from.hue) as i16;
+    HSBK {
+        hue: from
+            .hue
+            .wrapping_add((hue_delta as f32 * amount).round() as i16 as u16),
+        saturation: lerp(from.saturation, to.saturation),
+        brightness: lerp(from.brightness, to.brightness),
+        kelvin: lerp(from.kelvin, to.kelvin),
+    }
+}
+
+/// The distance between two hues around the hue wheel, at most half of it.
+pub fn hue_distance(a: u16, b: u16) -> u16 {
+    let delta = a.wrapping_sub(b);
+    delta.min(delta.wrapping_neg())
+}
+
+/// Parse a `#rrggbb` (or `rrggbb`) hex string into its RGB components.
+pub fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+    Some((r, g, b))
+}
+
+/// The color temperatures accepted by [`ColorSpec`]; values outside are clamped.
+pub const SPEC_KELVIN_RANGE: std::ops::RangeInclusive<u16> = 1500..=9000;
+
+/// A partial color change, parsed from the color string syntax of the LIFX HTTP API.
+/// Fields left as `None` keep the device's current value when applied.
+///
+/// Accepted tokens (space separated, later tokens override earlier ones):
+/// named colors (`white`, `red`, `orange`, `yellow`, `cyan`, `green`, `blue`,
+/// `purple`, `pink`), `#rrggbb`, `rgb:r,g,b`, `hue:0-360`, `saturation:0-1`,
+/// `brightness:0-1`, `kelvin:1500-9000` and the shorthand `hsbk:h,s,b,k`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorSpec {
+    pub hue: Option<u16>,
+    pub saturation: Option<u16>,
+    pub brightness: Option<u16>,
+    pub kelvin: Option<u16>,
+}
+
+impl ColorSpec {
+    /// Apply the change on top of `base`.
+    pub fn apply(&self, base: HSBK) -> HSBK {
+        HSBK {
+            hue: self.hue.unwrap_or(base.hue),
+            saturation: self.saturation.unwrap_or(base.saturation),
+            brightness: self.brightness.unwrap_or(base.brightness),
+            kelvin: self.kelvin.unwrap_or(base.kelvin),
+        }
+    }
+
+    fn set_rgb(&mut self, red: u8, green: u8, blue: u8) {
+        let hsbk: HSBK = RGB8::new(red, green, blue, None).into();
+        self.hue = Some(hsbk.hue);
+        self.saturation = Some(hsbk.saturation);
+        self.brightness = Some(hsbk.brightness);
+    }
+
+    fn set_named_hue(&mut self, degrees: f32) {
+        self.hue = Some(degrees_to_u16(degrees));
+        self.saturation = Some(u16::MAX);
+    }
+}
+
+fn degrees_to_u16(degrees: f32) -> u16 {
+    ((degrees.rem_euclid(360.0) / 360.0) * u16::MAX as f32).round() as u16
+}
+
+fn ratio_to_u16(ratio: f32) -> Option<u16> {
+    (0.0..=1.0)
+        .contains(&ratio)
+        .then(|| (ratio * u16::MAX as f32).round() as u16)
+}
+
+impl std::str::FromStr for ColorSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!(t!("error.invalid_color", color = s));
+        let mut spec = ColorSpec::default();
+        for token in s.split_whitespace() {
+            let token = token.to_lowercase();
+            match token.split_once(':') {
+                Some(("hue", v)) => {
+                    let degrees: f32 = v.parse().map_err(|_| invalid())?;
+                    if !(0.0..=360.0).contains(&degrees) {
+                        return Err(invalid());
+                    }
+                    spec.hue = Some(degrees_to_u16(degrees));
+                }
+                Some(("saturation", v)) => {
+                    spec.saturation = ratio_to_u16(v.parse().map_err(|_| invalid())?);
+                    spec.saturation.ok_or_else(invalid)?;
+                }
+                Some(("brightness", v)) => {
+                    spec.brightness = ratio_to_u16(v.parse().map_err(|_| invalid())?);
+                    spec.brightness.ok_or_else(invalid)?;
+                }
+                Some(("kelvin", v)) => {
+                    let kelvin: u16 = v.parse().map_err(|_| invalid())?;
+                    spec.kelvin =
+                        Some(kelvin.clamp(*SPEC_KELVIN_RANGE.start(), *SPEC_KELVIN_RANGE.end()));
+                    spec.saturation = Some(0);
+                }
+                Some(("rgb", v)) => {
+                    let parts: Vec<u8> = v
+                        .split(',')
+                        .map(|c| c.trim().parse::<u8>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid())?;
+                    let [r, g, b] = parts[..] else {
+                        return Err(invalid());
+                    };
+                    spec.set_rgb(r, g, b);
+                }
+                Some(("hsbk", v)) => {
+                    let parts: Vec<f32> = v
+                        .split(',')
+                        .map(|c| c.trim().parse::<f32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid())?;
+                    let [h, s, b, k] = parts[..] else {
+                        return Err(invalid());
+                    };
+                    if !(0.0..=360.0).contains(&h) || !(0.0..=u16::MAX as f32).contains(&k) {
+                        return Err(invalid());
+                    }
+                    spec.hue = Some(degrees_to_u16(h));
+                    spec.saturation = Some(ratio_to_u16(s).ok_or_else(invalid)?);
+                    spec.brightness = Some(ratio_to_u16(b).ok_or_else(invalid)?);
+                    spec.kelvin = Some(
+                        (k as u16).clamp(*SPEC_KELVIN_RANGE.start(), *SPEC_KELVIN_RANGE.end()),
+                    );
+                }
+                Some(_) => return Err(invalid()),
+                None => match token.as_str() {
+                    "white" => spec.saturation = Some(0),
+                    "red" => spec.set_named_hue(0.0),
+                    "orange" => spec.set_named_hue(36.0),
+                    "yellow" => spec.set_named_hue(60.0),
+                    "green" => spec.set_named_hue(120.0),
+                    "cyan" => spec.set_named_hue(180.0),
+                    "blue" => spec.set_named_hue(250.0),
+                    "purple" => spec.set_named_hue(280.0),
+                    "pink" => spec.set_named_hue(325.0),
+                    hex if hex.starts_with('#') => {
+                        let (r, g, b) = parse_hex_color(hex).ok_or_else(invalid)?;
+                        spec.set_rgb(r, g, b);
+                    }
+                    _ => return Err(invalid()),
+                },
+            }
+        }
+        if spec == ColorSpec::default() {
+            return Err(invalid());
+        }
+        Ok(spec)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn mix_hsbk_takes_the_short_way_around_the_hue_wheel() {
+        let red = HSBK {
+            hue: 0,
+            saturation: u16::MAX,
+            brightness: u16::MAX,
+            kelvin: DEFAULT_KELVIN,
+        };
+        let purple = HSBK { hue: 52_800, ..red };
+        let middle = mix_hsbk(purple, red, 0.5);
+        assert!(middle.hue > purple.hue, "{}", middle.hue);
+        assert_eq!(mix_hsbk(red, purple, 0.0), red);
+        assert_eq!(mix_hsbk(red, purple, 1.0), purple);
+        assert_eq!(hue_distance(1_000, 64_536), 2_000);
+        assert_eq!(hue_distance(64_536, 1_000), 2_000);
+    }
+
     #[test]
     fn test_kelvin_to_rgb() {
         let color = kelvin_to_rgb(DEFAULT_KELVIN);
@@ -340,6 +543,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "gui")]
     fn test_contrast_color() {
         let color = Color32::BLACK;
         assert_eq!(contrast_color(color), Color32::WHITE);
@@ -407,6 +611,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "gui")]
     fn rgb8_to_color32() {
         let rgb = RGB8::new(100, 150, 200, None);
         let c32: Color32 = rgb.into();
@@ -416,6 +621,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "gui")]
     fn color32_to_hsbk32_to_color32() {
         let original = Color32::from_rgb(255, 0, 0);
         let hsbk32: HSBK32 = original.into();
@@ -434,6 +640,93 @@ mod tests {
         assert_eq!(h.kelvin, 0);
     }
 
+    #[test]
+    fn parse_hex_color_valid_and_invalid() {
+        assert_eq!(parse_hex_color("#ff8000"), Some((255, 128, 0)));
+        assert_eq!(parse_hex_color("00ff00"), Some((0, 255, 0)));
+        assert_eq!(parse_hex_color("#fff"), None);
+        assert_eq!(parse_hex_color("#gg0000"), None);
+        assert_eq!(parse_hex_color("#aé€"), None);
+        assert_eq!(parse_hex_color("+1+2+3"), None);
+    }
+
+    #[test]
+    fn color_spec_hex_and_named() {
+        let red: ColorSpec = "#ff0000".parse().unwrap();
+        assert_eq!(red.hue, Some(0));
+        assert_eq!(red.saturation, Some(u16::MAX));
+        assert_eq!(red.brightness, Some(u16::MAX));
+        assert_eq!(red.kelvin, None);
+
+        let blue: ColorSpec = "blue".parse().unwrap();
+        assert_eq!(blue.hue, Some(degrees_to_u16(250.0)));
+        assert_eq!(blue.brightness, None);
+    }
+
+    #[test]
+    fn color_spec_kelvin_clears_saturation() {
+        let spec: ColorSpec = "kelvin:2700 brightness:0.5".parse().unwrap();
+        assert_eq!(spec.kelvin, Some(2700));
+        assert_eq!(spec.saturation, Some(0));
+        assert_eq!(spec.brightness, Some(32768));
+        assert_eq!(spec.hue, None);
+
+        let hot: ColorSpec = "kelvin:20000".parse().unwrap();
+        assert_eq!(hot.kelvin, Some(9000));
+        let cold: ColorSpec = "kelvin:10".parse().unwrap();
+        assert_eq!(cold.kelvin, Some(1500));
+    }
+
+    #[test]
+    fn color_spec_hsbk_shorthand() {
+        let spec: ColorSpec = "hsbk:180,1,0.25,3500".parse().unwrap();
+        let base = HSBK {
+            hue: 1,
+            saturation: 2,
+            brightness: 3,
+            kelvin: 4,
+        };
+        let applied = spec.apply(base);
+        assert_eq!(applied.hue, degrees_to_u16(180.0));
+        assert_eq!(applied.saturation, u16::MAX);
+        assert_eq!(applied.brightness, 16384);
+        assert_eq!(applied.kelvin, 3500);
+    }
+
+    #[test]
+    fn color_spec_partial_keeps_base() {
+        let spec: ColorSpec = "brightness:0".parse().unwrap();
+        let base = HSBK {
+            hue: 100,
+            saturation: 200,
+            brightness: 300,
+            kelvin: 4000,
+        };
+        assert_eq!(
+            spec.apply(base),
+            HSBK {
+                brightness: 0,
+                ..base
+            }
+        );
+    }
+
+    #[test]
+    fn color_spec_rejects_garbage() {
+        for bad in [
+            "",
+            "mauve",
+            "hue:400",
+            "saturation:1.5",
+            "rgb:1,2",
+            "hsbk:1,2,3",
+            "#12345",
+            "volume:11",
+        ] {
+            assert!(bad.parse::<ColorSpec>().is_err(), "{bad} should not parse");
+        }
+    }
+
     #[test]
     fn rgb8_iter() {
         let rgb = RGB8::new(10, 20, 30, None);
@@ -458,6 +751,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "gui")]
     fn contrast_color_mid_gray() {
         let dark_gray = Color32::from_rgb(80, 80, 80);
         assert_eq!(contrast_color(dark_gray), Color32::WHITE);
@@ -16,6 +16,9 @@ use std::time::{Duration, Instant, SystemTime};
 
 const HOUR: Duration = Duration::from_secs(60 * 60);
 
+/// A bulb that hasn't answered for longer than this is shown as offline.
+pub const ONLINE_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct GroupInfo {
     #[serde(with = "LifxIdentDef")]
@@ -224,6 +227,10 @@ impl BulbInfo {
         self.addr = addr;
     }
 
+    pub fn is_online(&self) -> bool {
+        self.last_seen.elapsed() < ONLINE_TIMEOUT
+    }
+
     fn refresh_if_needed<T>(
         &self,
         sock: &UdpSocket,
@@ -255,7 +262,8 @@ impl BulbInfo {
             DeviceColor::Single(d) => self.refresh_if_needed(sock, d)?,
             DeviceColor::Multi(d) | DeviceColor::Matrix(d) => self.refresh_if_needed(sock, d)?,
         }
-        self.features = Features::get_features(self.model.as_ref());
+        self.features =
+            Features::get_features_for_firmware(self.model.as_ref(), self.host_firmware.as_ref());
         if self.features.infrared == Some(true) {
             self.refresh_if_needed(sock, &self.infrared)?;
         }
@@ -300,6 +308,11 @@ impl BulbInfo {
         matches!(self.color, DeviceColor::Matrix(_))
     }
 
+    /// Whether the strip takes every zone's color in one extended multizone message.
+    pub fn supports_extended_multizone(&self) -> bool {
+        self.features.extended_multizone == Some(true)
+    }
+
     pub fn get_zone_colors(&self) -> Option<&Vec<Option<HSBK>>> {
         match &self.color {
             DeviceColor::Multi(ref data) | DeviceColor::Matrix(ref data) => data.as_ref(),
@@ -331,6 +344,15 @@ impl BulbInfo {
             .as_ref()
             .map(|s| s.to_string_lossy().into_owned())
     }
+
+    /// The device serial number (its MAC address) as printed on the bulb and
+    /// used by the LIFX cloud API, e.g. `d073d5001337`.
+    pub fn serial(&self) -> String {
+        self.target.to_le_bytes()[..6]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
 }
 
 /// Helper function to get the first non-None value from a list of colors
@@ -509,7 +531,6 @@ mod tests {
     use lifx_core::{LifxIdent, LifxString, HSBK};
     use std::ffi::CString;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-    use std::u8;
 
     #[test]
     fn test_bulbinfo_new() {
@@ -528,6 +549,14 @@ mod tests {
         assert!(bulb.location.data.is_none());
     }
 
+    #[test]
+    fn test_bulbinfo_serial() {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 56700);
+        let target = u64::from_le_bytes([0xd0, 0x73, 0xd5, 0x00, 0x13, 0x37, 0, 0]);
+        let bulb = BulbInfo::new(1234, target, addr);
+        assert_eq!(bulb.serial(), "d073d5001337");
+    }
+
     #[test]
     fn test_bulbinfo_update() {
         let source = 1234;
@@ -638,7 +667,7 @@ mod tests {
     fn test_groupinfo_get_bulbs() {
         let ident = LifxIdent([1u8; 16]);
         let label = LifxString::new(&CString::new("TestGroup").unwrap());
-        let group = GroupInfo::new(ident.clone(), label);
+        let group = GroupInfo::new(ident, label);
 
         let bulb1 = BulbInfo::new(
             1,
@@ -729,7 +758,7 @@ mod tests {
             brightness: 7000,
             kelvin: 8000,
         });
-        let data = Some(vec![hsbk1.clone(), hsbk2.clone()]);
+        let data = Some(vec![hsbk1, hsbk2]);
 
         let color = extract_primary_color(data.as_ref());
         assert!(color.is_some());
@@ -1,15 +1,18 @@
-use crate::color::{default_hsbk, HSBKField, HSBK32};
-use crate::device_info::{BulbInfo, GroupInfo};
+use crate::color::{default_hsbk, ColorSpec, HSBKField, BASE_COLOR, HSBK32};
+use crate::device_info::{BulbInfo, DeviceInfo, GroupInfo};
+use crate::effects::safety::{SafetyFilter, SafetyLimits};
 use crate::products::Features;
 use crate::refreshable_data::RefreshableData;
 use crate::DeviceColor;
 use get_if_addrs::{get_if_addrs, IfAddr, Ifv4Addr};
 use lifx_core::{
-    get_product_info, ApplicationRequest, BuildOptions, Message, RawMessage, Service, HSBK,
+    get_product_info, ApplicationRequest, BuildOptions, Message, RawMessage, Service, Waveform,
+    HSBK,
 };
 use std::collections::HashMap;
-use std::net::{IpAddr, SocketAddr, UdpSocket};
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::ops::RangeInclusive;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::thread::spawn;
 use std::time::{Duration, Instant};
 
@@ -22,8 +25,15 @@ pub struct LifxManager {
     /// address/port of the client that sent the originating message.  If zero, then the LIFX
     /// device may send a broadcast message that can be received by all clients on the same sub-net.
     pub source: u32,
+    /// Senders notified with a device's target whenever a reply updates its state.
+    listeners: Arc<Mutex<Vec<mpsc::Sender<u64>>>>,
+    /// The photosensitivity limiter every color, power and waveform change passes through.
+    pub safety: Arc<Mutex<SafetyFilter>>,
 }
 
+/// The key the safety filter tracks a light's power level under, apart from its zones.
+const POWER_ZONE: usize = usize::MAX;
+
 impl Clone for LifxManager {
     fn clone(&self) -> Self {
         LifxManager {
@@ -32,22 +42,33 @@ impl Clone for LifxManager {
             last_discovery: self.last_discovery,
             socket: self.socket.try_clone().expect("Failed to clone socket"),
             source: self.source,
+            listeners: self.listeners.clone(),
+            safety: self.safety.clone(),
         }
     }
 }
 
 impl LifxManager {
     pub fn new() -> Result<LifxManager, anyhow::Error> {
-        let sock = UdpSocket::bind("0.0.0.0:56700")?;
+        Self::with_bind_addr("0.0.0.0:56700")
+    }
+
+    /// Create a manager whose socket is bound to `addr` instead of the LIFX port.
+    /// Binding to port 0 lets short-lived tools run alongside the GUI, since
+    /// devices reply to the sender's address when a non-zero source is used.
+    pub fn with_bind_addr(addr: impl ToSocketAddrs) -> Result<LifxManager, anyhow::Error> {
+        let sock = UdpSocket::bind(addr)?;
         sock.set_broadcast(true)?;
 
         let recv_sock = sock.try_clone()?;
 
         let bulbs = Arc::new(Mutex::new(HashMap::new()));
         let receiver_bulbs = bulbs.clone();
+        let listeners = Arc::new(Mutex::new(Vec::new()));
+        let receiver_listeners = listeners.clone();
         let source = 0x72757374;
 
-        spawn(move || Self::worker(recv_sock, source, receiver_bulbs));
+        spawn(move || Self::worker(recv_sock, source, receiver_bulbs, receiver_listeners));
 
         let mut lifx_manager = LifxManager {
             bulbs,
@@ -55,11 +76,23 @@ impl LifxManager {
             socket: sock,
             source,
             all_bulbs_group: GroupInfo::build_all_group(),
+            listeners,
+            safety: Arc::default(),
         };
         lifx_manager.discover()?;
         Ok(lifx_manager)
     }
 
+    /// Receive the target of each device as replies from it are applied to `bulbs`.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> mpsc::Receiver<u64> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(tx);
+        }
+        rx
+    }
+
     /// Handle a `RawMessage` and update the internal state of a device.
     fn handle_message(raw: RawMessage, bulb: &mut BulbInfo) -> Result<(), lifx_core::Error> {
         match Message::from_raw(&raw)? {
@@ -234,6 +267,7 @@ impl LifxManager {
         recv_sock: UdpSocket,
         source: u32,
         receiver_bulbs: Arc<Mutex<HashMap<u64, BulbInfo>>>,
+        listeners: Arc<Mutex<Vec<mpsc::Sender<u64>>>>,
     ) {
         let mut buf = [0; 1024];
         loop {
@@ -251,8 +285,11 @@ impl LifxManager {
                                 .or_insert_with(|| {
                                     BulbInfo::new(source, raw.frame_addr.target, addr)
                                 });
+                            let target = raw.frame_addr.target;
                             if let Err(e) = Self::handle_message(raw, bulb) {
                                 log::error!("Error handling message from {}: {}", addr, e)
+                            } else if let Ok(mut listeners) = listeners.lock() {
+                                listeners.retain(|tx| tx.send(target).is_ok());
                             }
                         }
                     }
@@ -312,6 +349,47 @@ impl LifxManager {
         Ok(count)
     }
 
+    pub fn set_safety_limits(&self, limits: SafetyLimits) {
+        if let Ok(mut safety) = self.safety.lock() {
+            safety.set_limits(limits);
+        }
+    }
+
+    /// Whether the photosensitivity limiter recently held back or slowed what was sent to
+    /// `device`, or to any light in it.
+    pub fn is_limiting(&self, device: &DeviceInfo) -> bool {
+        let Ok(safety) = self.safety.lock() else {
+            return false;
+        };
+        match device {
+            DeviceInfo::Bulb(bulb) => safety.is_engaged(bulb.target),
+            DeviceInfo::Group(group) => self.bulbs.lock().is_ok_and(|bulbs| {
+                group
+                    .get_bulbs(&bulbs)
+                    .iter()
+                    .any(|bulb| safety.is_engaged(bulb.target))
+            }),
+        }
+    }
+
+    /// The transition in milliseconds for a change of `bulb`, lengthened by the safety
+    /// filter when the new `(zone, brightness)` levels would flash.
+    fn safe_duration(
+        &self,
+        bulb: &BulbInfo,
+        levels: impl IntoIterator<Item = (usize, u16)>,
+        duration: u32,
+    ) -> u32 {
+        let Ok(mut safety) = self.safety.lock() else {
+            return duration;
+        };
+        let now = Instant::now();
+        levels
+            .into_iter()
+            .map(|(zone, brightness)| safety.stretch(bulb.target, zone, brightness, duration, now))
+            .fold(duration, u32::max)
+    }
+
     /// Send a message to a specific bulb.
     fn send_message(&self, bulb: &&BulbInfo, message: Message) -> Result<usize, std::io::Error> {
         let target = bulb.addr;
@@ -329,7 +407,18 @@ impl LifxManager {
 
     /// Set the power level of a specific bulb.
     pub fn set_power(&self, bulb: &&BulbInfo, level: u16) -> Result<usize, std::io::Error> {
-        self.send_message(bulb, Message::LightSetPower { level, duration: 0 })
+        self.set_power_with_duration(bulb, level, 0)
+    }
+
+    /// Set the power level of a specific bulb, fading over `duration` milliseconds.
+    pub fn set_power_with_duration(
+        &self,
+        bulb: &&BulbInfo,
+        level: u16,
+        duration: u32,
+    ) -> Result<usize, std::io::Error> {
+        let duration = self.safe_duration(bulb, [(POWER_ZONE, level)], duration);
+        self.send_message(bulb, Message::LightSetPower { level, duration })
     }
 
     /// Set the power level of all bulbs in a group.
@@ -353,6 +442,9 @@ impl LifxManager {
         duration: u32,
         apply: ApplicationRequest,
     ) -> Result<usize, std::io::Error> {
+        let zones =
+            (start_index as usize..=end_index as usize).map(|zone| (zone, color.brightness));
+        let duration = self.safe_duration(bulb, zones, duration);
         self.send_message(
             bulb,
             Message::SetColorZones {
@@ -389,6 +481,10 @@ impl LifxManager {
                 colors[i] = *c;
             }
         }
+        let levels = updates
+            .iter()
+            .map(|(zone, color)| (*zone, color.brightness));
+        let duration = self.safe_duration(bulb, levels, duration);
         self.send_message(
             bulb,
             Message::SetExtendedColorZones {
@@ -401,6 +497,55 @@ impl LifxManager {
         )
     }
 
+    /// Apply `spec` on top of the own color of each zone in `zones` of a multizone or
+    /// matrix bulb, so a partial change such as `brightness:0.5` keeps every zone's hue.
+    /// Strips without extended multizone get one message per run of zones that end up
+    /// the same color.
+    pub fn set_zones_color_spec(
+        &self,
+        bulb: &&BulbInfo,
+        zones: RangeInclusive<usize>,
+        spec: &ColorSpec,
+        duration: u32,
+    ) -> Result<usize, std::io::Error> {
+        let updates: Vec<(usize, HSBK)> = zones
+            .map(|zone| {
+                let base = bulb.get_zone_color(zone).copied().unwrap_or(BASE_COLOR);
+                (zone, spec.apply(base))
+            })
+            .collect();
+        let current = bulb.get_zone_colors().cloned().unwrap_or_default();
+        // One extended multizone message carries up to 82 zones.
+        if bulb.is_matrix() || (bulb.supports_extended_multizone() && current.len() <= 82) {
+            let updates: HashMap<usize, HSBK> = updates.into_iter().collect();
+            return self.set_extended_color_zones(bulb, &current, &updates, duration);
+        }
+        let mut runs: Vec<(usize, usize, HSBK)> = Vec::new();
+        for (zone, color) in updates {
+            match runs.last_mut() {
+                Some((_, end, last)) if *last == color => *end = zone,
+                _ => runs.push((zone, zone, color)),
+            }
+        }
+        let mut sent = 0;
+        for (i, (start, end, color)) in runs.iter().enumerate() {
+            let apply = if i == runs.len() - 1 {
+                ApplicationRequest::Apply
+            } else {
+                ApplicationRequest::NoApply
+            };
+            sent += self.set_color_zones(
+                bulb,
+                (*start).min(u8::MAX as usize) as u8,
+                (*end).min(u8::MAX as usize) as u8,
+                *color,
+                duration,
+                apply,
+            )?;
+        }
+        Ok(sent)
+    }
+
     /// Set the color of a specific bulb.
     pub fn set_color(
         &self,
@@ -408,12 +553,47 @@ impl LifxManager {
         color: HSBK,
         duration: Option<u32>,
     ) -> Result<usize, std::io::Error> {
+        let duration = self.safe_duration(bulb, [(0, color.brightness)], duration.unwrap_or(0u32));
         self.send_message(
             bulb,
             Message::LightSetColor {
                 reserved: 0,
                 color,
-                duration: duration.unwrap_or(0u32),
+                duration,
+            },
+        )
+    }
+
+    /// Run a waveform effect on a specific bulb.
+    /// `period` is the length of one cycle in milliseconds; `skew_ratio` is in `[0, 1]`
+    /// and only affects `Waveform::Pulse` (the fraction of the cycle spent on `color`).
+    /// When `transient` is set the bulb returns to its original color afterwards.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_waveform(
+        &self,
+        bulb: &&BulbInfo,
+        waveform: Waveform,
+        color: HSBK,
+        period: u32,
+        cycles: f32,
+        skew_ratio: f32,
+        transient: bool,
+    ) -> Result<usize, std::io::Error> {
+        let skew_ratio = (skew_ratio.clamp(0.0, 1.0) * 65535.0 - 32768.0).round() as i16;
+        let period = match self.safety.lock() {
+            Ok(mut safety) => safety.stretch_period(bulb.target, period, Instant::now()),
+            Err(_) => period,
+        };
+        self.send_message(
+            bulb,
+            Message::SetWaveform {
+                reserved: 0,
+                transient,
+                color,
+                period,
+                cycles,
+                skew_ratio,
+                waveform,
             },
         )
     }
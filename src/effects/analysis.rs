@@ -0,0 +1,261 @@
+//! Ways of reducing the pixels of a screen capture to the colors the lights show.
+
+use lifx_core::HSBK;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use rust_i18n::t;
+
+use crate::RGB8;
+
+pub const PALETTE_SIZE_RANGE: std::ops::RangeInclusive<usize> = 2..=8;
+
+/// Most pixels clustered per capture; larger captures are sampled evenly.
+pub const MAX_SAMPLES: usize = 4096;
+/// Clusters looked for when picking a dominant or saturated color.
+const CLUSTERS: usize = 5;
+const ITERATIONS: usize = 8;
+/// Smallest share of the pixels a cluster needs to count as the most saturated one.
+const MIN_CLUSTER_SHARE: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum AnalysisMode {
+    /// The average of every pixel.
+    #[default]
+    Average,
+    /// The center of the largest cluster of similar colors.
+    Dominant,
+    /// The most saturated cluster that covers a noticeable part of the screen.
+    Saturated,
+    /// An average in which bright pixels count for more than dark ones.
+    BrightnessWeighted,
+    /// The largest clusters, one per light in turn.
+    Palette,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenAnalysis {
+    pub mode: AnalysisMode,
+    /// Colors extracted in `AnalysisMode::Palette`.
+    pub palette_size: usize,
+}
+
+impl Default for ScreenAnalysis {
+    fn default() -> Self {
+        ScreenAnalysis {
+            mode: AnalysisMode::default(),
+            palette_size: 4,
+        }
+    }
+}
+
+impl ScreenAnalysis {
+    /// The colors for `pixels`, most important first. Every mode but `Palette` gives one
+    /// color; none are given for no pixels.
+    pub fn colors(&self, pixels: &[[u8; 3]]) -> Vec<HSBK> {
+        if pixels.is_empty() {
+            return Vec::new();
+        }
+        let colors = match self.mode {
+            AnalysisMode::Average => vec![weighted_average(pixels, |_| 1.0)],
+            AnalysisMode::BrightnessWeighted => vec![weighted_average(pixels, |pixel| {
+                let value = *pixel.iter().max().unwrap_or(&0) as f32 / u8::MAX as f32;
+                value * value
+            })],
+            AnalysisMode::Dominant => vec![clusters(pixels, CLUSTERS)[0].color],
+            AnalysisMode::Saturated => {
+                let clusters = clusters(pixels, CLUSTERS);
+                let min_count = (pixels.len() as f32 * MIN_CLUSTER_SHARE).ceil() as usize;
+                let saturated = clusters
+                    .iter()
+                    .filter(|cluster| cluster.count >= min_count)
+                    .max_by(|a, b| saturation(a.color).total_cmp(&saturation(b.color)))
+                    .unwrap_or(&clusters[0]);
+                vec![saturated.color]
+            }
+            AnalysisMode::Palette => clusters(pixels, self.palette_size.max(1))
+                .into_iter()
+                .map(|cluster| cluster.color)
+                .collect(),
+        };
+        colors
+            .into_iter()
+            .map(|[red, green, blue]| RGB8::new(red, green, blue, None).into())
+            .collect()
+    }
+}
+
+struct Cluster {
+    color: [u8; 3],
+    count: usize,
+}
+
+fn weighted_average(pixels: &[[u8; 3]], weight: impl Fn(&[u8; 3]) -> f32) -> [u8; 3] {
+    let mut sum = [0.0f32; 3];
+    let mut total = 0.0;
+    for pixel in pixels {
+        let weight = weight(pixel);
+        for (sum, channel) in sum.iter_mut().zip(pixel) {
+            *sum += *channel as f32 * weight;
+        }
+        total += weight;
+    }
+    if total <= 0.0 {
+        return [0, 0, 0];
+    }
+    sum.map(|sum| (sum / total).round() as u8)
+}
+
+fn saturation([red, green, blue]: [u8; 3]) -> f32 {
+    let max = red.max(green).max(blue) as f32;
+    let min = red.min(green).min(blue) as f32;
+    if max == 0.0 {
+        0.0
+    } else {
+        (max - min) / max
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// k-means clusters of `pixels`, largest first. Starts from the average and then the pixels
+/// farthest from the centers so far, so the result doesn't depend on chance.
+fn clusters(pixels: &[[u8; 3]], k: usize) -> Vec<Cluster> {
+    let points: Vec<[f32; 3]> = pixels.iter().map(|p| p.map(|c| c as f32)).collect();
+    let mut centers = vec![weighted_average(pixels, |_| 1.0).map(|c| c as f32)];
+    while centers.len() < k {
+        let farthest = points.iter().copied().max_by(|a, b| {
+            let nearest = |p: &[f32; 3]| {
+                centers
+                    .iter()
+                    .map(|c| distance(*p, *c))
+                    .fold(f32::MAX, f32::min)
+            };
+            nearest(a).total_cmp(&nearest(b))
+        });
+        match farthest {
+            Some(point) if !centers.contains(&point) => centers.push(point),
+            _ => break,
+        }
+    }
+
+    let mut assignment = vec![0; points.len()];
+    for _ in 0..ITERATIONS {
+        for (point, assigned) in points.iter().zip(assignment.iter_mut()) {
+            *assigned = (0..centers.len())
+                .min_by(|a, b| {
+                    distance(*point, centers[*a]).total_cmp(&distance(*point, centers[*b]))
+                })
+                .unwrap_or(0);
+        }
+        let mut sums = vec![([0.0f32; 3], 0usize); centers.len()];
+        for (point, assigned) in points.iter().zip(&assignment) {
+            let (sum, count) = &mut sums[*assigned];
+            for (sum, channel) in sum.iter_mut().zip(point) {
+                *sum += channel;
+            }
+            *count += 1;
+        }
+        for (center, (sum, count)) in centers.iter_mut().zip(&sums) {
+            if *count > 0 {
+                *center = sum.map(|sum| sum / *count as f32);
+            }
+        }
+    }
+
+    let mut counts = vec![0; centers.len()];
+    for assigned in &assignment {
+        counts[*assigned] += 1;
+    }
+    let mut clusters: Vec<Cluster> = centers
+        .into_iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(center, count)| Cluster {
+            color: center.map(|c| c.round() as u8),
+            count,
+        })
+        .collect();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.count));
+    clusters
+}
+
+impl std::fmt::Display for AnalysisMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            AnalysisMode::Average => t!("analysis.average"),
+            AnalysisMode::Dominant => t!("analysis.dominant"),
+            AnalysisMode::Saturated => t!("analysis.saturated"),
+            AnalysisMode::BrightnessWeighted => t!("analysis.brightness_weighted"),
+            AnalysisMode::Palette => t!("analysis.palette"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 3] = [220, 20, 20];
+    const GRAY: [u8; 3] = [100, 100, 100];
+    const BLUE: [u8; 3] = [10, 10, 200];
+
+    fn screen(parts: &[([u8; 3], usize)]) -> Vec<[u8; 3]> {
+        parts
+            .iter()
+            .flat_map(|(color, count)| std::iter::repeat_n(*color, *count))
+            .collect()
+    }
+
+    fn analyze(mode: AnalysisMode, pixels: &[[u8; 3]]) -> Vec<HSBK> {
+        ScreenAnalysis {
+            mode,
+            palette_size: 3,
+        }
+        .colors(pixels)
+    }
+
+    fn rgb(color: [u8; 3]) -> HSBK {
+        RGB8::new(color[0], color[1], color[2], None).into()
+    }
+
+    #[test]
+    fn dominant_and_saturated_pick_different_clusters() {
+        let pixels = screen(&[(GRAY, 70), (RED, 20), (BLUE, 10)]);
+        assert_eq!(analyze(AnalysisMode::Dominant, &pixels), vec![rgb(GRAY)]);
+        assert_eq!(analyze(AnalysisMode::Saturated, &pixels), vec![rgb(BLUE)]);
+        // A sliver of color isn't enough to take over.
+        let pixels = screen(&[(GRAY, 98), (BLUE, 2)]);
+        assert_eq!(analyze(AnalysisMode::Saturated, &pixels), vec![rgb(GRAY)]);
+    }
+
+    #[test]
+    fn brightness_weighting_favours_bright_pixels() {
+        let pixels = screen(&[([0, 0, 0], 50), ([200, 200, 200], 50)]);
+        assert_eq!(
+            analyze(AnalysisMode::Average, &pixels),
+            vec![rgb([100, 100, 100])]
+        );
+        assert_eq!(
+            analyze(AnalysisMode::BrightnessWeighted, &pixels),
+            vec![rgb([200, 200, 200])]
+        );
+        assert!(analyze(AnalysisMode::Average, &[]).is_empty());
+    }
+
+    #[test]
+    fn palette_orders_colors_by_coverage() {
+        let pixels = screen(&[(BLUE, 10), (GRAY, 70), (RED, 20)]);
+        assert_eq!(
+            analyze(AnalysisMode::Palette, &pixels),
+            vec![rgb(GRAY), rgb(RED), rgb(BLUE)]
+        );
+        // Fewer distinct colors than requested gives fewer colors.
+        let pixels = screen(&[(RED, 5)]);
+        assert_eq!(analyze(AnalysisMode::Palette, &pixels), vec![rgb(RED)]);
+    }
+}
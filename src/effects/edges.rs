@@ -0,0 +1,287 @@
+//! Ambilight-style mapping of the edges of a screen image onto the zones of a strip.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use rust_i18n::t;
+
+use super::mirror::MirrorFit;
+
+pub const DEPTH_RANGE: std::ops::RangeInclusive<f32> = 0.02..=0.5;
+
+/// How screen sync turns a captured image into light colors.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ScreenMapping {
+    /// Every light shows the average color of the image.
+    #[default]
+    Average,
+    /// Strips show the colors along an edge of the image, zone by zone. Other lights show
+    /// the average color.
+    Edge(EdgeMapping),
+    /// Matrix lights show the image downsampled to their zones. Other lights show the
+    /// average color.
+    Mirror(MirrorFit),
+}
+
+/// Which part of the image's border a strip follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum ScreenEdge {
+    Top,
+    #[default]
+    Bottom,
+    Left,
+    Right,
+    Perimeter,
+}
+
+/// The corner of the screen nearest the strip's first zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum StartCorner {
+    TopLeft,
+    TopRight,
+    BottomRight,
+    #[default]
+    BottomLeft,
+}
+
+/// The direction a strip runs around the perimeter, as seen from the front of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum EdgeDirection {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeMapping {
+    pub edge: ScreenEdge,
+    pub start: StartCorner,
+    /// Only used for `ScreenEdge::Perimeter`; a single edge runs away from `start`.
+    pub direction: EdgeDirection,
+    /// Shifts the image along the strip by this many zones.
+    pub zone_offset: i32,
+    /// How far into the image each sample reaches, as a fraction of its width or height.
+    pub depth: f32,
+}
+
+impl Default for EdgeMapping {
+    fn default() -> Self {
+        EdgeMapping {
+            edge: ScreenEdge::default(),
+            start: StartCorner::default(),
+            direction: EdgeDirection::default(),
+            zone_offset: 0,
+            depth: 0.1,
+        }
+    }
+}
+
+/// A rectangle of the image, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl EdgeMapping {
+    /// The rectangle of a `width` by `height` image that each of `zones` zones shows, in
+    /// zone order.
+    pub fn regions(&self, width: u32, height: u32, zones: usize) -> Vec<SampleRect> {
+        if width == 0 || height == 0 || zones == 0 {
+            return Vec::new();
+        }
+        let (w, h) = (width as f32, height as f32);
+        let perimeter = 2.0 * (w + h);
+        // Positions are measured clockwise around the border from the top-left corner.
+        let corner = |corner: StartCorner| match corner {
+            StartCorner::TopLeft => 0.0,
+            StartCorner::TopRight => w,
+            StartCorner::BottomRight => w + h,
+            StartCorner::BottomLeft => 2.0 * w + h,
+        };
+        let (from, length, step) = match self.edge {
+            ScreenEdge::Perimeter => {
+                let step = match self.direction {
+                    EdgeDirection::Clockwise => 1.0,
+                    EdgeDirection::CounterClockwise => -1.0,
+                };
+                (corner(self.start), perimeter, step)
+            }
+            edge => {
+                let (first, last, length) = match edge {
+                    ScreenEdge::Top => (StartCorner::TopLeft, StartCorner::TopRight, w),
+                    ScreenEdge::Right => (StartCorner::TopRight, StartCorner::BottomRight, h),
+                    ScreenEdge::Bottom => (StartCorner::BottomRight, StartCorner::BottomLeft, w),
+                    _ => (StartCorner::BottomLeft, StartCorner::TopLeft, h),
+                };
+                if corner_distance(self.start, first) <= corner_distance(self.start, last) {
+                    (corner(first), length, 1.0)
+                } else {
+                    (corner(last), length, -1.0)
+                }
+            }
+        };
+
+        let slot = length / zones as f32;
+        let depth_x = ((w * self.depth).round() as u32).clamp(1, width);
+        let depth_y = ((h * self.depth).round() as u32).clamp(1, height);
+        (0..zones)
+            .map(|zone| {
+                let index = (zone as i64 - self.zone_offset as i64).rem_euclid(zones as i64);
+                let center = (from + step * (index as f32 + 0.5) * slot).rem_euclid(perimeter);
+                // The span of the slot along whichever edge its center falls on.
+                let span = |along: f32, limit: u32| {
+                    let start = (along - slot / 2.0).clamp(0.0, limit as f32 - 1.0) as u32;
+                    let end = ((along + slot / 2.0).ceil() as u32).clamp(start + 1, limit);
+                    (start, end - start)
+                };
+                if center < w {
+                    let (x, width) = span(center, width);
+                    SampleRect {
+                        x,
+                        y: 0,
+                        width,
+                        height: depth_y,
+                    }
+                } else if center < w + h {
+                    let (y, height) = span(center - w, height);
+                    SampleRect {
+                        x: width - depth_x,
+                        y,
+                        width: depth_x,
+                        height,
+                    }
+                } else if center < 2.0 * w + h {
+                    let (x, width) = span(2.0 * w + h - center, width);
+                    SampleRect {
+                        x,
+                        y: height - depth_y,
+                        width,
+                        height: depth_y,
+                    }
+                } else {
+                    let (y, height) = span(perimeter - center, height);
+                    SampleRect {
+                        x: 0,
+                        y,
+                        width: depth_x,
+                        height,
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// How many sides of the screen lie between two corners.
+fn corner_distance(a: StartCorner, b: StartCorner) -> u8 {
+    let (a, b) = (a as u8, b as u8);
+    let apart = a.abs_diff(b);
+    apart.min(4 - apart)
+}
+
+impl std::fmt::Display for ScreenEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            ScreenEdge::Top => t!("edges.top"),
+            ScreenEdge::Bottom => t!("edges.bottom"),
+            ScreenEdge::Left => t!("edges.left"),
+            ScreenEdge::Right => t!("edges.right"),
+            ScreenEdge::Perimeter => t!("edges.perimeter"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl std::fmt::Display for StartCorner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            StartCorner::TopLeft => t!("edges.top_left"),
+            StartCorner::TopRight => t!("edges.top_right"),
+            StartCorner::BottomRight => t!("edges.bottom_right"),
+            StartCorner::BottomLeft => t!("edges.bottom_left"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl std::fmt::Display for EdgeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            EdgeDirection::Clockwise => t!("edges.clockwise"),
+            EdgeDirection::CounterClockwise => t!("edges.counter_clockwise"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(edge: ScreenEdge, start: StartCorner) -> EdgeMapping {
+        EdgeMapping {
+            edge,
+            start,
+            depth: 0.1,
+            ..EdgeMapping::default()
+        }
+    }
+
+    #[test]
+    fn bottom_edge_runs_away_from_the_start_corner() {
+        let regions = mapping(ScreenEdge::Bottom, StartCorner::BottomLeft).regions(100, 50, 4);
+        let xs: Vec<u32> = regions.iter().map(|r| r.x).collect();
+        assert_eq!(xs, vec![0, 25, 50, 75]);
+        assert!(regions
+            .iter()
+            .all(|r| r.y == 45 && r.height == 5 && r.width == 25));
+
+        let regions = mapping(ScreenEdge::Bottom, StartCorner::TopRight).regions(100, 50, 4);
+        let xs: Vec<u32> = regions.iter().map(|r| r.x).collect();
+        assert_eq!(xs, vec![75, 50, 25, 0]);
+    }
+
+    #[test]
+    fn perimeter_follows_direction_and_offset() {
+        let clockwise = mapping(ScreenEdge::Perimeter, StartCorner::TopLeft).regions(100, 100, 4);
+        // One zone per side, starting along the top.
+        assert_eq!(clockwise[0].y, 0);
+        assert_eq!(clockwise[1].x, 90);
+        assert_eq!(clockwise[2].y, 90);
+        assert_eq!(clockwise[3].x, 0);
+
+        let counter_clockwise = EdgeMapping {
+            direction: EdgeDirection::CounterClockwise,
+            ..mapping(ScreenEdge::Perimeter, StartCorner::TopLeft)
+        }
+        .regions(100, 100, 4);
+        assert_eq!(counter_clockwise[0].x, 0);
+        assert_eq!(counter_clockwise[1].y, 90);
+
+        let offset = EdgeMapping {
+            zone_offset: 1,
+            ..mapping(ScreenEdge::Perimeter, StartCorner::TopLeft)
+        }
+        .regions(100, 100, 4);
+        assert_eq!(offset[1], clockwise[0]);
+        assert_eq!(offset[0], clockwise[3]);
+    }
+
+    #[test]
+    fn regions_stay_inside_the_image() {
+        for edge in [ScreenEdge::Left, ScreenEdge::Right, ScreenEdge::Perimeter] {
+            for region in mapping(edge, StartCorner::BottomRight).regions(64, 36, 30) {
+                assert!(region.width > 0 && region.height > 0);
+                assert!(region.x + region.width <= 64, "{:?}", region);
+                assert!(region.y + region.height <= 36, "{:?}", region);
+            }
+        }
+        assert!(mapping(ScreenEdge::Top, StartCorner::TopLeft)
+            .regions(0, 10, 4)
+            .is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::Instant;
+
+use lifx_core::{ApplicationRequest, HSBK};
+use rust_i18n::t;
+
+use super::{DeviceLayout, Effect, EffectInputs, Frame, SpectralFeatures, ENGINE_TICK};
+use crate::{device_info::DeviceInfo, BulbInfo, LifxManager};
+
+/// Shared analysis of the latest audio.
+type AudioSource = Arc<Mutex<SpectralFeatures>>;
+
+type EffectMap = Arc<Mutex<HashMap<u64, RunningEffect>>>;
+
+/// The effects of the engine the app runs, so actions can start and stop effects.
+static ACTIVE_EFFECTS: Mutex<Option<EffectMap>> = Mutex::new(None);
+
+/// Runs every active effect on one thread and sends their frames through `LifxManager`.
+/// Each device or group runs at most one effect at a time.
+pub struct EffectEngine {
+    effects: EffectMap,
+    audio: Arc<Mutex<Option<AudioSource>>>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct RunningEffect {
+    /// Kept outside the state's lock so it can be read while a frame renders.
+    kind: &'static str,
+    state: Arc<Mutex<ActiveEffect>>,
+}
+
+struct ActiveEffect {
+    device: DeviceInfo,
+    effect: Box<dyn Effect>,
+    started: Instant,
+    last_frame: Option<Instant>,
+    /// The last frame sent to each light, so unchanged frames aren't resent.
+    sent: HashMap<u64, Frame>,
+}
+
+impl EffectEngine {
+    pub fn start(manager: LifxManager) -> Self {
+        let effects: EffectMap = Arc::default();
+        let audio: Arc<Mutex<Option<AudioSource>>> = Arc::default();
+        let running = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let (effects, audio, running) = (effects.clone(), audio.clone(), running.clone());
+            spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    let started = Instant::now();
+                    tick(&manager, &effects, &audio);
+                    std::thread::sleep(ENGINE_TICK.saturating_sub(started.elapsed()));
+                }
+            })
+        };
+        EffectEngine {
+            effects,
+            audio,
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    /// Share the audio analysis that effects receive as `EffectInputs::audio`.
+    pub fn set_audio_source(&self, features: AudioSource) {
+        if let Ok(mut audio) = self.audio.lock() {
+            *audio = Some(features);
+        }
+    }
+
+    /// Make this the engine that `start_active_effect` and `stop_active_effect` use.
+    pub fn make_active(&self) {
+        if let Ok(mut active) = ACTIVE_EFFECTS.lock() {
+            *active = Some(self.effects.clone());
+        }
+    }
+
+    /// Run `effect` on `device`, replacing the effect already running there.
+    pub fn start_effect(&self, device: DeviceInfo, effect: Box<dyn Effect>) {
+        insert_effect(&self.effects, device, effect);
+    }
+
+    /// Stop the effect on the device with `device_id`. Its lights keep their last colors.
+    pub fn stop_effect(&self, device_id: u64) {
+        if let Ok(mut effects) = self.effects.lock() {
+            effects.remove(&device_id);
+        }
+    }
+
+    pub fn stop_all(&self) {
+        if let Ok(mut effects) = self.effects.lock() {
+            effects.clear();
+        }
+    }
+
+    /// The kind of effect running on the device with `device_id`.
+    pub fn active_kind(&self, device_id: u64) -> Option<&'static str> {
+        self.effects
+            .lock()
+            .ok()?
+            .get(&device_id)
+            .map(|effect| effect.kind)
+    }
+
+    pub fn is_running(&self, device_id: u64, kind: &str) -> bool {
+        self.active_kind(device_id) == Some(kind)
+    }
+}
+
+impl Drop for EffectEngine {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_EFFECTS.lock() {
+            if active
+                .as_ref()
+                .is_some_and(|effects| Arc::ptr_eq(effects, &self.effects))
+            {
+                *active = None;
+            }
+        }
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Run `effect` on `device` with the active engine.
+pub fn start_active_effect(
+    device: DeviceInfo,
+    effect: Box<dyn Effect>,
+) -> Result<(), anyhow::Error> {
+    let effects = active_effects()?;
+    insert_effect(&effects, device, effect);
+    Ok(())
+}
+
+/// Stop the effect on the device with `device_id` in the active engine.
+pub fn stop_active_effect(device_id: u64) -> Result<(), anyhow::Error> {
+    if let Ok(mut effects) = active_effects()?.lock() {
+        effects.remove(&device_id);
+    }
+    Ok(())
+}
+
+fn active_effects() -> Result<EffectMap, anyhow::Error> {
+    ACTIVE_EFFECTS
+        .lock()
+        .ok()
+        .and_then(|active| active.clone())
+        .ok_or_else(|| anyhow::anyhow!(t!("error.effects_off")))
+}
+
+fn insert_effect(
+    effects: &Mutex<HashMap<u64, RunningEffect>>,
+    device: DeviceInfo,
+    effect: Box<dyn Effect>,
+) {
+    let kind = effect.kind();
+    let state = ActiveEffect {
+        device: device.clone(),
+        effect,
+        started: Instant::now(),
+        last_frame: None,
+        sent: HashMap::new(),
+    };
+    if let Ok(mut effects) = effects.lock() {
+        effects.insert(
+            device.id(),
+            RunningEffect {
+                kind,
+                state: Arc::new(Mutex::new(state)),
+            },
+        );
+    }
+}
+
+fn tick(
+    manager: &LifxManager,
+    effects: &Mutex<HashMap<u64, RunningEffect>>,
+    audio: &Mutex<Option<AudioSource>>,
+) {
+    // Render outside the map lock so starting or stopping an effect never waits on one.
+    let active: Vec<Arc<Mutex<ActiveEffect>>> = match effects.lock() {
+        Ok(effects) => effects.values().map(|e| e.state.clone()).collect(),
+        Err(_) => return,
+    };
+    if active.is_empty() {
+        return;
+    }
+    let inputs = EffectInputs {
+        audio: audio
+            .lock()
+            .ok()
+            .and_then(|audio| {
+                audio
+                    .as_ref()
+                    .and_then(|s| s.lock().ok().map(|s| s.clone()))
+            })
+            .unwrap_or_default(),
+    };
+    for effect in active {
+        let Ok(mut effect) = effect.lock() else {
+            continue;
+        };
+        if effect
+            .last_frame
+            .is_some_and(|last| last.elapsed() < effect.effect.interval())
+        {
+            continue;
+        }
+        effect.last_frame = Some(Instant::now());
+        let bulbs = effect_bulbs(manager, &effect.device);
+        let layouts: Vec<DeviceLayout> = bulbs
+            .iter()
+            .enumerate()
+            .map(|(index, bulb)| DeviceLayout::from_bulb(bulb, index))
+            .collect();
+        let time = effect.started.elapsed();
+        let frames = effect.effect.render(time, &layouts, &inputs);
+        let transition = effect.effect.transition_ms();
+        for (target, frame) in frames {
+            let frame = match manager.safety.lock() {
+                Ok(mut safety) => safety.apply(target, frame, Instant::now()),
+                Err(_) => continue,
+            };
+            if effect.sent.get(&target) == Some(&frame) {
+                continue;
+            }
+            let Some(bulb) = bulbs.iter().find(|b| b.target == target) else {
+                continue;
+            };
+            if let Err(e) = send_frame(manager, bulb, &frame, transition) {
+                log::error!("Failed to send effect frame: {}", e);
+                continue;
+            }
+            effect.sent.insert(target, frame);
+        }
+    }
+}
+
+/// The lights of `device`, ordered by label so effects can lay them out consistently.
+fn effect_bulbs(manager: &LifxManager, device: &DeviceInfo) -> Vec<BulbInfo> {
+    let Ok(bulbs) = manager.bulbs.lock() else {
+        return Vec::new();
+    };
+    let mut selected: Vec<BulbInfo> = match device {
+        DeviceInfo::Bulb(bulb) => bulbs.get(&bulb.target).cloned().into_iter().collect(),
+        DeviceInfo::Group(group) => group.get_bulbs(&bulbs).into_iter().cloned().collect(),
+    };
+    selected.sort_by_key(|b| (b.name_label(), b.target));
+    selected
+}
+
+fn send_frame(
+    manager: &LifxManager,
+    bulb: &BulbInfo,
+    frame: &Frame,
+    duration: u32,
+) -> Result<(), std::io::Error> {
+    match frame {
+        Frame::Whole(color) => manager.set_color(&bulb, *color, Some(duration)).map(|_| ()),
+        Frame::Zones(colors) if colors.is_empty() => Ok(()),
+        Frame::Zones(colors) => match bulb.get_zone_colors() {
+            // One extended multizone message carries up to 82 zones.
+            Some(current)
+                if bulb.is_matrix()
+                    || (bulb.supports_extended_multizone() && current.len() <= 82) =>
+            {
+                let updates: HashMap<usize, HSBK> = colors
+                    .iter()
+                    .take(current.len())
+                    .copied()
+                    .enumerate()
+                    .collect();
+                manager
+                    .set_extended_color_zones(&bulb, current, &updates, duration)
+                    .map(|_| ())
+            }
+            Some(current) => {
+                let colors = &colors[..colors.len().min(current.len())];
+                let runs = zone_runs(colors);
+                for (i, (start, end, color)) in runs.iter().enumerate() {
+                    let apply = if i == runs.len() - 1 {
+                        ApplicationRequest::Apply
+                    } else {
+                        ApplicationRequest::NoApply
+                    };
+                    manager.set_color_zones(
+                        &bulb,
+                        *start as u8,
+                        *end as u8,
+                        *color,
+                        duration,
+                        apply,
+                    )?;
+                }
+                Ok(())
+            }
+            None => manager
+                .set_color(&bulb, colors[0], Some(duration))
+                .map(|_| ()),
+        },
+    }
+}
+
+/// Collapse per-zone colors into `(start, end, color)` runs of equal neighbours, so a
+/// strip showing few distinct colors takes few messages.
+fn zone_runs(colors: &[HSBK]) -> Vec<(usize, usize, HSBK)> {
+    let mut runs: Vec<(usize, usize, HSBK)> = Vec::new();
+    for (i, color) in colors.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end, last)) if last == color => *end = i,
+            _ => runs.push((i, i, *color)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive};
+    use lifx_core::Message;
+    use std::time::Duration;
+
+    const RED: HSBK = HSBK {
+        hue: 0,
+        saturation: u16::MAX,
+        brightness: u16::MAX,
+        kelvin: 3500,
+    };
+    const BLUE: HSBK = HSBK {
+        hue: 43690,
+        saturation: u16::MAX,
+        brightness: u16::MAX,
+        kelvin: 3500,
+    };
+
+    /// Shows `RED` until one second has passed, then `BLUE`, with a 10ms transition.
+    struct TwoColors;
+
+    impl Effect for TwoColors {
+        fn kind(&self) -> &'static str {
+            "two_colors"
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        fn render(
+            &mut self,
+            time: Duration,
+            devices: &[DeviceLayout],
+            _inputs: &EffectInputs,
+        ) -> Vec<(u64, Frame)> {
+            let color = if time < Duration::from_secs(1) {
+                RED
+            } else {
+                BLUE
+            };
+            devices
+                .iter()
+                .map(|device| (device.target, Frame::Whole(color)))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn sends_frames_only_when_they_change() {
+        let (manager, bulb) = manager_with_fake_bulb(u16::MAX);
+        let device = DeviceInfo::Bulb(Box::new(manager.bulbs.lock().unwrap()[&42].clone()));
+        let engine = EffectEngine::start(manager);
+        engine.start_effect(device, Box::new(TwoColors));
+        assert!(engine.is_running(42, "two_colors"));
+
+        match receive(&bulb) {
+            Message::LightSetColor {
+                color, duration, ..
+            } => {
+                assert_eq!(color, RED);
+                assert_eq!(duration, 10);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        // The next message is the change to blue, not a repeat of red.
+        match receive(&bulb) {
+            Message::LightSetColor { color, .. } => assert_eq!(color, BLUE),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        engine.stop_effect(42);
+        assert_eq!(engine.active_kind(42), None);
+    }
+
+    #[test]
+    fn groups_report_the_limiter_on_any_of_their_lights() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let bulb = manager.bulbs.lock().unwrap()[&42].clone();
+        let group = DeviceInfo::Group(bulb.group.data.clone().unwrap());
+        assert!(!manager.is_limiting(&group));
+        // Red/black faster than three flashes a second gets its transitions stretched.
+        for step in 0..4 {
+            let color = HSBK {
+                brightness: if step % 2 == 0 { u16::MAX } else { 0 },
+                ..RED
+            };
+            manager.set_color(&&bulb, color, None).unwrap();
+        }
+        assert!(manager.is_limiting(&group));
+        assert!(manager.is_limiting(&DeviceInfo::Bulb(Box::new(bulb))));
+    }
+
+    #[test]
+    fn layouts_describe_zones() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let bulbs = manager.bulbs.lock().unwrap();
+        let layout = DeviceLayout::from_bulb(&bulbs[&42], 0);
+        assert_eq!(layout.label, "Desk");
+        assert_eq!(layout.zones, super::super::ZoneLayout::Single);
+        assert!(layout.colors.is_empty());
+    }
+
+    #[test]
+    fn zone_runs_merge_equal_neighbours() {
+        assert_eq!(
+            zone_runs(&[RED, RED, BLUE, RED]),
+            vec![(0, 1, RED), (2, 2, BLUE), (3, 3, RED)]
+        );
+        assert!(zone_runs(&[]).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! Parts of a screen capture left out of screen sync: letterbox and pillarbox bars found
+//! automatically, and rectangles the user marks, such as a taskbar.
+
+use serde::{Deserialize, Serialize};
+
+use super::edges::SampleRect;
+
+/// Bars can't be taller or wider than this fraction of the capture, so a dark scene isn't
+/// mistaken for one big letterbox.
+const MAX_BAR_FRACTION: f32 = 1.0 / 3.0;
+/// Share of a row or column that has to be dark for it to count as part of a bar, leaving
+/// room for subtitles and logos.
+const BAR_DARK_SHARE: f32 = 0.98;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureExclusions {
+    /// Leave out near-black bars at the edges of the capture.
+    pub auto_letterbox: bool,
+    pub rects: Vec<ExclusionRect>,
+}
+
+impl Default for CaptureExclusions {
+    fn default() -> Self {
+        CaptureExclusions {
+            auto_letterbox: true,
+            rects: Vec::new(),
+        }
+    }
+}
+
+/// A rectangle of the capture, in fractions of its width and height.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExclusionRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for ExclusionRect {
+    /// A strip along the bottom, where taskbars usually are.
+    fn default() -> Self {
+        ExclusionRect {
+            x: 0.0,
+            y: 0.95,
+            width: 1.0,
+            height: 0.05,
+        }
+    }
+}
+
+impl ExclusionRect {
+    /// The rectangle in pixels of a `width` by `height` capture, or `None` if it misses it.
+    pub fn to_pixels(&self, width: u32, height: u32) -> Option<SampleRect> {
+        let span = |start: f32, size: f32, limit: u32| {
+            let from = (start.clamp(0.0, 1.0) * limit as f32).round() as u32;
+            let to = ((start + size).clamp(0.0, 1.0) * limit as f32).round() as u32;
+            (to > from).then_some((from, to - from))
+        };
+        let (x, width) = span(self.x, self.width, width)?;
+        let (y, height) = span(self.y, self.height, height)?;
+        Some(SampleRect {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+/// Thickness of the bars at each edge of a capture, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Insets {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Insets {
+    /// What is left of a `width` by `height` capture inside the bars.
+    pub fn inner(&self, width: u32, height: u32) -> SampleRect {
+        SampleRect {
+            x: self.left,
+            y: self.top,
+            width: width - self.left - self.right,
+            height: height - self.top - self.bottom,
+        }
+    }
+}
+
+/// Find letterbox bars above and below and pillarbox bars beside the picture of a `width`
+/// by `height` capture, where `dark(x, y)` tells whether a pixel is near black.
+pub fn detect_letterbox(width: u32, height: u32, dark: impl Fn(u32, u32) -> bool) -> Insets {
+    if width == 0 || height == 0 {
+        return Insets::default();
+    }
+    let row_is_bar = |y: u32| {
+        let count = (0..width).filter(|x| dark(*x, y)).count();
+        count as f32 >= width as f32 * BAR_DARK_SHARE
+    };
+    let column_is_bar = |x: u32| {
+        let count = (0..height).filter(|y| dark(x, *y)).count();
+        count as f32 >= height as f32 * BAR_DARK_SHARE
+    };
+    let max_rows = (height as f32 * MAX_BAR_FRACTION) as u32;
+    let max_columns = (width as f32 * MAX_BAR_FRACTION) as u32;
+    let top = (0..max_rows).take_while(|y| row_is_bar(*y)).count() as u32;
+    let bottom = (0..max_rows)
+        .take_while(|y| row_is_bar(height - 1 - y))
+        .count() as u32;
+    let left = (0..max_columns).take_while(|x| column_is_bar(*x)).count() as u32;
+    let right = (0..max_columns)
+        .take_while(|x| column_is_bar(width - 1 - x))
+        .count() as u32;
+    // A capture that is dark all over has no picture to find bars around.
+    let all_dark =
+        top == max_rows && bottom == max_rows && (max_rows..height - max_rows).all(row_is_bar);
+    if all_dark {
+        return Insets::default();
+    }
+    Insets {
+        top,
+        bottom,
+        left,
+        right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` by `height` capture with a bright picture inside `picture`.
+    fn capture(picture: SampleRect) -> impl Fn(u32, u32) -> bool {
+        move |x, y| {
+            let inside = x >= picture.x
+                && x < picture.x + picture.width
+                && y >= picture.y
+                && y < picture.y + picture.height;
+            !inside
+        }
+    }
+
+    #[test]
+    fn finds_letterbox_and_pillarbox_bars() {
+        let picture = SampleRect {
+            x: 0,
+            y: 12,
+            width: 160,
+            height: 66,
+        };
+        let insets = detect_letterbox(160, 90, capture(picture));
+        assert_eq!(
+            insets,
+            Insets {
+                top: 12,
+                bottom: 12,
+                left: 0,
+                right: 0
+            }
+        );
+        assert_eq!(insets.inner(160, 90), picture);
+
+        let picture = SampleRect {
+            x: 20,
+            y: 0,
+            width: 120,
+            height: 90,
+        };
+        let insets = detect_letterbox(160, 90, capture(picture));
+        assert_eq!((insets.left, insets.right, insets.top), (20, 20, 0));
+    }
+
+    #[test]
+    fn dark_scenes_are_not_bars() {
+        assert_eq!(detect_letterbox(160, 90, |_, _| true), Insets::default());
+        // Bars are capped, so a mostly dark scene keeps its middle.
+        let picture = SampleRect {
+            x: 0,
+            y: 40,
+            width: 160,
+            height: 10,
+        };
+        let insets = detect_letterbox(160, 90, capture(picture));
+        assert_eq!((insets.top, insets.bottom), (30, 30));
+    }
+
+    #[test]
+    fn exclusion_rects_scale_to_the_capture() {
+        let taskbar = ExclusionRect::default();
+        assert_eq!(
+            taskbar.to_pixels(1920, 1080),
+            Some(SampleRect {
+                x: 0,
+                y: 1026,
+                width: 1920,
+                height: 54
+            })
+        );
+        let outside = ExclusionRect {
+            x: 1.2,
+            ..ExclusionRect::default()
+        };
+        assert_eq!(outside.to_pixels(1920, 1080), None);
+    }
+}
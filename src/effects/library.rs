@@ -0,0 +1,715 @@
+//! Built-in effects that animate lights from a palette, tuned by a speed and an intensity.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lifx_core::HSBK;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use super::{DeviceLayout, Effect, EffectInputs, Frame, ZoneLayout};
+use crate::color::mix_hsbk;
+
+pub const SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.1..=5.0;
+pub const INTENSITY_RANGE: std::ops::RangeInclusive<f32> = 0.0..=1.0;
+
+/// The effects in the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectKind {
+    #[default]
+    Candle,
+    ColorCycle,
+    Strobe,
+    Twinkle,
+    Chase,
+    Lightning,
+}
+
+impl EffectKind {
+    /// The `Effect::kind` of effects of this kind.
+    pub fn id(&self) -> &'static str {
+        match self {
+            EffectKind::Candle => "candle",
+            EffectKind::ColorCycle => "color_cycle",
+            EffectKind::Strobe => "strobe",
+            EffectKind::Twinkle => "twinkle",
+            EffectKind::Chase => "chase",
+            EffectKind::Lightning => "lightning",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::iter().find(|kind| kind.id() == id)
+    }
+
+    pub fn default_palette(&self) -> Palette {
+        match self {
+            EffectKind::Candle => Palette::Warm,
+            EffectKind::Strobe | EffectKind::Lightning => Palette::White,
+            EffectKind::ColorCycle | EffectKind::Twinkle | EffectKind::Chase => Palette::Rainbow,
+        }
+    }
+}
+
+impl Display for EffectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EffectKind::Candle => t!("effects.kind.candle"),
+            EffectKind::ColorCycle => t!("effects.kind.color_cycle"),
+            EffectKind::Strobe => t!("effects.kind.strobe"),
+            EffectKind::Twinkle => t!("effects.kind.twinkle"),
+            EffectKind::Chase => t!("effects.kind.chase"),
+            EffectKind::Lightning => t!("effects.kind.lightning"),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The colors an effect draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    #[default]
+    Rainbow,
+    Warm,
+    Cool,
+    Fire,
+    Ocean,
+    White,
+}
+
+impl Palette {
+    pub fn colors(&self) -> Vec<HSBK> {
+        let hues: &[(f32, f32, u16)] = match self {
+            Palette::Rainbow => &[
+                (0.0, 1.0, 3500),
+                (30.0, 1.0, 3500),
+                (60.0, 1.0, 3500),
+                (120.0, 1.0, 3500),
+                (180.0, 1.0, 3500),
+                (240.0, 1.0, 3500),
+                (290.0, 1.0, 3500),
+            ],
+            Palette::Warm => &[(25.0, 0.85, 2500), (32.0, 0.75, 2500), (40.0, 0.6, 2500)],
+            Palette::Cool => &[
+                (180.0, 0.8, 6500),
+                (210.0, 0.8, 6500),
+                (240.0, 0.8, 6500),
+                (270.0, 0.7, 6500),
+            ],
+            Palette::Fire => &[
+                (0.0, 1.0, 2500),
+                (15.0, 1.0, 2500),
+                (30.0, 1.0, 2500),
+                (45.0, 0.9, 2500),
+            ],
+            Palette::Ocean => &[
+                (170.0, 0.9, 5000),
+                (190.0, 0.9, 5000),
+                (210.0, 0.9, 5000),
+                (230.0, 0.9, 5000),
+            ],
+            Palette::White => &[(0.0, 0.0, 6500), (0.0, 0.0, 4000)],
+        };
+        hues.iter()
+            .map(|&(hue, saturation, kelvin)| HSBK {
+                hue: (hue / 360.0 * u16::MAX as f32) as u16,
+                saturation: (saturation * u16::MAX as f32) as u16,
+                brightness: u16::MAX,
+                kelvin,
+            })
+            .collect()
+    }
+}
+
+impl Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Palette::Rainbow => t!("effects.palette.rainbow"),
+            Palette::Warm => t!("effects.palette.warm"),
+            Palette::Cool => t!("effects.palette.cool"),
+            Palette::Fire => t!("effects.palette.fire"),
+            Palette::Ocean => t!("effects.palette.ocean"),
+            Palette::White => t!("effects.palette.white"),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A library effect and its parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectConfig {
+    pub kind: EffectKind,
+    pub palette: Palette,
+    /// Multiplier on the effect's base rate, within `SPEED_RANGE`.
+    pub speed: f32,
+    /// How pronounced the effect is, from 0 to 1. Each effect describes its meaning.
+    pub intensity: f32,
+}
+
+impl Default for EffectConfig {
+    fn default() -> Self {
+        EffectConfig::new(EffectKind::default())
+    }
+}
+
+impl EffectConfig {
+    pub fn new(kind: EffectKind) -> Self {
+        EffectConfig {
+            kind,
+            palette: kind.default_palette(),
+            speed: 1.0,
+            intensity: 0.5,
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Effect> {
+        let params = Params {
+            colors: self.palette.colors(),
+            speed: self.speed.clamp(*SPEED_RANGE.start(), *SPEED_RANGE.end()),
+            intensity: self
+                .intensity
+                .clamp(*INTENSITY_RANGE.start(), *INTENSITY_RANGE.end()),
+            rng: Rng::seeded(),
+        };
+        match self.kind {
+            EffectKind::Candle => Box::new(Candle {
+                params,
+                levels: HashMap::new(),
+            }),
+            EffectKind::ColorCycle => Box::new(ColorCycle { params }),
+            EffectKind::Strobe => Box::new(Strobe { params }),
+            EffectKind::Twinkle => Box::new(Twinkle {
+                params,
+                sparkles: HashMap::new(),
+            }),
+            EffectKind::Chase => Box::new(Chase { params }),
+            EffectKind::Lightning => Box::new(Lightning {
+                params,
+                strike: None,
+            }),
+        }
+    }
+}
+
+struct Params {
+    colors: Vec<HSBK>,
+    speed: f32,
+    intensity: f32,
+    rng: Rng,
+}
+
+impl Params {
+    fn color(&self, index: usize) -> HSBK {
+        self.colors[index % self.colors.len()]
+    }
+
+    /// `base` divided by the speed.
+    fn scaled_interval(&self, base: Duration) -> Duration {
+        base.div_f32(self.speed)
+    }
+}
+
+/// A small xorshift generator; effects only need cheap, unpredictable-looking numbers.
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Rng(nanos | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0.0..1.0`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+fn with_brightness(color: HSBK, brightness: f32) -> HSBK {
+    HSBK {
+        brightness: (brightness.clamp(0.0, 1.0) * u16::MAX as f32) as u16,
+        ..color
+    }
+}
+
+/// A frame with `color(zone)` for each zone of `device`.
+fn frame(device: &DeviceLayout, mut color: impl FnMut(usize) -> HSBK) -> Frame {
+    match device.zones {
+        ZoneLayout::Single => Frame::Whole(color(0)),
+        zones => Frame::Zones((0..zones.zone_count()).map(color).collect()),
+    }
+}
+
+/// Flickers each zone like a flame. Intensity sets how deep the flicker goes.
+struct Candle {
+    params: Params,
+    levels: HashMap<(u64, usize), f32>,
+}
+
+impl Effect for Candle {
+    fn kind(&self) -> &'static str {
+        EffectKind::Candle.id()
+    }
+
+    fn interval(&self) -> Duration {
+        self.params.scaled_interval(Duration::from_millis(150))
+    }
+
+    fn render(
+        &mut self,
+        _time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let Candle { params, levels } = self;
+        devices
+            .iter()
+            .map(|device| {
+                let frame = frame(device, |zone| {
+                    let level = levels.entry((device.target, zone)).or_insert(1.0);
+                    // Mostly bright, with the occasional deep dip.
+                    let target = 1.0 - params.intensity * params.rng.next_f32().powi(2);
+                    *level = (*level + target) / 2.0;
+                    with_brightness(params.color(device.index + zone), *level)
+                });
+                (device.target, frame)
+            })
+            .collect()
+    }
+}
+
+/// Cycles through the palette. Intensity sets how much of the palette is spread across
+/// the lights, or across the zones of a single light, at once.
+struct ColorCycle {
+    params: Params,
+}
+
+impl ColorCycle {
+    const PERIOD: Duration = Duration::from_secs(10);
+
+    fn color_at(&self, position: f32) -> HSBK {
+        let colors = &self.params.colors;
+        let scaled = position.rem_euclid(1.0) * colors.len() as f32;
+        let index = scaled as usize % colors.len();
+        mix_hsbk(
+            colors[index],
+            colors[(index + 1) % colors.len()],
+            scaled.fract(),
+        )
+    }
+}
+
+impl Effect for ColorCycle {
+    fn kind(&self) -> &'static str {
+        EffectKind::ColorCycle.id()
+    }
+
+    fn render(
+        &mut self,
+        time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let position = time.as_secs_f32() * self.params.speed / Self::PERIOD.as_secs_f32();
+        let spread = self.params.intensity;
+        devices
+            .iter()
+            .map(|device| {
+                let device_offset = spread * device.index as f32 / devices.len() as f32;
+                let zones = device.zones.zone_count() as f32;
+                let frame = frame(device, |zone| {
+                    let zone_offset = spread * zone as f32 / zones / devices.len() as f32;
+                    self.color_at(position + device_offset + zone_offset)
+                });
+                (device.target, frame)
+            })
+            .collect()
+    }
+}
+
+/// Flashes every light on and off, stepping through the palette. Intensity sets the
+/// brightness of the flashes.
+struct Strobe {
+    params: Params,
+}
+
+impl Strobe {
+    /// Flashes per second at a speed of 1.
+    const RATE: f32 = 2.0;
+    /// Most flashes per second at any speed, below the 3 Hz photosensitivity threshold.
+    const MAX_RATE: f32 = 2.5;
+
+    fn rate(&self) -> f32 {
+        (Self::RATE * self.params.speed).min(Self::MAX_RATE)
+    }
+}
+
+impl Effect for Strobe {
+    fn kind(&self) -> &'static str {
+        EffectKind::Strobe.id()
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f32(0.5 / self.rate())
+    }
+
+    fn transition_ms(&self) -> u32 {
+        0
+    }
+
+    fn render(
+        &mut self,
+        time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let flashes = time.as_secs_f32() * self.rate();
+        let color = self.params.color(flashes as usize);
+        let brightness = if flashes.fract() < 0.5 {
+            self.params.intensity.max(0.1)
+        } else {
+            0.0
+        };
+        devices
+            .iter()
+            .map(|device| {
+                (
+                    device.target,
+                    Frame::Whole(with_brightness(color, brightness)),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Lights random zones in palette colors that fade back to a dim glow. Intensity sets
+/// how many zones sparkle.
+struct Twinkle {
+    params: Params,
+    /// The brightness and color of every zone, keyed by light.
+    sparkles: HashMap<u64, Vec<(f32, HSBK)>>,
+}
+
+impl Twinkle {
+    const GLOW: f32 = 0.05;
+    const FADE: f32 = 0.7;
+}
+
+impl Effect for Twinkle {
+    fn kind(&self) -> &'static str {
+        EffectKind::Twinkle.id()
+    }
+
+    fn interval(&self) -> Duration {
+        self.params.scaled_interval(Duration::from_millis(120))
+    }
+
+    fn render(
+        &mut self,
+        _time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let Twinkle { params, sparkles } = self;
+        devices
+            .iter()
+            .map(|device| {
+                let zones = sparkles.entry(device.target).or_default();
+                zones.resize(device.zones.zone_count(), (Self::GLOW, params.color(0)));
+                for zone in zones.iter_mut() {
+                    zone.0 = (zone.0 * Self::FADE).max(Self::GLOW);
+                    if params.rng.chance(params.intensity * 0.2) {
+                        let index = params.rng.next_u64() as usize;
+                        *zone = (1.0, params.color(index));
+                    }
+                }
+                let frame = frame(device, |zone| {
+                    let (level, color) = zones[zone];
+                    with_brightness(color, level)
+                });
+                (device.target, frame)
+            })
+            .collect()
+    }
+}
+
+/// Runs a light along the members of a group in order, or along the zones of a single
+/// light, changing color each lap. Intensity sets the length of the trail behind it.
+struct Chase {
+    params: Params,
+}
+
+impl Chase {
+    /// Steps per second at a speed of 1.
+    const RATE: f32 = 2.0;
+}
+
+impl Effect for Chase {
+    fn kind(&self) -> &'static str {
+        EffectKind::Chase.id()
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / (Self::RATE * self.params.speed))
+    }
+
+    fn transition_ms(&self) -> u32 {
+        self.interval().as_millis() as u32 / 2
+    }
+
+    fn render(
+        &mut self,
+        time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let zone_chase = devices.len() == 1;
+        let steps = match devices {
+            [device] => device.zones.zone_count(),
+            _ => devices.len(),
+        };
+        if steps == 0 {
+            return Vec::new();
+        }
+        let step = (time.as_secs_f32() * Self::RATE * self.params.speed) as usize;
+        let head = step % steps;
+        let color = self.params.color(step / steps);
+        let trail = 1.0 + self.params.intensity * (steps - 1) as f32;
+        let brightness = |position: usize| {
+            let behind = (head + steps - position) % steps;
+            (1.0 - behind as f32 / trail).max(0.0)
+        };
+        devices
+            .iter()
+            .map(|device| {
+                let frame = if zone_chase {
+                    frame(device, |zone| with_brightness(color, brightness(zone)))
+                } else {
+                    Frame::Whole(with_brightness(color, brightness(device.index)))
+                };
+                (device.target, frame)
+            })
+            .collect()
+    }
+}
+
+/// A dark sky with sudden flashes of lightning on some of the lights. Intensity sets how
+/// often lightning strikes and how many lights it reaches.
+struct Lightning {
+    params: Params,
+    strike: Option<Strike>,
+}
+
+struct Strike {
+    started: Duration,
+    color: HSBK,
+    targets: Vec<u64>,
+}
+
+impl Lightning {
+    const SKY: HSBK = HSBK {
+        hue: 43690,
+        saturation: 39321,
+        brightness: 1310,
+        kelvin: 6500,
+    };
+
+    /// Brightness of a strike over time: a bright flash, a flicker and a fading afterglow.
+    fn strike_brightness(elapsed: Duration) -> Option<f32> {
+        match elapsed.as_millis() {
+            0..50 => Some(1.0),
+            50..100 => Some(0.15),
+            100..150 => Some(0.8),
+            150..400 => Some(0.8 * (400 - elapsed.as_millis()) as f32 / 250.0),
+            _ => None,
+        }
+    }
+}
+
+impl Effect for Lightning {
+    fn kind(&self) -> &'static str {
+        EffectKind::Lightning.id()
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(50)
+    }
+
+    fn transition_ms(&self) -> u32 {
+        0
+    }
+
+    fn render(
+        &mut self,
+        time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let params = &mut self.params;
+        let strike_ended = self
+            .strike
+            .as_ref()
+            .is_none_or(|strike| Self::strike_brightness(time - strike.started).is_none());
+        if strike_ended {
+            self.strike = None;
+            let chance = 0.01 * params.speed * (0.5 + params.intensity);
+            if params.rng.chance(chance) {
+                let reach = 0.3 + 0.7 * params.intensity;
+                let mut targets: Vec<u64> = devices
+                    .iter()
+                    .filter(|_| params.rng.chance(reach))
+                    .map(|device| device.target)
+                    .collect();
+                if targets.is_empty() {
+                    let index = params.rng.next_u64() as usize;
+                    targets.extend(devices.get(index % devices.len().max(1)).map(|d| d.target));
+                }
+                let index = params.rng.next_u64() as usize;
+                self.strike = Some(Strike {
+                    started: time,
+                    color: params.color(index),
+                    targets,
+                });
+            }
+        }
+        devices
+            .iter()
+            .map(|device| {
+                let flash = self.strike.as_ref().and_then(|strike| {
+                    let brightness = Self::strike_brightness(time - strike.started)?;
+                    strike
+                        .targets
+                        .contains(&device.target)
+                        .then(|| with_brightness(strike.color, brightness))
+                });
+                (device.target, Frame::Whole(flash.unwrap_or(Self::SKY)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(target: u64, index: usize, zones: ZoneLayout) -> DeviceLayout {
+        DeviceLayout {
+            target,
+            label: format!("Light {}", index),
+            index,
+            zones,
+            colors: Vec::new(),
+        }
+    }
+
+    fn brightness(frame: &Frame) -> u16 {
+        match frame {
+            Frame::Whole(color) => color.brightness,
+            Frame::Zones(colors) => colors[0].brightness,
+        }
+    }
+
+    #[test]
+    fn every_kind_renders_a_frame_per_light() {
+        let devices = vec![
+            layout(1, 0, ZoneLayout::Single),
+            layout(2, 1, ZoneLayout::Strip { zones: 8 }),
+            layout(
+                3,
+                2,
+                ZoneLayout::Matrix {
+                    width: 8,
+                    height: 8,
+                },
+            ),
+        ];
+        for kind in EffectKind::iter() {
+            let mut effect = EffectConfig::new(kind).build();
+            assert_eq!(effect.kind(), kind.id());
+            assert_eq!(EffectKind::from_id(effect.kind()), Some(kind));
+            let frames = effect.render(Duration::ZERO, &devices, &EffectInputs::default());
+            let targets: Vec<u64> = frames.iter().map(|(target, _)| *target).collect();
+            assert_eq!(targets, vec![1, 2, 3], "{:?}", kind);
+            if let Some((_, Frame::Zones(zones))) = frames.iter().find(|(t, _)| *t == 3) {
+                assert_eq!(zones.len(), 64);
+            }
+        }
+    }
+
+    #[test]
+    fn chase_lights_group_members_in_order() {
+        let devices: Vec<DeviceLayout> = (0..3)
+            .map(|i| layout(10 + i as u64, i, ZoneLayout::Single))
+            .collect();
+        let mut chase = EffectConfig {
+            intensity: 0.0,
+            ..EffectConfig::new(EffectKind::Chase)
+        }
+        .build();
+        let step = chase.interval();
+        for position in 0..3 {
+            let frames = chase.render(step * position as u32, &devices, &EffectInputs::default());
+            let lit: Vec<u64> = frames
+                .iter()
+                .filter(|(_, frame)| brightness(frame) > 0)
+                .map(|(target, _)| *target)
+                .collect();
+            assert_eq!(lit, vec![10 + position as u64]);
+        }
+    }
+
+    #[test]
+    fn strobe_alternates_on_and_off() {
+        let devices = vec![layout(1, 0, ZoneLayout::Single)];
+        let mut strobe = EffectConfig::new(EffectKind::Strobe).build();
+        let half = strobe.interval();
+        let on = strobe.render(Duration::ZERO, &devices, &EffectInputs::default());
+        let off = strobe.render(half + half / 2, &devices, &EffectInputs::default());
+        assert!(brightness(&on[0].1) > 0);
+        assert_eq!(brightness(&off[0].1), 0);
+        assert_eq!(strobe.transition_ms(), 0);
+    }
+
+    #[test]
+    fn strobe_rate_is_capped_at_full_speed() {
+        let fastest = EffectConfig {
+            speed: *SPEED_RANGE.end(),
+            ..EffectConfig::new(EffectKind::Strobe)
+        }
+        .build();
+        let flashes_per_second = 0.5 / fastest.interval().as_secs_f32();
+        assert!(flashes_per_second < 3.0, "{flashes_per_second}");
+    }
+
+    #[test]
+    fn config_round_trips_through_json() {
+        let config = EffectConfig {
+            speed: 2.5,
+            ..EffectConfig::new(EffectKind::Lightning)
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains("\"lightning\""));
+        let parsed: EffectConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(parsed.palette, Palette::White);
+    }
+}
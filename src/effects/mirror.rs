@@ -0,0 +1,160 @@
+//! Downsampling of a screen image onto the zones of a matrix light such as a tile, candle
+//! or ceiling light.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use rust_i18n::t;
+
+use super::edges::SampleRect;
+
+/// How an image whose aspect ratio differs from the matrix's is fitted onto it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum MirrorFit {
+    /// Stretch the whole image over the whole matrix.
+    Stretch,
+    /// Show the whole image at its own aspect ratio and leave the rest of the matrix dark.
+    #[default]
+    Fit,
+    /// Fill the matrix and cut off the sides of the image that don't fit.
+    Crop,
+}
+
+impl MirrorFit {
+    /// The rectangle of a `width` by `height` image that each zone of a `columns` by `rows`
+    /// matrix shows, row by row from the top left. Zones outside the image are `None`.
+    pub fn regions(
+        &self,
+        width: u32,
+        height: u32,
+        columns: usize,
+        rows: usize,
+    ) -> Vec<Option<SampleRect>> {
+        if width == 0 || height == 0 || columns == 0 || rows == 0 {
+            return Vec::new();
+        }
+        let image_aspect = width as f32 / height as f32;
+        let matrix_aspect = columns as f32 / rows as f32;
+        // The part of the image that is shown, and the cells it covers.
+        let (mut source, mut cells) = (
+            (0.0, 0.0, width as f32, height as f32),
+            (0, 0, columns, rows),
+        );
+        match self {
+            MirrorFit::Stretch => {}
+            MirrorFit::Fit if image_aspect > matrix_aspect => {
+                let shown = ((columns as f32 / image_aspect).round() as usize).clamp(1, rows);
+                cells = (0, (rows - shown) / 2, columns, shown);
+            }
+            MirrorFit::Fit => {
+                let shown = ((rows as f32 * image_aspect).round() as usize).clamp(1, columns);
+                cells = ((columns - shown) / 2, 0, shown, rows);
+            }
+            MirrorFit::Crop if image_aspect > matrix_aspect => {
+                let shown = height as f32 * matrix_aspect;
+                source = ((width as f32 - shown) / 2.0, 0.0, shown, height as f32);
+            }
+            MirrorFit::Crop => {
+                let shown = width as f32 / matrix_aspect;
+                source = (0.0, (height as f32 - shown) / 2.0, width as f32, shown);
+            }
+        }
+
+        let (source_x, source_y, source_width, source_height) = source;
+        let (first_column, first_row, shown_columns, shown_rows) = cells;
+        let span = |start: f32, size: f32, index: usize, count: usize, limit: u32| {
+            let from = (start + size * index as f32 / count as f32) as u32;
+            let from = from.min(limit - 1);
+            let to = ((start + size * (index + 1) as f32 / count as f32).ceil() as u32)
+                .clamp(from + 1, limit);
+            (from, to - from)
+        };
+        let mut regions = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let inside = (first_column..first_column + shown_columns).contains(&column)
+                    && (first_row..first_row + shown_rows).contains(&row);
+                if !inside {
+                    regions.push(None);
+                    continue;
+                }
+                let (x, width) = span(
+                    source_x,
+                    source_width,
+                    column - first_column,
+                    shown_columns,
+                    width,
+                );
+                let (y, height) =
+                    span(source_y, source_height, row - first_row, shown_rows, height);
+                regions.push(Some(SampleRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                }));
+            }
+        }
+        regions
+    }
+}
+
+impl std::fmt::Display for MirrorFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            MirrorFit::Stretch => t!("mirror.stretch"),
+            MirrorFit::Fit => t!("mirror.fit"),
+            MirrorFit::Crop => t!("mirror.crop"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretch_covers_the_whole_image() {
+        let regions = MirrorFit::Stretch.regions(160, 90, 8, 8);
+        assert_eq!(regions.len(), 64);
+        assert_eq!(
+            regions[0],
+            Some(SampleRect {
+                x: 0,
+                y: 0,
+                width: 20,
+                height: 12
+            })
+        );
+        let last = regions[63].unwrap();
+        assert_eq!((last.x + last.width, last.y + last.height), (160, 90));
+    }
+
+    #[test]
+    fn fit_letterboxes_a_wide_image() {
+        let regions = MirrorFit::Fit.regions(160, 80, 8, 8);
+        // Half the rows show the image, centered.
+        let shown: Vec<bool> = regions
+            .chunks(8)
+            .map(|row| row.iter().all(Option::is_some))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![false, false, true, true, true, true, false, false]
+        );
+        assert_eq!(regions[16].unwrap().y, 0);
+        let last = regions[47].unwrap();
+        assert_eq!((last.x + last.width, last.y + last.height), (160, 80));
+    }
+
+    #[test]
+    fn crop_cuts_the_sides_of_a_wide_image() {
+        let regions = MirrorFit::Crop.regions(160, 80, 4, 4);
+        assert!(regions.iter().all(Option::is_some));
+        assert_eq!(regions[0].unwrap().x, 40);
+        let last = regions[15].unwrap();
+        assert_eq!((last.x + last.width, last.y + last.height), (120, 80));
+        assert!(MirrorFit::Crop.regions(160, 80, 0, 4).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! Client-side animations: an `Effect` renders colors for a set of lights from the time
+//! since it started, their layout and shared inputs such as the audio spectrum, and the
+//! `EffectEngine` sends the resulting frames to the lights.
+
+pub mod analysis;
+pub mod edges;
+mod engine;
+pub mod exclusions;
+pub mod library;
+pub mod mirror;
+pub mod safety;
+pub mod session;
+pub mod smoothing;
+pub mod spectrum;
+pub mod visualizer;
+
+pub use analysis::ScreenAnalysis;
+pub use edges::{EdgeMapping, ScreenMapping};
+pub use engine::{start_active_effect, stop_active_effect, EffectEngine};
+pub use exclusions::CaptureExclusions;
+pub use library::{EffectConfig, EffectKind, Palette};
+pub use safety::SafetyLimits;
+pub use session::ScreenSession;
+pub use smoothing::{ColorSmoother, SmoothingSettings};
+pub use spectrum::{AudioMapping, SpectralFeatures};
+
+use std::time::Duration;
+
+use lifx_core::HSBK;
+
+use crate::BulbInfo;
+
+/// How often the engine checks whether an effect wants a new frame.
+pub const ENGINE_TICK: Duration = Duration::from_millis(25);
+
+/// A client-side animation that runs on one device or group.
+pub trait Effect: Send {
+    /// Stable identifier of the kind of effect, e.g. `"audio"`, used to tell which
+    /// effect is running on a device.
+    fn kind(&self) -> &'static str;
+
+    /// How often the effect wants a new frame.
+    fn interval(&self) -> Duration {
+        Duration::from_millis(100)
+    }
+
+    /// Transition time for each frame, in milliseconds. Defaults to the interval so
+    /// lights glide from one frame to the next.
+    fn transition_ms(&self) -> u32 {
+        self.interval().as_millis() as u32
+    }
+
+    /// Colors for `devices` at `time` since the effect started. Devices without a
+    /// frame keep their current colors.
+    fn render(
+        &mut self,
+        time: Duration,
+        devices: &[DeviceLayout],
+        inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)>;
+}
+
+/// The colors an effect sends to one light.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// One color for the whole light, including every zone.
+    Whole(HSBK),
+    /// One color per zone, in zone order. Single-zone lights use the first color.
+    Zones(Vec<HSBK>),
+}
+
+/// The zone arrangement of a light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneLayout {
+    Single,
+    Strip { zones: usize },
+    Matrix { width: usize, height: usize },
+}
+
+impl ZoneLayout {
+    pub fn zone_count(&self) -> usize {
+        match self {
+            ZoneLayout::Single => 1,
+            ZoneLayout::Strip { zones } => *zones,
+            ZoneLayout::Matrix { width, height } => width * height,
+        }
+    }
+}
+
+/// A light an effect is running on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceLayout {
+    pub target: u64,
+    pub label: String,
+    /// Position among the effect's lights, which are ordered by label.
+    pub index: usize,
+    pub zones: ZoneLayout,
+    /// Current color of each zone, or of the whole light for single-zone lights.
+    pub colors: Vec<HSBK>,
+}
+
+impl DeviceLayout {
+    pub fn from_bulb(bulb: &BulbInfo, index: usize) -> Self {
+        let zones = match bulb.get_zone_count() {
+            0 => ZoneLayout::Single,
+            count if bulb.is_matrix() => {
+                let width = bulb.get_matrix_width().max(1);
+                ZoneLayout::Matrix {
+                    width,
+                    height: count.div_ceil(width),
+                }
+            }
+            zones => ZoneLayout::Strip { zones },
+        };
+        let colors = match bulb.get_zone_colors() {
+            Some(zones) => zones
+                .iter()
+                .map(|zone| zone.unwrap_or(crate::color::BASE_COLOR))
+                .collect(),
+            None => bulb.get_color().copied().into_iter().collect(),
+        };
+        DeviceLayout {
+            target: bulb.target,
+            label: bulb.name_label().unwrap_or_default(),
+            index,
+            zones,
+            colors,
+        }
+    }
+}
+
+/// Inputs shared by every effect, sampled once per engine tick.
+#[derive(Debug, Clone, Default)]
+pub struct EffectInputs {
+    /// The latest analysis of the audio, or empty when there's no audio source.
+    pub audio: SpectralFeatures,
+}
@@ -0,0 +1,332 @@
+//! Photosensitivity limiter for everything sent to the lights. It caps how often a light's
+//! brightness may flash between bright and dark, and how fast brightness may change at all.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use lifx_core::HSBK;
+use serde::{Deserialize, Serialize};
+
+use super::Frame;
+
+pub const FLASH_HZ_RANGE: std::ops::RangeInclusive<f32> = 0.5..=10.0;
+pub const BRIGHTNESS_RATE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=20.0;
+
+/// Brightness changes smaller than this fraction of full brightness don't count as flashes.
+const FLASH_THRESHOLD: f32 = 0.1;
+/// How long a device counts as limited after the limiter last changed one of its frames.
+const ENGAGED_FOR: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetyLimits {
+    pub enabled: bool,
+    /// Most flashes per second, where a flash is a rise and a fall in brightness.
+    pub max_flash_hz: f32,
+    /// Most brightness change per second, in multiples of the full brightness range.
+    pub max_brightness_change: f32,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        SafetyLimits {
+            enabled: true,
+            max_flash_hz: 3.0,
+            max_brightness_change: 4.0,
+        }
+    }
+}
+
+/// Applies `SafetyLimits` to the colors sent to each light, remembering what each zone
+/// last showed across effects and commands so restarting an effect doesn't reset the limits.
+/// `LifxManager` owns one filter shared by all of its clones.
+#[derive(Default)]
+pub struct SafetyFilter {
+    limits: SafetyLimits,
+    zones: HashMap<(u64, usize), ZoneHistory>,
+    engaged: HashMap<u64, Instant>,
+}
+
+struct ZoneHistory {
+    brightness: f32,
+    at: Instant,
+    /// The brightness at the last significant change, and its direction.
+    anchor: f32,
+    direction: f32,
+    last_reversal: Option<Instant>,
+}
+
+impl SafetyFilter {
+    pub fn set_limits(&mut self, limits: SafetyLimits) {
+        self.limits = limits;
+    }
+
+    /// Each half of a flash needs at least half a flash period.
+    fn min_gap(&self) -> Duration {
+        Duration::from_secs_f32(0.5 / self.limits.max_flash_hz.max(0.1))
+    }
+
+    /// Limit `frame` for the light `target`.
+    pub fn apply(&mut self, target: u64, frame: Frame, now: Instant) -> Frame {
+        if !self.limits.enabled {
+            return frame;
+        }
+        let mut limited = false;
+        let mut limit = |zone: usize, color: HSBK| {
+            let (color, changed) = self.limit(target, zone, color, now);
+            limited |= changed;
+            color
+        };
+        let frame = match frame {
+            Frame::Whole(color) => Frame::Whole(limit(0, color)),
+            Frame::Zones(colors) => Frame::Zones(
+                colors
+                    .into_iter()
+                    .enumerate()
+                    .map(|(zone, color)| limit(zone, color))
+                    .collect(),
+            ),
+        };
+        if limited {
+            self.engaged.insert(target, now);
+        }
+        frame
+    }
+
+    /// Lengthen the transition of a one-off change of zone `zone` of the light `target` to
+    /// `brightness`, instead of holding the change back like `apply`, so commands from the
+    /// UI, scripts and the APIs still end where they were asked to. Reversals that come
+    /// faster than the flash limit are spread out until they no longer count as flashes.
+    /// Returns the transition in milliseconds.
+    pub fn stretch(
+        &mut self,
+        target: u64,
+        zone: usize,
+        brightness: u16,
+        duration: u32,
+        now: Instant,
+    ) -> u32 {
+        if !self.limits.enabled {
+            return duration;
+        }
+        let min_gap = self.min_gap();
+        let wanted = brightness as f32 / u16::MAX as f32;
+        let Some(history) = self.zones.get_mut(&(target, zone)) else {
+            self.zones.insert(
+                (target, zone),
+                ZoneHistory {
+                    brightness: wanted,
+                    at: now,
+                    anchor: wanted,
+                    direction: 0.0,
+                    last_reversal: None,
+                },
+            );
+            return duration;
+        };
+
+        let step = (wanted - history.brightness).abs();
+        let mut needed = step / self.limits.max_brightness_change.max(0.1);
+        let change = wanted - history.anchor;
+        if change.abs() >= FLASH_THRESHOLD {
+            let direction = change.signum();
+            if direction != history.direction {
+                if history
+                    .last_reversal
+                    .is_some_and(|at| now.saturating_duration_since(at) < min_gap)
+                {
+                    // Less than a flash is shown before the next reversal may come.
+                    needed = needed.max(step * min_gap.as_secs_f32() / FLASH_THRESHOLD);
+                }
+                history.direction = direction;
+                history.last_reversal = Some(now);
+            }
+            history.anchor = wanted;
+        }
+        history.brightness = wanted;
+        history.at = now;
+
+        let needed = (needed * 1000.0).ceil() as u32;
+        if needed > duration {
+            self.engaged.insert(target, now);
+            needed
+        } else {
+            duration
+        }
+    }
+
+    /// Lengthen the `period` in milliseconds of a waveform on the light `target` so each
+    /// cycle, which may be a flash, stays within the flash limit.
+    pub fn stretch_period(&mut self, target: u64, period: u32, now: Instant) -> u32 {
+        if !self.limits.enabled {
+            return period;
+        }
+        let min_period = (2.0 * self.min_gap().as_secs_f32() * 1000.0).ceil() as u32;
+        if period < min_period {
+            self.engaged.insert(target, now);
+            min_period
+        } else {
+            period
+        }
+    }
+
+    /// Whether the limiter changed what was sent to the light `target` recently.
+    pub fn is_engaged(&self, target: u64) -> bool {
+        self.engaged
+            .get(&target)
+            .is_some_and(|at| at.elapsed() < ENGAGED_FOR)
+    }
+
+    fn limit(&mut self, target: u64, zone: usize, color: HSBK, now: Instant) -> (HSBK, bool) {
+        let min_gap = self.min_gap();
+        let wanted = color.brightness as f32 / u16::MAX as f32;
+        let Some(history) = self.zones.get_mut(&(target, zone)) else {
+            self.zones.insert(
+                (target, zone),
+                ZoneHistory {
+                    brightness: wanted,
+                    at: now,
+                    anchor: wanted,
+                    direction: 0.0,
+                    last_reversal: None,
+                },
+            );
+            return (color, false);
+        };
+
+        let max_step = self.limits.max_brightness_change
+            * now.saturating_duration_since(history.at).as_secs_f32();
+        let mut next = wanted.clamp(history.brightness - max_step, history.brightness + max_step);
+        let change = next - history.anchor;
+        if change.abs() >= FLASH_THRESHOLD {
+            let direction = change.signum();
+            if direction == history.direction {
+                history.anchor = next;
+            } else if history
+                .last_reversal
+                .is_some_and(|at| now.saturating_duration_since(at) < min_gap)
+            {
+                next = history.brightness;
+            } else {
+                history.anchor = next;
+                history.direction = direction;
+                history.last_reversal = Some(now);
+            }
+        }
+        history.brightness = next;
+        history.at = now;
+
+        let brightness = (next * u16::MAX as f32).round() as u16;
+        let limited = brightness.abs_diff(color.brightness) > u16::MAX / 255;
+        (
+            HSBK {
+                brightness,
+                ..color
+            },
+            limited,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(brightness: f32) -> Frame {
+        Frame::Whole(HSBK {
+            hue: 0,
+            saturation: 0,
+            brightness: (brightness * u16::MAX as f32) as u16,
+            kelvin: 3500,
+        })
+    }
+
+    fn brightness(frame: &Frame) -> f32 {
+        match frame {
+            Frame::Whole(color) => color.brightness as f32 / u16::MAX as f32,
+            Frame::Zones(colors) => colors[0].brightness as f32 / u16::MAX as f32,
+        }
+    }
+
+    #[test]
+    fn fast_strobe_is_held_back() {
+        let mut filter = SafetyFilter::default();
+        let start = Instant::now();
+        // Ten flashes per second, well over the default limit of three.
+        let mut flashes = 0;
+        let mut was_bright = false;
+        for step in 0..40 {
+            let wanted = if step % 2 == 0 { 1.0 } else { 0.0 };
+            let now = start + Duration::from_millis(50 * step);
+            let frame = filter.apply(1, gray(wanted), now);
+            let bright = brightness(&frame) > 0.5;
+            if bright && !was_bright {
+                flashes += 1;
+            }
+            was_bright = bright;
+        }
+        assert!(flashes <= 7, "{} flashes in two seconds", flashes);
+        assert!(filter.is_engaged(1));
+        assert!(!filter.is_engaged(2));
+    }
+
+    #[test]
+    fn brightness_changes_are_rate_limited() {
+        let mut filter = SafetyFilter::default();
+        let start = Instant::now();
+        filter.apply(1, gray(0.0), start);
+        let frame = filter.apply(1, gray(1.0), start + Duration::from_millis(100));
+        // Four full ranges per second allow 40% in 100ms.
+        assert!(
+            (brightness(&frame) - 0.4).abs() < 0.01,
+            "{}",
+            brightness(&frame)
+        );
+    }
+
+    #[test]
+    fn slow_changes_and_disabled_limits_pass_through() {
+        let mut filter = SafetyFilter::default();
+        let start = Instant::now();
+        filter.apply(1, gray(0.0), start);
+        let frame = filter.apply(1, gray(1.0), start + Duration::from_secs(1));
+        assert_eq!(brightness(&frame), 1.0);
+
+        filter.set_limits(SafetyLimits {
+            enabled: false,
+            ..SafetyLimits::default()
+        });
+        let frame = filter.apply(1, gray(0.0), start + Duration::from_millis(1010));
+        assert_eq!(brightness(&frame), 0.0);
+    }
+
+    #[test]
+    fn fast_commands_are_stretched_instead_of_held() {
+        let mut filter = SafetyFilter::default();
+        let start = Instant::now();
+        assert_eq!(filter.stretch(1, 0, 0, 0, start), 0);
+        // A full swing at four ranges per second takes a quarter second.
+        let rise = filter.stretch(1, 0, u16::MAX, 0, start + Duration::from_secs(1));
+        assert_eq!(rise, 250);
+        assert!(!filter.is_engaged(2));
+        // Red/black at ten per second: every reversal comes too soon to be a flash.
+        for step in 1..10 {
+            let wanted = if step % 2 == 0 { u16::MAX } else { 0 };
+            let now = start + Duration::from_secs(1) + Duration::from_millis(100 * step);
+            let duration = filter.stretch(1, 0, wanted, 0, now);
+            if step > 1 {
+                assert!(duration > 1_000, "{}", duration);
+            }
+        }
+        assert!(filter.is_engaged(1));
+        // Slow waveforms and disabled limits pass through.
+        assert_eq!(filter.stretch_period(1, 100, start), 334);
+        assert_eq!(filter.stretch_period(1, 1000, start), 1000);
+        filter.set_limits(SafetyLimits {
+            enabled: false,
+            ..SafetyLimits::default()
+        });
+        assert_eq!(filter.stretch(1, 0, u16::MAX, 0, start), 0);
+        assert_eq!(filter.stretch_period(1, 100, start), 100);
+    }
+}
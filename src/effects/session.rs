@@ -0,0 +1,235 @@
+//! Screen sync sessions: several regions of one monitor, each assigned to a light, a zone
+//! range or a group, all computed from a single capture per frame.
+
+use std::collections::HashMap;
+
+use lifx_core::HSBK;
+use serde::{Deserialize, Serialize};
+
+use super::{smoothing::SmoothingSettings, DeviceLayout, Frame, ZoneLayout};
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenSession {
+    /// Name of the monitor the regions are drawn on. The primary monitor if it's gone.
+    pub monitor: String,
+    pub assignments: Vec<RegionAssignment>,
+    pub smoothing: SmoothingSettings,
+}
+
+/// A rectangle of the session's monitor, in its pixels, and where its color goes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionAssignment {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Regions without a target are drawn but not sent anywhere.
+    pub target: Option<AssignmentTarget>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssignmentTarget {
+    /// Every zone of a light.
+    Bulb(u64),
+    /// Zones `start` to `end` of a multizone light, inclusive.
+    Zones {
+        target: u64,
+        start: usize,
+        end: usize,
+    },
+    /// Every light of a group, by group id.
+    Group(u64),
+}
+
+impl AssignmentTarget {
+    /// Whether the region covers the whole of the light `target`, given the lights of each
+    /// group by group id.
+    fn covers(&self, target: u64, groups: &HashMap<u64, Vec<u64>>) -> bool {
+        match self {
+            AssignmentTarget::Bulb(bulb) => *bulb == target,
+            AssignmentTarget::Group(group) => groups
+                .get(group)
+                .is_some_and(|bulbs| bulbs.contains(&target)),
+            AssignmentTarget::Zones { .. } => false,
+        }
+    }
+}
+
+/// Frames for `devices` from `colors`, the color of each of `assignments` in order, or
+/// `None` where a region had nothing to show. Where regions overlap on a light, the later
+/// one wins. Lights without an assignment get no frame.
+pub fn session_frames(
+    assignments: &[RegionAssignment],
+    colors: &[Option<HSBK>],
+    devices: &[DeviceLayout],
+    groups: &HashMap<u64, Vec<u64>>,
+) -> Vec<(u64, Frame)> {
+    devices
+        .iter()
+        .filter_map(|device| {
+            let mut frame = None;
+            for (assignment, color) in assignments.iter().zip(colors) {
+                let (Some(target), Some(color)) = (assignment.target, *color) else {
+                    continue;
+                };
+                match target {
+                    AssignmentTarget::Zones { target, start, end }
+                        if target == device.target && device.zones != ZoneLayout::Single =>
+                    {
+                        let count = device.zones.zone_count();
+                        let mut zones = match frame {
+                            Some(Frame::Whole(whole)) => vec![whole; count],
+                            Some(Frame::Zones(zones)) => zones,
+                            // Zones outside every range keep their colors.
+                            None => (0..count)
+                                .map(|zone| device.colors.get(zone).copied().unwrap_or(color))
+                                .collect(),
+                        };
+                        for zone in zones.iter_mut().take(end + 1).skip(start) {
+                            *zone = color;
+                        }
+                        frame = Some(Frame::Zones(zones));
+                    }
+                    AssignmentTarget::Zones { target, .. } if target == device.target => {
+                        frame = Some(Frame::Whole(color));
+                    }
+                    target if target.covers(device.target, groups) => {
+                        frame = Some(Frame::Whole(color));
+                    }
+                    _ => {}
+                }
+            }
+            frame.map(|frame| (device.target, frame))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: HSBK = HSBK {
+        hue: 0,
+        saturation: u16::MAX,
+        brightness: u16::MAX,
+        kelvin: 3500,
+    };
+    const BLUE: HSBK = HSBK {
+        hue: 43690,
+        saturation: u16::MAX,
+        brightness: u16::MAX,
+        kelvin: 3500,
+    };
+    const GRAY: HSBK = HSBK {
+        hue: 0,
+        saturation: 0,
+        brightness: 1000,
+        kelvin: 3500,
+    };
+
+    fn device(target: u64, zones: ZoneLayout) -> DeviceLayout {
+        DeviceLayout {
+            target,
+            label: target.to_string(),
+            index: 0,
+            zones,
+            colors: vec![GRAY; zones.zone_count()],
+        }
+    }
+
+    fn assign(target: AssignmentTarget) -> RegionAssignment {
+        RegionAssignment {
+            width: 100,
+            height: 100,
+            target: Some(target),
+            ..RegionAssignment::default()
+        }
+    }
+
+    #[test]
+    fn regions_go_to_their_lights_and_groups() {
+        let devices = [
+            device(1, ZoneLayout::Single),
+            device(2, ZoneLayout::Single),
+            device(3, ZoneLayout::Single),
+            device(4, ZoneLayout::Single),
+        ];
+        let groups = HashMap::from([(10, vec![2, 3])]);
+        let assignments = [
+            assign(AssignmentTarget::Bulb(1)),
+            assign(AssignmentTarget::Group(10)),
+            RegionAssignment::default(),
+        ];
+        let frames = session_frames(
+            &assignments,
+            &[Some(RED), Some(BLUE), Some(GRAY)],
+            &devices,
+            &groups,
+        );
+        assert_eq!(
+            frames,
+            vec![
+                (1, Frame::Whole(RED)),
+                (2, Frame::Whole(BLUE)),
+                (3, Frame::Whole(BLUE)),
+            ]
+        );
+        // A region with nothing to show leaves its lights alone.
+        let frames = session_frames(&assignments, &[Some(RED), None], &devices, &groups);
+        assert_eq!(frames, vec![(1, Frame::Whole(RED))]);
+    }
+
+    #[test]
+    fn zone_ranges_share_a_strip() {
+        let devices = [device(1, ZoneLayout::Strip { zones: 6 })];
+        let assignments = [
+            assign(AssignmentTarget::Zones {
+                target: 1,
+                start: 0,
+                end: 1,
+            }),
+            assign(AssignmentTarget::Zones {
+                target: 1,
+                start: 4,
+                end: 9,
+            }),
+        ];
+        let frames = session_frames(
+            &assignments,
+            &[Some(RED), Some(BLUE)],
+            &devices,
+            &HashMap::new(),
+        );
+        assert_eq!(
+            frames,
+            vec![(1, Frame::Zones(vec![RED, RED, GRAY, GRAY, BLUE, BLUE]))]
+        );
+    }
+
+    #[test]
+    fn later_regions_win() {
+        let devices = [device(1, ZoneLayout::Strip { zones: 3 })];
+        let assignments = [
+            assign(AssignmentTarget::Zones {
+                target: 1,
+                start: 0,
+                end: 0,
+            }),
+            assign(AssignmentTarget::Bulb(1)),
+            assign(AssignmentTarget::Zones {
+                target: 1,
+                start: 2,
+                end: 2,
+            }),
+        ];
+        let frames = session_frames(
+            &assignments,
+            &[Some(GRAY), Some(RED), Some(BLUE)],
+            &devices,
+            &HashMap::new(),
+        );
+        assert_eq!(frames, vec![(1, Frame::Zones(vec![RED, RED, BLUE]))]);
+    }
+}
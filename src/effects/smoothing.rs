@@ -0,0 +1,208 @@
+//! Temporal smoothing for colors sampled from the screen or audio, so small noise doesn't
+//! make lights shimmer or send a packet on every sample.
+
+use std::collections::HashMap;
+
+use lifx_core::HSBK;
+use serde::{Deserialize, Serialize};
+
+use crate::color::{hue_distance, mix_hsbk, DeltaColor};
+
+pub const RESPONSIVENESS_RANGE: std::ops::RangeInclusive<f32> = 0.05..=1.0;
+pub const HUE_DEAD_BAND_RANGE: std::ops::RangeInclusive<f32> = 0.0..=30.0;
+pub const LEVEL_DEAD_BAND_RANGE: std::ops::RangeInclusive<f32> = 0.0..=0.2;
+pub const KELVIN_DEAD_BAND_RANGE: std::ops::RangeInclusive<u16> = 0..=1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingSettings {
+    pub enabled: bool,
+    /// Weight of each new sample in the moving average. 1 follows samples exactly.
+    pub responsiveness: f32,
+    /// Smallest hue change worth sending, in degrees.
+    pub hue_dead_band: f32,
+    /// Smallest saturation change worth sending, as a fraction of the full range.
+    pub saturation_dead_band: f32,
+    /// Smallest brightness change worth sending, as a fraction of the full range.
+    pub brightness_dead_band: f32,
+    /// Smallest kelvin change worth sending.
+    pub kelvin_dead_band: u16,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        SmoothingSettings {
+            enabled: true,
+            responsiveness: 0.5,
+            hue_dead_band: 3.0,
+            saturation_dead_band: 0.02,
+            brightness_dead_band: 0.02,
+            kelvin_dead_band: 50,
+        }
+    }
+}
+
+impl SmoothingSettings {
+    /// Whether `next` differs from `sent` by more than a dead-band on any channel.
+    fn exceeds_dead_band(&self, sent: HSBK, next: HSBK) -> bool {
+        let level = |band: f32| (band * u16::MAX as f32) as u16;
+        hue_distance(sent.hue, next.hue) > level(self.hue_dead_band / 360.0)
+            || sent.saturation.abs_diff(next.saturation) > level(self.saturation_dead_band)
+            || sent.brightness.abs_diff(next.brightness) > level(self.brightness_dead_band)
+            || sent.kelvin.abs_diff(next.kelvin) > self.kelvin_dead_band
+    }
+}
+
+/// Smooths the colors of each light with an exponential moving average and drops the
+/// ones too close to what the light already shows.
+#[derive(Default)]
+pub struct ColorSmoother {
+    lights: HashMap<u64, SmoothedLight>,
+}
+
+struct SmoothedLight {
+    /// One color per zone, or a single color for lights smoothed as a whole.
+    average: Vec<HSBK>,
+    sent: Vec<HSBK>,
+}
+
+impl ColorSmoother {
+    /// Feed the latest sample for the light `target`. Returns the color to send, or `None`
+    /// when it wouldn't visibly change the light.
+    pub fn update(
+        &mut self,
+        settings: &SmoothingSettings,
+        target: u64,
+        sample: DeltaColor,
+    ) -> Option<DeltaColor> {
+        let next = self.update_zones(settings, target, &[sample.next])?;
+        Some(DeltaColor {
+            next: next[0],
+            duration: sample.duration,
+        })
+    }
+
+    /// Like `update` for one color per zone of a strip. Every zone is returned as soon as
+    /// any of them moves past the dead-band, so the strip is set in one message.
+    pub fn update_zones(
+        &mut self,
+        settings: &SmoothingSettings,
+        target: u64,
+        samples: &[HSBK],
+    ) -> Option<Vec<HSBK>> {
+        if !settings.enabled {
+            self.lights.remove(&target);
+            return Some(samples.to_vec());
+        }
+        let light = match self.lights.get_mut(&target) {
+            Some(light) if light.average.len() == samples.len() => light,
+            _ => {
+                self.lights.insert(
+                    target,
+                    SmoothedLight {
+                        average: samples.to_vec(),
+                        sent: samples.to_vec(),
+                    },
+                );
+                return Some(samples.to_vec());
+            }
+        };
+        for (average, sample) in light.average.iter_mut().zip(samples) {
+            *average = mix_hsbk(*average, *sample, settings.responsiveness);
+        }
+        let changed = light
+            .sent
+            .iter()
+            .zip(&light.average)
+            .any(|(sent, average)| settings.exceeds_dead_band(*sent, *average));
+        if !changed {
+            return None;
+        }
+        light.sent = light.average.clone();
+        Some(light.average.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(hue: u16, brightness: u16) -> DeltaColor {
+        DeltaColor {
+            next: HSBK {
+                hue,
+                saturation: u16::MAX,
+                brightness,
+                kelvin: 3500,
+            },
+            duration: Some(100),
+        }
+    }
+
+    #[test]
+    fn noise_inside_the_dead_band_is_dropped() {
+        let settings = SmoothingSettings::default();
+        let mut smoother = ColorSmoother::default();
+        assert!(smoother
+            .update(&settings, 1, sample(1000, 30_000))
+            .is_some());
+        assert!(smoother
+            .update(&settings, 1, sample(1100, 30_400))
+            .is_none());
+        assert!(smoother.update(&settings, 1, sample(900, 29_800)).is_none());
+        // Another light has its own history.
+        assert!(smoother.update(&settings, 2, sample(900, 29_800)).is_some());
+    }
+
+    #[test]
+    fn large_changes_are_averaged_the_short_way_around() {
+        let settings = SmoothingSettings::default();
+        let mut smoother = ColorSmoother::default();
+        smoother.update(&settings, 1, sample(65_000, 30_000));
+        let next = smoother
+            .update(&settings, 1, sample(2_000, 60_000))
+            .expect("a large change is sent");
+        // Halfway from 65000 to 2000 through 0, not back through the middle of the wheel.
+        assert_eq!(next.next.hue, 732);
+        assert_eq!(next.next.brightness, 45_000);
+        assert_eq!(next.duration, Some(100));
+    }
+
+    #[test]
+    fn disabled_smoothing_passes_samples_through() {
+        let settings = SmoothingSettings {
+            enabled: false,
+            ..SmoothingSettings::default()
+        };
+        let mut smoother = ColorSmoother::default();
+        let first = smoother.update(&settings, 1, sample(1000, 30_000)).unwrap();
+        let second = smoother.update(&settings, 1, sample(1001, 30_001)).unwrap();
+        assert_eq!(first.next.hue, 1000);
+        assert_eq!(second.next.hue, 1001);
+    }
+
+    #[test]
+    fn zones_are_sent_together() {
+        let settings = SmoothingSettings::default();
+        let mut smoother = ColorSmoother::default();
+        let zones = |hues: [u16; 3]| hues.map(|hue| sample(hue, 30_000).next);
+        assert!(smoother
+            .update_zones(&settings, 1, &zones([0, 20_000, 40_000]))
+            .is_some());
+        assert!(smoother
+            .update_zones(&settings, 1, &zones([100, 20_000, 40_000]))
+            .is_none());
+        // One zone moving sends all of them.
+        let next = smoother
+            .update_zones(&settings, 1, &zones([100, 30_000, 40_000]))
+            .expect("a zone moved past the dead-band");
+        assert_eq!(next.len(), 3);
+        assert_eq!(next[1].hue, 25_000);
+        assert_eq!(next[2].hue, 40_000);
+        // A different zone count starts over.
+        assert_eq!(
+            smoother.update_zones(&settings, 1, &zones([1, 2, 3])[..2]),
+            Some(zones([1, 2, 3])[..2].to_vec())
+        );
+    }
+}
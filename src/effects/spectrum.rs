@@ -0,0 +1,350 @@
+//! Features of the audio spectrum that audio effects map to light: how loud it is, where
+//! its energy sits in frequency, how that energy splits over log-spaced bands, and the
+//! beats found in it.
+
+use std::collections::VecDeque;
+
+use lifx_core::HSBK;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use rust_i18n::t;
+
+use crate::color::DEFAULT_KELVIN;
+
+/// Lowest and highest frequencies the bands and the hue cover, in Hz.
+pub const MIN_FREQUENCY: f32 = 40.0;
+pub const MAX_FREQUENCY: f32 = 16_000.0;
+pub const BAND_COUNT: usize = 16;
+/// Levels this far below full scale, in dB, count as silence.
+const FLOOR_DB: f32 = -60.0;
+/// Seconds of spectral flux the adaptive beat threshold follows.
+const THRESHOLD_WINDOW: f64 = 1.0;
+/// Standard deviations above the recent mean flux that make an onset.
+const THRESHOLD_DEVIATIONS: f32 = 1.5;
+/// Flux that never counts as an onset, so hiss in quiet passages doesn't beat.
+const MIN_FLUX: f32 = 0.1;
+/// Onsets closer than this to the last beat belong to it, capping the tempo at 300 BPM.
+const MIN_BEAT_SPACING: f64 = 0.2;
+/// Beats the tempo is estimated from.
+const TEMPO_BEATS: usize = 9;
+/// Tempos are folded by octaves into this range, as beats may be found on every other
+/// beat or between beats.
+const BPM_RANGE: std::ops::RangeInclusive<f32> = 70.0..=180.0;
+/// Brightness a beat pulse fades to before the next beat.
+const PULSE_FLOOR: f32 = 0.1;
+
+/// The latest analysis of the audio stream. Empty until audio arrives.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpectralFeatures {
+    /// Sample rate of the analyzed stream in Hz.
+    pub sample_rate: u32,
+    /// Level of each band from `MIN_FREQUENCY` up, in 0..=1, see [`amplitude_to_level`].
+    pub bands: Vec<f32>,
+    /// Root mean square of the latest frame, 1.0 for a full-scale square wave.
+    pub rms: f32,
+    /// Frequency of the strongest component in Hz.
+    pub peak_frequency: f32,
+    /// Power-weighted mean frequency in Hz, which sets how bright the sound is.
+    pub centroid: f32,
+    /// Seconds of audio analyzed when these features were taken.
+    pub time: f64,
+    /// Beats found so far. Effects compare it with the count they last saw to catch every
+    /// beat, however often they render.
+    pub beats: u64,
+    /// Time of the latest beat, in the same seconds as `time`.
+    pub last_beat: Option<f64>,
+    /// Estimated tempo in beats per minute, once a few beats have been found.
+    pub bpm: Option<f32>,
+}
+
+impl SpectralFeatures {
+    pub fn is_empty(&self) -> bool {
+        self.sample_rate == 0
+    }
+
+    /// Loudness in 0..=1.
+    pub fn level(&self) -> f32 {
+        amplitude_to_level(self.rms)
+    }
+
+    /// Hue for the centroid, rising with it on a log scale from `MIN_FREQUENCY` to
+    /// `MAX_FREQUENCY`, so each octave gets an equal share of the color wheel.
+    pub fn hue(&self) -> u16 {
+        if self.centroid <= 0.0 {
+            return 0;
+        }
+        let position = (self.centroid / MIN_FREQUENCY).ln() / (MAX_FREQUENCY / MIN_FREQUENCY).ln();
+        (position.clamp(0.0, 1.0) * u16::MAX as f32) as u16
+    }
+
+    /// Seconds since the latest beat, if there was one.
+    pub fn since_beat(&self) -> Option<f32> {
+        self.last_beat
+            .map(|beat| (self.time - beat).max(0.0) as f32)
+    }
+
+    /// A color with the hue of the centroid and the brightness of the level.
+    pub fn to_hsbk(&self) -> HSBK {
+        HSBK {
+            hue: self.hue(),
+            saturation: u16::MAX,
+            brightness: (self.level() * u16::MAX as f32) as u16,
+            kelvin: DEFAULT_KELVIN,
+        }
+    }
+}
+
+/// An amplitude relative to full scale as a level in 0..=1, on a dB scale from -60 dB to
+/// 0 dB, which follows loudness as heard far better than the raw amplitude.
+pub fn amplitude_to_level(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+    let db = 20.0 * amplitude.log10();
+    ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// How to turn the audio into light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumIter)]
+pub enum AudioMapping {
+    /// The hue follows the centroid and the brightness the level.
+    #[default]
+    Spectrum,
+    /// Full brightness on each beat, fading until the next.
+    BeatPulse,
+    /// The hue moves a step around the wheel on each beat.
+    HueStep,
+    /// The next saved scene on each beat.
+    SceneChange,
+    /// A VU-meter bar per band across the zones of strips and matrices.
+    Bars,
+    /// Bars from the middle of strips and matrices outwards, lowest band in the middle.
+    MirroredBars,
+    /// The bands scrolling along strips and down matrices.
+    Spectrogram,
+}
+
+impl std::fmt::Display for AudioMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let label = match self {
+            AudioMapping::Spectrum => t!("audio_mapping.spectrum"),
+            AudioMapping::BeatPulse => t!("audio_mapping.beat_pulse"),
+            AudioMapping::HueStep => t!("audio_mapping.hue_step"),
+            AudioMapping::SceneChange => t!("audio_mapping.scene_change"),
+            AudioMapping::Bars => t!("audio_mapping.bars"),
+            AudioMapping::MirroredBars => t!("audio_mapping.mirrored_bars"),
+            AudioMapping::Spectrogram => t!("audio_mapping.spectrogram"),
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// Brightness in 0..=1 of a beat pulse `since_beat` seconds after the beat. It fades over
+/// one beat at `bpm`, or half a second before the tempo is known.
+pub fn pulse_level(since_beat: f32, bpm: Option<f32>) -> f32 {
+    let beat_length = bpm.map_or(0.5, |bpm| 60.0 / bpm);
+    (1.0 - since_beat / beat_length).max(PULSE_FLOOR)
+}
+
+/// How one analysis frame fared in beat detection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Onset {
+    /// Rise of the band levels since the previous frame.
+    pub flux: f32,
+    /// Flux the frame had to beat, which follows the recent flux.
+    pub threshold: f32,
+    pub beat: bool,
+}
+
+/// Finds beats by spectral flux, how much the band levels rise from one frame to the
+/// next, against a threshold that adapts to the recent flux, so loud and quiet music both
+/// beat.
+#[derive(Debug, Clone, Default)]
+pub struct BeatDetector {
+    previous: Vec<f32>,
+    /// Time and flux of the frames within `THRESHOLD_WINDOW`.
+    history: VecDeque<(f64, f32)>,
+    beats: VecDeque<f64>,
+}
+
+impl BeatDetector {
+    /// Take the band levels of the frame at `time` seconds.
+    pub fn push(&mut self, time: f64, bands: &[f32]) -> Onset {
+        let flux = if self.previous.len() == bands.len() {
+            bands
+                .iter()
+                .zip(&self.previous)
+                .map(|(level, previous)| (level - previous).max(0.0))
+                .sum()
+        } else {
+            0.0
+        };
+        self.previous = bands.to_vec();
+
+        while self
+            .history
+            .front()
+            .is_some_and(|(at, _)| time - at > THRESHOLD_WINDOW)
+        {
+            self.history.pop_front();
+        }
+        let count = self.history.len().max(1) as f32;
+        let mean = self.history.iter().map(|(_, flux)| flux).sum::<f32>() / count;
+        let variance = self
+            .history
+            .iter()
+            .map(|(_, flux)| (flux - mean) * (flux - mean))
+            .sum::<f32>()
+            / count;
+        let threshold = (mean + THRESHOLD_DEVIATIONS * variance.sqrt()).max(MIN_FLUX);
+        self.history.push_back((time, flux));
+
+        let spaced = self
+            .beats
+            .back()
+            .is_none_or(|last| time - last >= MIN_BEAT_SPACING);
+        let beat = flux > threshold && spaced;
+        if beat {
+            self.beats.push_back(time);
+            if self.beats.len() > TEMPO_BEATS {
+                self.beats.pop_front();
+            }
+        }
+        Onset {
+            flux,
+            threshold,
+            beat,
+        }
+    }
+
+    pub fn last_beat(&self) -> Option<f64> {
+        self.beats.back().copied()
+    }
+
+    /// The tempo from the median time between the recent beats, once there are four.
+    pub fn bpm(&self) -> Option<f32> {
+        if self.beats.len() < 4 {
+            return None;
+        }
+        let mut intervals: Vec<f64> = self
+            .beats
+            .iter()
+            .zip(self.beats.iter().skip(1))
+            .map(|(a, b)| b - a)
+            .collect();
+        intervals.sort_by(|a, b| a.total_cmp(b));
+        let mut bpm = (60.0 / intervals[intervals.len() / 2]) as f32;
+        while bpm < *BPM_RANGE.start() {
+            bpm *= 2.0;
+        }
+        while bpm > *BPM_RANGE.end() {
+            bpm /= 2.0;
+        }
+        Some(bpm)
+    }
+}
+
+/// The `count + 1` edges of `count` bands spaced evenly on a log scale from `min` to `max`
+/// Hz.
+pub fn log_band_edges(count: usize, min: f32, max: f32) -> Vec<f32> {
+    let ratio = max / min;
+    (0..=count)
+        .map(|index| min * ratio.powf(index as f32 / count.max(1) as f32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn band_edges_are_log_spaced() {
+        let edges = log_band_edges(4, 100.0, 1600.0);
+        assert_eq!(edges.len(), 5);
+        for (edge, expected) in edges.iter().zip([100.0, 200.0, 400.0, 800.0, 1600.0]) {
+            assert!((edge - expected).abs() < 0.01, "{edge} != {expected}");
+        }
+    }
+
+    #[test]
+    fn levels_follow_decibels() {
+        assert_eq!(amplitude_to_level(0.0), 0.0);
+        assert_eq!(amplitude_to_level(1.0), 1.0);
+        assert!((amplitude_to_level(0.001) - 0.0).abs() < 0.001);
+        assert!((amplitude_to_level(0.0316) - 0.5).abs() < 0.01);
+    }
+
+    /// Band levels of a kick on every `period`-th frame over a quiet bed.
+    fn kicks(frames: usize, period: usize) -> impl Iterator<Item = Vec<f32>> {
+        (0..frames).map(move |frame| {
+            let level = if frame % period == 0 { 0.9 } else { 0.2 };
+            vec![level; 4]
+        })
+    }
+
+    #[test]
+    fn beats_are_found_on_kicks() {
+        let mut detector = BeatDetector::default();
+        let frame_length = 1024.0 / 48_000.0;
+        let beats: Vec<usize> = kicks(200, 24)
+            .enumerate()
+            .filter(|(frame, bands)| detector.push(*frame as f64 * frame_length, bands).beat)
+            .map(|(frame, _)| frame)
+            .collect();
+        // The very first frame has nothing to rise from.
+        assert_eq!(beats, vec![24, 48, 72, 96, 120, 144, 168, 192]);
+        // 24 frames of 21.3 ms apart is 117 BPM.
+        let bpm = detector.bpm().unwrap();
+        assert!((bpm - 117.2).abs() < 0.5, "{bpm}");
+    }
+
+    #[test]
+    fn steady_sound_has_no_beats() {
+        let mut detector = BeatDetector::default();
+        let mut beats = 0;
+        for frame in 0..200 {
+            // A slow swell rises a little every frame, but never stands out.
+            let level = 0.2 + frame as f32 * 0.002;
+            if detector.push(frame as f64 * 0.02, &[level; 4]).beat {
+                beats += 1;
+            }
+        }
+        assert_eq!(beats, 0);
+        assert_eq!(detector.bpm(), None);
+    }
+
+    #[test]
+    fn tempos_fold_into_range() {
+        let mut detector = BeatDetector::default();
+        // Beats found on every other beat of a 120 BPM song.
+        for beat in 0..6 {
+            detector.beats.push_back(beat as f64);
+        }
+        assert_eq!(detector.bpm(), Some(120.0));
+    }
+
+    #[test]
+    fn pulses_fade_over_a_beat() {
+        assert_eq!(pulse_level(0.0, Some(120.0)), 1.0);
+        assert!((pulse_level(0.25, Some(120.0)) - 0.5).abs() < 1e-6);
+        assert_eq!(pulse_level(1.0, Some(120.0)), PULSE_FLOOR);
+        assert_eq!(pulse_level(1.0, None), PULSE_FLOOR);
+    }
+
+    #[test]
+    fn hue_rises_with_the_centroid() {
+        let hue = |centroid| {
+            SpectralFeatures {
+                sample_rate: 48_000,
+                centroid,
+                ..SpectralFeatures::default()
+            }
+            .hue()
+        };
+        assert_eq!(hue(0.0), 0);
+        assert_eq!(hue(MIN_FREQUENCY), 0);
+        assert!(hue(200.0) < hue(2_000.0));
+        assert_eq!(hue(MAX_FREQUENCY * 2.0), u16::MAX);
+    }
+}
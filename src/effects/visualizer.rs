@@ -0,0 +1,193 @@
+//! Audio visualizers for multizone lights: the band levels of the audio drawn across the
+//! zones of strips and matrices, as VU-meter bars or as a scrolling spectrogram.
+
+use std::collections::VecDeque;
+
+use lifx_core::HSBK;
+
+use super::{SpectralFeatures, ZoneLayout};
+use crate::color::DEFAULT_KELVIN;
+
+/// Frames of band levels kept for spectrograms, more than the zones of a strip or the rows
+/// of a matrix.
+const HISTORY_LENGTH: usize = 128;
+
+/// The latest band levels and spectrum colors, newest first, which spectrograms scroll
+/// through.
+#[derive(Debug, Clone, Default)]
+pub struct SpectrumHistory {
+    frames: VecDeque<(Vec<f32>, HSBK)>,
+}
+
+impl SpectrumHistory {
+    pub fn push(&mut self, features: &SpectralFeatures) {
+        self.frames
+            .push_front((features.bands.clone(), features.to_hsbk()));
+        self.frames.truncate(HISTORY_LENGTH);
+    }
+}
+
+/// Color of a band at `position` from the lowest band, 0.0, to the highest, 1.0, lit to
+/// `level`. Bands take the hue their frequencies give the spectrum color.
+pub fn band_color(position: f32, level: f32) -> HSBK {
+    HSBK {
+        hue: (position.clamp(0.0, 1.0) * u16::MAX as f32) as u16,
+        saturation: u16::MAX,
+        brightness: (level.clamp(0.0, 1.0) * u16::MAX as f32) as u16,
+        kelvin: DEFAULT_KELVIN,
+    }
+}
+
+/// The band position and level each of `columns` shows, lowest band first, or from the
+/// middle outwards when `mirrored`. Columns narrower than a band show the loudest band
+/// they span.
+fn spread(bands: &[f32], columns: usize, mirrored: bool) -> Vec<(f32, f32)> {
+    if bands.is_empty() || columns == 0 {
+        return vec![(0.0, 0.0); columns];
+    }
+    let count = if mirrored {
+        columns.div_ceil(2)
+    } else {
+        columns
+    };
+    let half: Vec<(f32, f32)> = (0..count)
+        .map(|column| {
+            let start = column * bands.len() / count;
+            let end = ((column + 1) * bands.len() / count).max(start + 1);
+            let level = bands[start..end].iter().copied().fold(0.0, f32::max);
+            let position = (start + end) as f32 / 2.0 / bands.len() as f32;
+            (position, level)
+        })
+        .collect();
+    if !mirrored {
+        return half;
+    }
+    (0..columns)
+        .map(|column| half[(2 * column + 1).abs_diff(columns) / 2])
+        .collect()
+}
+
+/// VU-meter bars of `bands` on a light laid out as `layout`: one zone per bar on a strip,
+/// lit as loud as its band, and bars rising from the bottom on a matrix. `None` for
+/// single-zone lights.
+pub fn render_bars(layout: ZoneLayout, bands: &[f32], mirrored: bool) -> Option<Vec<HSBK>> {
+    match layout {
+        ZoneLayout::Single => None,
+        ZoneLayout::Strip { zones } => Some(
+            spread(bands, zones, mirrored)
+                .into_iter()
+                .map(|(position, level)| band_color(position, level))
+                .collect(),
+        ),
+        ZoneLayout::Matrix { width, height } => {
+            let columns = spread(bands, width, mirrored);
+            let mut colors = Vec::with_capacity(width * height);
+            for row in 0..height {
+                for (position, level) in &columns {
+                    let lit = (level * height as f32).round() as usize;
+                    let level = if row >= height.saturating_sub(lit) {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    colors.push(band_color(*position, level));
+                }
+            }
+            Some(colors)
+        }
+    }
+}
+
+/// A spectrogram of `history` on a light laid out as `layout`, scrolling away from the
+/// newest frame: along a strip, each zone the spectrum color of a past frame; down a
+/// matrix, each row the bands of a past frame. `None` for single-zone lights.
+pub fn render_spectrogram(layout: ZoneLayout, history: &SpectrumHistory) -> Option<Vec<HSBK>> {
+    let off = band_color(0.0, 0.0);
+    match layout {
+        ZoneLayout::Single => None,
+        ZoneLayout::Strip { zones } => Some(
+            (0..zones)
+                .map(|age| history.frames.get(age).map_or(off, |(_, color)| *color))
+                .collect(),
+        ),
+        ZoneLayout::Matrix { width, height } => {
+            let mut colors = Vec::with_capacity(width * height);
+            for row in 0..height {
+                match history.frames.get(row) {
+                    Some((bands, _)) => colors.extend(
+                        spread(bands, width, false)
+                            .into_iter()
+                            .map(|(position, level)| band_color(position, level)),
+                    ),
+                    None => colors.extend(std::iter::repeat_n(off, width)),
+                }
+            }
+            Some(colors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(colors: &[HSBK]) -> Vec<u16> {
+        colors.iter().map(|color| color.brightness / 257).collect()
+    }
+
+    #[test]
+    fn strip_bars_spread_the_bands() {
+        let bands = [1.0, 0.0, 0.5, 0.0];
+        let colors = render_bars(ZoneLayout::Strip { zones: 8 }, &bands, false).unwrap();
+        assert_eq!(levels(&colors), vec![255, 255, 0, 0, 127, 127, 0, 0]);
+        assert!(colors[0].hue < colors[4].hue);
+        // Fewer zones than bands show the loudest band they cover.
+        let colors = render_bars(ZoneLayout::Strip { zones: 2 }, &bands, false).unwrap();
+        assert_eq!(levels(&colors), vec![255, 127]);
+        assert_eq!(render_bars(ZoneLayout::Single, &bands, false), None);
+    }
+
+    #[test]
+    fn mirrored_bars_grow_from_the_middle() {
+        let bands = [1.0, 0.0, 0.5];
+        let colors = render_bars(ZoneLayout::Strip { zones: 6 }, &bands, true).unwrap();
+        assert_eq!(levels(&colors), vec![127, 0, 255, 255, 0, 127]);
+        let colors = render_bars(ZoneLayout::Strip { zones: 5 }, &bands, true).unwrap();
+        assert_eq!(levels(&colors), vec![127, 0, 255, 0, 127]);
+    }
+
+    #[test]
+    fn matrix_bars_rise_from_the_bottom() {
+        let layout = ZoneLayout::Matrix {
+            width: 2,
+            height: 4,
+        };
+        let colors = render_bars(layout, &[1.0, 0.5], false).unwrap();
+        // Row by row from the top left.
+        assert_eq!(levels(&colors), vec![255, 0, 255, 0, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn spectrograms_scroll_away_from_the_newest_frame() {
+        let mut history = SpectrumHistory::default();
+        for level in [0.2, 1.0] {
+            history.push(&SpectralFeatures {
+                sample_rate: 48_000,
+                bands: vec![level, 0.0],
+                rms: 1.0,
+                centroid: level * 1000.0,
+                ..SpectralFeatures::default()
+            });
+        }
+        let layout = ZoneLayout::Matrix {
+            width: 2,
+            height: 3,
+        };
+        let colors = render_spectrogram(layout, &history).unwrap();
+        assert_eq!(levels(&colors), vec![255, 0, 51, 0, 0, 0]);
+
+        let strip = render_spectrogram(ZoneLayout::Strip { zones: 3 }, &history).unwrap();
+        assert!(strip[0].hue > strip[1].hue);
+        assert_eq!(strip[2].brightness, 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Frames read from files instead of the screen, so screen sync can be demoed and tested
+//! without a live desktop: a still image, a folder of images played in name order, or an
+//! animated GIF.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use rust_i18n::t;
+use xcap::image::{codecs::gif::GifDecoder, AnimationDecoder, ImageFormat, RgbaImage};
+
+use crate::capture::downsample;
+
+pub const FRAME_RATE_RANGE: std::ops::RangeInclusive<f32> = 0.1..=60.0;
+pub const DEFAULT_FRAME_RATE: f32 = 10.0;
+
+/// Frames of an image file or sequence, played in a loop at `frame_rate` from when they
+/// were opened. Clones share the frames.
+#[derive(Clone)]
+pub struct FileFrames {
+    pub path: PathBuf,
+    /// Frames per second. Still images show their only frame throughout.
+    pub frame_rate: f32,
+    frames: Arc<Vec<RgbaImage>>,
+    started: Instant,
+}
+
+impl FileFrames {
+    /// Read the frames at `path`, downsampled like screen captures to at most `budget`
+    /// pixels each. A folder is read as a sequence of the images in it, by file name.
+    pub fn open(
+        path: impl AsRef<Path>,
+        frame_rate: f32,
+        budget: usize,
+    ) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let frames = if path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.is_file() && ImageFormat::from_path(file).is_ok())
+                .collect();
+            files.sort();
+            files
+                .iter()
+                .map(|file| Ok(xcap::image::open(file)?.to_rgba8()))
+                .collect::<Result<Vec<_>, anyhow::Error>>()?
+        } else if ImageFormat::from_path(path).ok() == Some(ImageFormat::Gif) {
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+            decoder
+                .into_frames()
+                .collect_frames()?
+                .into_iter()
+                .map(|frame| frame.into_buffer())
+                .collect()
+        } else {
+            vec![xcap::image::open(path)?.to_rgba8()]
+        };
+        if frames.is_empty() {
+            return Err(anyhow!(t!(
+                "error.no_frames",
+                path = path.display().to_string()
+            )));
+        }
+        let frames = frames
+            .iter()
+            .map(|frame| downsample(frame, budget))
+            .collect();
+        Ok(FileFrames {
+            path: path.to_path_buf(),
+            ..FileFrames::from_frames(frames, frame_rate)
+        })
+    }
+
+    /// Play `frames`, which must not be empty, as they are.
+    pub fn from_frames(frames: Vec<RgbaImage>, frame_rate: f32) -> Self {
+        FileFrames {
+            path: PathBuf::new(),
+            frame_rate,
+            frames: Arc::new(frames),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The frame shown `elapsed` after the frames were opened.
+    pub fn frame_at(&self, elapsed: Duration) -> &RgbaImage {
+        let index = (elapsed.as_secs_f32() * self.frame_rate.max(0.0)) as usize;
+        &self.frames[index % self.frames.len()]
+    }
+
+    pub fn current_frame(&self) -> &RgbaImage {
+        self.frame_at(self.started.elapsed())
+    }
+}
+
+impl std::fmt::Debug for FileFrames {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FileFrames")
+            .field("path", &self.path)
+            .field("frame_rate", &self.frame_rate)
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl PartialEq for FileFrames {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.frame_rate == other.frame_rate
+            && Arc::ptr_eq(&self.frames, &other.frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screencap::average_images_color;
+    use crate::RGB8;
+    use lifx_core::HSBK;
+    use xcap::image::{codecs::gif::GifEncoder, Delay, Frame, Rgba};
+
+    fn solid(color: [u8; 3]) -> RgbaImage {
+        RgbaImage::from_pixel(8, 8, Rgba([color[0], color[1], color[2], 255]))
+    }
+
+    fn rgb(color: [u8; 3]) -> HSBK {
+        RGB8::new(color[0], color[1], color[2], None).into()
+    }
+
+    #[test]
+    fn frames_loop_at_the_frame_rate() {
+        let frames = FileFrames::from_frames(vec![solid([255, 0, 0]), solid([0, 255, 0])], 10.0);
+        let color = |ms| frames.frame_at(Duration::from_millis(ms)).get_pixel(0, 0)[1];
+        assert_eq!(color(0), 0);
+        assert_eq!(color(150), 255);
+        assert_eq!(color(250), 0);
+    }
+
+    #[test]
+    fn gif_frames_feed_the_screen_analysis() {
+        let path = std::env::temp_dir().join(format!("mantle-test-{}.gif", std::process::id()));
+        {
+            let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+            for color in [[255, 0, 0], [0, 0, 255]] {
+                let delay = Delay::from_numer_denom_ms(100, 1);
+                encoder
+                    .encode_frame(Frame::from_parts(solid(color), 0, 0, delay))
+                    .unwrap();
+            }
+        }
+        let frames = FileFrames::open(&path, 1.0, 16).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        // Downsampled to the budget like a screen capture.
+        assert_eq!(frames.frame_at(Duration::ZERO).dimensions(), (4, 4));
+        // GIF palettes may shift colors a little.
+        let hue = |secs| {
+            let frame = frames.frame_at(Duration::from_secs(secs)).clone();
+            average_images_color(&[frame]).map(|color| color.hue)
+        };
+        let near = |hue: Option<u16>, expected: HSBK| {
+            hue.is_some_and(|hue| crate::color::hue_distance(hue, expected.hue) < 1000)
+        };
+        assert!(near(hue(0), rgb([255, 0, 0])));
+        assert!(near(hue(1), rgb([0, 0, 255])));
+    }
+
+    #[test]
+    fn missing_files_are_an_error() {
+        let path = std::env::temp_dir().join("mantle-does-not-exist.png");
+        assert!(FileFrames::open(path, 1.0, 16).is_err());
+    }
+}
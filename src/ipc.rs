@@ -0,0 +1,408 @@
+//! Single-instance enforcement and local control channel.
+//!
+//! The first Mantle process binds a local socket (a Unix domain socket on Linux and macOS, a
+//! named pipe on Windows). Later launches connect to it, forward their command line as a
+//! command and exit instead of fighting over the LIFX port. Any other program can speak the
+//! same protocol: one JSON command per line, answered by one JSON response per line.
+//!
+//! ```text
+//! {"command":"apply_scene","name":"Movie Night"}
+//! {"ok":true}
+//! ```
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::Duration;
+
+use interprocess::local_socket::{
+    prelude::*, Listener, ListenerNonblockingMode, ListenerOptions, Name, Stream,
+};
+use serde::{Deserialize, Serialize};
+
+/// How long a connection waits for the app to carry out a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub const USAGE: &str =
+    "Usage: mantle [--show | --toggle-power | --scene <NAME> | --shortcut <NAME>]
+
+Options:
+      --show              Show and focus the window of the running instance
+      --toggle-power      Toggle power on all lights
+      --scene <NAME>      Apply a saved scene
+      --shortcut <NAME>   Run the action of a saved keyboard shortcut
+  -h, --help              Print help";
+
+/// A request to the running instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum IpcCommand {
+    Show,
+    ApplyScene { name: String },
+    TogglePower,
+    RunShortcut { name: String },
+}
+
+impl IpcCommand {
+    /// Parse the command line of a launch. No arguments means no command; a second launch
+    /// without arguments forwards [`IpcCommand::Show`] instead.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>, String> {
+        let mut args = args.into_iter();
+        let mut command = None;
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} requires a name", arg))
+            };
+            let parsed = match arg.as_str() {
+                "--show" => IpcCommand::Show,
+                "--toggle-power" => IpcCommand::TogglePower,
+                "--scene" => IpcCommand::ApplyScene { name: value()? },
+                "--shortcut" => IpcCommand::RunShortcut { name: value()? },
+                other => return Err(format!("Unexpected argument '{}'", other)),
+            };
+            if command.replace(parsed).is_some() {
+                return Err("Only one command can be given at a time".to_string());
+            }
+        }
+        Ok(command)
+    }
+}
+
+/// The answer to an [`IpcCommand`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<Result<(), String>> for IpcResponse {
+    fn from(result: Result<(), String>) -> Self {
+        Self {
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+/// A command received by the server, waiting for the app to carry it out.
+pub struct IpcRequest {
+    pub command: IpcCommand,
+    reply: mpsc::Sender<IpcResponse>,
+}
+
+impl IpcRequest {
+    /// Send the outcome back to the connection the command came from.
+    pub fn respond(self, result: Result<(), String>) {
+        let _ = self.reply.send(result.into());
+    }
+}
+
+/// Where the running instance listens. Per user, so two people logged into the same machine
+/// each get their own instance.
+pub fn socket_path() -> PathBuf {
+    #[cfg(windows)]
+    {
+        PathBuf::from(format!("mantle-{}.sock", user_name()))
+    }
+    #[cfg(not(windows))]
+    {
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("mantle.sock"),
+            _ => std::env::temp_dir().join(format!("mantle-{}.sock", user_name())),
+        }
+    }
+}
+
+fn user_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "user".to_string())
+}
+
+fn socket_name(path: &Path) -> io::Result<Name<'static>> {
+    #[cfg(windows)]
+    {
+        use interprocess::local_socket::GenericNamespaced;
+        let name = path.as_os_str().to_os_string();
+        name.to_ns_name::<GenericNamespaced>()
+            .map(|name| name.into_owned())
+    }
+    #[cfg(not(windows))]
+    {
+        use interprocess::local_socket::GenericFilePath;
+        path.to_path_buf()
+            .to_fs_name::<GenericFilePath>()
+            .map(|name| name.into_owned())
+    }
+}
+
+/// A connection to the running instance.
+pub struct IpcClient {
+    reader: BufReader<Stream>,
+}
+
+impl IpcClient {
+    /// Connect to the running instance. Fails when there is none.
+    pub fn connect() -> io::Result<IpcClient> {
+        Self::connect_at(&socket_path())
+    }
+
+    pub fn connect_at(path: &Path) -> io::Result<IpcClient> {
+        let stream = Stream::connect(socket_name(path)?)?;
+        stream.set_recv_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(1)))?;
+        Ok(IpcClient {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    pub fn send(&mut self, command: &IpcCommand) -> io::Result<IpcResponse> {
+        let mut line = serde_json::to_string(command)?;
+        line.push('\n');
+        self.reader.get_mut().write_all(line.as_bytes())?;
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "Mantle closed the connection",
+            ));
+        }
+        Ok(serde_json::from_str(&response)?)
+    }
+}
+
+/// The bound socket of the first instance. Holding it is what makes an instance the only one.
+pub struct IpcListener {
+    listener: Listener,
+}
+
+impl IpcListener {
+    pub fn bind() -> io::Result<IpcListener> {
+        Self::bind_at(&socket_path())
+    }
+
+    /// Claim the socket, replacing one left behind by an instance that did not exit cleanly.
+    /// Fails with [`ErrorKind::AddrInUse`] if an instance is still answering on it.
+    pub fn bind_at(path: &Path) -> io::Result<IpcListener> {
+        let name = socket_name(path)?;
+        if Stream::connect(name.borrow()).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AddrInUse,
+                "Mantle is already running",
+            ));
+        }
+        let listener = ListenerOptions::new()
+            .name(name)
+            // Non-blocking so the accept loop notices when the server is dropped.
+            .nonblocking(ListenerNonblockingMode::Accept)
+            .try_overwrite(true)
+            .create_sync()?;
+        Ok(IpcListener { listener })
+    }
+
+    /// Start accepting connections. `wake` is called after each command is queued so the app
+    /// can pick it up even while its window is hidden.
+    pub fn serve(self, wake: impl Fn() + Send + Sync + 'static) -> IpcServer {
+        let (tx, requests) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let wake: Arc<dyn Fn() + Send + Sync> = Arc::new(wake);
+        let handle = {
+            let running = running.clone();
+            let listener = self.listener;
+            spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok(stream) => {
+                            let tx = tx.clone();
+                            let wake = wake.clone();
+                            spawn(move || {
+                                if let Err(e) = serve_connection(stream, &tx, &*wake) {
+                                    log::warn!("IPC connection closed: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(POLL_INTERVAL),
+                        Err(e) => {
+                            log::error!("IPC accept error: {}", e);
+                            sleep(POLL_INTERVAL);
+                        }
+                    }
+                }
+            })
+        };
+        IpcServer {
+            requests,
+            running,
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Answer commands on one connection until the client hangs up.
+fn serve_connection(
+    stream: Stream,
+    tx: &mpsc::Sender<IpcRequest>,
+    wake: &(dyn Fn() + Send + Sync),
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<IpcCommand>(&line) {
+            Ok(command) => {
+                let (reply, response) = mpsc::channel();
+                if tx.send(IpcRequest { command, reply }).is_err() {
+                    return Ok(());
+                }
+                wake();
+                response
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Err("Mantle did not respond".to_string()).into())
+            }
+            Err(e) => Err(format!("Invalid command: {}", e)).into(),
+        };
+        let mut out = serde_json::to_string(&response)?;
+        out.push('\n');
+        reader.get_mut().write_all(out.as_bytes())?;
+    }
+}
+
+/// The accepting side of the control channel. Stops listening and releases the socket when
+/// dropped.
+pub struct IpcServer {
+    requests: mpsc::Receiver<IpcRequest>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl IpcServer {
+    /// The next command waiting to be carried out, if any.
+    pub fn try_recv(&self) -> Option<IpcRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<IpcCommand>, String> {
+        IpcCommand::from_args(args.iter().map(|a| a.to_string()))
+    }
+
+    fn temp_socket(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mantle-test-{}-{}.sock", name, std::process::id()))
+    }
+
+    #[test]
+    fn parses_command_line() {
+        assert_eq!(args(&[]), Ok(None));
+        assert_eq!(args(&["--show"]), Ok(Some(IpcCommand::Show)));
+        assert_eq!(args(&["--toggle-power"]), Ok(Some(IpcCommand::TogglePower)));
+        assert_eq!(
+            args(&["--scene", "Movie Night"]),
+            Ok(Some(IpcCommand::ApplyScene {
+                name: "Movie Night".to_string()
+            }))
+        );
+        assert_eq!(
+            args(&["--shortcut", "Desk off"]),
+            Ok(Some(IpcCommand::RunShortcut {
+                name: "Desk off".to_string()
+            }))
+        );
+        assert!(args(&["--scene"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+        assert!(args(&["--show", "--toggle-power"]).is_err());
+    }
+
+    #[test]
+    fn commands_use_tagged_json() {
+        let command = IpcCommand::ApplyScene {
+            name: "Evening".to_string(),
+        };
+        let json = serde_json::to_string(&command).unwrap();
+        assert_eq!(json, r#"{"command":"apply_scene","name":"Evening"}"#);
+        assert_eq!(
+            serde_json::from_str::<IpcCommand>(r#"{"command":"toggle_power"}"#).unwrap(),
+            IpcCommand::TogglePower
+        );
+        let ok = serde_json::to_string(&IpcResponse::from(Ok(()))).unwrap();
+        assert_eq!(ok, r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn forwards_commands_to_the_running_instance() {
+        let path = temp_socket("forward");
+        let server = IpcListener::bind_at(&path).unwrap().serve(|| {});
+        assert_eq!(
+            IpcListener::bind_at(&path).err().map(|e| e.kind()),
+            Some(ErrorKind::AddrInUse)
+        );
+
+        let app = spawn(move || {
+            let mut handled = Vec::new();
+            while handled.len() < 2 {
+                match server.try_recv() {
+                    Some(request) => {
+                        let result = match &request.command {
+                            IpcCommand::ApplyScene { name } => Err(format!("No scene {}", name)),
+                            _ => Ok(()),
+                        };
+                        handled.push(request.command.clone());
+                        request.respond(result);
+                    }
+                    None => sleep(Duration::from_millis(10)),
+                }
+            }
+            handled
+        });
+
+        let mut client = IpcClient::connect_at(&path).unwrap();
+        let response = client.send(&IpcCommand::TogglePower).unwrap();
+        assert!(response.ok);
+        let response = client
+            .send(&IpcCommand::ApplyScene {
+                name: "Nope".to_string(),
+            })
+            .unwrap();
+        assert!(!response.ok);
+        assert_eq!(response.error.as_deref(), Some("No scene Nope"));
+
+        let handled = app.join().unwrap();
+        assert_eq!(handled[0], IpcCommand::TogglePower);
+        // The server was dropped with the app thread, so the name is free again.
+        assert!(IpcClient::connect_at(&path).is_err());
+        drop(IpcListener::bind_at(&path).unwrap());
+    }
+
+    #[test]
+    fn replaces_a_stale_socket() {
+        let path = temp_socket("stale");
+        std::fs::write(&path, b"").unwrap();
+        let listener = IpcListener::bind_at(&path);
+        #[cfg(unix)]
+        assert!(listener.is_ok());
+        drop(listener);
+        let _ = std::fs::remove_file(&path);
+    }
+}
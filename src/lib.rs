@@ -4,27 +4,50 @@ extern crate rust_i18n;
 i18n!("locales", fallback = "en");
 
 pub mod action;
+pub mod api;
+#[cfg(feature = "gui")]
 pub mod app;
+#[cfg(feature = "gui")]
 pub mod audio;
+#[cfg(feature = "gui")]
+pub mod capture;
 pub mod color;
 pub mod device_info;
 pub mod device_manager;
+pub mod effects;
+#[cfg(feature = "gui")]
+pub mod frame_source;
+#[cfg(feature = "ipc")]
+pub mod ipc;
+#[cfg(feature = "gui")]
 pub mod listener;
 pub mod products;
 pub mod refreshable_data;
 pub mod scenes;
+#[cfg(feature = "gui")]
 pub mod screencap;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod selector;
 pub mod serializers;
+#[cfg(feature = "gui")]
 pub mod settings;
+#[cfg(feature = "gui")]
 pub mod shortcut;
+pub mod storage;
+#[cfg(feature = "gui")]
 pub mod ui;
 pub mod utils;
 
-pub use color::{contrast_color, HSBK32, RGB8};
+#[cfg(feature = "gui")]
+pub use color::contrast_color;
+pub use color::{HSBK32, RGB8};
 pub use device_info::{BulbInfo, DeviceColor};
 pub use device_manager::LifxManager;
 pub use products::{get_products, Product};
 pub use refreshable_data::RefreshableData;
+#[cfg(feature = "gui")]
 pub use screencap::ScreencapManager;
+#[cfg(feature = "gui")]
 pub use ui::{color_slider, display_color_circle, toggle_button};
 pub use utils::{capitalize_first_letter, AngleIter};
@@ -3,16 +3,61 @@
     windows_subsystem = "windows"
 )]
 
+use std::io::ErrorKind;
+use std::time::Duration;
+
 use mantle::app::MantleApp;
+use mantle::ipc::{IpcClient, IpcCommand, IpcListener, USAGE};
 use mantle::ui::setup_eframe_options;
 use mantle::utils::init_log4rs;
 
+/// How often to try reaching an instance that won the race to start, and how long to wait
+/// between tries while it sets up.
+const IPC_CONNECT_ATTEMPTS: usize = 10;
+const IPC_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 fn main() -> eframe::Result {
     #[cfg(feature = "puffin")]
     start_puffin_server();
 
     init_log4rs();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let command = match IpcCommand::from_args(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // Hand the command to the running instance instead of starting a second one.
+    if let Ok(client) = IpcClient::connect() {
+        forward(client, command.as_ref());
+    }
+    let ipc_listener = match IpcListener::bind() {
+        Ok(listener) => Some(listener),
+        // Another instance started between our connect and bind; it owns the lights now.
+        Err(e) if e.kind() == ErrorKind::AddrInUse => {
+            for _ in 0..IPC_CONNECT_ATTEMPTS {
+                if let Ok(client) = IpcClient::connect() {
+                    forward(client, command.as_ref());
+                }
+                std::thread::sleep(IPC_RETRY_DELAY);
+            }
+            eprintln!("Mantle is already running but not answering: {e}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::error!("Failed to start the IPC listener: {}", e);
+            None
+        }
+    };
+
     #[cfg(target_os = "linux")]
     gtk::init().expect("Failed to initialize GTK");
 
@@ -23,11 +68,30 @@ fn main() -> eframe::Result {
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            Ok(Box::new(MantleApp::new(cc)))
+            let mut app = MantleApp::new(cc);
+            if let Some(listener) = ipc_listener {
+                app.attach_ipc(listener, &cc.egui_ctx);
+            }
+            if let Some(command) = command {
+                if let Err(e) = app.run_ipc_command(&command, &cc.egui_ctx) {
+                    log::error!("Startup command {:?} failed: {}", command, e);
+                }
+            }
+            Ok(Box::new(app))
         }),
     )
 }
 
+/// Send `command` (or `show`) to the running instance and exit with its result.
+fn forward(mut client: IpcClient, command: Option<&IpcCommand>) -> ! {
+    match client.send(command.unwrap_or(&IpcCommand::Show)) {
+        Ok(response) if response.ok => std::process::exit(0),
+        Ok(response) => eprintln!("{}", response.error.unwrap_or_default()),
+        Err(e) => eprintln!("Failed to reach the running Mantle instance: {e}"),
+    }
+    std::process::exit(1);
+}
+
 #[cfg(feature = "puffin")]
 fn start_puffin_server() {
     puffin::set_scopes_on(true); // tell puffin to collect data
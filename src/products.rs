@@ -61,6 +61,8 @@ pub struct Features {
     /// Pixel width of the matrix grid (for 2D layout). Height is derived from
     /// total zone count. If absent, the UI auto-calculates a square-ish layout.
     pub matrix_width: Option<u32>,
+    /// Supports setting every zone of a strip in one message.
+    pub extended_multizone: Option<bool>,
 }
 
 impl Features {
@@ -72,6 +74,30 @@ impl Features {
             .unwrap_or_default()
     }
 
+    /// Like `get_features`, including features that firmware upgrades up to `firmware`
+    /// unlock. Only `extended_multizone` is taken from upgrades.
+    pub fn get_features_for_firmware(
+        model: Option<&(u32, u32)>,
+        firmware: Option<&(u16, u16)>,
+    ) -> Features {
+        let products = get_products();
+        let Some(info) = model.and_then(|(_, product)| products.get(product)) else {
+            return Features::default();
+        };
+        let mut features = info.features.clone();
+        if let Some(&(major, minor)) = firmware {
+            let firmware = (major as u32, minor as u32);
+            for upgrade in &info.upgrades {
+                if firmware >= (upgrade.major, upgrade.minor) {
+                    if let Some(extended) = upgrade.features.extended_multizone {
+                        features.extended_multizone = Some(extended);
+                    }
+                }
+            }
+        }
+        features
+    }
+
     pub fn as_ref(&self) -> Option<Features> {
         Some(self.clone())
     }
@@ -115,6 +141,18 @@ mod tests {
         assert_eq!(product.name, "LIFX Original 1000");
     }
 
+    #[test]
+    fn upgrades_unlock_extended_multizone() {
+        let lifx_z = (1u32, 32u32);
+        let old = Features::get_features_for_firmware(Some(&lifx_z), Some(&(2, 76)));
+        assert_ne!(old.extended_multizone, Some(true));
+        let new = Features::get_features_for_firmware(Some(&lifx_z), Some(&(2, 77)));
+        assert_eq!(new.extended_multizone, Some(true));
+        assert_eq!(new.multizone, Some(true));
+        let unknown_firmware = Features::get_features_for_firmware(Some(&lifx_z), None);
+        assert_ne!(unknown_firmware.extended_multizone, Some(true));
+    }
+
     #[test]
     fn get_product_name_valid_model() {
         let model = (1u32, 1u32);
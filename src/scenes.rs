@@ -33,6 +33,14 @@ pub struct Scene {
     pub name: String,
 }
 
+/// Look up a scene by name, preferring an exact match over a case-insensitive one.
+pub fn find_scene<'a>(scenes: &'a [Scene], name: &str) -> Option<&'a Scene> {
+    scenes
+        .iter()
+        .find(|s| s.name == name)
+        .or_else(|| scenes.iter().find(|s| s.name.eq_ignore_ascii_case(name)))
+}
+
 /// A scene defines a set of devices and their colors so that they can be applied all at once.
 /// This is useful for setting up a specific lighting configuration that you want to be able to
 /// apply quickly.
@@ -1,4 +1,6 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use lifx_core::HSBK;
 use serde::{Deserialize, Serialize};
@@ -7,9 +9,24 @@ use xcap::{
     Monitor, Window, XCapError,
 };
 
+use crate::capture::{CaptureService, CaptureSource};
+use crate::color::DeltaColor;
+use crate::effects::{
+    analysis::{AnalysisMode, MAX_SAMPLES},
+    edges::SampleRect,
+    exclusions::{detect_letterbox, Insets},
+    session::session_frames,
+    CaptureExclusions, ColorSmoother, DeviceLayout, Effect, EffectInputs, Frame, ScreenAnalysis,
+    ScreenMapping, ScreenSession, SmoothingSettings, ZoneLayout,
+};
+use crate::frame_source::FileFrames;
+use crate::listener::input_listener::InputListener;
 use crate::RGB8;
 use rust_i18n::t;
 
+/// Pixels no brighter than this in any channel count as part of a letterbox bar.
+const BAR_LEVEL: u8 = 24;
+
 /// A subregion of a screen that can be captured.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ScreenSubregion {
@@ -45,21 +62,122 @@ impl PartialEq for ScreenSubregion {
 #[derive(Clone, Debug)]
 pub enum RegionCaptureTarget {
     Monitor(Vec<Monitor>),
-    Window(Vec<Window>),
+    /// A window, found again on every frame wherever it has moved.
+    Window(WindowIdentity),
     Subregion(Vec<ScreenSubregion>),
+    /// Frames from an image, image sequence or GIF instead of the screen.
+    File(FileFrames),
+    /// A square around the mouse cursor, wherever it goes.
+    Cursor(CursorSample),
     All,
 }
 
+pub const CURSOR_SIZE_RANGE: std::ops::RangeInclusive<u32> = 8..=512;
+
+/// The square around the mouse cursor that `RegionCaptureTarget::Cursor` samples.
+#[derive(Clone)]
+pub struct CursorSample {
+    pub listener: InputListener,
+    /// Width and height of the square, in screen pixels.
+    pub size: u32,
+}
+
+impl CursorSample {
+    pub fn new(listener: InputListener) -> Self {
+        CursorSample { listener, size: 64 }
+    }
+
+    /// The square centered on the cursor, as (x, y, width, height) on the desktop, or
+    /// `None` before the cursor has moved.
+    pub fn area(&self) -> Option<(i32, i32, u32, u32)> {
+        let position = self.listener.get_last_mouse_position()?;
+        let half = (self.size / 2) as i32;
+        Some((position.x - half, position.y - half, self.size, self.size))
+    }
+}
+
+impl std::fmt::Debug for CursorSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CursorSample")
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl PartialEq for CursorSample {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+    }
+}
+
+/// Finds a window again by its app and title after it has been moved, resized or reopened,
+/// as window ids don't survive a restart.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowIdentity {
+    pub app_name: String,
+    /// The title, where `*` stands for any text. Case is ignored, and an empty pattern
+    /// takes any window of the app.
+    pub title_pattern: String,
+}
+
+impl WindowIdentity {
+    /// The identity of `window` with its current title.
+    pub fn of(window: &Window) -> Self {
+        WindowIdentity {
+            app_name: window.app_name().to_string(),
+            title_pattern: window.title().to_string(),
+        }
+    }
+
+    pub fn matches(&self, app_name: &str, title: &str) -> bool {
+        self.app_name == app_name && title_matches(&self.title_pattern, title)
+    }
+
+    /// The window this identity picks out of `windows`, preferring one that isn't
+    /// minimized.
+    pub fn find<'a>(&self, windows: &'a [Window]) -> Option<&'a Window> {
+        let mut matching = windows
+            .iter()
+            .filter(|window| self.matches(window.app_name(), window.title()));
+        let first = matching.clone().next();
+        matching.find(|window| !window.is_minimized()).or(first)
+    }
+}
+
+fn title_matches(pattern: &str, title: &str) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    let pattern = pattern.to_lowercase();
+    let title = title.to_lowercase();
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = title.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 impl PartialEq for RegionCaptureTarget {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (RegionCaptureTarget::Monitor(m1), RegionCaptureTarget::Monitor(m2)) => {
                 Self::compare_by_id(m1, m2, |m| m.id().into())
             }
-            (RegionCaptureTarget::Window(w1), RegionCaptureTarget::Window(w2)) => {
-                Self::compare_by_id(w1, w2, |w| w.id().into())
-            }
+            (RegionCaptureTarget::Window(w1), RegionCaptureTarget::Window(w2)) => w1 == w2,
             (RegionCaptureTarget::Subregion(s1), RegionCaptureTarget::Subregion(s2)) => s1 == s2,
+            (RegionCaptureTarget::File(f1), RegionCaptureTarget::File(f2)) => f1 == f2,
+            (RegionCaptureTarget::Cursor(c1), RegionCaptureTarget::Cursor(c2)) => c1 == c2,
             (RegionCaptureTarget::All, RegionCaptureTarget::All) => true,
             _ => false,
         }
@@ -67,7 +185,23 @@ impl PartialEq for RegionCaptureTarget {
 }
 
 impl RegionCaptureTarget {
-    /// Helper function to compare vectors of monitors/windows based on their IDs
+    /// Identifies the target across restarts, to remember its exclusions. Windows are
+    /// known by their app, as titles change.
+    pub fn exclusion_key(&self) -> String {
+        match self {
+            RegionCaptureTarget::Monitor(monitors) => monitors
+                .first()
+                .map(|monitor| format!("monitor:{}", monitor.name()))
+                .unwrap_or_default(),
+            RegionCaptureTarget::Window(window) => format!("window:{}", window.app_name),
+            RegionCaptureTarget::Subregion(_) => "subregion".to_string(),
+            RegionCaptureTarget::File(file) => format!("file:{}", file.path.display()),
+            RegionCaptureTarget::Cursor(_) => "cursor".to_string(),
+            RegionCaptureTarget::All => "all".to_string(),
+        }
+    }
+
+    /// Helper function to compare vectors of monitors based on their IDs
     fn compare_by_id<T, F>(v1: &[T], v2: &[T], id_fn: F) -> bool
     where
         F: Fn(&T) -> u64,
@@ -177,38 +311,22 @@ impl ScreencapManager {
         )
     }
 
-    pub fn calculate_average_color(
+    /// Capture every image that makes up `capture_target`.
+    pub fn capture_images(
         &self,
         capture_target: RegionCaptureTarget,
-    ) -> Result<HSBK, XCapError> {
-        let mut red: u64 = 0;
-        let mut green: u64 = 0;
-        let mut blue: u64 = 0;
-        let mut count: u64 = 0;
-
-        let mut calculate_image_pixel_average = |image: &RgbaImage| {
-            for pixel in image.pixels() {
-                if pixel[3] == 0 {
-                    continue;
-                }
-                red += pixel[0] as u64;
-                green += pixel[1] as u64;
-                blue += pixel[2] as u64;
-                count += 1;
-            }
-        };
-
+    ) -> Result<Vec<RgbaImage>, XCapError> {
+        let mut images = Vec::new();
         match capture_target {
             RegionCaptureTarget::Monitor(monitors) => {
                 for monitor in monitors {
-                    let image = monitor.capture_image()?;
-                    calculate_image_pixel_average(&image);
+                    images.push(monitor.capture_image()?);
                 }
             }
-            RegionCaptureTarget::Window(windows) => {
-                for window in windows {
-                    let image = window.capture_image()?;
-                    calculate_image_pixel_average(&image);
+            RegionCaptureTarget::Window(identity) => {
+                // A window that has closed leaves nothing to capture.
+                if let Some(window) = identity.find(&self.windows) {
+                    images.push(window.capture_image()?);
                 }
             }
             RegionCaptureTarget::Subregion(subregions) => {
@@ -221,7 +339,7 @@ impl ScreencapManager {
                             subregion.width,
                             subregion.height,
                         );
-                        calculate_image_pixel_average(&sub_image.to_image());
+                        images.push(sub_image.to_image());
                     } else {
                         // Handle the case when subregion.monitor is None
                         // For now, we skip it
@@ -229,13 +347,51 @@ impl ScreencapManager {
                     }
                 }
             }
+            RegionCaptureTarget::File(file) => images.push(file.current_frame().clone()),
+            RegionCaptureTarget::Cursor(cursor) => {
+                if let Some((x, y, width, height)) = cursor.area() {
+                    let monitor = Monitor::from_point(x + width as i32 / 2, y + height as i32 / 2)?;
+                    let image = monitor.capture_image()?;
+                    let left = (x - monitor.x()).clamp(0, image.width() as i32) as u32;
+                    let top = (y - monitor.y()).clamp(0, image.height() as i32) as u32;
+                    let view = image.view(
+                        left,
+                        top,
+                        width.min(image.width() - left),
+                        height.min(image.height() - top),
+                    );
+                    images.push(view.to_image());
+                }
+            }
             RegionCaptureTarget::All => {
                 for monitor in &self.monitors {
-                    let image = monitor.capture_image()?;
-                    calculate_image_pixel_average(&image);
+                    images.push(monitor.capture_image()?);
                 }
             }
         }
+        Ok(images)
+    }
+
+    pub fn calculate_average_color(
+        &self,
+        capture_target: RegionCaptureTarget,
+    ) -> Result<HSBK, XCapError> {
+        let mut red: u64 = 0;
+        let mut green: u64 = 0;
+        let mut blue: u64 = 0;
+        let mut count: u64 = 0;
+
+        for image in self.capture_images(capture_target)? {
+            for pixel in image.pixels() {
+                if pixel[3] == 0 {
+                    continue;
+                }
+                red += pixel[0] as u64;
+                green += pixel[1] as u64;
+                blue += pixel[2] as u64;
+                count += 1;
+            }
+        }
 
         if count == 0 {
             return Err(XCapError::new(&*t!("error.no_pixels")));
@@ -251,6 +407,407 @@ impl ScreencapManager {
     }
 }
 
+/// Up to about `max_samples` opaque pixels spread evenly over `images`.
+pub fn sample_pixels(images: &[RgbaImage], max_samples: usize) -> Vec<[u8; 3]> {
+    let total: usize = images
+        .iter()
+        .map(|image| (image.width() * image.height()) as usize)
+        .sum();
+    let step = (total / max_samples.max(1)).max(1);
+    images
+        .iter()
+        .flat_map(|image| image.pixels().step_by(step))
+        .filter(|pixel| pixel[3] != 0)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect()
+}
+
+/// Leave the user's exclusion rectangles out of `image` by making them transparent, then
+/// crop away letterbox bars if detection is on.
+pub fn apply_exclusions(mut image: RgbaImage, exclusions: &CaptureExclusions) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    for rect in exclusions
+        .rects
+        .iter()
+        .filter_map(|rect| rect.to_pixels(width, height))
+    {
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                image.get_pixel_mut(x, y)[3] = 0;
+            }
+        }
+    }
+    if !exclusions.auto_letterbox {
+        return image;
+    }
+    let insets = detect_letterbox(width, height, |x, y| {
+        let pixel = image.get_pixel(x, y);
+        pixel[3] == 0 || pixel[0].max(pixel[1]).max(pixel[2]) <= BAR_LEVEL
+    });
+    if insets == Insets::default() {
+        return image;
+    }
+    let inner = insets.inner(width, height);
+    image
+        .view(inner.x, inner.y, inner.width, inner.height)
+        .to_image()
+}
+
+/// Average color of the opaque pixels of all `images`, if there are any.
+pub fn average_images_color(images: &[RgbaImage]) -> Option<HSBK> {
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+    for pixel in images.iter().flat_map(|image| image.pixels()) {
+        if pixel[3] == 0 {
+            continue;
+        }
+        for (total, channel) in sum.iter_mut().zip(pixel.0) {
+            *total += channel as u64;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    Some(
+        RGB8 {
+            red: (sum[0] / count) as u8,
+            green: (sum[1] / count) as u8,
+            blue: (sum[2] / count) as u8,
+            temperature: None,
+        }
+        .into(),
+    )
+}
+
+/// Average color of the opaque pixels of `image` inside `rect`, if there are any.
+pub fn average_rect_color(image: &RgbaImage, rect: SampleRect) -> Option<HSBK> {
+    let view = image.view(rect.x, rect.y, rect.width, rect.height);
+    average_images_color(&[view.to_image()])
+}
+
+/// Sets every light to the average color of a region of the screen, or another color
+/// picked by its `ScreenAnalysis`, smoothed over time.
+/// With an edge mapping, strips instead show the colors along an edge of the region, and
+/// when mirroring, matrix lights show a downsampled copy of it.
+pub struct ScreenEffect {
+    region: RegionCaptureTarget,
+    /// Shared with the subregion editor, so edits apply while the effect runs.
+    subregion: Option<Arc<Mutex<ScreenSubregion>>>,
+    options: ScreenSyncOptions,
+    interval: Duration,
+    smoother: ColorSmoother,
+    capture: CaptureService,
+}
+
+/// Screen sync settings shared with the device panel, so changes apply while the effect
+/// runs.
+#[derive(Clone, Default)]
+pub struct ScreenSyncOptions {
+    pub mapping: Arc<Mutex<ScreenMapping>>,
+    pub analysis: Arc<Mutex<ScreenAnalysis>>,
+    pub smoothing: Arc<Mutex<SmoothingSettings>>,
+    /// Exclusions of every capture target, by `RegionCaptureTarget::exclusion_key`.
+    pub exclusions: Arc<Mutex<HashMap<String, CaptureExclusions>>>,
+}
+
+impl ScreenEffect {
+    pub const KIND: &'static str = "screen";
+
+    pub fn new(
+        region: RegionCaptureTarget,
+        subregion: Option<Arc<Mutex<ScreenSubregion>>>,
+        options: ScreenSyncOptions,
+        interval: Duration,
+        capture: CaptureService,
+    ) -> Self {
+        ScreenEffect {
+            region,
+            subregion,
+            options,
+            interval,
+            smoother: ColorSmoother::default(),
+            capture,
+        }
+    }
+
+    fn capture_region(&self) -> RegionCaptureTarget {
+        match &self.subregion {
+            Some(subregion) => match subregion.lock() {
+                Ok(subregion) => RegionCaptureTarget::Subregion(vec![subregion.clone()]),
+                Err(_) => RegionCaptureTarget::Subregion(vec![]),
+            },
+            None => self.region.clone(),
+        }
+    }
+
+    /// The latest frames of `region` from the capture thread or its file, cropped to
+    /// subregions and with its exclusions left out. For `All`, the primary monitor comes first. Empty
+    /// until the first frames arrive.
+    fn latest_images(&self, region: &RegionCaptureTarget) -> Vec<RgbaImage> {
+        let exclusions = self
+            .options
+            .exclusions
+            .lock()
+            .ok()
+            .and_then(|exclusions| exclusions.get(&region.exclusion_key()).cloned())
+            .unwrap_or_default();
+        let sources: Vec<(CaptureSource, Option<&ScreenSubregion>)> = match region {
+            RegionCaptureTarget::Monitor(monitors) => monitors
+                .iter()
+                .map(|monitor| (CaptureSource::Monitor(monitor.id()), None))
+                .collect(),
+            RegionCaptureTarget::Window(identity) => {
+                vec![(CaptureSource::Window(identity.clone()), None)]
+            }
+            RegionCaptureTarget::Subregion(subregions) => subregions
+                .iter()
+                .filter_map(|subregion| {
+                    let monitor = subregion.monitor.as_ref()?;
+                    Some((CaptureSource::Monitor(monitor.id()), Some(subregion)))
+                })
+                .collect(),
+            // Files are already in memory, so they skip the capture thread.
+            RegionCaptureTarget::File(file) => {
+                return vec![apply_exclusions(file.current_frame().clone(), &exclusions)];
+            }
+            // The square is cropped from the monitor under the cursor as it moves. It's too
+            // small for exclusions to make sense.
+            RegionCaptureTarget::Cursor(cursor) => {
+                let Some((x, y, width, height)) = cursor.area() else {
+                    return Vec::new();
+                };
+                let center = (x + width as i32 / 2, y + height as i32 / 2);
+                let Some(monitor) = self.capture.monitor_at(center.0, center.1) else {
+                    return Vec::new();
+                };
+                let Some(frame) = self
+                    .capture
+                    .request(CaptureSource::Monitor(monitor.id), self.interval)
+                else {
+                    return Vec::new();
+                };
+                return vec![frame.crop(x - monitor.x, y - monitor.y, width, height)];
+            }
+            RegionCaptureTarget::All => self
+                .capture
+                .monitors()
+                .into_iter()
+                .map(|id| (CaptureSource::Monitor(id), None))
+                .collect(),
+        };
+        sources
+            .into_iter()
+            .filter_map(|(source, subregion)| {
+                let frame = self.capture.request(source, self.interval)?;
+                let image = match subregion {
+                    Some(sub) => frame.crop(sub.x, sub.y, sub.width, sub.height),
+                    None => frame.image.clone(),
+                };
+                Some(apply_exclusions(image, &exclusions))
+            })
+            .collect()
+    }
+}
+
+impl Effect for ScreenEffect {
+    fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn transition_ms(&self) -> u32 {
+        (self.interval.as_millis() / 2) as u32
+    }
+
+    fn render(
+        &mut self,
+        _time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let started = Instant::now();
+        let region = self.capture_region();
+        let images = self.latest_images(&region);
+        if images.is_empty() {
+            return Vec::new();
+        }
+        let smoothing = self
+            .options
+            .smoothing
+            .lock()
+            .map(|s| *s)
+            .unwrap_or_default();
+        let transition_ms = self.transition_ms();
+        let mapping = self.options.mapping.lock().map(|m| *m).unwrap_or_default();
+        let analysis = self.options.analysis.lock().map(|a| *a).unwrap_or_default();
+        // Edge mapping and mirroring show a single image, the primary monitor for `All`.
+        let image = (mapping != ScreenMapping::Average).then(|| &images[0]);
+        let shown = match image {
+            Some(image) => std::slice::from_ref(image),
+            None => &images[..],
+        };
+        // The colors of lights that show the screen as a whole, handed out in turn.
+        let whole = match analysis.mode {
+            AnalysisMode::Average => average_images_color(shown).into_iter().collect(),
+            _ => analysis.colors(&sample_pixels(shown, MAX_SAMPLES)),
+        };
+        if whole.is_empty() {
+            return Vec::new();
+        }
+        let frames: Vec<(u64, Frame)> = devices
+            .iter()
+            .filter_map(|device| {
+                let average = whole[device.index % whole.len()];
+                let colors: Vec<HSBK> = match (mapping, device.zones, image) {
+                    (ScreenMapping::Edge(edge), ZoneLayout::Strip { zones }, Some(image)) => edge
+                        .regions(image.width(), image.height(), zones)
+                        .into_iter()
+                        .map(|rect| average_rect_color(image, rect).unwrap_or(average))
+                        .collect(),
+                    (
+                        ScreenMapping::Mirror(fit),
+                        ZoneLayout::Matrix { width, height },
+                        Some(image),
+                    ) => fit
+                        .regions(image.width(), image.height(), width, height)
+                        .into_iter()
+                        .map(|rect| match rect {
+                            Some(rect) => average_rect_color(image, rect).unwrap_or(average),
+                            None => HSBK {
+                                brightness: 0,
+                                ..average
+                            },
+                        })
+                        .collect(),
+                    _ => {
+                        let sample = DeltaColor {
+                            next: average,
+                            duration: Some(transition_ms),
+                        };
+                        let delta = self.smoother.update(&smoothing, device.target, sample)?;
+                        return Some((device.target, Frame::Whole(delta.next)));
+                    }
+                };
+                let colors = self
+                    .smoother
+                    .update_zones(&smoothing, device.target, &colors)?;
+                Some((device.target, Frame::Zones(colors)))
+            })
+            .collect();
+        self.capture.record_analysis(started.elapsed());
+        frames
+    }
+}
+
+/// Runs a `ScreenSession`: captures its monitor once per frame and sends the average color
+/// of each region to the lights it's assigned to.
+pub struct ScreenSessionEffect {
+    /// Shared with the session editor, so regions can be redrawn while it runs.
+    session: Arc<Mutex<ScreenSession>>,
+    /// Monitor ids by name.
+    monitors: HashMap<String, u32>,
+    /// The lights of each group by group id, as they were when the session started.
+    groups: HashMap<u64, Vec<u64>>,
+    interval: Duration,
+    smoother: ColorSmoother,
+    capture: CaptureService,
+}
+
+impl ScreenSessionEffect {
+    pub const KIND: &'static str = "screen_session";
+
+    pub fn new(
+        session: Arc<Mutex<ScreenSession>>,
+        monitors: HashMap<String, u32>,
+        groups: HashMap<u64, Vec<u64>>,
+        interval: Duration,
+        capture: CaptureService,
+    ) -> Self {
+        ScreenSessionEffect {
+            session,
+            monitors,
+            groups,
+            interval,
+            smoother: ColorSmoother::default(),
+            capture,
+        }
+    }
+}
+
+impl Effect for ScreenSessionEffect {
+    fn kind(&self) -> &'static str {
+        Self::KIND
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn transition_ms(&self) -> u32 {
+        (self.interval.as_millis() / 2) as u32
+    }
+
+    fn render(
+        &mut self,
+        _time: Duration,
+        devices: &[DeviceLayout],
+        _inputs: &EffectInputs,
+    ) -> Vec<(u64, Frame)> {
+        let started = Instant::now();
+        let Some(session) = self.session.lock().ok().map(|s| s.clone()) else {
+            return Vec::new();
+        };
+        let Some(monitor) = self
+            .monitors
+            .get(&session.monitor)
+            .copied()
+            .or_else(|| self.capture.monitors().first().copied())
+        else {
+            return Vec::new();
+        };
+        let Some(frame) = self
+            .capture
+            .request(CaptureSource::Monitor(monitor), self.interval)
+        else {
+            return Vec::new();
+        };
+        let colors: Vec<Option<HSBK>> = session
+            .assignments
+            .iter()
+            .map(|region| {
+                let rect = frame.sample_rect(region.x, region.y, region.width, region.height);
+                average_rect_color(&frame.image, rect)
+            })
+            .collect();
+        let transition_ms = self.transition_ms();
+        let frames = session_frames(&session.assignments, &colors, devices, &self.groups)
+            .into_iter()
+            .filter_map(|(target, frame)| match frame {
+                Frame::Whole(color) => {
+                    let sample = DeltaColor {
+                        next: color,
+                        duration: Some(transition_ms),
+                    };
+                    let delta = self.smoother.update(&session.smoothing, target, sample)?;
+                    Some((target, Frame::Whole(delta.next)))
+                }
+                Frame::Zones(colors) => {
+                    let colors = self
+                        .smoother
+                        .update_zones(&session.smoothing, target, &colors)?;
+                    Some((target, Frame::Zones(colors)))
+                }
+            })
+            .collect();
+        self.capture.record_analysis(started.elapsed());
+        frames
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -348,6 +905,30 @@ mod tests {
         assert!(RegionCaptureTarget::compare_by_id(&items, &items2, |x| *x));
     }
 
+    #[test]
+    fn window_titles_match_patterns() {
+        assert!(title_matches("Mantle", "mantle"));
+        assert!(!title_matches("Mantle", "Mantle 2"));
+        assert!(title_matches(
+            "* - YouTube*",
+            "Lo-fi beats - YouTube — Firefox"
+        ));
+        assert!(!title_matches("* - YouTube*", "YouTube"));
+        assert!(title_matches("a*b*c", "abc"));
+        assert!(!title_matches("a*b*c", "acb"));
+        assert!(title_matches("", "anything"));
+    }
+
+    #[test]
+    fn window_identity_needs_the_app() {
+        let identity = WindowIdentity {
+            app_name: "firefox".to_string(),
+            title_pattern: String::new(),
+        };
+        assert!(identity.matches("firefox", "New Tab"));
+        assert!(!identity.matches("chrome", "New Tab"));
+    }
+
     #[test]
     fn region_capture_target_all_eq() {
         assert_eq!(RegionCaptureTarget::All, RegionCaptureTarget::All);
@@ -396,4 +977,19 @@ mod tests {
         let deserialized: ScreenSubregion = serde_json::from_str(&json).unwrap();
         assert_eq!(sub, deserialized);
     }
+
+    #[test]
+    fn file_frames_average_like_the_screen() {
+        let manager = ScreencapManager {
+            monitors: Vec::new(),
+            windows: Vec::new(),
+        };
+        let frame = RgbaImage::from_pixel(4, 4, xcap::image::Rgba([200, 40, 40, 255]));
+        let file = FileFrames::from_frames(vec![frame], 1.0);
+        let color = manager
+            .calculate_average_color(RegionCaptureTarget::File(file))
+            .unwrap();
+        let expected: HSBK = RGB8::new(200, 40, 40, None).into();
+        assert_eq!(color, expected);
+    }
 }
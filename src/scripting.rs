@@ -0,0 +1,922 @@
+//! User scripts written in [Rhai](https://rhai.rs) that drive lights through a restricted
+//! view of `LifxManager`.
+//!
+//! Scripts can list devices, read their colors and set colors, zones and power by
+//! selector (`all`, `id:<serial>`, `label:<name>`, `group:<name>`, `location:<name>`).
+//! They run when started from the script editor, a keyboard shortcut or a remote API,
+//! on an interval, once a day, or whenever a light changes.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lifx_core::HSBK;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::light_state,
+    color::{ColorSpec, BASE_COLOR},
+    device_info::DeviceColor,
+    scenes::{find_scene, Scene},
+    selector::Selector,
+    BulbInfo, LifxManager,
+};
+use rust_i18n::t;
+
+const LOG_CAPACITY: usize = 1000;
+const TICK: Duration = Duration::from_millis(100);
+/// `sleep` wakes up this often to check whether the script was stopped.
+const SLEEP_STEP: Duration = Duration::from_millis(10);
+
+/// The host that `UserAction::RunScript` starts scripts on. Set while a `ScriptRunner`
+/// is alive, so shortcuts and the remote APIs can run scripts by name.
+static ACTIVE_HOST: Mutex<Option<ScriptHost>> = Mutex::new(None);
+
+/// Source of a newly created script.
+pub const EXAMPLE_SCRIPT: &str = r#"// Lights are chosen with selectors: "all", "id:d073d5000000", "label:Desk", "group:Office".
+for light in devices() {
+    print(`${light.label} is ${if light.power { "on" } else { "off" }}`);
+}
+set_color("all", "hue:30 saturation:0.8", 500);
+"#;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// When a script runs on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScriptTrigger {
+    /// Only from the editor, a shortcut or a remote API.
+    #[default]
+    Manual,
+    /// Every `seconds` seconds.
+    Interval { seconds: u32 },
+    /// Once a day at the given UTC time, like scheduled scenes.
+    Daily { hour: u8, minute: u8 },
+    /// Whenever the state of a light changes.
+    DeviceChange,
+}
+
+impl Display for ScriptTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptTrigger::Manual => write!(f, "{}", t!("scripting.trigger.manual")),
+            ScriptTrigger::Interval { .. } => write!(f, "{}", t!("scripting.trigger.interval")),
+            ScriptTrigger::Daily { .. } => write!(f, "{}", t!("scripting.trigger.daily")),
+            ScriptTrigger::DeviceChange => {
+                write!(f, "{}", t!("scripting.trigger.device_change"))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Script {
+    pub name: String,
+    pub source: String,
+    pub trigger: ScriptTrigger,
+    /// Disabled scripts ignore their trigger but can still be run by hand.
+    pub enabled: bool,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            name: String::new(),
+            source: String::new(),
+            trigger: ScriptTrigger::Manual,
+            enabled: true,
+        }
+    }
+}
+
+/// Why a script was started, available to the script as the `event` map.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScriptEvent {
+    /// `manual`, `shortcut`, `interval`, `daily` or `device_change`.
+    pub kind: &'static str,
+    /// Serials of the devices involved: the changed lights, or the shortcut's device.
+    pub devices: Vec<String>,
+}
+
+impl ScriptEvent {
+    pub fn manual() -> Self {
+        ScriptEvent {
+            kind: "manual",
+            devices: Vec::new(),
+        }
+    }
+
+    fn to_map(&self) -> Map {
+        let devices: Array = self.devices.iter().cloned().map(Dynamic::from).collect();
+        let mut map = Map::new();
+        map.insert("kind".into(), self.kind.into());
+        map.insert(
+            "device".into(),
+            self.devices.first().cloned().unwrap_or_default().into(),
+        );
+        map.insert("devices".into(), devices.into());
+        map
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptLogLevel {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLogEntry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub script: String,
+    pub level: ScriptLogLevel,
+    pub message: String,
+}
+
+/// Output and errors of recent script runs, oldest first. Also forwarded to `log`.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptLog {
+    entries: Arc<Mutex<VecDeque<ScriptLogEntry>>>,
+}
+
+impl ScriptLog {
+    pub fn push(&self, script: &str, level: ScriptLogLevel, message: impl Into<String>) {
+        let message = message.into();
+        match level {
+            ScriptLogLevel::Info => log::info!("[script {}] {}", script, message),
+            ScriptLogLevel::Error => log::error!("[script {}] {}", script, message),
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() == LOG_CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(ScriptLogEntry {
+                timestamp,
+                script: script.to_string(),
+                level,
+                message,
+            });
+        }
+    }
+
+    pub fn entries(&self) -> Vec<ScriptLogEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+/// Runs scripts against one `LifxManager`. Cheap to clone; clones share the scripts,
+/// scenes, log and running scripts.
+#[derive(Clone)]
+pub struct ScriptHost {
+    inner: Arc<HostInner>,
+}
+
+struct HostInner {
+    manager: LifxManager,
+    scripts: RwLock<Vec<Script>>,
+    scenes: RwLock<Vec<Scene>>,
+    log: ScriptLog,
+    /// Stop flag of each running script, by name.
+    active: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl ScriptHost {
+    pub fn new(manager: LifxManager, scripts: Vec<Script>, scenes: Vec<Scene>) -> Self {
+        ScriptHost {
+            inner: Arc::new(HostInner {
+                manager,
+                scripts: RwLock::new(scripts),
+                scenes: RwLock::new(scenes),
+                log: ScriptLog::default(),
+                active: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// The host of the running `ScriptRunner`, if there is one.
+    pub fn active() -> Option<ScriptHost> {
+        ACTIVE_HOST.lock().ok().and_then(|host| host.clone())
+    }
+
+    pub fn log(&self) -> &ScriptLog {
+        &self.inner.log
+    }
+
+    pub fn scripts(&self) -> Vec<Script> {
+        self.inner
+            .scripts
+            .read()
+            .map(|scripts| scripts.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_scripts(&self, scripts: &[Script]) {
+        if let Ok(mut current) = self.inner.scripts.write() {
+            if current.as_slice() != scripts {
+                *current = scripts.to_vec();
+            }
+        }
+    }
+
+    pub fn set_scenes(&self, scenes: &[Scene]) {
+        if let Ok(mut current) = self.inner.scenes.write() {
+            if current.as_slice() != scenes {
+                *current = scenes.to_vec();
+            }
+        }
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.inner
+            .active
+            .lock()
+            .is_ok_and(|active| active.contains_key(name))
+    }
+
+    /// Start the saved script called `name` in the background.
+    pub fn run_named(&self, name: &str, event: ScriptEvent) -> Result<(), anyhow::Error> {
+        let script = self
+            .scripts()
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| anyhow::anyhow!(t!("error.script_not_found", name = name)))?;
+        self.run(script, event);
+        Ok(())
+    }
+
+    /// Start `script` in the background. Returns `false` without starting it if a script
+    /// with the same name is still running.
+    pub fn run(&self, script: Script, event: ScriptEvent) -> bool {
+        let stop = Arc::new(AtomicBool::new(false));
+        match self.inner.active.lock() {
+            Ok(mut active) if !active.contains_key(&script.name) => {
+                active.insert(script.name.clone(), stop.clone());
+            }
+            _ => return false,
+        }
+        let host = self.clone();
+        spawn(move || {
+            if let Err(e) = host.run_blocking(&script, &event, &stop) {
+                host.inner
+                    .log
+                    .push(&script.name, ScriptLogLevel::Error, e.to_string());
+            }
+            if let Ok(mut active) = host.inner.active.lock() {
+                active.remove(&script.name);
+            }
+        });
+        true
+    }
+
+    pub fn stop(&self, name: &str) {
+        if let Ok(active) = self.inner.active.lock() {
+            if let Some(stop) = active.get(name) {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn stop_all(&self) {
+        if let Ok(active) = self.inner.active.lock() {
+            for stop in active.values() {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Run `script` on the calling thread until it finishes or `stop` is set.
+    pub fn run_blocking(
+        &self,
+        script: &Script,
+        event: &ScriptEvent,
+        stop: &Arc<AtomicBool>,
+    ) -> Result<Dynamic, anyhow::Error> {
+        let engine = self.engine(&script.name, stop.clone());
+        let ast = engine
+            .compile(&script.source)
+            .map_err(|e| anyhow::anyhow!(t!("error.script_compile", error = e.to_string())))?;
+        let mut scope = Scope::new();
+        scope.push_constant("event", event.to_map());
+        engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => anyhow::anyhow!(t!("scripting.stopped")),
+                e => anyhow::anyhow!(e.to_string()),
+            })
+    }
+
+    /// An engine whose only way to reach the outside world is the light API below.
+    fn engine(&self, script: &str, stop: Arc<AtomicBool>) -> Engine {
+        let mut engine = Engine::new();
+        engine
+            .set_max_call_levels(64)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(1 << 20)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000);
+        engine.disable_symbol("eval");
+
+        let stopped = stop.clone();
+        engine.on_progress(move |_| stopped.load(Ordering::SeqCst).then_some(Dynamic::UNIT));
+        let (log, name) = (self.inner.log.clone(), script.to_string());
+        engine.on_print(move |text| log.push(&name, ScriptLogLevel::Info, text));
+        let (log, name) = (self.inner.log.clone(), script.to_string());
+        engine.on_debug(move |text, _, pos| {
+            log.push(&name, ScriptLogLevel::Info, format!("{} {}", pos, text))
+        });
+
+        let lights = Lights {
+            manager: self.inner.manager.clone(),
+        };
+        let l = lights.clone();
+        engine.register_fn("devices", move || l.devices());
+        let l = lights.clone();
+        engine.register_fn("get_color", move |selector: &str| l.get_color(selector));
+        let l = lights.clone();
+        engine.register_fn("set_color", move |selector: &str, color: &str| {
+            l.set_color(selector, color, 0)
+        });
+        let l = lights.clone();
+        engine.register_fn(
+            "set_color",
+            move |selector: &str, color: &str, duration: i64| {
+                l.set_color(selector, color, duration)
+            },
+        );
+        let l = lights.clone();
+        engine.register_fn(
+            "set_hsbk",
+            move |selector: &str, h: Dynamic, s: Dynamic, b: Dynamic, k: Dynamic| {
+                l.set_hsbk(selector, hsbk(h, s, b, k)?, 0)
+            },
+        );
+        let l = lights.clone();
+        engine.register_fn(
+            "set_hsbk",
+            move |selector: &str, h: Dynamic, s: Dynamic, b: Dynamic, k: Dynamic, duration: i64| {
+                l.set_hsbk(selector, hsbk(h, s, b, k)?, duration)
+            },
+        );
+        let l = lights.clone();
+        engine.register_fn("set_power", move |selector: &str, on: bool| {
+            l.set_power(selector, on, 0)
+        });
+        let l = lights.clone();
+        engine.register_fn(
+            "set_power",
+            move |selector: &str, on: bool, duration: i64| l.set_power(selector, on, duration),
+        );
+        let l = lights.clone();
+        engine.register_fn(
+            "set_zone",
+            move |selector: &str, index: i64, color: &str| {
+                l.set_zones(selector, index, index, color, 0)
+            },
+        );
+        let l = lights.clone();
+        engine.register_fn(
+            "set_zones",
+            move |selector: &str, start: i64, end: i64, color: &str| {
+                l.set_zones(selector, start, end, color, 0)
+            },
+        );
+        let l = lights;
+        engine.register_fn(
+            "set_zones",
+            move |selector: &str, start: i64, end: i64, color: &str, duration: i64| {
+                l.set_zones(selector, start, end, color, duration)
+            },
+        );
+
+        let host = self.clone();
+        engine.register_fn("apply_scene", move |name: &str| host.apply_scene(name));
+        engine.register_fn("sleep", move |ms: i64| sleep(&stop, ms));
+        engine
+    }
+
+    fn apply_scene(&self, name: &str) -> ScriptResult<()> {
+        let scene = self
+            .inner
+            .scenes
+            .read()
+            .ok()
+            .and_then(|scenes| find_scene(&scenes, name).cloned())
+            .ok_or_else(|| t!("error.scene_not_found", name = name).to_string())?;
+        let mut manager = self.inner.manager.clone();
+        scene
+            .apply(&mut manager)
+            .map_err(|errors| errors.join(", ").into())
+    }
+}
+
+/// The light operations scripts can call. Every call takes a selector string.
+#[derive(Clone)]
+struct Lights {
+    manager: LifxManager,
+}
+
+impl Lights {
+    fn select(&self, selector: &str) -> ScriptResult<Vec<BulbInfo>> {
+        let selector = Selector::from_str(selector).map_err(|e| e.to_string())?;
+        let bulbs = self.manager.bulbs.lock().map_err(|e| e.to_string())?;
+        Ok(selector.select(&bulbs).into_iter().cloned().collect())
+    }
+
+    fn devices(&self) -> Array {
+        self.select("all")
+            .unwrap_or_default()
+            .iter()
+            .map(|bulb| device_map(bulb).into())
+            .collect()
+    }
+
+    /// The color of the first selected light, or `()` if none matched.
+    fn get_color(&self, selector: &str) -> ScriptResult<Dynamic> {
+        Ok(self
+            .select(selector)?
+            .first()
+            .and_then(|bulb| bulb.get_color().copied())
+            .map(|color| color_map(color).into())
+            .unwrap_or(Dynamic::UNIT))
+    }
+
+    fn set_color(&self, selector: &str, color: &str, duration: i64) -> ScriptResult<i64> {
+        let spec = ColorSpec::from_str(color).map_err(|e| e.to_string())?;
+        let mut count = 0;
+        for bulb in self.select(selector)? {
+            let current = bulb.get_color().copied().unwrap_or(BASE_COLOR);
+            self.manager
+                .set_color(&&bulb, spec.apply(current), Some(millis(duration)))
+                .map_err(|e| e.to_string())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn set_hsbk(&self, selector: &str, color: HSBK, duration: i64) -> ScriptResult<i64> {
+        let mut count = 0;
+        for bulb in self.select(selector)? {
+            self.manager
+                .set_color(&&bulb, color, Some(millis(duration)))
+                .map_err(|e| e.to_string())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn set_power(&self, selector: &str, on: bool, duration: i64) -> ScriptResult<i64> {
+        let level = if on { u16::MAX } else { 0 };
+        let mut count = 0;
+        for bulb in self.select(selector)? {
+            self.manager
+                .set_power_with_duration(&&bulb, level, millis(duration))
+                .map_err(|e| e.to_string())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Set zones `start..=end` of the selected multizone and matrix lights; other lights
+    /// are skipped. Returns the number of lights changed.
+    fn set_zones(
+        &self,
+        selector: &str,
+        start: i64,
+        end: i64,
+        color: &str,
+        duration: i64,
+    ) -> ScriptResult<i64> {
+        let spec = ColorSpec::from_str(color).map_err(|e| e.to_string())?;
+        if start < 0 || end < start {
+            return Err(t!("error.script_zone_range", start = start, end = end)
+                .to_string()
+                .into());
+        }
+        let (start, end) = (start as usize, end as usize);
+        let mut count = 0;
+        for bulb in self.select(selector)? {
+            let zones = match &bulb.color {
+                DeviceColor::Multi(data) | DeviceColor::Matrix(data) => data.data.as_ref(),
+                DeviceColor::Single(_) | DeviceColor::Unknown => None,
+            };
+            let Some(zones) = zones.filter(|zones| start < zones.len()) else {
+                continue;
+            };
+            let end = end.min(zones.len() - 1);
+            let result =
+                self.manager
+                    .set_zones_color_spec(&&bulb, start..=end, &spec, millis(duration));
+            result.map_err(|e| e.to_string())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+fn device_map(bulb: &BulbInfo) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), bulb.serial().into());
+    map.insert("label".into(), bulb.name_label().unwrap_or_default().into());
+    map.insert(
+        "group".into(),
+        bulb.group_label().unwrap_or_default().into(),
+    );
+    map.insert("online".into(), bulb.is_online().into());
+    map.insert(
+        "power".into(),
+        bulb.power_level.data.is_some_and(|level| level > 0).into(),
+    );
+    map.insert("zones".into(), (bulb.get_zone_count() as i64).into());
+    if let Some(color) = bulb.get_color() {
+        map.insert("color".into(), color_map(*color).into());
+    }
+    map
+}
+
+/// A color in the units scripts use: hue in degrees, saturation and brightness in `[0, 1]`.
+fn color_map(color: HSBK) -> Map {
+    let ratio = |value: u16| value as f64 / u16::MAX as f64;
+    let mut map = Map::new();
+    map.insert("hue".into(), (ratio(color.hue) * 360.0).into());
+    map.insert("saturation".into(), ratio(color.saturation).into());
+    map.insert("brightness".into(), ratio(color.brightness).into());
+    map.insert("kelvin".into(), (color.kelvin as i64).into());
+    map
+}
+
+fn number(value: &Dynamic) -> ScriptResult<f64> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .map_err(|_| t!("error.script_number", value = value.to_string()).into())
+}
+
+fn hsbk(h: Dynamic, s: Dynamic, b: Dynamic, k: Dynamic) -> ScriptResult<HSBK> {
+    let unit = |value: f64| (value.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16;
+    Ok(HSBK {
+        hue: ((number(&h)?.rem_euclid(360.0) / 360.0) * u16::MAX as f64).round() as u16,
+        saturation: unit(number(&s)?),
+        brightness: unit(number(&b)?),
+        kelvin: number(&k)?.clamp(1500.0, 9000.0) as u16,
+    })
+}
+
+fn millis(duration: i64) -> u32 {
+    duration.clamp(0, u32::MAX as i64) as u32
+}
+
+fn sleep(stop: &AtomicBool, ms: i64) -> ScriptResult<()> {
+    let deadline = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+    while Instant::now() < deadline {
+        if stop.load(Ordering::SeqCst) {
+            return Err(EvalAltResult::ErrorTerminated(Dynamic::UNIT, rhai::Position::NONE).into());
+        }
+        std::thread::sleep(SLEEP_STEP.min(deadline - Instant::now()));
+    }
+    Ok(())
+}
+
+/// Start the saved script `name` on the active host, for `UserAction::RunScript`.
+pub fn run_script(name: &str, device: Option<String>) -> Result<(), anyhow::Error> {
+    let host = ScriptHost::active().ok_or_else(|| anyhow::anyhow!(t!("error.scripting_off")))?;
+    host.run_named(
+        name,
+        ScriptEvent {
+            kind: "shortcut",
+            devices: device.into_iter().collect(),
+        },
+    )
+}
+
+/// Fires the interval, daily and device-change triggers of enabled scripts.
+pub struct ScriptRunner {
+    host: ScriptHost,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ScriptRunner {
+    /// Start watching triggers and make the host the one `UserAction::RunScript` uses.
+    pub fn start(manager: LifxManager, scripts: Vec<Script>, scenes: Vec<Scene>) -> Self {
+        let changes = manager.subscribe();
+        let host = ScriptHost::new(manager, scripts, scenes);
+        let running = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let (host, running) = (host.clone(), running.clone());
+            spawn(move || Triggers::default().run(&host, &changes, &running))
+        };
+        if let Ok(mut active) = ACTIVE_HOST.lock() {
+            *active = Some(host.clone());
+        }
+        ScriptRunner {
+            host,
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn host(&self) -> &ScriptHost {
+        &self.host
+    }
+}
+
+impl Drop for ScriptRunner {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_HOST.lock() {
+            if active
+                .as_ref()
+                .is_some_and(|host| Arc::ptr_eq(&host.inner, &self.host.inner))
+            {
+                *active = None;
+            }
+        }
+        self.host.stop_all();
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Trigger bookkeeping owned by the runner thread.
+#[derive(Default)]
+struct Triggers {
+    last_run: HashMap<String, Instant>,
+    /// Day (since the Unix epoch) each daily script last fired.
+    fired_on: HashMap<String, u64>,
+    /// Last seen state of each light, so replies that change nothing are ignored.
+    states: HashMap<u64, serde_json::Map<String, serde_json::Value>>,
+}
+
+impl Triggers {
+    fn run(&mut self, host: &ScriptHost, changes: &mpsc::Receiver<u64>, running: &AtomicBool) {
+        while running.load(Ordering::SeqCst) {
+            let mut targets = HashSet::new();
+            match changes.recv_timeout(TICK) {
+                Ok(target) => {
+                    targets.insert(target);
+                    targets.extend(changes.try_iter());
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => std::thread::sleep(TICK),
+            }
+            let changed = self.changed_devices(host, &targets);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            for script in host.scripts().into_iter().filter(|s| s.enabled) {
+                if let Some(event) = self.due(&script, &changed, now) {
+                    host.run(script, event);
+                }
+            }
+        }
+    }
+
+    fn changed_devices(&mut self, host: &ScriptHost, targets: &HashSet<u64>) -> Vec<String> {
+        let Ok(bulbs) = host.inner.manager.bulbs.lock() else {
+            return Vec::new();
+        };
+        let mut changed: Vec<String> = targets
+            .iter()
+            .filter_map(|target| bulbs.get(target))
+            .filter_map(|bulb| {
+                let state = light_state(bulb);
+                let previous = self.states.insert(bulb.target, state.clone());
+                (previous.is_some_and(|previous| previous != state)).then(|| bulb.serial())
+            })
+            .collect();
+        changed.sort();
+        changed
+    }
+
+    /// The event to run `script` with if its trigger has fired.
+    fn due(&mut self, script: &Script, changed: &[String], now: u64) -> Option<ScriptEvent> {
+        match script.trigger {
+            ScriptTrigger::Manual => None,
+            ScriptTrigger::Interval { seconds } => {
+                let interval = Duration::from_secs(seconds.max(1) as u64);
+                let last_run = self
+                    .last_run
+                    .entry(script.name.clone())
+                    .or_insert_with(Instant::now);
+                (last_run.elapsed() >= interval).then(|| {
+                    *last_run = Instant::now();
+                    ScriptEvent {
+                        kind: "interval",
+                        devices: Vec::new(),
+                    }
+                })
+            }
+            ScriptTrigger::Daily { hour, minute } => {
+                let (today, secs_today) = (now / 86400, now % 86400);
+                let target = hour as u64 * 3600 + minute as u64 * 60;
+                let fired_today = self.fired_on.get(&script.name) == Some(&today);
+                (secs_today >= target && secs_today < target + 60 && !fired_today).then(|| {
+                    self.fired_on.insert(script.name.clone(), today);
+                    ScriptEvent {
+                        kind: "daily",
+                        devices: Vec::new(),
+                    }
+                })
+            }
+            ScriptTrigger::DeviceChange => (!changed.is_empty()).then(|| ScriptEvent {
+                kind: "device_change",
+                devices: changed.to_vec(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::tests::{manager_with_fake_bulb, receive, reply};
+    use lifx_core::{LifxString, Message};
+
+    fn script(source: &str) -> Script {
+        Script {
+            name: "test".to_string(),
+            source: source.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn run(host: &ScriptHost, source: &str) -> Result<Dynamic, anyhow::Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        host.run_blocking(&script(source), &ScriptEvent::manual(), &stop)
+    }
+
+    #[test]
+    fn scripts_see_devices_and_event() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager, Vec::new(), Vec::new());
+        let result = run(
+            &host,
+            r#"let d = devices(); `${d.len()} ${d[0].label} ${d[0].id} ${event.kind}`"#,
+        )
+        .unwrap();
+        assert_eq!(result.to_string(), "1 Desk 2a0000000000 manual");
+    }
+
+    #[test]
+    fn scripts_control_lights() {
+        let (manager, bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager, Vec::new(), Vec::new());
+
+        let count = run(&host, r#"set_power("label:desk", false, 250)"#).unwrap();
+        assert_eq!(count.as_int().unwrap(), 1);
+        match receive(&bulb) {
+            Message::LightSetPower { level, duration } => {
+                assert_eq!(level, 0);
+                assert_eq!(duration, 250);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let count = run(&host, r#"set_hsbk("id:2a0000000000", 180, 1, 0.5, 3500)"#).unwrap();
+        assert_eq!(count.as_int().unwrap(), 1);
+        match receive(&bulb) {
+            Message::LightSetColor { color, .. } => {
+                assert_eq!(color.hue, u16::MAX / 2 + 1);
+                assert_eq!(color.saturation, u16::MAX);
+                assert_eq!(color.brightness, u16::MAX / 2 + 1);
+                assert_eq!(color.kelvin, 3500);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        assert_eq!(
+            run(&host, r#"set_color("group:nowhere", "red")"#)
+                .unwrap()
+                .as_int()
+                .unwrap(),
+            0
+        );
+        assert!(run(&host, r#"set_color("all", "not a color")"#).is_err());
+        assert!(run(&host, r#"set_power("bogus", true)"#).is_err());
+    }
+
+    #[test]
+    fn scripts_are_sandboxed_and_stoppable() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager, Vec::new(), Vec::new());
+        assert!(run(&host, r#"eval("1 + 1")"#).is_err());
+        assert!(run(&host, "let x = ").is_err());
+
+        let stop = Arc::new(AtomicBool::new(true));
+        let err = host
+            .run_blocking(&script("loop { }"), &ScriptEvent::manual(), &stop)
+            .unwrap_err();
+        assert_eq!(err.to_string(), t!("scripting.stopped"));
+    }
+
+    #[test]
+    fn print_goes_to_the_log() {
+        let (manager, _bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager, Vec::new(), Vec::new());
+        assert!(run(&host, r#"print("hello")"#).unwrap().is_unit());
+        let entries = host.log().entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].script, "test");
+        assert_eq!(entries[0].message, "hello");
+        assert_eq!(entries[0].level, ScriptLogLevel::Info);
+    }
+
+    #[test]
+    fn runs_named_scripts_in_the_background() {
+        let (manager, bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager, Vec::new(), Vec::new());
+        host.set_scripts(&[Script {
+            name: "Off".to_string(),
+            source: r#"set_power("all", false)"#.to_string(),
+            ..Default::default()
+        }]);
+        assert!(host.run_named("Missing", ScriptEvent::manual()).is_err());
+        host.run_named("Off", ScriptEvent::manual()).unwrap();
+        assert!(matches!(
+            receive(&bulb),
+            Message::LightSetPower { level: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn device_change_trigger_ignores_unchanged_replies() {
+        let (manager, bulb) = manager_with_fake_bulb(u16::MAX);
+        let host = ScriptHost::new(manager.clone(), Vec::new(), Vec::new());
+        let target = manager
+            .bulbs
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .next()
+            .unwrap();
+        let targets = HashSet::from([target]);
+        let changes = manager.subscribe();
+        let mut triggers = Triggers::default();
+        assert!(triggers.changed_devices(&host, &targets).is_empty());
+        assert!(triggers.changed_devices(&host, &targets).is_empty());
+
+        reply(
+            &bulb,
+            &manager,
+            Message::StateLabel {
+                label: LifxString::new(&std::ffi::CString::new("Lamp").unwrap()),
+            },
+        );
+        changes.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            triggers.changed_devices(&host, &targets),
+            vec!["2a0000000000".to_string()]
+        );
+
+        let watcher = Script {
+            trigger: ScriptTrigger::DeviceChange,
+            ..script("")
+        };
+        let event = triggers
+            .due(&watcher, &["2a0000000000".to_string()], 0)
+            .unwrap();
+        assert_eq!(event.kind, "device_change");
+        assert!(triggers.due(&watcher, &[], 0).is_none());
+    }
+
+    #[test]
+    fn daily_trigger_fires_once_per_day() {
+        let mut triggers = Triggers::default();
+        let daily = Script {
+            trigger: ScriptTrigger::Daily {
+                hour: 7,
+                minute: 30,
+            },
+            ..script("")
+        };
+        let at = |day: u64, secs: u64| day * 86400 + secs;
+        assert!(triggers.due(&daily, &[], at(3, 7 * 3600)).is_none());
+        assert!(triggers.due(&daily, &[], at(3, 7 * 3600 + 1800)).is_some());
+        assert!(triggers.due(&daily, &[], at(3, 7 * 3600 + 1810)).is_none());
+        assert!(triggers.due(&daily, &[], at(4, 7 * 3600 + 1805)).is_some());
+    }
+}
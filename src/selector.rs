@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device_info::BulbInfo;
+use rust_i18n::t;
+
+/// Identifies a set of devices using the selector syntax of the LIFX HTTP API:
+/// `all`, `id:<serial>`, `label:<name>`, `group:<name>` or `location:<name>`.
+/// Label, group and location names are matched case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Selector {
+    #[default]
+    All,
+    Id(String),
+    Label(String),
+    Group(String),
+    Location(String),
+}
+
+impl Selector {
+    pub fn matches(&self, bulb: &BulbInfo) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Id(serial) => bulb.serial() == *serial,
+            Selector::Label(label) => bulb
+                .name_label()
+                .is_some_and(|name| name.eq_ignore_ascii_case(label)),
+            Selector::Group(group) => bulb
+                .group_label()
+                .is_some_and(|name| name.eq_ignore_ascii_case(group)),
+            Selector::Location(location) => bulb
+                .location
+                .data
+                .as_ref()
+                .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(location)),
+        }
+    }
+
+    /// Return the matching bulbs, ordered by label and then serial so that
+    /// output built from the selection is stable between runs.
+    pub fn select<'a>(&self, bulbs: &'a HashMap<u64, BulbInfo>) -> Vec<&'a BulbInfo> {
+        let mut selected: Vec<&BulbInfo> = bulbs.values().filter(|b| self.matches(b)).collect();
+        selected.sort_by_key(|b| (b.name_label(), b.serial()));
+        selected
+    }
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Selector::All);
+        }
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!(t!("error.invalid_selector", selector = s)))?;
+        if value.is_empty() {
+            return Err(anyhow::anyhow!(t!("error.invalid_selector", selector = s)));
+        }
+        match kind {
+            "id" => Ok(Selector::Id(value.to_lowercase().replace(':', ""))),
+            "label" => Ok(Selector::Label(value.to_string())),
+            "group" => Ok(Selector::Group(value.to_string())),
+            "location" => Ok(Selector::Location(value.to_string())),
+            _ => Err(anyhow::anyhow!(t!("error.invalid_selector", selector = s))),
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Selector::All => write!(f, "all"),
+            Selector::Id(serial) => write!(f, "id:{}", serial),
+            Selector::Label(label) => write!(f, "label:{}", label),
+            Selector::Group(group) => write!(f, "group:{}", group),
+            Selector::Location(location) => write!(f, "location:{}", location),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_info::GroupInfo;
+    use lifx_core::{LifxIdent, LifxString};
+    use std::ffi::CString;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    fn bulb(target: u64, name: &str, group: &str) -> BulbInfo {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 56700);
+        let mut bulb = BulbInfo::new(0, target, addr);
+        bulb.name.update(CString::new(name).unwrap());
+        bulb.group.update(GroupInfo::new(
+            LifxIdent([target as u8; 16]),
+            LifxString::new(&CString::new(group).unwrap()),
+        ));
+        bulb
+    }
+
+    #[test]
+    fn parse_selectors() {
+        assert_eq!("all".parse::<Selector>().unwrap(), Selector::All);
+        assert_eq!(
+            "id:D0:73:D5:00:13:37".parse::<Selector>().unwrap(),
+            Selector::Id("d073d5001337".to_string())
+        );
+        assert_eq!(
+            "label:Desk Lamp".parse::<Selector>().unwrap(),
+            Selector::Label("Desk Lamp".to_string())
+        );
+        assert_eq!(
+            "group:Office".parse::<Selector>().unwrap(),
+            Selector::Group("Office".to_string())
+        );
+        assert!("".parse::<Selector>().is_err());
+        assert!("label:".parse::<Selector>().is_err());
+        assert!("name:Desk".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["all", "id:d073d5001337", "label:Desk", "group:Office"] {
+            assert_eq!(s.parse::<Selector>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn select_matches_and_sorts() {
+        let mut bulbs = HashMap::new();
+        bulbs.insert(1, bulb(1, "Desk", "Office"));
+        bulbs.insert(2, bulb(2, "Ceiling", "Office"));
+        bulbs.insert(3, bulb(3, "Porch", "Outside"));
+
+        let names = |sel: Selector| -> Vec<String> {
+            sel.select(&bulbs)
+                .iter()
+                .map(|b| b.name_label().unwrap())
+                .collect()
+        };
+
+        assert_eq!(names(Selector::All), vec!["Ceiling", "Desk", "Porch"]);
+        assert_eq!(
+            names(Selector::Group("office".to_string())),
+            vec!["Ceiling", "Desk"]
+        );
+        assert_eq!(names(Selector::Label("PORCH".to_string())), vec!["Porch"]);
+        let serial = bulbs[&1].serial();
+        assert_eq!(names(Selector::Id(serial)), vec!["Desk"]);
+        assert!(names(Selector::Label("Garage".to_string())).is_empty());
+    }
+}
@@ -4,13 +4,28 @@ use eframe::egui::{self, Context};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 
+#[cfg(target_os = "linux")]
+use crate::api::dbus::DbusSettings;
 use crate::{
     action::UserAction,
+    api::{
+        generate_token,
+        http::HttpApiSettings,
+        mqtt::MqttSettings,
+        osc::{OscControl, OscMapping, OscSettings},
+        ws::WebSocketApiSettings,
+    },
     app::MantleApp,
     audio::AUDIO_BUFFER_DEFAULT,
+    capture::{CaptureSettings, SAMPLE_BUDGET_RANGE},
     color::default_hsbk,
     device_info::DeviceInfo,
+    effects::{
+        safety::{BRIGHTNESS_RATE_RANGE, FLASH_HZ_RANGE},
+        SafetyLimits,
+    },
     scenes::{Scene, ScheduledScene},
+    scripting::Script,
     shortcut::{KeyboardShortcutAction, ShortcutEdit},
     HSBK32,
 };
@@ -38,6 +53,23 @@ pub struct Settings {
     pub custom_colors: Vec<(String, HSBK32)>,
     #[serde(default)]
     pub run_on_startup: bool,
+    #[serde(default)]
+    pub http_api: HttpApiSettings,
+    #[serde(default)]
+    pub websocket_api: WebSocketApiSettings,
+    #[serde(default)]
+    pub mqtt: MqttSettings,
+    #[serde(default)]
+    pub osc: OscSettings,
+    #[cfg(target_os = "linux")]
+    #[serde(default)]
+    pub dbus: DbusSettings,
+    #[serde(default)]
+    pub scripts: Vec<Script>,
+    #[serde(default)]
+    pub safety: SafetyLimits,
+    #[serde(default)]
+    pub capture: CaptureSettings,
 }
 
 impl Default for Settings {
@@ -53,6 +85,15 @@ impl Default for Settings {
             audio_buffer_size: AUDIO_BUFFER_DEFAULT,
             custom_colors: Vec::new(),
             run_on_startup: false,
+            http_api: HttpApiSettings::default(),
+            websocket_api: WebSocketApiSettings::default(),
+            mqtt: MqttSettings::default(),
+            osc: OscSettings::default(),
+            #[cfg(target_os = "linux")]
+            dbus: DbusSettings::default(),
+            scripts: Vec::new(),
+            safety: SafetyLimits::default(),
+            capture: CaptureSettings::default(),
         }
     }
 }
@@ -69,6 +110,48 @@ fn locale_display_name(code: &str) -> &str {
     }
 }
 
+/// Address and token fields shared by the API server sections.
+/// They can only be changed while the server is stopped.
+fn render_api_server_fields(
+    ui: &mut egui::Ui,
+    id: &str,
+    bind_addr: &mut String,
+    token: &mut String,
+    editable: bool,
+) {
+    egui::Grid::new(id)
+        .num_columns(2)
+        .spacing([10.0, 5.0])
+        .show(ui, |ui| {
+            ui.label(t!("api_server.address").to_string());
+            ui.add_enabled(editable, egui::TextEdit::singleline(bind_addr));
+            ui.end_row();
+
+            ui.label(t!("api_server.token").to_string());
+            ui.horizontal(|ui| {
+                ui.add_enabled(
+                    editable,
+                    egui::TextEdit::singleline(token)
+                        .password(true)
+                        .desired_width(180.0),
+                );
+                if ui.button(t!("api_server.copy_token").to_string()).clicked() {
+                    ui.ctx().copy_text(token.clone());
+                }
+                if ui
+                    .add_enabled(
+                        editable,
+                        egui::Button::new(t!("api_server.regenerate_token").to_string()),
+                    )
+                    .clicked()
+                {
+                    *token = generate_token();
+                }
+            });
+            ui.end_row();
+        });
+}
+
 impl MantleApp {
     pub fn settings_ui(&mut self, ctx: &Context) {
         let mut show_settings = self.show_settings;
@@ -91,10 +174,29 @@ impl MantleApp {
 
                     self.render_update_rate(ui);
 
+                    self.render_capture_budget(ui);
+
                     self.render_transition_duration(ui);
 
                     self.render_audio_buffer_size(ui);
 
+                    ui.separator();
+                    self.render_safety(ui);
+
+                    ui.separator();
+                    self.render_http_api(ui);
+                    ui.separator();
+                    self.render_websocket_api(ui);
+                    ui.separator();
+                    self.render_mqtt(ui);
+                    ui.separator();
+                    self.render_osc(ui);
+                    #[cfg(target_os = "linux")]
+                    {
+                        ui.separator();
+                        self.render_dbus(ui);
+                    }
+
                     self.render_add_shortcut_ui(ui);
 
                     ui.separator();
@@ -210,6 +312,7 @@ impl MantleApp {
                 ui,
                 self.shortcut_manager.new_shortcut.device.clone(),
                 self.settings.scenes.clone(),
+                &self.settings.scripts,
             );
             ui.end_row();
 
@@ -324,6 +427,26 @@ impl MantleApp {
         });
     }
 
+    fn render_capture_budget(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(t!("settings.capture_budget").to_string());
+            if ui
+                .add(
+                    egui::Slider::new(
+                        &mut self.settings.capture.sample_budget,
+                        SAMPLE_BUDGET_RANGE,
+                    )
+                    .logarithmic(true)
+                    .text(t!("settings.pixels").to_string()),
+                )
+                .on_hover_text(t!("settings.capture_budget_hover").to_string())
+                .changed()
+            {
+                self.sync_capture();
+            }
+        });
+    }
+
     fn render_transition_duration(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label(t!("settings.transition_duration").to_string());
@@ -349,6 +472,42 @@ impl MantleApp {
         });
     }
 
+    fn render_safety(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("safety.title").to_string());
+        ui.add_space(5.0);
+
+        let safety = &mut self.settings.safety;
+        let mut changed = ui
+            .checkbox(&mut safety.enabled, t!("safety.enabled").to_string())
+            .on_hover_text(t!("safety.enabled_hover").to_string())
+            .changed();
+        ui.add_enabled_ui(safety.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(t!("safety.max_flash").to_string());
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut safety.max_flash_hz, FLASH_HZ_RANGE)
+                            .text(t!("safety.hz").to_string()),
+                    )
+                    .on_hover_text(t!("safety.max_flash_hover").to_string())
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label(t!("safety.max_change").to_string());
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut safety.max_brightness_change, BRIGHTNESS_RATE_RANGE)
+                            .text(t!("safety.per_second").to_string()),
+                    )
+                    .on_hover_text(t!("safety.max_change_hover").to_string())
+                    .changed();
+            });
+        });
+        if changed {
+            self.sync_safety();
+        }
+    }
+
     fn render_locale_selector(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label(t!("settings.language").to_string());
@@ -396,6 +555,309 @@ impl MantleApp {
         }
     }
 
+    fn render_http_api(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("http_api.title").to_string());
+        ui.add_space(5.0);
+
+        if ui
+            .checkbox(
+                &mut self.settings.http_api.enabled,
+                t!("http_api.enabled").to_string(),
+            )
+            .on_hover_text(t!("http_api.enabled_hover").to_string())
+            .changed()
+        {
+            self.sync_http_api();
+        }
+
+        let api = &mut self.settings.http_api;
+        render_api_server_fields(
+            ui,
+            "http_api_grid",
+            &mut api.bind_addr,
+            &mut api.token,
+            !api.enabled,
+        );
+
+        if let Some(addr) = self.http_server.as_ref().and_then(|s| s.local_addr()) {
+            ui.label(t!("http_api.running", address = addr.to_string()).to_string());
+        }
+        ui.add_space(10.0);
+    }
+
+    fn render_websocket_api(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("websocket_api.title").to_string());
+        ui.add_space(5.0);
+
+        if ui
+            .checkbox(
+                &mut self.settings.websocket_api.enabled,
+                t!("websocket_api.enabled").to_string(),
+            )
+            .on_hover_text(t!("websocket_api.enabled_hover").to_string())
+            .changed()
+        {
+            self.sync_websocket_api();
+        }
+
+        let api = &mut self.settings.websocket_api;
+        render_api_server_fields(
+            ui,
+            "websocket_api_grid",
+            &mut api.bind_addr,
+            &mut api.token,
+            !api.enabled,
+        );
+
+        if let Some(server) = &self.websocket_server {
+            let address = server.local_addr().to_string();
+            ui.label(t!("websocket_api.running", address = address).to_string());
+        }
+        ui.add_space(10.0);
+    }
+
+    fn render_mqtt(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("mqtt.title").to_string());
+        ui.add_space(5.0);
+
+        if ui
+            .checkbox(
+                &mut self.settings.mqtt.enabled,
+                t!("mqtt.enabled").to_string(),
+            )
+            .on_hover_text(t!("mqtt.enabled_hover").to_string())
+            .changed()
+        {
+            self.sync_mqtt();
+        }
+
+        // Connection settings can only be changed while the bridge is stopped.
+        let mqtt = &mut self.settings.mqtt;
+        let editable = !mqtt.enabled;
+        egui::Grid::new("mqtt_grid")
+            .num_columns(2)
+            .spacing([10.0, 5.0])
+            .show(ui, |ui| {
+                ui.label(t!("mqtt.host").to_string());
+                ui.horizontal(|ui| {
+                    ui.add_enabled(
+                        editable,
+                        egui::TextEdit::singleline(&mut mqtt.host).desired_width(180.0),
+                    );
+                    ui.label(t!("mqtt.port").to_string());
+                    ui.add_enabled(editable, egui::DragValue::new(&mut mqtt.port));
+                });
+                ui.end_row();
+
+                ui.label(t!("mqtt.username").to_string());
+                ui.add_enabled(editable, egui::TextEdit::singleline(&mut mqtt.username));
+                ui.end_row();
+
+                ui.label(t!("mqtt.password").to_string());
+                ui.add_enabled(
+                    editable,
+                    egui::TextEdit::singleline(&mut mqtt.password).password(true),
+                );
+                ui.end_row();
+
+                ui.label(t!("mqtt.client_id").to_string());
+                ui.add_enabled(editable, egui::TextEdit::singleline(&mut mqtt.client_id));
+                ui.end_row();
+
+                ui.label(t!("mqtt.base_topic").to_string());
+                ui.add_enabled(editable, egui::TextEdit::singleline(&mut mqtt.base_topic));
+                ui.end_row();
+
+                ui.label(t!("mqtt.discovery_prefix").to_string());
+                ui.add_enabled(
+                    editable,
+                    egui::TextEdit::singleline(&mut mqtt.discovery_prefix),
+                );
+                ui.end_row();
+            });
+
+        if let Some(bridge) = &self.mqtt_bridge {
+            if bridge.is_connected() {
+                ui.label(t!("mqtt.connected").to_string());
+            } else {
+                ui.label(t!("mqtt.connecting").to_string());
+            }
+        }
+        ui.add_space(10.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn render_dbus(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("dbus.title").to_string());
+        ui.add_space(5.0);
+
+        ui.checkbox(
+            &mut self.settings.dbus.enabled,
+            t!("dbus.enabled").to_string(),
+        )
+        .on_hover_text(t!("dbus.enabled_hover").to_string());
+        ui.checkbox(
+            &mut self.settings.dbus.flash_on_notifications,
+            t!("dbus.flash_on_notifications").to_string(),
+        );
+
+        let lights: Vec<(String, String)> = match self.lighting_manager.bulbs.lock() {
+            Ok(bulbs) => bulbs
+                .values()
+                .map(|bulb| {
+                    let label = bulb.name_label().unwrap_or_else(|| bulb.serial());
+                    (format!("id:{}", bulb.serial()), label)
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let dbus = &mut self.settings.dbus;
+        let selected = lights
+            .iter()
+            .find(|(selector, _)| *selector == dbus.notification_light)
+            .map(|(_, label)| label.clone())
+            .unwrap_or_else(|| {
+                if dbus.notification_light.is_empty() {
+                    t!("dbus.no_light").to_string()
+                } else {
+                    dbus.notification_light.clone()
+                }
+            });
+        ui.add_enabled_ui(dbus.flash_on_notifications, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(t!("dbus.notification_light").to_string());
+                egui::ComboBox::from_id_salt("dbus_notification_light")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (selector, label) in lights {
+                            ui.selectable_value(&mut dbus.notification_light, selector, label);
+                        }
+                    });
+            });
+        });
+
+        // The service restarts with the new settings on the next sync.
+        if self.dbus_service.is_some() {
+            ui.label(t!("dbus.running", name = crate::api::dbus::BUS_NAME).to_string());
+        }
+        ui.add_space(10.0);
+    }
+
+    fn render_osc(&mut self, ui: &mut egui::Ui) {
+        ui.heading(t!("osc.title").to_string());
+        ui.add_space(5.0);
+
+        if ui
+            .checkbox(
+                &mut self.settings.osc.enabled,
+                t!("osc.enabled").to_string(),
+            )
+            .on_hover_text(t!("osc.enabled_hover").to_string())
+            .changed()
+        {
+            self.sync_osc();
+        }
+
+        let scene_names: Vec<String> = self
+            .settings
+            .scenes
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        let osc = &mut self.settings.osc;
+        let editable = !osc.enabled;
+        egui::Grid::new("osc_grid")
+            .num_columns(2)
+            .spacing([10.0, 5.0])
+            .show(ui, |ui| {
+                ui.label(t!("api_server.address").to_string());
+                ui.add_enabled(editable, egui::TextEdit::singleline(&mut osc.bind_addr));
+                ui.end_row();
+
+                ui.add_enabled(
+                    editable,
+                    egui::Checkbox::new(&mut osc.feedback, t!("osc.feedback").to_string()),
+                )
+                .on_hover_text(t!("osc.feedback_hover").to_string());
+                ui.add_enabled(
+                    editable && osc.feedback,
+                    egui::TextEdit::singleline(&mut osc.feedback_addr),
+                );
+                ui.end_row();
+            });
+
+        ui.label(t!("osc.mappings").to_string())
+            .on_hover_text(t!("osc.mappings_hover").to_string());
+        let mut to_remove = Vec::new();
+        for (i, mapping) in osc.mappings.iter_mut().enumerate() {
+            ui.add_enabled_ui(editable, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut mapping.address)
+                            .hint_text("/1/fader1")
+                            .desired_width(100.0),
+                    );
+                    egui::ComboBox::from_id_salt(format!("osc_control_{}", i))
+                        .selected_text(mapping.control.to_string())
+                        .width(90.0)
+                        .show_ui(ui, |ui| {
+                            for control in OscControl::iter() {
+                                ui.selectable_value(
+                                    &mut mapping.control,
+                                    control,
+                                    control.to_string(),
+                                );
+                            }
+                        });
+                    if mapping.control == OscControl::Scene {
+                        egui::ComboBox::from_id_salt(format!("osc_scene_{}", i))
+                            .selected_text(&mapping.scene)
+                            .width(100.0)
+                            .show_ui(ui, |ui| {
+                                for name in &scene_names {
+                                    ui.selectable_value(&mut mapping.scene, name.clone(), name);
+                                }
+                            });
+                    } else {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut mapping.selector)
+                                .hint_text("group:Stage")
+                                .desired_width(100.0),
+                        );
+                    }
+                    if mapping.control == OscControl::ZoneColor {
+                        ui.label(t!("osc.zone").to_string());
+                        ui.add(egui::DragValue::new(&mut mapping.zone).range(0..=255));
+                    }
+                    if ui.small_button(t!("osc.remove").to_string()).clicked() {
+                        to_remove.push(i);
+                    }
+                });
+            });
+        }
+        for i in to_remove.into_iter().rev() {
+            osc.mappings.remove(i);
+        }
+        if ui
+            .add_enabled(
+                editable,
+                egui::Button::new(t!("osc.add_mapping").to_string()).small(),
+            )
+            .clicked()
+        {
+            osc.mappings.push(OscMapping {
+                selector: "all".to_string(),
+                ..Default::default()
+            });
+        }
+
+        if let Some(server) = &self.osc_server {
+            let address = server.local_addr().to_string();
+            ui.label(t!("osc.running", address = address).to_string());
+        }
+        ui.add_space(10.0);
+    }
+
     fn build_auto_launch() -> Result<auto_launch::AutoLaunch, String> {
         let exe = std::env::current_exe().map_err(|e| e.to_string())?;
         let exe_str = exe.to_str().ok_or("Invalid executable path")?;
@@ -776,6 +1238,7 @@ impl MantleApp {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scripting::ScriptTrigger;
 
     #[test]
     fn settings_default_refresh_rate() {
@@ -820,6 +1283,47 @@ mod tests {
             audio_buffer_size: 4096,
             custom_colors: Vec::new(),
             run_on_startup: false,
+            http_api: HttpApiSettings {
+                enabled: true,
+                bind_addr: "127.0.0.1:9000".to_string(),
+                token: "secret".to_string(),
+            },
+            websocket_api: WebSocketApiSettings {
+                enabled: true,
+                bind_addr: "0.0.0.0:9001".to_string(),
+                token: "other".to_string(),
+            },
+            mqtt: MqttSettings {
+                enabled: true,
+                host: "broker.lan".to_string(),
+                ..Default::default()
+            },
+            osc: OscSettings {
+                enabled: true,
+                bind_addr: "0.0.0.0:9002".to_string(),
+                feedback: true,
+                ..Default::default()
+            },
+            #[cfg(target_os = "linux")]
+            dbus: DbusSettings {
+                enabled: true,
+                flash_on_notifications: true,
+                notification_light: "id:d073d5000001".to_string(),
+            },
+            scripts: vec![Script {
+                name: "Sunrise".to_string(),
+                source: "set_power(\"all\", true)".to_string(),
+                trigger: ScriptTrigger::Daily { hour: 7, minute: 0 },
+                enabled: true,
+            }],
+            safety: SafetyLimits {
+                enabled: false,
+                max_flash_hz: 2.0,
+                max_brightness_change: 1.5,
+            },
+            capture: CaptureSettings {
+                sample_budget: 16_384,
+            },
         };
         let json = serde_json::to_string(&settings).unwrap();
         let deserialized: Settings = serde_json::from_str(&json).unwrap();
@@ -830,6 +1334,15 @@ mod tests {
         assert!(deserialized.scenes.is_empty());
         assert!(deserialized.custom_colors.is_empty());
         assert!(!deserialized.run_on_startup);
+        assert_eq!(deserialized.http_api, settings.http_api);
+        assert_eq!(deserialized.websocket_api, settings.websocket_api);
+        assert_eq!(deserialized.mqtt, settings.mqtt);
+        assert_eq!(deserialized.osc, settings.osc);
+        #[cfg(target_os = "linux")]
+        assert_eq!(deserialized.dbus, settings.dbus);
+        assert_eq!(deserialized.scripts, settings.scripts);
+        assert_eq!(deserialized.safety, settings.safety);
+        assert_eq!(deserialized.capture, settings.capture);
     }
 
     #[test]
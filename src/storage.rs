@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::scenes::{find_scene, Scene, ScheduledScene};
+use rust_i18n::t;
+
+/// Name the GUI passes to `eframe::run_native`, which eframe also uses as its storage id.
+pub const APP_ID: &str = "Mantle";
+
+/// Key eframe stores the serialized `MantleApp` under (mirrors `eframe::APP_KEY`).
+const APP_KEY: &str = "app";
+
+/// The part of the GUI's persisted `Settings` that is useful without a display.
+/// Unknown fields are ignored, so this keeps working as the GUI settings grow.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PersistedSettings {
+    pub locale: String,
+    pub scenes: Vec<Scene>,
+    pub scheduled_scenes: Vec<ScheduledScene>,
+}
+
+impl PersistedSettings {
+    /// Load the settings saved by the GUI from its default location.
+    pub fn load() -> Result<PersistedSettings, anyhow::Error> {
+        let path = app_state_path()
+            .ok_or_else(|| anyhow::anyhow!(t!("error.no_storage_dir").to_string()))?;
+        Self::load_from(&path)
+    }
+
+    /// Load the settings from an eframe `app.ron` file.
+    pub fn load_from(path: &Path) -> Result<PersistedSettings, anyhow::Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!(t!(
+                "error.read_file",
+                error = format!("{}: {}", path.display(), e)
+            ))
+        })?;
+        Self::from_ron(&contents)
+    }
+
+    fn from_ron(contents: &str) -> Result<PersistedSettings, anyhow::Error> {
+        #[derive(Deserialize)]
+        struct PersistedApp {
+            #[serde(default)]
+            settings: PersistedSettings,
+        }
+
+        let kv: HashMap<String, String> = ron::from_str(contents)?;
+        let app = kv
+            .get(APP_KEY)
+            .ok_or_else(|| anyhow::anyhow!(t!("error.no_saved_settings").to_string()))?;
+        let app: PersistedApp = ron::from_str(app)?;
+        Ok(app.settings)
+    }
+
+    pub fn find_scene(&self, name: &str) -> Option<&Scene> {
+        find_scene(&self.scenes, name)
+    }
+}
+
+/// The directory eframe persists the GUI state in. Mirrors `eframe::storage_dir`
+/// so tools built without the `gui` feature read the same file as the app.
+pub fn storage_dir() -> Option<PathBuf> {
+    use std::env::var_os;
+    if cfg!(target_os = "windows") {
+        var_os("APPDATA").map(|p| PathBuf::from(p).join(APP_ID).join("data"))
+    } else if cfg!(target_os = "macos") {
+        var_os("HOME").map(|p| {
+            PathBuf::from(p)
+                .join("Library")
+                .join("Application Support")
+                .join(APP_ID.replace(|c: char| c.is_ascii_whitespace(), "-"))
+        })
+    } else {
+        var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| var_os("HOME").map(|p| PathBuf::from(p).join(".local").join("share")))
+            .map(|p| {
+                p.join(
+                    APP_ID
+                        .to_lowercase()
+                        .replace(|c: char| c.is_ascii_whitespace(), ""),
+                )
+            })
+    }
+}
+
+/// Path of the `app.ron` file holding the GUI state.
+pub fn app_state_path() -> Option<PathBuf> {
+    storage_dir().map(|dir| dir.join("app.ron"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_info::{DeviceInfo, GroupInfo};
+    use crate::HSBK32;
+    use lifx_core::{LifxIdent, LifxString};
+    use serde::Serialize;
+    use std::ffi::CString;
+
+    #[derive(Serialize)]
+    struct FakeSettings {
+        custom_shortcuts: Vec<(String, Vec<u32>)>,
+        locale: String,
+        refresh_rate_ms: u64,
+        scenes: Vec<Scene>,
+        scheduled_scenes: Vec<ScheduledScene>,
+    }
+
+    #[derive(Serialize)]
+    struct FakeApp {
+        new_scene: Scene,
+        settings: FakeSettings,
+        search_query: String,
+        show_eyedropper: HashMap<u64, bool>,
+    }
+
+    fn app_ron(app: &FakeApp) -> String {
+        let mut kv = HashMap::new();
+        kv.insert(APP_KEY.to_string(), ron::ser::to_string(app).unwrap());
+        kv.insert("egui".to_string(), "(zoom_factor:1.0)".to_string());
+        ron::ser::to_string(&kv).unwrap()
+    }
+
+    #[test]
+    fn reads_scenes_and_ignores_other_state() {
+        let group = GroupInfo::new(
+            LifxIdent([7; 16]),
+            LifxString::new(&CString::new("Living Room").unwrap()),
+        );
+        let color = HSBK32 {
+            hue: 1000,
+            saturation: 2000,
+            brightness: 3000,
+            kelvin: 3500,
+        };
+        let scene = Scene::new(
+            vec![(DeviceInfo::Group(group), color)],
+            "Movie Night".to_string(),
+        );
+        let app = FakeApp {
+            new_scene: Scene::new(Vec::new(), "draft".to_string()),
+            settings: FakeSettings {
+                custom_shortcuts: vec![("toggle".to_string(), vec![1, 2])],
+                locale: "fr".to_string(),
+                refresh_rate_ms: 500,
+                scenes: vec![scene.clone()],
+                scheduled_scenes: vec![ScheduledScene {
+                    scene_name: "Movie Night".to_string(),
+                    ..Default::default()
+                }],
+            },
+            search_query: String::new(),
+            show_eyedropper: HashMap::from([(1, true)]),
+        };
+
+        let settings = PersistedSettings::from_ron(&app_ron(&app)).unwrap();
+        assert_eq!(settings.locale, "fr");
+        assert_eq!(settings.scenes, vec![scene]);
+        assert_eq!(settings.scheduled_scenes.len(), 1);
+    }
+
+    #[test]
+    fn find_scene_prefers_exact_match() {
+        let settings = PersistedSettings {
+            scenes: vec![
+                Scene::new(vec![], "evening".to_string()),
+                Scene::new(vec![], "Evening".to_string()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(settings.find_scene("Evening").unwrap().name, "Evening");
+        assert_eq!(settings.find_scene("EVENING").unwrap().name, "evening");
+        assert!(settings.find_scene("Morning").is_none());
+    }
+
+    #[test]
+    fn missing_app_key_is_an_error() {
+        let kv: HashMap<String, String> = HashMap::new();
+        let contents = ron::ser::to_string(&kv).unwrap();
+        assert!(PersistedSettings::from_ron(&contents).is_err());
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let path = std::env::temp_dir()
+            .join("mantle-does-not-exist")
+            .join("app.ron");
+        assert!(PersistedSettings::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn storage_dir_ends_with_app_id() {
+        if let Some(dir) = storage_dir() {
+            let dir = dir.to_string_lossy().to_lowercase();
+            assert!(dir.contains("mantle"));
+        }
+    }
+}
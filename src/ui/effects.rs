@@ -0,0 +1,109 @@
+use crate::{
+    app::MantleApp,
+    device_info::DeviceInfo,
+    effects::{
+        library::{INTENSITY_RANGE, SPEED_RANGE},
+        EffectConfig, EffectKind, Palette,
+    },
+};
+
+use eframe::egui::{self, Color32, Response, RichText, Ui};
+use rust_i18n::t;
+use strum::IntoEnumIterator;
+
+/// Controls for the kind, palette, speed and intensity of an effect. The response is
+/// marked changed when any of them changes.
+pub fn effect_config_ui(ui: &mut Ui, config: &mut EffectConfig) -> Response {
+    let mut changed = false;
+    let mut response = ui
+        .vertical(|ui| {
+            ui.horizontal(|ui| {
+                let previous_kind = config.kind;
+                egui::ComboBox::from_id_salt("effect_kind")
+                    .selected_text(config.kind.to_string())
+                    .show_ui(ui, |ui| {
+                        for kind in EffectKind::iter() {
+                            ui.selectable_value(&mut config.kind, kind, kind.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(t!("effects.kind_hover").to_string());
+                if config.kind != previous_kind {
+                    config.palette = config.kind.default_palette();
+                    changed = true;
+                }
+                let previous_palette = config.palette;
+                egui::ComboBox::from_id_salt("effect_palette")
+                    .selected_text(config.palette.to_string())
+                    .show_ui(ui, |ui| {
+                        for palette in Palette::iter() {
+                            ui.selectable_value(&mut config.palette, palette, palette.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(t!("effects.palette_hover").to_string());
+                changed |= config.palette != previous_palette;
+            });
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut config.speed, SPEED_RANGE)
+                        .logarithmic(true)
+                        .text(t!("effects.speed").to_string()),
+                )
+                .on_hover_text(t!("effects.speed_hover").to_string())
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut config.intensity, INTENSITY_RANGE)
+                        .text(t!("effects.intensity").to_string()),
+                )
+                .on_hover_text(t!("effects.intensity_hover").to_string())
+                .changed();
+        })
+        .response;
+    if changed {
+        response.mark_changed();
+    }
+    response
+}
+
+/// The effect library section of the device panel, with a notice while the
+/// photosensitivity limiter holds back changes to the device. Changing a running
+/// effect restarts it with the new settings.
+pub fn render_effect_controls(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let device_id = device.id();
+    let running = app
+        .effect_engine
+        .active_kind(device_id)
+        .and_then(EffectKind::from_id)
+        .is_some();
+    if app.lighting_manager.is_limiting(device) {
+        ui.label(
+            RichText::new(t!("safety.engaged").to_string()).color(Color32::from_rgb(230, 170, 40)),
+        )
+        .on_hover_text(t!("safety.engaged_hover").to_string());
+    }
+    let config = app.effect_configs.entry(device_id).or_default();
+    egui::CollapsingHeader::new(t!("effects.title").to_string())
+        .id_salt(("effects", device_id))
+        .show(ui, |ui| {
+            ui.push_id(device_id, |ui| {
+                let changed = effect_config_ui(ui, config).changed();
+                let label = if running {
+                    t!("effects.stop")
+                } else {
+                    t!("effects.start")
+                };
+                let toggled = ui
+                    .selectable_label(running, label.to_string())
+                    .on_hover_text(t!("effects.start_hover").to_string())
+                    .clicked();
+                if toggled && running {
+                    app.effect_engine.stop_effect(device_id);
+                } else if toggled || (changed && running) {
+                    app.effect_engine
+                        .start_effect(device.clone(), config.build());
+                }
+            });
+        });
+}
@@ -1,8 +1,12 @@
+pub mod effects;
 pub mod screencap;
+pub mod session;
 pub mod waveform;
 pub mod widgets;
 
+pub use effects::*;
 pub use screencap::*;
+pub use session::*;
 pub use waveform::*;
 pub use widgets::*;
 
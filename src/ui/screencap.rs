@@ -1,32 +1,40 @@
 use std::{
     sync::{Arc, Mutex},
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use crate::{
-    app::{
-        MantleApp, WaveformMode, WaveformTracker, EYEDROPPER_ICON, MONITOR_ICON, SCREENSHOT_ICON,
-        SUBREGION_ICON,
-    },
+    app::{MantleApp, EYEDROPPER_ICON, MONITOR_ICON, SCREENSHOT_ICON, SUBREGION_ICON},
+    capture::CaptureMetrics,
     color::DeltaColor,
     device_info::DeviceInfo,
-    screencap::{RegionCaptureTarget, ScreenSubregion, ScreencapManager},
-};
-
-use super::{
-    waveform::{
-        ensure_waveform_channel, has_time_elapsed, initialize_waveform_tracker,
-        update_color_from_channel,
+    effects::{
+        analysis::{AnalysisMode, PALETTE_SIZE_RANGE},
+        edges::{EdgeDirection, EdgeMapping, ScreenEdge, StartCorner, DEPTH_RANGE},
+        exclusions::ExclusionRect,
+        mirror::MirrorFit,
+        smoothing::{
+            HUE_DEAD_BAND_RANGE, KELVIN_DEAD_BAND_RANGE, LEVEL_DEAD_BAND_RANGE,
+            RESPONSIVENESS_RANGE,
+        },
+        CaptureExclusions, ScreenMapping,
+    },
+    frame_source::{FileFrames, DEFAULT_FRAME_RATE, FRAME_RATE_RANGE},
+    screencap::{
+        CursorSample, RegionCaptureTarget, ScreenEffect, ScreenSubregion, ScreenSyncOptions,
+        ScreencapManager, WindowIdentity, CURSOR_SIZE_RANGE,
     },
-    widgets::create_highlighted_button,
 };
 
+use super::{waveform::toggle_effect, widgets::create_highlighted_button};
+
 use std::collections::HashMap;
 
 use eframe::egui::{self, pos2, vec2, Color32, Pos2, Rect, Sense, Stroke, TextureHandle, Ui};
 use lifx_core::HSBK;
 use rust_i18n::t;
+use strum::IntoEnumIterator;
 
 const DEBOUNCE_DELAY_MS: u64 = 100;
 
@@ -65,64 +73,84 @@ pub fn handle_eyedropper(
     })
 }
 
-pub fn handle_screencap(
-    app: &mut MantleApp,
-    ui: &mut Ui,
-    device: &DeviceInfo,
-) -> Option<DeltaColor> {
-    let mut color: Option<HSBK> = None;
-    let update_interval_ms = app.settings.update_interval_ms;
+pub fn handle_screencap(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
     update_subregion_bounds(app, ui, device.id());
-
-    ensure_waveform_channel(app, device.id());
-    app.waveform_map
-        .entry(device.id())
-        .or_insert(WaveformTracker {
-            active: false,
-            last_update: Instant::now(),
-            mode: WaveformMode::Screencap,
-            region: RegionCaptureTarget::All,
-            stop_tx: None,
-        });
-
-    if let Some(color_channel) = app.waveform_channel.get(&device.id()) {
-        if let Some(tracker) = app.waveform_map.get_mut(&device.id()) {
-            if tracker.active
-                && tracker.mode == WaveformMode::Screencap
-                && has_time_elapsed(update_interval_ms, tracker)
-            {
-                update_color_from_channel(&mut color, tracker, color_channel);
-            }
-        }
-    }
-
+    app.capture_smoothing.entry(device.id()).or_default();
+    app.screen_mappings.entry(device.id()).or_default();
+    app.screen_analysis.entry(device.id()).or_default();
     let is_active = app
-        .waveform_map
-        .get(&device.id())
-        .is_some_and(|w| w.active && w.mode == WaveformMode::Screencap);
+        .effect_engine
+        .is_running(device.id(), ScreenEffect::KIND);
     if create_highlighted_button(ui, "monitor", MONITOR_ICON, is_active)
         .on_hover_text(t!("capture.monitor_hover").to_string())
         .clicked()
     {
-        initialize_waveform_tracker(
-            app,
-            device,
-            update_interval_ms,
-            WaveformMode::Screencap,
-            ui.ctx().clone(),
-        );
+        toggle_effect(app, device, is_active, || {
+            Box::new(screen_effect(app, device.id()))
+        });
     }
-    color.map(|color| DeltaColor {
-        next: color,
-        duration: Some((update_interval_ms / 2) as u32),
-    })
+}
+
+/// The capture target a device starts with: the window it followed when the app last ran,
+/// or everything.
+fn saved_capture_target(
+    windows: &HashMap<u64, WindowIdentity>,
+    device_id: u64,
+) -> RegionCaptureTarget {
+    windows
+        .get(&device_id)
+        .cloned()
+        .map(RegionCaptureTarget::Window)
+        .unwrap_or(RegionCaptureTarget::All)
+}
+
+/// A screen effect for the device's selected capture target.
+fn screen_effect(app: &MantleApp, device_id: u64) -> ScreenEffect {
+    let region = app
+        .capture_regions
+        .get(&device_id)
+        .cloned()
+        .unwrap_or_else(|| saved_capture_target(&app.capture_windows, device_id));
+    let subregion = if matches!(region, RegionCaptureTarget::Subregion(_)) {
+        app.subregion_points.get(&device_id).cloned()
+    } else {
+        None
+    };
+    let options = ScreenSyncOptions {
+        mapping: app
+            .screen_mappings
+            .get(&device_id)
+            .cloned()
+            .unwrap_or_default(),
+        analysis: app
+            .screen_analysis
+            .get(&device_id)
+            .cloned()
+            .unwrap_or_default(),
+        smoothing: app
+            .capture_smoothing
+            .get(&device_id)
+            .cloned()
+            .unwrap_or_default(),
+        exclusions: app.capture_exclusions.clone(),
+    };
+    ScreenEffect::new(
+        region,
+        subregion,
+        options,
+        Duration::from_millis(app.settings.update_interval_ms),
+        app.capture_service.clone(),
+    )
 }
 
 pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
-    let update_interval_ms = app.settings.update_interval_ms;
-    let mut region_type_changed = false;
-    if let Some(waveform) = app.waveform_map.get_mut(&device.id()) {
-        let prev_discriminant = std::mem::discriminant(&waveform.region);
+    let mut open_error = None;
+    let region_changed = {
+        let region = app
+            .capture_regions
+            .entry(device.id())
+            .or_insert_with(|| saved_capture_target(&app.capture_windows, device.id()));
+        let prev_region = region.clone();
 
         let mut subregion = app
             .subregion_points
@@ -154,26 +182,34 @@ pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceIn
             .map(|window| {
                 (
                     window.title().to_string(),
-                    RegionCaptureTarget::Window(vec![window.clone()]),
+                    RegionCaptureTarget::Window(WindowIdentity::of(window)),
                 )
             })
             .collect();
         window_options.sort_by(|a, b| a.0.cmp(&b.0));
         options.extend(window_options);
 
-        let selected_text = match &waveform.region {
+        let selected_text = match &*region {
             RegionCaptureTarget::All => t!("capture.all").to_string(),
             RegionCaptureTarget::Monitor(monitors) => monitors
                 .first()
                 .map(|m| m.name().to_string())
                 .unwrap_or(t!("capture.monitor").to_string()),
-            RegionCaptureTarget::Window(windows) => windows
-                .first()
-                .map(|w| w.title().to_string())
-                .unwrap_or(t!("capture.window").to_string()),
+            RegionCaptureTarget::Window(window) if !window.title_pattern.is_empty() => {
+                window.title_pattern.clone()
+            }
+            RegionCaptureTarget::Window(window) => window.app_name.clone(),
             RegionCaptureTarget::Subregion(_) => t!("capture.subregion").to_string(),
+            RegionCaptureTarget::Cursor(_) => t!("capture.cursor").to_string(),
+            RegionCaptureTarget::File(file) => file
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(t!("capture.file").to_string()),
         };
-        let is_subregion = matches!(&waveform.region, RegionCaptureTarget::Subregion(_));
+        // Whether to pick a file or a folder of frames.
+        let mut pick_folder = None;
+        let is_subregion = matches!(&*region, RegionCaptureTarget::Subregion(_));
         ui.push_id(device.id(), |ui| {
             ui.horizontal(|ui| {
                 ui.label(t!("capture.target_label").to_string());
@@ -181,7 +217,7 @@ pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceIn
                     .on_hover_text(t!("capture.subregion_hover").to_string())
                     .clicked()
                 {
-                    waveform.region = RegionCaptureTarget::Subregion(vec![subregion.clone()]);
+                    *region = RegionCaptureTarget::Subregion(vec![subregion.clone()]);
                 }
                 let combo_width = (ui.available_width() - 8.0).max(80.0);
                 egui::ComboBox::from_id_salt("capture_target")
@@ -189,7 +225,33 @@ pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceIn
                     .width(combo_width)
                     .show_ui(ui, |ui| {
                         for (label, capture_target) in options {
-                            ui.selectable_value(&mut waveform.region, capture_target, label);
+                            ui.selectable_value(&mut *region, capture_target, label);
+                        }
+                        let is_cursor = matches!(&*region, RegionCaptureTarget::Cursor(_));
+                        if ui
+                            .selectable_label(is_cursor, t!("capture.cursor").to_string())
+                            .on_hover_text(t!("capture.cursor_hover").to_string())
+                            .clicked()
+                            && !is_cursor
+                        {
+                            *region = RegionCaptureTarget::Cursor(CursorSample::new(
+                                app.input_listener.clone(),
+                            ));
+                        }
+                        ui.separator();
+                        if ui
+                            .selectable_label(false, t!("capture.open_file").to_string())
+                            .on_hover_text(t!("capture.open_file_hover").to_string())
+                            .clicked()
+                        {
+                            pick_folder = Some(false);
+                        }
+                        if ui
+                            .selectable_label(false, t!("capture.open_folder").to_string())
+                            .on_hover_text(t!("capture.open_folder_hover").to_string())
+                            .clicked()
+                        {
+                            pick_folder = Some(true);
                         }
                     })
                     .response
@@ -197,7 +259,75 @@ pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceIn
             });
         });
 
-        if let RegionCaptureTarget::Subregion(_) = waveform.region {
+        if let Some(folder) = pick_folder {
+            let dialog = rfd::FileDialog::new();
+            let path = if folder {
+                dialog.pick_folder()
+            } else {
+                dialog
+                    .add_filter(
+                        t!("capture.images").to_string(),
+                        &["png", "jpg", "jpeg", "gif", "bmp", "webp"],
+                    )
+                    .pick_file()
+            };
+            if let Some(path) = path {
+                match FileFrames::open(
+                    &path,
+                    DEFAULT_FRAME_RATE,
+                    app.settings.capture.sample_budget,
+                ) {
+                    Ok(file) => *region = RegionCaptureTarget::File(file),
+                    Err(e) => {
+                        log::error!("Failed to open frames: {}", e);
+                        open_error = Some(e);
+                    }
+                }
+            }
+        }
+        if let RegionCaptureTarget::File(file) = &mut *region {
+            ui.horizontal(|ui| {
+                ui.label(t!("capture.frame_rate").to_string());
+                ui.add(
+                    egui::DragValue::new(&mut file.frame_rate)
+                        .range(FRAME_RATE_RANGE)
+                        .speed(0.1)
+                        .suffix(" fps"),
+                )
+                .on_hover_text(t!("capture.frame_rate_hover", count = file.len()).to_string());
+            });
+        }
+
+        if let RegionCaptureTarget::Window(window) = &mut *region {
+            ui.horizontal(|ui| {
+                ui.label(t!("capture.window_title").to_string());
+                ui.text_edit_singleline(&mut window.title_pattern)
+                    .on_hover_text(t!("capture.window_title_hover").to_string());
+            });
+            app.capture_windows.insert(device.id(), window.clone());
+        } else {
+            app.capture_windows.remove(&device.id());
+        }
+
+        if let RegionCaptureTarget::Cursor(cursor) = &mut *region {
+            ui.add(
+                egui::Slider::new(&mut cursor.size, CURSOR_SIZE_RANGE)
+                    .suffix(" px")
+                    .text(t!("capture.cursor_size").to_string()),
+            )
+            .on_hover_text(t!("capture.cursor_size_hover").to_string());
+        }
+
+        let mut all_exclusions = app
+            .capture_exclusions
+            .lock()
+            .expect("Failed to get capture exclusions");
+        let exclusions = all_exclusions.entry(region.exclusion_key()).or_default();
+        if !matches!(&*region, RegionCaptureTarget::Cursor(_)) {
+            render_capture_exclusions(ui, device.id(), exclusions);
+        }
+
+        if let RegionCaptureTarget::Subregion(_) = *region {
             let wide = ui.available_width() > 300.0;
             ui.horizontal(|ui| {
                 ui.label(t!("capture.x_label").to_string());
@@ -229,25 +359,297 @@ pub fn render_capture_target(app: &mut MantleApp, ui: &mut Ui, device: &DeviceIn
                 ui,
                 &app.screen_manager,
                 &mut subregion,
+                exclusions,
                 &mut app.monitor_preview_textures,
             );
         }
 
-        region_type_changed = waveform.active
-            && waveform.mode == WaveformMode::Screencap
-            && std::mem::discriminant(&waveform.region) != prev_discriminant;
+        *region != prev_region
+    };
+    if let Some(e) = open_error {
+        app.error_toast(&t!("error.open_frames", error = e.to_string()));
     }
-    if region_type_changed {
-        initialize_waveform_tracker(
-            app,
-            device,
-            update_interval_ms,
-            WaveformMode::Screencap,
-            ui.ctx().clone(),
-        );
+    if region_changed
+        && app
+            .effect_engine
+            .is_running(device.id(), ScreenEffect::KIND)
+    {
+        app.effect_engine
+            .start_effect(device.clone(), Box::new(screen_effect(app, device.id())));
     }
 }
 
+/// Areas of the capture target left out of screen sync: letterbox bars, found on every
+/// frame, and rectangles such as a taskbar, in percent of the target.
+fn render_capture_exclusions(ui: &mut Ui, device_id: u64, exclusions: &mut CaptureExclusions) {
+    egui::CollapsingHeader::new(t!("exclusions.title").to_string())
+        .id_salt(("exclusions", device_id))
+        .show(ui, |ui| {
+            ui.checkbox(
+                &mut exclusions.auto_letterbox,
+                t!("exclusions.auto_letterbox").to_string(),
+            )
+            .on_hover_text(t!("exclusions.auto_letterbox_hover").to_string());
+            let mut removed = None;
+            for (index, rect) in exclusions.rects.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    for (label, value) in [
+                        (t!("capture.x_label"), &mut rect.x),
+                        (t!("capture.y_label"), &mut rect.y),
+                        (t!("capture.width_label"), &mut rect.width),
+                        (t!("capture.height_label"), &mut rect.height),
+                    ] {
+                        ui.label(label.to_string());
+                        let mut percent = *value * 100.0;
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut percent)
+                                    .range(0.0..=100.0)
+                                    .speed(0.5)
+                                    .suffix("%"),
+                            )
+                            .changed()
+                        {
+                            *value = percent / 100.0;
+                        }
+                    }
+                    if ui
+                        .small_button(t!("exclusions.remove").to_string())
+                        .on_hover_text(t!("exclusions.remove_hover").to_string())
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                exclusions.rects.remove(index);
+            }
+            if ui
+                .button(t!("exclusions.add").to_string())
+                .on_hover_text(t!("exclusions.add_hover").to_string())
+                .clicked()
+            {
+                exclusions.rects.push(ExclusionRect::default());
+            }
+        });
+}
+
+/// How the device's screen sync picks colors from the captured image, applied while it
+/// runs. A palette hands its colors to the lights of a group in turn.
+pub fn render_screen_analysis(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let analysis = app.screen_analysis.entry(device.id()).or_default();
+    let Ok(mut analysis) = analysis.lock() else {
+        return;
+    };
+    ui.push_id(("screen_analysis", device.id()), |ui| {
+        ui.horizontal(|ui| {
+            ui.label(t!("analysis.label").to_string());
+            egui::ComboBox::from_id_salt("analysis_mode")
+                .selected_text(analysis.mode.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in AnalysisMode::iter() {
+                        ui.selectable_value(&mut analysis.mode, mode, mode.to_string());
+                    }
+                })
+                .response
+                .on_hover_text(t!("analysis.mode_hover").to_string());
+            if analysis.mode == AnalysisMode::Palette {
+                ui.add(
+                    egui::Slider::new(&mut analysis.palette_size, PALETTE_SIZE_RANGE)
+                        .text(t!("analysis.palette_size").to_string()),
+                )
+                .on_hover_text(t!("analysis.palette_size_hover").to_string());
+            }
+        });
+    });
+}
+
+/// How the device's screen sync maps the captured image onto its lights, applied while
+/// it runs. Edge mapping only changes strips and mirroring only changes matrix lights;
+/// other lights keep the average color.
+pub fn render_screen_mapping(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let mapping = app.screen_mappings.entry(device.id()).or_default();
+    let Ok(mut mapping) = mapping.lock() else {
+        return;
+    };
+    ui.push_id(("screen_mapping", device.id()), |ui| {
+        ui.horizontal(|ui| {
+            ui.label(t!("edges.mapping_label").to_string());
+            let is_average = *mapping == ScreenMapping::Average;
+            let is_edge = matches!(*mapping, ScreenMapping::Edge(_));
+            let is_mirror = matches!(*mapping, ScreenMapping::Mirror(_));
+            if ui
+                .selectable_label(is_average, t!("edges.average").to_string())
+                .on_hover_text(t!("edges.average_hover").to_string())
+                .clicked()
+            {
+                *mapping = ScreenMapping::Average;
+            }
+            if ui
+                .selectable_label(is_edge, t!("edges.edge").to_string())
+                .on_hover_text(t!("edges.edge_hover").to_string())
+                .clicked()
+                && !is_edge
+            {
+                *mapping = ScreenMapping::Edge(EdgeMapping::default());
+            }
+            if ui
+                .selectable_label(is_mirror, t!("mirror.title").to_string())
+                .on_hover_text(t!("mirror.title_hover").to_string())
+                .clicked()
+                && !is_mirror
+            {
+                *mapping = ScreenMapping::Mirror(MirrorFit::default());
+            }
+        });
+        let edge = match &mut *mapping {
+            ScreenMapping::Average => return,
+            ScreenMapping::Edge(edge) => edge,
+            ScreenMapping::Mirror(fit) => {
+                egui::ComboBox::from_id_salt("mirror_fit")
+                    .selected_text(fit.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in MirrorFit::iter() {
+                            ui.selectable_value(fit, option, option.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(t!("mirror.fit_hover").to_string());
+                return;
+            }
+        };
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("screen_edge")
+                .selected_text(edge.edge.to_string())
+                .show_ui(ui, |ui| {
+                    for option in ScreenEdge::iter() {
+                        ui.selectable_value(&mut edge.edge, option, option.to_string());
+                    }
+                })
+                .response
+                .on_hover_text(t!("edges.edge_combo_hover").to_string());
+            egui::ComboBox::from_id_salt("start_corner")
+                .selected_text(edge.start.to_string())
+                .show_ui(ui, |ui| {
+                    for option in StartCorner::iter() {
+                        ui.selectable_value(&mut edge.start, option, option.to_string());
+                    }
+                })
+                .response
+                .on_hover_text(t!("edges.start_hover").to_string());
+            if edge.edge == ScreenEdge::Perimeter {
+                egui::ComboBox::from_id_salt("edge_direction")
+                    .selected_text(edge.direction.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in EdgeDirection::iter() {
+                            ui.selectable_value(&mut edge.direction, option, option.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(t!("edges.direction_hover").to_string());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(t!("edges.offset").to_string());
+            ui.add(egui::DragValue::new(&mut edge.zone_offset).speed(0.2))
+                .on_hover_text(t!("edges.offset_hover").to_string());
+            ui.add(
+                egui::Slider::new(&mut edge.depth, DEPTH_RANGE)
+                    .fixed_decimals(2)
+                    .text(t!("edges.depth").to_string()),
+            )
+            .on_hover_text(t!("edges.depth_hover").to_string());
+        });
+    });
+}
+
+/// Timings of the screen capture pipeline for the debug window.
+pub fn render_capture_metrics(ui: &mut Ui, metrics: &CaptureMetrics) {
+    ui.heading(t!("capture_metrics.title").to_string());
+    if metrics.sources == 0 {
+        ui.label(t!("capture_metrics.idle").to_string());
+        return;
+    }
+    egui::Grid::new("capture_metrics")
+        .num_columns(2)
+        .show(ui, |ui| {
+            let mut row = |label: std::borrow::Cow<str>, value: String| {
+                ui.label(label.to_string());
+                ui.monospace(value);
+                ui.end_row();
+            };
+            row(t!("capture_metrics.sources"), metrics.sources.to_string());
+            row(
+                t!("capture_metrics.frame_rate"),
+                format!("{:.1} fps", metrics.frames_per_second),
+            );
+            row(
+                t!("capture_metrics.capture"),
+                format!("{:.1} ms", metrics.capture_ms),
+            );
+            row(
+                t!("capture_metrics.downsample"),
+                format!("{:.1} ms", metrics.downsample_ms),
+            );
+            row(
+                t!("capture_metrics.analysis"),
+                format!("{:.1} ms", metrics.analysis_ms),
+            );
+            row(
+                t!("capture_metrics.resolution"),
+                format!("{} × {}", metrics.sample_width, metrics.sample_height),
+            );
+            row(t!("capture_metrics.errors"), metrics.errors.to_string());
+        });
+    ui.ctx().request_repaint_after(Duration::from_millis(250));
+}
+
+/// Smoothing controls for the device's screen and audio sync, applied while they run.
+pub fn render_smoothing_controls(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let smoothing = app.capture_smoothing.entry(device.id()).or_default();
+    let Ok(mut smoothing) = smoothing.lock() else {
+        return;
+    };
+    egui::CollapsingHeader::new(t!("smoothing.title").to_string())
+        .id_salt(("smoothing", device.id()))
+        .show(ui, |ui| {
+            ui.checkbox(&mut smoothing.enabled, t!("smoothing.enabled").to_string())
+                .on_hover_text(t!("smoothing.enabled_hover").to_string());
+            ui.add_enabled_ui(smoothing.enabled, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut smoothing.responsiveness, RESPONSIVENESS_RANGE)
+                        .text(t!("smoothing.responsiveness").to_string()),
+                )
+                .on_hover_text(t!("smoothing.responsiveness_hover").to_string());
+                ui.add(
+                    egui::Slider::new(&mut smoothing.hue_dead_band, HUE_DEAD_BAND_RANGE)
+                        .suffix("°")
+                        .text(t!("smoothing.hue_dead_band").to_string()),
+                )
+                .on_hover_text(t!("smoothing.dead_band_hover").to_string());
+                ui.add(
+                    egui::Slider::new(&mut smoothing.saturation_dead_band, LEVEL_DEAD_BAND_RANGE)
+                        .fixed_decimals(2)
+                        .text(t!("smoothing.saturation_dead_band").to_string()),
+                )
+                .on_hover_text(t!("smoothing.dead_band_hover").to_string());
+                ui.add(
+                    egui::Slider::new(&mut smoothing.brightness_dead_band, LEVEL_DEAD_BAND_RANGE)
+                        .fixed_decimals(2)
+                        .text(t!("smoothing.brightness_dead_band").to_string()),
+                )
+                .on_hover_text(t!("smoothing.dead_band_hover").to_string());
+                ui.add(
+                    egui::Slider::new(&mut smoothing.kelvin_dead_band, KELVIN_DEAD_BAND_RANGE)
+                        .suffix("K")
+                        .text(t!("smoothing.kelvin_dead_band").to_string()),
+                )
+                .on_hover_text(t!("smoothing.dead_band_hover").to_string());
+            });
+        });
+}
+
 pub fn update_subregion_bounds(app: &mut MantleApp, ui: &mut Ui, device_id: u64) {
     let subregion_lock = app
         .subregion_points
@@ -318,6 +720,7 @@ fn render_subregion_preview(
     ui: &mut Ui,
     screen_manager: &ScreencapManager,
     subregion: &mut ScreenSubregion,
+    exclusions: &CaptureExclusions,
     textures: &mut HashMap<u32, TextureHandle>,
 ) {
     let monitors = &screen_manager.monitors;
@@ -499,6 +902,23 @@ fn render_subregion_preview(
                 Color32::from_rgba_unmultiplied(220, 160, 50, stroke_alpha),
             ),
         );
+        let pixels = (subregion.width, subregion.height);
+        for rect in exclusions
+            .rects
+            .iter()
+            .filter_map(|rect| rect.to_pixels(pixels.0, pixels.1))
+        {
+            let excluded = egui::Rect::from_min_size(
+                sub_rect.min + vec2(rect.x as f32 * scale, rect.y as f32 * scale),
+                vec2(rect.width as f32 * scale, rect.height as f32 * scale),
+            );
+            painter.rect(
+                excluded,
+                0.0,
+                Color32::from_rgba_unmultiplied(200, 40, 40, 90),
+                Stroke::new(1.0, Color32::from_rgb(220, 60, 60)),
+            );
+        }
         ui.ctx().request_repaint();
     }
 
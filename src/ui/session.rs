@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use eframe::egui::{self, pos2, vec2, Align2, Color32, FontId, Pos2, Rect, Sense, Stroke, Ui};
+use rust_i18n::t;
+use xcap::Monitor;
+
+use crate::{
+    app::MantleApp,
+    device_info::DeviceInfo,
+    effects::session::{AssignmentTarget, RegionAssignment},
+    screencap::{ScreenSessionEffect, ScreencapManager},
+};
+
+const PREVIEW_MAX_WIDTH: u32 = 480;
+/// Regions smaller than this on the preview, in points, are taken for stray clicks.
+const MIN_DRAWN_SIZE: f32 = 4.0;
+const REGION_COLORS: [Color32; 6] = [
+    Color32::from_rgb(230, 160, 50),
+    Color32::from_rgb(80, 180, 230),
+    Color32::from_rgb(120, 210, 100),
+    Color32::from_rgb(220, 90, 160),
+    Color32::from_rgb(170, 130, 240),
+    Color32::from_rgb(240, 220, 90),
+];
+
+/// A light or group a region can be assigned to.
+struct TargetOption {
+    label: String,
+    target: AssignmentTarget,
+    /// Zones of a multizone light, which can take a range of them.
+    zones: usize,
+}
+
+/// The screen sync session editor: draw regions on a monitor, assign each to a light, a
+/// zone range or a group, and run them all from one capture per frame.
+pub fn render_screen_session(app: &mut MantleApp, ui: &mut Ui) {
+    let session = app.screen_session.clone();
+    let Ok(mut session) = session.lock() else {
+        return;
+    };
+    let monitors = app.screen_manager.monitors.clone();
+    let Some(monitor) = monitors
+        .iter()
+        .find(|monitor| monitor.name() == session.monitor)
+        .or_else(|| monitors.iter().find(|monitor| monitor.is_primary()))
+        .or_else(|| monitors.first())
+        .cloned()
+    else {
+        ui.label(t!("session.no_monitors").to_string());
+        return;
+    };
+
+    let all = DeviceInfo::Group(app.lighting_manager.all_bulbs_group.clone());
+    let is_running = app
+        .effect_engine
+        .is_running(all.id(), ScreenSessionEffect::KIND);
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label(t!("session.monitor").to_string())
+            .selected_text(monitor.name())
+            .show_ui(ui, |ui| {
+                for option in &monitors {
+                    if ui
+                        .selectable_label(option.name() == monitor.name(), option.name())
+                        .clicked()
+                    {
+                        session.monitor = option.name().to_string();
+                    }
+                }
+            });
+        let label = if is_running {
+            t!("session.stop")
+        } else {
+            t!("session.start")
+        };
+        if ui
+            .button(label.to_string())
+            .on_hover_text(t!("session.start_hover").to_string())
+            .clicked()
+        {
+            if is_running {
+                app.effect_engine.stop_effect(all.id());
+            } else {
+                start_session(app, &all, &monitors, &session.assignments);
+            }
+        }
+    });
+    ui.checkbox(
+        &mut session.smoothing.enabled,
+        t!("smoothing.enabled").to_string(),
+    )
+    .on_hover_text(t!("smoothing.enabled_hover").to_string());
+
+    if let Some(drawn) = render_session_preview(app, ui, &monitor, &session.assignments) {
+        session.assignments.push(drawn);
+    }
+    if session.monitor.is_empty() {
+        session.monitor = monitor.name().to_string();
+    }
+
+    let options = target_options(app);
+    let mut removed = None;
+    for (index, assignment) in session.assignments.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    REGION_COLORS[index % REGION_COLORS.len()],
+                    format!("{}", index + 1),
+                );
+                render_target_picker(ui, assignment, &options);
+                if ui
+                    .small_button(t!("session.remove").to_string())
+                    .on_hover_text(t!("session.remove_hover").to_string())
+                    .clicked()
+                {
+                    removed = Some(index);
+                }
+            });
+        });
+    }
+    if let Some(index) = removed {
+        session.assignments.remove(index);
+    }
+    if session.assignments.is_empty() {
+        ui.label(t!("session.draw_hint").to_string());
+    }
+}
+
+/// Stop whatever else runs on the session's lights, so they don't fight over them, and
+/// start the session on all lights.
+fn start_session(
+    app: &MantleApp,
+    all: &DeviceInfo,
+    monitors: &[Monitor],
+    assignments: &[RegionAssignment],
+) {
+    let mut groups: HashMap<u64, Vec<u64>> = HashMap::new();
+    if let Ok(bulbs) = app.lighting_manager.bulbs.lock() {
+        for bulb in bulbs.values() {
+            if let Some(group) = bulb.group.data.as_ref() {
+                groups.entry(group.id()).or_default().push(bulb.target);
+            }
+        }
+    }
+    for target in assignments.iter().filter_map(|a| a.target) {
+        let id = match target {
+            AssignmentTarget::Bulb(id) | AssignmentTarget::Group(id) => id,
+            AssignmentTarget::Zones { target, .. } => target,
+        };
+        app.effect_engine.stop_effect(id);
+    }
+    let monitors = monitors
+        .iter()
+        .map(|monitor| (monitor.name().to_string(), monitor.id()))
+        .collect();
+    app.effect_engine.start_effect(
+        all.clone(),
+        Box::new(ScreenSessionEffect::new(
+            app.screen_session.clone(),
+            monitors,
+            groups,
+            Duration::from_millis(app.settings.update_interval_ms),
+            app.capture_service.clone(),
+        )),
+    );
+}
+
+/// Every group, then every light, by label.
+fn target_options(app: &MantleApp) -> Vec<TargetOption> {
+    let Ok(bulbs) = app.lighting_manager.bulbs.lock() else {
+        return Vec::new();
+    };
+    let mut groups = BTreeMap::new();
+    let mut lights = Vec::new();
+    for bulb in bulbs.values() {
+        if let Some(group) = bulb.group.data.as_ref() {
+            let label = group.label.cstr().to_string_lossy().to_string();
+            groups.insert(label, group.id());
+        }
+        lights.push(TargetOption {
+            label: bulb.name_label().unwrap_or_default(),
+            target: AssignmentTarget::Bulb(bulb.target),
+            zones: bulb.get_zone_count(),
+        });
+    }
+    lights.sort_by(|a, b| a.label.cmp(&b.label));
+    groups
+        .into_iter()
+        .map(|(label, id)| TargetOption {
+            label: t!("session.group", group = label).to_string(),
+            target: AssignmentTarget::Group(id),
+            zones: 0,
+        })
+        .chain(lights)
+        .collect()
+}
+
+fn render_target_picker(ui: &mut Ui, assignment: &mut RegionAssignment, options: &[TargetOption]) {
+    let light = match assignment.target {
+        Some(AssignmentTarget::Zones { target, .. }) => Some(AssignmentTarget::Bulb(target)),
+        target => target,
+    };
+    let selected = options.iter().find(|option| Some(option.target) == light);
+    let selected_text = match (assignment.target, selected) {
+        (None, _) => t!("session.unassigned").to_string(),
+        (_, Some(option)) => option.label.clone(),
+        (_, None) => t!("session.missing").to_string(),
+    };
+    egui::ComboBox::from_id_salt("session_target")
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            if ui
+                .selectable_label(
+                    assignment.target.is_none(),
+                    t!("session.unassigned").to_string(),
+                )
+                .clicked()
+            {
+                assignment.target = None;
+            }
+            for option in options {
+                if ui
+                    .selectable_label(light == Some(option.target), &option.label)
+                    .clicked()
+                    && light != Some(option.target)
+                {
+                    assignment.target = Some(option.target);
+                }
+            }
+        });
+
+    let Some(option) = selected.filter(|option| option.zones > 1) else {
+        return;
+    };
+    let AssignmentTarget::Bulb(target) = option.target else {
+        return;
+    };
+    let mut zoned = matches!(assignment.target, Some(AssignmentTarget::Zones { .. }));
+    if ui
+        .checkbox(&mut zoned, t!("session.zones").to_string())
+        .on_hover_text(t!("session.zones_hover").to_string())
+        .changed()
+    {
+        assignment.target = Some(if zoned {
+            AssignmentTarget::Zones {
+                target,
+                start: 0,
+                end: option.zones - 1,
+            }
+        } else {
+            AssignmentTarget::Bulb(target)
+        });
+    }
+    if let Some(AssignmentTarget::Zones { start, end, .. }) = &mut assignment.target {
+        let last = option.zones - 1;
+        ui.add(egui::DragValue::new(start).range(0..=last));
+        ui.label("–");
+        ui.add(egui::DragValue::new(end).range(*start..=last));
+    }
+}
+
+/// The monitor with the session's regions on it. Dragging on it draws a new region, which
+/// is returned once the drag ends.
+fn render_session_preview(
+    app: &mut MantleApp,
+    ui: &mut Ui,
+    monitor: &Monitor,
+    assignments: &[RegionAssignment],
+) -> Option<RegionAssignment> {
+    if monitor.width() == 0 || monitor.height() == 0 {
+        return None;
+    }
+    let width = ui.available_width();
+    let scale = width / monitor.width() as f32;
+    let texture = app
+        .monitor_preview_textures
+        .entry(monitor.id())
+        .or_insert_with(|| {
+            let image = ScreencapManager::capture_monitor_preview(monitor, PREVIEW_MAX_WIDTH)
+                .unwrap_or_else(|_| egui::ColorImage::new([1, 1], Color32::from_rgb(30, 30, 42)));
+            ui.ctx().load_texture(
+                format!("monitor_preview_{}", monitor.id()),
+                image,
+                egui::TextureOptions::LINEAR,
+            )
+        })
+        .clone();
+
+    let (response, painter) = ui.allocate_painter(
+        vec2(width, monitor.height() as f32 * scale),
+        Sense::click_and_drag(),
+    );
+    let origin = response.rect.min;
+    painter.image(
+        texture.id(),
+        response.rect,
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    let to_preview = |x: i32, y: i32, width: u32, height: u32| {
+        Rect::from_min_size(
+            origin + vec2(x as f32 * scale, y as f32 * scale),
+            vec2(width as f32 * scale, height as f32 * scale),
+        )
+    };
+    for (index, assignment) in assignments.iter().enumerate() {
+        let color = REGION_COLORS[index % REGION_COLORS.len()];
+        let rect = to_preview(
+            assignment.x,
+            assignment.y,
+            assignment.width,
+            assignment.height,
+        );
+        painter.rect(
+            rect,
+            2.0,
+            color.gamma_multiply(0.2),
+            Stroke::new(2.0, color),
+        );
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            format!("{}", index + 1),
+            FontId::proportional(14.0),
+            Color32::WHITE,
+        );
+    }
+
+    let drag_start_id = response.id.with("drag_start");
+    if response.drag_started() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            ui.memory_mut(|mem| mem.data.insert_temp(drag_start_id, pos));
+        }
+    }
+    let start = ui.memory(|mem| mem.data.get_temp::<Pos2>(drag_start_id));
+    let end = ui.input(|input| input.pointer.latest_pos());
+    let drawn = match (start, end) {
+        (Some(start), Some(end)) => Some(Rect::from_two_pos(start, end).intersect(response.rect)),
+        _ => None,
+    };
+    if let Some(drawn) = drawn.filter(|_| response.dragged()) {
+        painter.rect_stroke(drawn, 2.0, Stroke::new(2.0, Color32::WHITE));
+    }
+    if response.hovered() {
+        ui.ctx()
+            .output_mut(|out| out.cursor_icon = egui::CursorIcon::Crosshair);
+    }
+    let finished = response.drag_stopped();
+    response.on_hover_text(t!("session.draw_hint").to_string());
+    if !finished {
+        return None;
+    }
+    ui.memory_mut(|mem| mem.data.remove::<Pos2>(drag_start_id));
+    let drawn = drawn?;
+    if drawn.width() < MIN_DRAWN_SIZE || drawn.height() < MIN_DRAWN_SIZE {
+        return None;
+    }
+    Some(RegionAssignment {
+        x: ((drawn.min.x - origin.x) / scale) as i32,
+        y: ((drawn.min.y - origin.y) / scale) as i32,
+        width: (drawn.width() / scale) as u32,
+        height: (drawn.height() / scale) as u32,
+        target: None,
+    })
+}
@@ -1,15 +1,11 @@
-use std::{
-    sync::mpsc,
-    thread,
-    time::{Duration, Instant},
-};
+use std::collections::HashMap;
+use std::time::Duration;
 
 use crate::{
-    app::{ColorChannelEntry, MantleApp, WaveformMode, WaveformTracker, AUDIO_ICON},
-    audio::AudioManager,
-    color::DeltaColor,
+    app::{MantleApp, AUDIO_ICON},
+    audio::{AudioEffect, AudioSyncOptions},
     device_info::DeviceInfo,
-    screencap::{RegionCaptureTarget, ScreencapManager},
+    effects::{AudioMapping, Effect},
 };
 
 use super::widgets::create_highlighted_button;
@@ -17,214 +13,104 @@ use super::widgets::create_highlighted_button;
 use eframe::egui::{self, Ui};
 use lifx_core::HSBK;
 use rust_i18n::t;
+use strum::IntoEnumIterator;
 
-pub fn handle_audio(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) -> Option<DeltaColor> {
-    let mut color: Option<HSBK> = None;
-    let update_interval_ms = app.settings.update_interval_ms;
-
-    ensure_waveform_channel(app, device.id());
-    app.waveform_map
-        .entry(device.id())
-        .or_insert(WaveformTracker {
-            active: false,
-            last_update: Instant::now(),
-            mode: WaveformMode::Audio,
-            region: RegionCaptureTarget::All,
-            stop_tx: None,
-        });
-
-    if let Some(color_channel) = app.waveform_channel.get(&device.id()) {
-        if let Some(tracker) = app.waveform_map.get_mut(&device.id()) {
-            if tracker.active
-                && tracker.mode == WaveformMode::Audio
-                && has_time_elapsed(update_interval_ms, tracker)
-            {
-                update_color_from_channel(&mut color, tracker, color_channel);
-            }
-        }
-    }
-
-    let is_active = app
-        .waveform_map
-        .get(&device.id())
-        .is_some_and(|w| w.active && w.mode == WaveformMode::Audio);
+pub fn handle_audio(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let is_active = app.effect_engine.is_running(device.id(), AudioEffect::KIND);
     if create_highlighted_button(ui, "audio", AUDIO_ICON, is_active)
         .on_hover_text(t!("waveform.audio_hover").to_string())
         .clicked()
     {
-        initialize_waveform_tracker(
-            app,
-            device,
-            update_interval_ms,
-            WaveformMode::Audio,
-            ui.ctx().clone(),
-        );
-    }
-
-    color.map(|color| DeltaColor {
-        next: color,
-        duration: Some((update_interval_ms / 2) as u32),
-    })
-}
-
-pub(crate) fn ensure_waveform_channel(app: &mut MantleApp, device_id: u64) {
-    app.waveform_channel.entry(device_id).or_insert_with(|| {
-        let (tx, rx) = mpsc::channel();
-        ColorChannelEntry {
-            tx,
-            rx,
-            handle: None,
-        }
-    });
-}
-
-fn stop_active_waveform(app: &mut MantleApp, device_id: u64) {
-    if let Some(tracker) = app.waveform_map.get_mut(&device_id) {
-        if let Some(stop_tx) = tracker.stop_tx.take() {
-            let _ = stop_tx.send(());
-        }
-        tracker.active = false;
-    }
-    if let Some(channel) = app.waveform_channel.get_mut(&device_id) {
-        if let Some(handle) = channel.handle.take() {
-            let _ = handle.join();
-        }
+        let interval = Duration::from_millis(app.settings.update_interval_ms);
+        let options = AudioSyncOptions {
+            mapping: app.audio_mappings.entry(device.id()).or_default().clone(),
+            smoothing: app
+                .capture_smoothing
+                .entry(device.id())
+                .or_default()
+                .clone(),
+            scenes: scene_colors(app),
+        };
+        toggle_effect(app, device, is_active, || {
+            Box::new(AudioEffect::new(interval, options))
+        });
     }
 }
 
-pub(crate) fn initialize_waveform_tracker(
-    app: &mut MantleApp,
-    device: &DeviceInfo,
-    update_interval_ms: u64,
-    mode: WaveformMode,
-    ctx: egui::Context,
-) {
-    let device_id = device.id();
-
-    let is_toggle_off = app
-        .waveform_map
-        .get(&device_id)
-        .map(|w| w.active && w.mode == mode)
-        .unwrap_or(false);
-
-    let existing_region = app
-        .waveform_map
-        .get(&device_id)
-        .map(|w| w.region.clone())
-        .unwrap_or(RegionCaptureTarget::All);
-
-    stop_active_waveform(app, device_id);
-
-    if is_toggle_off {
-        return;
-    }
-
-    ensure_waveform_channel(app, device_id);
-
-    app.waveform_map.insert(
-        device_id,
-        WaveformTracker {
-            active: true,
-            last_update: Instant::now(),
-            mode: mode.clone(),
-            region: existing_region.clone(),
-            stop_tx: None,
-        },
-    );
-
-    let tx = match app.waveform_channel.get(&device_id) {
-        Some(channel) => channel.tx.clone(),
-        None => return,
+/// The color of each light in each saved scene. Lights set on their own win over the
+/// groups they're in.
+fn scene_colors(app: &MantleApp) -> Vec<HashMap<u64, HSBK>> {
+    let Ok(bulbs) = app.lighting_manager.bulbs.lock() else {
+        return Vec::new();
     };
-    let (stop_tx, stop_rx) = mpsc::channel::<()>();
-
-    let handle = match mode {
-        WaveformMode::Screencap => {
-            let region = existing_region;
-            let shared_subregion = if matches!(region, RegionCaptureTarget::Subregion(_)) {
-                app.subregion_points.get(&device_id).cloned()
-            } else {
-                None
-            };
-            let ctx = ctx.clone();
-            thread::spawn(move || {
-                let screen_manager = match ScreencapManager::new() {
-                    Ok(sm) => sm,
-                    Err(e) => {
-                        log::error!("Failed to create screen manager in capture thread: {}", e);
-                        return;
-                    }
-                };
-                loop {
-                    let capture_region = match &shared_subregion {
-                        Some(sub_lock) => {
-                            let sub = sub_lock.lock().expect("Failed to lock subregion");
-                            RegionCaptureTarget::Subregion(vec![sub.clone()])
-                        }
-                        None => region.clone(),
-                    };
-                    match screen_manager.calculate_average_color(capture_region) {
-                        Ok(color) => {
-                            if tx.send(color).is_err() {
-                                break;
-                            }
-                            ctx.request_repaint();
-                        }
-                        Err(e) => log::error!("Screen capture error: {}", e),
+    app.settings
+        .scenes
+        .iter()
+        .map(|scene| {
+            let mut colors = HashMap::new();
+            for (device, color) in &scene.device_color_pairs {
+                if let DeviceInfo::Group(group) = device {
+                    for bulb in group.get_bulbs(&bulbs) {
+                        colors.insert(bulb.target, HSBK::from(*color));
                     }
-                    thread::sleep(Duration::from_millis(update_interval_ms));
-                    if stop_rx.try_recv().is_ok() {
-                        break;
-                    }
-                }
-            })
-        }
-        WaveformMode::Audio => {
-            let buffer_clone = app.audio_manager.clone_samples_buffer();
-            thread::spawn(move || loop {
-                let samples = match buffer_clone.lock() {
-                    Ok(buf) => buf.clone(),
-                    Err(_) => break,
-                };
-                let audio_color = AudioManager::samples_to_hsbk(samples);
-                if tx.send(audio_color).is_err() {
-                    break;
                 }
-                ctx.request_repaint();
-                thread::sleep(Duration::from_millis(update_interval_ms));
-                if stop_rx.try_recv().is_ok() {
-                    break;
+            }
+            for (device, color) in &scene.device_color_pairs {
+                if let DeviceInfo::Bulb(bulb) = device {
+                    colors.insert(bulb.target, HSBK::from(*color));
                 }
-            })
-        }
-    };
-
-    if let Some(channel) = app.waveform_channel.get_mut(&device_id) {
-        channel.handle = Some(handle);
-    }
-    if let Some(tracker) = app.waveform_map.get_mut(&device_id) {
-        tracker.stop_tx = Some(stop_tx);
-    }
+            }
+            colors
+        })
+        .collect()
 }
 
-pub(crate) fn has_time_elapsed(
-    update_interval_ms: u64,
-    waveform_tracker: &mut WaveformTracker,
-) -> bool {
-    Instant::now() - waveform_tracker.last_update > Duration::from_millis(update_interval_ms)
+/// How the device's audio sync turns sound into light, applied while it runs, with the
+/// tempo found so far.
+pub fn render_audio_mapping(app: &mut MantleApp, ui: &mut Ui, device: &DeviceInfo) {
+    let features = app.audio_manager.features();
+    let no_scenes = app.settings.scenes.is_empty();
+    let mapping = app.audio_mappings.entry(device.id()).or_default();
+    let Ok(mut mapping) = mapping.lock() else {
+        return;
+    };
+    ui.push_id(("audio_mapping", device.id()), |ui| {
+        ui.horizontal(|ui| {
+            ui.label(t!("audio_mapping.label").to_string());
+            egui::ComboBox::from_id_salt("audio_mapping")
+                .selected_text(mapping.to_string())
+                .show_ui(ui, |ui| {
+                    for mode in AudioMapping::iter() {
+                        ui.selectable_value(&mut *mapping, mode, mode.to_string());
+                    }
+                })
+                .response
+                .on_hover_text(t!("audio_mapping.mode_hover").to_string());
+            let on_beats = matches!(
+                *mapping,
+                AudioMapping::BeatPulse | AudioMapping::HueStep | AudioMapping::SceneChange
+            );
+            if let Some(bpm) = features.bpm.filter(|_| on_beats) {
+                ui.label(t!("audio_mapping.bpm", bpm = format!("{:.0}", bpm)).to_string());
+            }
+        });
+        if *mapping == AudioMapping::SceneChange && no_scenes {
+            ui.label(t!("audio_mapping.no_scenes").to_string());
+        }
+    });
 }
 
-pub(crate) fn update_color_from_channel(
-    color: &mut Option<HSBK>,
-    tracker: &mut WaveformTracker,
-    color_channel: &ColorChannelEntry,
+/// Stop the effect on `device` if it's the one a button controls, otherwise replace
+/// whatever runs there with a new one.
+pub(crate) fn toggle_effect(
+    app: &MantleApp,
+    device: &DeviceInfo,
+    is_active: bool,
+    effect: impl FnOnce() -> Box<dyn Effect>,
 ) {
-    let mut latest = None;
-    while let Ok(computed_color) = color_channel.rx.try_recv() {
-        latest = Some(computed_color);
-    }
-    if let Some(latest_color) = latest {
-        *color = Some(latest_color);
-        tracker.last_update = Instant::now();
+    if is_active {
+        app.effect_engine.stop_effect(device.id());
+    } else {
+        app.effect_engine.start_effect(device.clone(), effect());
     }
 }
@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    color::kelvin_to_rgb,
+    color::{kelvin_to_rgb, parse_hex_color},
     contrast_color,
     device_info::DeviceInfo,
     products::{KELVIN_RANGE, LIFX_RANGE},
@@ -796,17 +796,6 @@ pub fn color_wheel(ui: &mut Ui, hue: &mut u16, saturation: &mut u16, radius: f32
     changed
 }
 
-fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
-        return None;
-    }
-    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-    Some((r, g, b))
-}
-
 /// RGB color input with hex field, R/G/B drag-value fields, and a preview swatch.
 /// Converts user input to HSBK via the existing `RGB8 -> HSBK` path.
 ///